@@ -0,0 +1,491 @@
+use std::f64;
+use std::fs::File;
+
+use fio::{ FileIO, FileIORequest, FileIOResponse };
+use io::*;
+use meter::clip_level;
+use sample::SampleFormat;
+use silence::{ Detection, Detector, Span };
+use wav::{ Encoding, SeekTarget, WaveFormat, WaveHeader, decode };
+
+// frames read from the data chunk at a time
+const READ_FRAMES : usize = 65536;
+
+// the worker is given a second a read, and this on top
+const TIMEOUT_MARGIN : u64 = 10;
+
+fn to_db(level: f64) -> f64 {
+    match level > 0.0 {
+        true => 20.0 * level.log10(),
+        _ => f64::NEG_INFINITY
+    }
+}
+
+// what a channel holds over a whole file. levels are linear, 1.0 is full
+// scale. the dc offset is the mean of the samples, and zero crossings
+// are given per second.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelStats {
+    pub min                : f64,
+    pub max                : f64,
+    pub peak               : f64,
+    pub rms                : f64,
+    pub dc_offset          : f64,
+    pub clips              : u64,
+    pub zero_crossing_rate : f64
+}
+
+impl ChannelStats {
+
+    pub fn peak_db(&self) -> f64 {
+        to_db(self.peak)
+    }
+
+    pub fn rms_db(&self) -> f64 {
+        to_db(self.rms)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Analysis {
+    pub format   : WaveFormat,
+    pub frames   : u64,
+    pub channels : Vec<ChannelStats>,
+    pub silences : Vec<Span>
+}
+
+// the running sums of a channel
+#[derive(Clone, Copy, Debug)]
+struct Sums {
+    min       : f64,
+    max       : f64,
+    sum       : f64,
+    squares   : f64,
+    clips     : u64,
+    crossings : u64,
+    negative  : Option<bool>
+}
+
+impl Sums {
+
+    fn new() -> Self {
+        Sums {
+            min: 0.0,
+            max: 0.0,
+            sum: 0.0,
+            squares: 0.0,
+            clips: 0,
+            crossings: 0,
+            negative: None
+        }
+    }
+}
+
+// gathers the stats of a file from its decoded samples, a buffer at
+// a time
+pub struct Analyzer {
+    format   : WaveFormat,
+    clip     : f64,
+    frames   : u64,
+    sums     : Vec<Sums>,
+    detector : Detector
+}
+
+impl Analyzer {
+
+    pub fn new(format: WaveFormat, detection: Detection) -> Self {
+
+        // adpcm is decoded to 16 bits
+        let sample_format = format.sample_format()
+            .unwrap_or(SampleFormat::S16_LE);
+        let channels = format.channels as usize;
+        let detector = Detector::new(detection, format.channels,
+            format.sample_rate);
+
+        Analyzer {
+            format: format,
+            clip: clip_level(sample_format),
+            frames: 0,
+            sums: vec![Sums::new(); channels],
+            detector: detector
+        }
+    }
+
+    // interleaved samples, whole frames of them
+    pub fn push(&mut self, samples: &[f64]) {
+
+        let channels = self.sums.len().max(1);
+
+        for frame in samples.chunks(channels) {
+
+            for (sums, &sample) in self.sums.iter_mut().zip(frame.iter()) {
+
+                if sums.negative.is_none() {
+                    sums.min = sample;
+                    sums.max = sample;
+                }
+
+                sums.min = sums.min.min(sample);
+                sums.max = sums.max.max(sample);
+                sums.sum += sample;
+                sums.squares += sample * sample;
+
+                if sample >= self.clip || sample <= -1.0 {
+                    sums.clips += 1;
+                }
+
+                // zero counts as positive
+                let negative = sample < 0.0;
+                if sums.negative.map_or(false, |last| last != negative) {
+                    sums.crossings += 1;
+                }
+                sums.negative = Some(negative);
+            }
+
+            self.frames += 1;
+        }
+
+        self.detector.push(samples);
+    }
+
+    pub fn finish(self) -> Analysis {
+
+        let frames = self.frames;
+        let seconds = frames as f64 / self.format.sample_rate as f64;
+
+        let channels = self.sums
+            .iter()
+            .map(|sums| {
+                let count = frames.max(1) as f64;
+                ChannelStats {
+                    min: sums.min,
+                    max: sums.max,
+                    peak: sums.min.abs().max(sums.max.abs()),
+                    rms: (sums.squares / count).sqrt(),
+                    dc_offset: sums.sum / count,
+                    clips: sums.clips,
+                    zero_crossing_rate: match frames {
+                        0 => 0.0,
+                        _ => sums.crossings as f64 / seconds
+                    }
+                }
+            })
+            .collect();
+
+        Analysis {
+            format: self.format,
+            frames: frames,
+            channels: channels,
+            silences: self.detector.finish()
+        }
+    }
+}
+
+fn expect_response(file_io: &FileIO) -> IOResult<FileIOResponse> {
+    match file_io.recv() {
+        Ok(FileIOResponse::Failed(err)) => Err(err),
+        Ok(FileIOResponse::Timeout) => Err(IOError::new(IO_ERROR,
+            "timeout")),
+        Ok(res) => Ok(res),
+        Err(err) => io_error(err)
+    }
+}
+
+fn request(file_io: &FileIO, req: FileIORequest)
+ -> IOResult<FileIOResponse> {
+    file_io.send(req)
+        .unwrap();
+    expect_response(file_io)
+}
+
+// reads the data chunk of the file at `path` through a file worker,
+// looking for silence as `detection` has it
+pub fn analyze(path: &str, detection: Detection) -> IOResult<Analysis> {
+
+    // the header is read here so that a file that isn't a wave fails
+    // before the worker is started
+    let header = try!(WaveHeader::parse(&mut try!(File::open(path))));
+    try!(header.format.encoding());
+
+    let format = header.format.clone();
+    let block_align = format.block_align as u64;
+    let blocks = (READ_FRAMES / format.frames_per_block()).max(1) as u64;
+    let chunk = blocks * block_align;
+    let reads = (header.data_size + chunk - 1) / chunk;
+
+    let mut file_io = FileIO::new(path.to_string(),
+        reads + TIMEOUT_MARGIN,
+        0);
+    try!(file_io.start());
+
+    match try!(request(&file_io, FileIORequest::Seek(SeekTarget::Frame(0)))) {
+        FileIOResponse::Sought(_) => (),
+        _ => panic!("unexpected response type")
+    }
+
+    let mut analyzer = Analyzer::new(format.clone(), detection);
+
+    // whole blocks only, a trailing partial one is left out
+    let mut left = header.data_size / block_align * block_align;
+
+    while left > 0 {
+
+        let size = chunk.min(left);
+
+        let samples = match try!(request(&file_io,
+            FileIORequest::Read(size as usize))) {
+            FileIOResponse::Read(buf) =>
+                try!(decode(&format, unsafe { buf.load() })),
+            _ => panic!("unexpected response type")
+        };
+
+        analyzer.push(&samples);
+        left -= size;
+    }
+
+    try!(file_io.stop());
+    Ok(analyzer.finish())
+}
+
+fn seconds(format: &WaveFormat, frames: u64) -> f64 {
+    frames as f64 / format.sample_rate as f64
+}
+
+fn encoding_name(format: &WaveFormat) -> &'static str {
+    match format.encoding() {
+        Ok(Encoding::Pcm) => "pcm",
+        Ok(Encoding::Float) => "float",
+        Ok(Encoding::ImaAdpcm) => "ima-adpcm",
+        _ => "unknown"
+    }
+}
+
+fn db_text(db: f64) -> String {
+    match db.is_finite() {
+        true => format!("{:.2}", db),
+        _ => "-inf".to_string()
+    }
+}
+
+// a report for people to read
+pub fn to_text(path: &str, analysis: &Analysis) -> String {
+
+    let format = &analysis.format;
+
+    let mut text = format!("{}\n", path);
+    text.push_str(&format!("  format    {} {} bit, {} channels, {} Hz\n",
+        encoding_name(format),
+        format.bits_per_sample,
+        format.channels,
+        format.sample_rate));
+    text.push_str(&format!("  duration  {:.3} s, {} frames\n",
+        seconds(format, analysis.frames),
+        analysis.frames));
+    text.push_str(&format!("  {:<8}{:>10}{:>10}{:>11}{:>10}{:>10}{:>8}{:>10}\n",
+        "channel", "min", "max", "peak dBFS", "rms dBFS", "dc", "clips",
+        "zcr/s"));
+
+    for (index, channel) in analysis.channels.iter().enumerate() {
+        text.push_str(&format!(
+            "  {:<8}{:>10.5}{:>10.5}{:>11}{:>10}{:>10.5}{:>8}{:>10.1}\n",
+            index + 1,
+            channel.min,
+            channel.max,
+            db_text(channel.peak_db()),
+            db_text(channel.rms_db()),
+            channel.dc_offset,
+            channel.clips,
+            channel.zero_crossing_rate));
+    }
+
+    for silence in analysis.silences.iter() {
+        text.push_str(&format!("  silence   {:.3} s to {:.3} s\n",
+            seconds(format, silence.start),
+            seconds(format, silence.end())));
+    }
+
+    text
+}
+
+fn json_string(value: &str) -> String {
+
+    let mut json = "\"".to_string();
+
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 =>
+                json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c)
+        }
+    }
+
+    json.push('"');
+    json
+}
+
+// json has no infinity, a level of silence is null
+fn json_number(value: f64) -> String {
+    match value.is_finite() {
+        true => format!("{}", value),
+        _ => "null".to_string()
+    }
+}
+
+// a report for other programs, on a single line
+pub fn to_json(path: &str, analysis: &Analysis) -> String {
+
+    let format = &analysis.format;
+
+    let channels = analysis.channels
+        .iter()
+        .map(|channel| format!("{{\"min\":{},\"max\":{},\"peak_dbfs\":{},\
+            \"rms_dbfs\":{},\"dc_offset\":{},\"clipped_samples\":{},\
+            \"zero_crossing_rate\":{}}}",
+            json_number(channel.min),
+            json_number(channel.max),
+            json_number(channel.peak_db()),
+            json_number(channel.rms_db()),
+            json_number(channel.dc_offset),
+            channel.clips,
+            json_number(channel.zero_crossing_rate)))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let silences = analysis.silences
+        .iter()
+        .map(|silence| format!("{{\"start\":{},\"end\":{}}}",
+            json_number(seconds(format, silence.start)),
+            json_number(seconds(format, silence.end()))))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    format!("{{\"path\":{},\"encoding\":{},\"bits_per_sample\":{},\
+        \"sample_rate\":{},\"frames\":{},\"duration\":{},\
+        \"channels\":[{}],\"silences\":[{}]}}",
+        json_string(path),
+        json_string(encoding_name(format)),
+        format.bits_per_sample,
+        format.sample_rate,
+        analysis.frames,
+        json_number(seconds(format, analysis.frames)),
+        channels,
+        silences)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use fixtures::{ TempDir, pcm };
+    use sample::SampleFormat;
+    use silence::{ Detection, Span };
+    use wav::WaveFormat;
+
+    // 16 bit stereo at 8000 Hz
+    fn stereo() -> WaveFormat {
+        WaveFormat {
+            format_tag: 1,
+            channels: 2,
+            sample_rate: 8000,
+            byte_per_sec: 32000,
+            block_align: 4,
+            bits_per_sample: 16,
+            valid_bits: 16,
+            channel_mask: 0,
+            samples_per_block: 0
+        }
+    }
+
+    #[test]
+    fn analyzer_test() {
+
+        let mut analyzer = Analyzer::new(stereo(), Detection::digital());
+
+        // a square wave of 0.25 to 0.75 on the left, clipped on the
+        // right, then 0.125 s of silence
+        let mut samples = Vec::new();
+        for n in 0..8000 {
+            samples.push(match (n / 4) % 2 { 0 => 0.75, _ => -0.25 });
+            samples.push(match n % 2 { 0 => 1.0, _ => -1.0 });
+        }
+        samples.extend(vec![0.0; 2000]);
+
+        analyzer.push(&samples[..6]);
+        analyzer.push(&samples[6..]);
+        let analysis = analyzer.finish();
+
+        assert_eq!(9000, analysis.frames);
+        assert_eq!(vec![Span { start: 8000, frames: 1000 }],
+            analysis.silences);
+
+        let left = &analysis.channels[0];
+        assert_eq!((-0.25, 0.75, 0.75), (left.min, left.max, left.peak));
+        assert!((left.dc_offset - 2000.0 / 9000.0).abs() < 1e-9);
+        assert_eq!(0, left.clips);
+        // a crossing every 4 frames, and one into the silence
+        assert!((left.zero_crossing_rate - 2000.0 / 1.125).abs() < 1e-9);
+
+        let right = &analysis.channels[1];
+        assert_eq!(8000, right.clips);
+        assert!((right.rms_db() - 20.0 * (8.0f64 / 9.0).sqrt().log10())
+            .abs() < 1e-9);
+    }
+
+    #[test]
+    fn analyze_test() {
+
+        let dir = TempDir::new("analyze_test");
+
+        // 16 bit stereo at 8000 Hz, 2 frames
+        let path = dir.write("analyze.wav", &pcm(SampleFormat::S16_LE, 2, 8000,
+            &[0, 0x40, 0xff, 0x7f, 0, 0xc0, 0, 0]));
+
+        let analysis = analyze(&path, Detection::digital())
+            .unwrap();
+
+        assert_eq!(2, analysis.frames);
+        assert_eq!((-0.5, 0.5), (analysis.channels[0].min,
+            analysis.channels[0].max));
+        assert_eq!(1, analysis.channels[1].clips);
+
+        assert!(to_text("a.wav", &analysis)
+            .starts_with("a.wav\n  format    pcm 16 bit, 2 channels, 8000 Hz\n\
+                          \x20 duration  0.000 s, 2 frames\n"));
+
+        assert!(analyze(&dir.path("missing.wav"), Detection::digital())
+            .is_err());
+    }
+
+    #[test]
+    fn json_test() {
+
+        let analysis = Analysis {
+            format: stereo(),
+            frames: 4000,
+            channels: vec![ChannelStats {
+                min: 0.0,
+                max: 0.0,
+                peak: 0.0,
+                rms: 0.0,
+                dc_offset: 0.0,
+                clips: 0,
+                zero_crossing_rate: 0.0
+            }],
+            silences: vec![Span { start: 0, frames: 4000 }]
+        };
+
+        assert_eq!("{\"path\":\"a \\\"b\\\".wav\",\"encoding\":\"pcm\",\
+                    \"bits_per_sample\":16,\"sample_rate\":8000,\
+                    \"frames\":4000,\"duration\":0.5,\
+                    \"channels\":[{\"min\":0,\"max\":0,\"peak_dbfs\":null,\
+                    \"rms_dbfs\":null,\"dc_offset\":0,\"clipped_samples\":0,\
+                    \"zero_crossing_rate\":0}],\
+                    \"silences\":[{\"start\":0,\"end\":0.5}]}",
+            to_json("a \"b\".wav", &analysis));
+    }
+}
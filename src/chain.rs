@@ -0,0 +1,579 @@
+use std::any::Any;
+use std::time::Duration;
+
+use fade::{ Curve, Fade, frames };
+use io::*;
+
+// how long a processor swapped in on a running stream takes over from
+// the one it replaces
+const SWAP_MILLIS : u64 = 10;
+
+// a step of the processing between the decoder and the encoder. samples
+// come in blocks of interleaved frames of any size, as f64 in -1.0 to
+// 1.0; a block may come out longer or shorter than it went in.
+pub trait Processor: Send {
+
+    // sets the processor up for frames of `channels` at `rate`.
+    // returns the channels and the rate of the frames it gives out.
+    fn configure(&mut self, channels: u16, rate: u32) -> (u16, u32) {
+        (channels, rate)
+    }
+
+    fn process(&mut self, samples: Vec<f64>) -> Vec<f64>;
+
+    // what is held back once the input has ended
+    fn flush(&mut self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    // forgets the input so far, as after a seek
+    fn reset(&mut self) {
+    }
+
+    // lets the chain hand out the processor as what it is
+    fn as_any(&mut self) -> &mut Any;
+}
+
+// a processor that lets everything through, where a step is left out
+pub struct Pass;
+
+impl Processor for Pass {
+
+    fn process(&mut self, samples: Vec<f64>) -> Vec<f64> {
+        samples
+    }
+
+    fn as_any(&mut self) -> &mut Any {
+        self
+    }
+}
+
+// the samples of each channel of interleaved frames, one after another
+pub fn deinterleave(samples: &[f64], channels: usize) -> Vec<Vec<f64>> {
+
+    let channels = channels.max(1);
+    let mut planes = vec![Vec::with_capacity(samples.len() / channels);
+        channels];
+
+    for frame in samples.chunks(channels) {
+        for (plane, sample) in planes.iter_mut().zip(frame.iter()) {
+            plane.push(*sample);
+        }
+    }
+
+    planes
+}
+
+pub fn interleave(planes: &[Vec<f64>]) -> Vec<f64> {
+
+    let frames = planes.iter()
+        .map(|plane| plane.len())
+        .min()
+        .unwrap_or(0);
+
+    let mut samples = Vec::with_capacity(frames * planes.len());
+
+    for frame in 0..frames {
+        for plane in planes.iter() {
+            samples.push(plane[frame]);
+        }
+    }
+
+    samples
+}
+
+// the processor a stage is moving away from, played alongside the new
+// one and faded out under it
+struct Retiring {
+    processor : Box<Processor>,
+    fade_out  : Fade,
+    fade_in   : Fade
+}
+
+impl Retiring {
+
+    // what the retiring processor has given out faded out under what the
+    // new one has
+    fn mix(&mut self, mut old: Vec<f64>, mut new: Vec<f64>, channels: usize)
+     -> Vec<f64> {
+
+        self.fade_out.process(&mut old, channels);
+        self.fade_in.process(&mut new, channels);
+
+        if old.len() > new.len() {
+            new.resize(old.len(), 0.0);
+        }
+        for (sample, other) in new.iter_mut().zip(old.iter()) {
+            *sample += *other;
+        }
+
+        new
+    }
+}
+
+struct Stage {
+    name      : String,
+    processor : Box<Processor>,
+    input     : (u16, u32),
+    output    : (u16, u32),
+    retiring  : Option<Retiring>,
+    // taken out of the chain once the processor has been faded out
+    removed   : bool
+}
+
+impl Stage {
+
+    fn new(name: &str, mut processor: Box<Processor>, input: (u16, u32))
+     -> Self {
+        let (channels, rate) = input;
+        let output = processor.configure(channels, rate);
+        Stage {
+            name: name.to_string(),
+            processor: processor,
+            input: input,
+            output: output,
+            retiring: None,
+            removed: false
+        }
+    }
+
+    // hands the stage over to `processor` over a few milliseconds
+    fn swap(&mut self, mut processor: Box<Processor>) -> IOResult<()> {
+
+        let (channels, rate) = self.input;
+
+        if processor.configure(channels, rate) != self.output {
+            return Err(IOError::new(IOErrorKind::InvalidInput,
+                "a processor can't change the format of a running stream"));
+        }
+
+        let (_, output_rate) = self.output;
+        let frames = frames(Duration::from_millis(SWAP_MILLIS), output_rate);
+        let old = ::std::mem::replace(&mut self.processor, processor);
+
+        // with no rate set up there is nothing to fade over, and the old
+        // processor goes at once
+        self.retiring = match frames {
+            0 => None,
+            frames => Some(Retiring {
+                processor: old,
+                fade_out: Fade::fade_out(Curve::Linear, frames),
+                fade_in: Fade::fade_in(Curve::Linear, frames)
+            })
+        };
+
+        Ok(())
+    }
+
+    fn process(&mut self, samples: Vec<f64>) -> Vec<f64> {
+
+        let (channels, _) = self.output;
+        let channels = channels as usize;
+
+        let (mixed, done) = match self.retiring {
+            Some(ref mut retiring) => {
+                let old = retiring.processor.process(samples.clone());
+                let new = self.processor.process(samples);
+                (retiring.mix(old, new, channels), retiring.fade_out.is_done())
+            },
+            _ => return self.processor.process(samples)
+        };
+
+        if done {
+            self.retiring = None;
+        }
+
+        mixed
+    }
+
+    // what is held back once the input has ended, along with what the
+    // retiring processor holds, faded out the same way
+    fn flush(&mut self) -> Vec<f64> {
+
+        let (channels, _) = self.output;
+        let new = self.processor.flush();
+
+        match self.retiring.take() {
+            Some(mut retiring) => {
+                let old = retiring.processor.flush();
+                retiring.mix(old, new, channels as usize)
+            },
+            _ => new
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.removed && self.retiring.is_none()
+    }
+}
+
+// processors run one after another, each on what the one before it has
+// given out. processors can be put in, swapped and taken out while a
+// stream is running, with the old one faded out under the new one so
+// that the sound doesn't jump.
+pub struct Chain {
+    stages : Vec<Stage>,
+    input  : (u16, u32)
+}
+
+impl Chain {
+
+    pub fn new() -> Self {
+        Chain {
+            stages: Vec::new(),
+            input: (0, 0)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.stages
+            .iter()
+            .filter(|stage| !stage.removed)
+            .count()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.stages
+            .iter()
+            .filter(|stage| !stage.removed)
+            .map(|stage| stage.name.clone())
+            .collect()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.position(name).is_some()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.stages
+            .iter()
+            .position(|stage| stage.name == name && !stage.removed)
+    }
+
+    // what goes into the stage at `index`
+    fn input_at(&self, index: usize) -> (u16, u32) {
+        match index {
+            0 => self.input,
+            index => self.stages[index - 1].output
+        }
+    }
+
+    // the channels and the rate that come out at the end
+    pub fn output(&self) -> (u16, u32) {
+        self.input_at(self.stages.len())
+    }
+
+    // sets every processor up for a new stream at once, finishing any
+    // swap still going on. returns the channels and the rate that come
+    // out at the end.
+    pub fn configure(&mut self, channels: u16, rate: u32) -> (u16, u32) {
+
+        self.stages.retain(|stage| !stage.removed);
+        self.input = (channels, rate);
+
+        let mut format = self.input;
+
+        for stage in self.stages.iter_mut() {
+            let (channels, rate) = format;
+            stage.retiring = None;
+            stage.input = format;
+            stage.output = stage.processor.configure(channels, rate);
+            format = stage.output;
+        }
+
+        format
+    }
+
+    // puts `processor` at the end, or in place of the one named `name`,
+    // at once. meant for setting a stream up rather than a running one;
+    // the stages after it are set up again when the format changes.
+    pub fn set(&mut self, name: &str, processor: Box<Processor>) {
+
+        let index = self.position(name);
+        let at = index.unwrap_or(self.stages.len());
+        let stage = Stage::new(name, processor, self.input_at(at));
+
+        match index {
+            Some(index) => self.stages[index] = stage,
+            _ => self.stages.push(stage)
+        }
+
+        if at + 1 < self.stages.len() &&
+            self.stages[at + 1].input != self.stages[at].output {
+            let (channels, rate) = self.input;
+            self.configure(channels, rate);
+        }
+    }
+
+    // puts `processor` in on a running stream, before the one named
+    // `before` or at the end. it has to give out frames as they come in.
+    pub fn insert(&mut self,
+        name: &str,
+        processor: Box<Processor>,
+        before: Option<&str>) -> IOResult<()> {
+
+        if self.contains(name) {
+            return Err(IOError::new(IOErrorKind::AlreadyExists,
+                "a processor of the same name is in the chain"));
+        }
+
+        let at = match before {
+            Some(before) => match self.position(before) {
+                Some(index) => index,
+                _ => return Err(IOError::new(IOErrorKind::NotFound,
+                    "no such processor in the chain"))
+            },
+            _ => self.stages.len()
+        };
+
+        let input = self.input_at(at);
+        let mut stage = Stage::new(name, Box::new(Pass), input);
+        try!(stage.swap(processor));
+        self.stages.insert(at, stage);
+        Ok(())
+    }
+
+    // hands a stage over to `processor` on a running stream, which has
+    // to give out frames in the same format as the one it replaces
+    pub fn swap(&mut self, name: &str, processor: Box<Processor>)
+     -> IOResult<()> {
+        match self.position(name) {
+            Some(index) => self.stages[index].swap(processor),
+            _ => Err(IOError::new(IOErrorKind::NotFound,
+                "no such processor in the chain"))
+        }
+    }
+
+    // fades a processor that gives out frames as they come in out of a
+    // running stream
+    pub fn remove(&mut self, name: &str) -> IOResult<()> {
+
+        let index = match self.position(name) {
+            Some(index) => index,
+            _ => return Err(IOError::new(IOErrorKind::NotFound,
+                "no such processor in the chain"))
+        };
+
+        let stage = &mut self.stages[index];
+        try!(stage.swap(Box::new(Pass)));
+        stage.removed = true;
+        Ok(())
+    }
+
+    // the processor named `name`, if it is a `P`
+    pub fn get_mut<P: Any>(&mut self, name: &str) -> Option<&mut P> {
+        match self.position(name) {
+            Some(index) => self.stages[index]
+                .processor
+                .as_any()
+                .downcast_mut::<P>(),
+            _ => None
+        }
+    }
+
+    pub fn process(&mut self, samples: Vec<f64>) -> Vec<f64> {
+        let samples = self.run(0, samples);
+        self.stages.retain(|stage| !stage.is_finished());
+        samples
+    }
+
+    // runs the stages from `from` on
+    fn run(&mut self, from: usize, mut samples: Vec<f64>) -> Vec<f64> {
+        for stage in self.stages[from..].iter_mut() {
+            samples = stage.process(samples);
+        }
+        samples
+    }
+
+    // everything the processors hold back once the input has ended, each
+    // flushed through the stages after it
+    pub fn flush(&mut self) -> Vec<f64> {
+
+        let mut samples = Vec::new();
+
+        for stage in self.stages.iter_mut() {
+            samples = stage.process(samples);
+            let held = stage.flush();
+            samples.extend(held);
+        }
+
+        self.stages.retain(|stage| !stage.is_finished());
+        samples
+    }
+
+    pub fn reset(&mut self) {
+        for stage in self.stages.iter_mut() {
+            stage.processor.reset();
+            if let Some(ref mut retiring) = stage.retiring {
+                retiring.processor.reset();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // multiplies every sample by a constant
+    struct Gain(f64);
+
+    impl Processor for Gain {
+
+        fn process(&mut self, samples: Vec<f64>) -> Vec<f64> {
+            samples.iter()
+                .map(|sample| sample * self.0)
+                .collect()
+        }
+
+        fn as_any(&mut self) -> &mut Any {
+            self
+        }
+    }
+
+    // keeps every other frame, at half the rate
+    struct Halve {
+        channels : usize,
+        next     : usize
+    }
+
+    impl Processor for Halve {
+
+        fn configure(&mut self, channels: u16, rate: u32) -> (u16, u32) {
+            self.channels = channels as usize;
+            (channels, rate / 2)
+        }
+
+        fn process(&mut self, samples: Vec<f64>) -> Vec<f64> {
+            let mut output = Vec::new();
+            for frame in samples.chunks(self.channels) {
+                if self.next % 2 == 0 {
+                    output.extend_from_slice(frame);
+                }
+                self.next += 1;
+            }
+            output
+        }
+
+        fn as_any(&mut self) -> &mut Any {
+            self
+        }
+    }
+
+    // holds the last frame of every block back until the next one
+    struct Delay {
+        channels : usize,
+        held     : Vec<f64>
+    }
+
+    impl Processor for Delay {
+
+        fn configure(&mut self, channels: u16, rate: u32) -> (u16, u32) {
+            self.channels = channels as usize;
+            (channels, rate)
+        }
+
+        fn process(&mut self, samples: Vec<f64>) -> Vec<f64> {
+            let mut output = self.held.split_off(0);
+            output.extend(samples);
+            let keep = output.len().saturating_sub(self.channels);
+            self.held = output.split_off(keep);
+            output
+        }
+
+        fn flush(&mut self) -> Vec<f64> {
+            self.held.split_off(0)
+        }
+
+        fn reset(&mut self) {
+            self.held.clear();
+        }
+
+        fn as_any(&mut self) -> &mut Any {
+            self
+        }
+    }
+
+    #[test]
+    fn chain_test() {
+
+        let mut chain = Chain::new();
+        chain.set("delay", Box::new(Delay { channels: 0, held: Vec::new() }));
+        chain.set("gain", Box::new(Gain(0.5)));
+        chain.set("halve", Box::new(Halve { channels: 0, next: 0 }));
+
+        assert_eq!((2, 22050), chain.configure(2, 44100));
+        assert_eq!(vec!["delay", "gain", "halve"], chain.names());
+
+        // the last frame is held back until the input ends, then goes
+        // through the stages after it
+        assert_eq!(vec![0.5, 0.5],
+            chain.process(vec![1.0, 1.0, 2.0, 2.0, 3.0, 3.0]));
+        assert_eq!(vec![1.5, 1.5], chain.flush());
+
+        chain.get_mut::<Gain>("gain").unwrap().0 = 2.0;
+        assert_eq!(vec![18.0, 18.0],
+            chain.process(vec![1.0, 1.0, 9.0, 9.0, 5.0, 5.0]));
+        assert!(chain.get_mut::<Halve>("gain").is_none());
+
+        chain.reset();
+        assert!(chain.flush().is_empty());
+
+        // replacing a processor that changes the format sets up what
+        // comes after it again
+        chain.set("halve", Box::new(Pass));
+        assert_eq!((2, 44100), chain.output());
+    }
+
+    #[test]
+    fn swap_test() {
+
+        let mut chain = Chain::new();
+        chain.set("gain", Box::new(Gain(1.0)));
+        chain.configure(1, 1000);
+
+        // 10 frames at 1000 Hz from one gain to the other
+        chain.swap("gain", Box::new(Gain(0.0))).unwrap();
+        let samples = chain.process(vec![1.0; 15]);
+
+        for n in 0..10 {
+            assert!((samples[n] - (1.0 - (n as f64 + 0.5) / 10.0)).abs() < 1e-12);
+        }
+        assert!(samples[10..].iter().all(|sample| *sample == 0.0));
+
+        // put in and taken out the same way
+        chain.insert("boost", Box::new(Gain(2.0)), Some("gain")).unwrap();
+        assert_eq!(vec!["boost", "gain"], chain.names());
+        chain.remove("boost").unwrap();
+        assert_eq!(1, chain.len());
+        chain.process(vec![0.0; 10]);
+        assert_eq!(vec!["gain"], chain.names());
+
+        // a running stream keeps its format
+        let halve = Halve { channels: 0, next: 0 };
+        assert!(chain.swap("gain", Box::new(halve)).is_err());
+        assert!(chain.remove("boost").is_err());
+        assert!(chain.insert("gain", Box::new(Pass), None).is_err());
+
+        // what the old processor holds back is faded out once the input
+        // ends
+        let mut chain = Chain::new();
+        chain.set("delay", Box::new(Delay { channels: 0, held: Vec::new() }));
+        chain.configure(1, 1000);
+        assert_eq!(vec![1.0; 3], chain.process(vec![1.0; 4]));
+
+        chain.swap("delay", Box::new(Pass)).unwrap();
+        let samples = chain.flush();
+        assert_eq!(1, samples.len());
+        assert!((samples[0] - 0.95).abs() < 1e-12);
+    }
+
+    #[test]
+    fn interleave_test() {
+        let samples = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let planes = deinterleave(&samples, 2);
+        assert_eq!(vec![vec![1.0, 3.0, 5.0], vec![2.0, 4.0, 6.0]], planes);
+        assert_eq!(samples, interleave(&planes));
+    }
+}
@@ -0,0 +1,1769 @@
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::{ BufWriter, Write, copy };
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::mpsc::{ Receiver, TryRecvError, channel };
+use std::thread::{ sleep, spawn };
+use std::time::Duration;
+
+use analyze;
+use convert::{ Dither, best_format };
+use device;
+use device::Direction;
+use eq::Band;
+use fade::{ Curve, Fades };
+use generator::{ DEFAULT_CHANNELS, DEFAULT_FREQUENCY, DEFAULT_LEVEL,
+                 DEFAULT_RATE, DEFAULT_SECONDS, Generator, Signal };
+use io::*;
+use loudness::{ DEFAULT_CEILING, DEFAULT_TARGET };
+use meter;
+use meter::{ Levels, PERIOD_MILLIS };
+use mix::Matrix;
+use mixer::{ Mixer, MixerControl, MixerVolume, card_of };
+use playlist;
+use playlist::Location;
+use queue::{ Queue, TrackResult };
+use resample::Quality;
+use volume::parse_gain;
+use sample::SampleFormat;
+use silence;
+use silence::{ Detection, Mode };
+use sink::PipeSink;
+use sp_io::{ NonBlockingSoundPcmPlaybackWriter, playback_format };
+use spectrum;
+use spectrum::{ Column, DEFAULT_OVERLAP, DEFAULT_SIZE, Export, MIN_SIZE, Stft,
+                Window };
+use wav::{ Encoding, WaveFormat, WaveReader };
+use waveform;
+use waveform::{ DEFAULT_BITS, DEFAULT_ZOOM };
+
+const DEFAULT_DEVICE : &'static str = "plughw:0,0";
+const STDOUT_PATH    : &'static str = "-";
+const ANALYZE        : &'static str = "analyze";
+const GENERATE       : &'static str = "generate";
+const SPECTRUM       : &'static str = "spectrum";
+const TRIM           : &'static str = "trim";
+const WAVEFORM       : &'static str = "waveform";
+const METER_WIDTH    : usize = 30;
+const SPECTRUM_WIDTH : usize = 40;
+const WAVEFORM_SIZE  : (usize, usize) = (80, 8);
+
+const USAGE : &'static str = "\
+usage: wave-player [options] <file>...
+       wave-player analyze [-t <dbfs>] [-d <ms>] [--summed] [--json]
+                           <file>...
+       wave-player spectrum [-s <n>] [-w <name>] [--overlap <f>] [-o <path>]
+                            <file>
+       wave-player waveform [-z <n>[,<n>...]] [-b <bits>] [-o <path>]
+                            [--width <n>] [--height <n>] [--ascii] <file>
+       wave-player trim [-t <dbfs>] [-d <ms>] [--summed] [--all] -o <path>
+                        <file>
+       wave-player generate [-s <signal>] [-f <format>] [-r <hz>] [-c <n>]
+                            [-t <ms>] [-l <gain>] [-d <name>] [-o <path>]
+
+options:
+    -o, --output <path>   write converted pcm to a file instead of playing it,
+                          `-` for stdout
+    -f, --format <name>   sample format of the output, an alsa format name
+                          such as s16le, s24_3le, float_le or f32le
+    -d, --device <name>   alsa pcm device to play on, plughw:0,0 by default
+    -c, --channels <n>    mix everything down or up to n channels
+    -m, --mix <matrix>    mix through a matrix of gains, a row for each
+                          output separated by `;`, e.g. 0,1;1,0 swaps
+                          left and right
+    -r, --rate <hz>       resample everything to the given rate
+    -q, --quality <name>  resampling quality: linear, low, medium (default)
+                          or high
+    -v, --volume <gain>   a linear gain such as 0.5, or decibels such as -6db
+    -M, --mixer <name>    set the volume with a mixer control of the card
+                          the device is on, such as Master
+    -D, --dither <name>   dither used when the output has fewer bits than a
+                          file: none, tpdf (default) or shaped
+    -F, --fade <ms>       fade in at the start and out on stop or seek
+    -X, --crossfade <ms>  fade each file out over the start of the next
+                          when they are in the same format
+    --curve <name>        shape of the fades: linear or equal-power
+                          (default)
+    -e, --eq <band>       add an equaliser band, kind:hz[:gain db[:q]],
+                          e.g. peak:1000:-3:2, ls:100:4 or hp:80. kinds
+                          are peak, ls, hs, lp, hp and notch. may be
+                          given more than once
+    -N, --normalize <lufs>
+                          turn every file to the loudness given, or to
+                          -23 with `ebu`, after measuring it
+    --ceiling <dbtp>      highest true peak normalised files reach, -1 by
+                          default
+    --meter               show the peak and rms level of each channel on
+                          stderr as it is played, with clips counted
+    --spectrum            show the spectrum of what is played on stderr,
+                          from 20 hz on the left up
+    --skip-silence        leave out the silence at the start and end of
+                          each file
+    --silence-threshold <dbfs>
+                          level every channel stays under in silence, -60
+                          by default
+    --silence-duration <ms>
+                          shortest silence skipped, 500 by default
+    --silence-summed      look at the channels mixed down instead of each
+                          of them
+    -l, --list-devices    list pcm devices and exit
+    --list-controls       list the mixer controls of the device's card
+                          and exit
+    -h, --help            print this message
+
+analyze reads each file through and reports its duration, the min, max,
+peak and rms level, dc offset, clipped samples and zero crossing rate of
+each channel, and its stretches of silence, digital silence unless a
+threshold is given.
+    -j, --json            report a json object a line instead of text
+    -t, --threshold <dbfs>
+                          level every channel stays under in silence
+    -d, --duration <ms>   shortest silence reported, 100 by default
+    --summed              look at the channels mixed down instead
+
+spectrum prints the average spectrum of a file, a line of the frequency
+and level in dB of each bin, or writes its spectrogram to a file.
+    -s, --size <n>        bins of the transform, a power of two, 2048 by
+                          default
+    -w, --window <name>   rectangular, hann (default), hamming or blackman
+    --overlap <f>         the part of each transform the next one starts
+                          over, 0.5 by default
+    -o, --output <path>   write the spectrogram to a .png, .ppm or .csv
+                          file
+
+waveform draws a file in braille on the terminal, or writes the lowest
+and highest sample of every pixel like audiowaveform does.
+    -z, --zoom <n>        frames a pixel, 256 by default. more than one,
+                          separated by `,`, are written to files named
+                          after the zoom, such as out-256.dat
+    -b, --bits <bits>     8 or 16 (default) bits a sample in the file
+    -o, --output <path>   write the peaks to a .dat or .json file
+    --width <n>           columns of the drawing, 80 by default
+    --height <n>          lines of the drawing, 8 by default
+    --ascii               draw with `#` instead of braille
+
+trim writes a file again without the silence at its start and end. the
+other chunks of the file, its metadata among them, are kept.
+    -o, --output <path>   the file to write
+    -t, --threshold <dbfs>
+                          level every channel stays under in silence, -60
+                          by default
+    -d, --duration <ms>   shortest silence removed, 500 by default
+    --summed              look at the channels mixed down instead
+    --all                 remove every silence, not only those at the ends
+
+generate plays a test signal, or writes it to a wave file.
+    -s, --signal <spec>   sine (default), square or saw, with :<hz> after
+                          it, 1000 by default; white or pink noise;
+                          sweep:<from hz>:<to hz>, 20 to 20000 by default;
+                          or impulse:<a second>
+    -f, --format <name>   u8, s16le (default), s24_3le, s32le, float_le or
+                          float64_le
+    -r, --rate <hz>       48000 by default
+    -c, --channels <n>    2 by default
+    -t, --time <ms>       the length of the signal, 5000 by default
+    -l, --level <gain>    the peak, linear or in decibels, -6db by default
+    -d, --device <name>   alsa pcm device to play on, plughw:0,0 by default
+    -o, --output <path>   write a wave file instead of playing it, `-` for
+                          stdout
+
+files are played one after another without gaps between those in the
+same format. m3u, m3u8 and pls playlists are replaced with their entries.
+without a format, a device plays in the format it takes that is closest
+to the first file, and files are converted into it. files with more
+channels than the device takes are mixed down.";
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub inputs   : Vec<String>,
+    pub output   : Option<String>,
+    pub format   : Option<SampleFormat>,
+    pub device   : Option<String>,
+    pub channels : Option<u16>,
+    pub mix      : Option<Matrix>,
+    pub rate     : Option<u32>,
+    pub quality  : Option<Quality>,
+    pub volume   : Option<f64>,
+    pub mixer    : Option<String>,
+    pub dither   : Option<Dither>,
+    pub fades    : Option<Fades>,
+    pub eq       : Vec<Band>,
+    pub loudness : Option<(f64, f64)>,
+    pub meter    : bool,
+    pub spectrum : bool,
+    pub silence  : Option<Detection>
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AnalyzeOptions {
+    pub inputs  : Vec<String>,
+    pub json    : bool,
+    pub silence : Detection
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SpectrumOptions {
+    pub input   : String,
+    pub output  : Option<String>,
+    pub size    : usize,
+    pub window  : Window,
+    pub overlap : f64
+}
+
+#[derive(Debug, PartialEq)]
+pub struct WaveformOptions {
+    pub input  : String,
+    pub output : Option<String>,
+    pub zooms  : Vec<u32>,
+    pub bits   : u8,
+    pub width  : usize,
+    pub height : usize,
+    pub ascii  : bool
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TrimOptions {
+    pub input   : String,
+    pub output  : String,
+    pub silence : Detection,
+    // silence in the middle is removed as well
+    pub all     : bool
+}
+
+#[derive(Debug, PartialEq)]
+pub struct GenerateOptions {
+    pub generator : Generator,
+    pub device    : Option<String>,
+    pub output    : Option<String>
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Play(Options),
+    ListDevices,
+    // the card of a device
+    ListControls(String),
+    Analyze(AnalyzeOptions),
+    Spectrum(SpectrumOptions),
+    Waveform(WaveformOptions),
+    Trim(TrimOptions),
+    Generate(GenerateOptions),
+    Help
+}
+
+fn value_of<I: Iterator<Item=String>>(args: &mut I, name: &str)
+ -> Result<String, String> {
+    args.next()
+        .ok_or(format!("{} requires a value", name))
+}
+
+fn duration_of(value: &str) -> Result<Duration, String> {
+    value.parse::<u64>()
+        .map(Duration::from_millis)
+        .map_err(|_| format!("invalid duration: {}", value))
+}
+
+fn threshold_of(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(dbfs) if dbfs.is_finite() && dbfs <= 0.0 => Ok(dbfs),
+        _ => Err(format!("invalid threshold: {}", value))
+    }
+}
+
+fn parse_analyze<I: Iterator<Item=String>>(mut args: I)
+ -> Result<Command, String> {
+
+    let (mut paths, mut json) = (Vec::new(), false);
+    let mut silence = Detection::digital();
+
+    while let Some(arg) = args.next() {
+
+        match arg.as_str() {
+
+            "-h" | "--help" => return Ok(Command::Help),
+
+            "-j" | "--json" => json = true,
+
+            "-t" | "--threshold" =>
+                silence.threshold = try!(threshold_of(&try!(value_of(
+                    &mut args, &arg)))),
+
+            "-d" | "--duration" =>
+                silence.duration = try!(duration_of(&try!(value_of(
+                    &mut args, &arg)))),
+
+            "--summed" => silence.mode = Mode::Summed,
+
+            opt if opt.starts_with("-") =>
+                return Err(format!("unknown option: {}", opt)),
+
+            _ => paths.push(arg)
+        }
+    }
+
+    match paths.is_empty() {
+        false => Ok(Command::Analyze(AnalyzeOptions {
+            inputs: paths,
+            json: json,
+            silence: silence
+        })),
+        _ => Err("no input file".to_string())
+    }
+}
+
+fn parse_trim<I: Iterator<Item=String>>(mut args: I)
+ -> Result<Command, String> {
+
+    let (mut input, mut output, mut all) = (None, None, false);
+    let mut silence = Detection::default();
+
+    while let Some(arg) = args.next() {
+
+        match arg.as_str() {
+
+            "-h" | "--help" => return Ok(Command::Help),
+
+            "-o" | "--output" =>
+                output = Some(try!(value_of(&mut args, &arg))),
+
+            "-t" | "--threshold" =>
+                silence.threshold = try!(threshold_of(&try!(value_of(
+                    &mut args, &arg)))),
+
+            "-d" | "--duration" =>
+                silence.duration = try!(duration_of(&try!(value_of(
+                    &mut args, &arg)))),
+
+            "--summed" => silence.mode = Mode::Summed,
+
+            "--all" => all = true,
+
+            opt if opt.starts_with("-") =>
+                return Err(format!("unknown option: {}", opt)),
+
+            _ if input.is_some() =>
+                return Err("a single input file is expected".to_string()),
+
+            _ => input = Some(arg)
+        }
+    }
+
+    match (input, output) {
+        (Some(input), Some(output)) => Ok(Command::Trim(TrimOptions {
+            input: input,
+            output: output,
+            silence: silence,
+            all: all
+        })),
+        (None, _) => Err("no input file".to_string()),
+        _ => Err("no output file".to_string())
+    }
+}
+
+fn parse_spectrum<I: Iterator<Item=String>>(mut args: I)
+ -> Result<Command, String> {
+
+    let (mut input, mut output) = (None, None);
+    let (mut size, mut window, mut overlap) =
+        (DEFAULT_SIZE, Window::default(), DEFAULT_OVERLAP);
+
+    while let Some(arg) = args.next() {
+
+        match arg.as_str() {
+
+            "-h" | "--help" => return Ok(Command::Help),
+
+            "-s" | "--size" => {
+                let value = try!(value_of(&mut args, &arg));
+                size = match value.parse::<usize>() {
+                    Ok(size) if size.is_power_of_two() && size >= MIN_SIZE =>
+                        size,
+                    _ => return Err(format!("invalid size: {}", value))
+                };
+            },
+
+            "-w" | "--window" => {
+                let name = try!(value_of(&mut args, &arg));
+                window = match Window::from_name(&name) {
+                    Some(window) => window,
+                    _ => return Err(format!("unknown window: {}", name))
+                };
+            },
+
+            "--overlap" => {
+                let value = try!(value_of(&mut args, &arg));
+                overlap = match value.parse::<f64>() {
+                    Ok(overlap) if overlap >= 0.0 && overlap < 1.0 => overlap,
+                    _ => return Err(format!("invalid overlap: {}", value))
+                };
+            },
+
+            "-o" | "--output" => {
+                let path = try!(value_of(&mut args, &arg));
+                if Export::from_path(&path).is_none() {
+                    return Err(format!("unknown image format: {}", path));
+                }
+                output = Some(path);
+            },
+
+            opt if opt.starts_with("-") =>
+                return Err(format!("unknown option: {}", opt)),
+
+            _ if input.is_some() =>
+                return Err("a single input file is expected".to_string()),
+
+            _ => input = Some(arg)
+        }
+    }
+
+    match input {
+        Some(input) => Ok(Command::Spectrum(SpectrumOptions {
+            input: input,
+            output: output,
+            size: size,
+            window: window,
+            overlap: overlap
+        })),
+        _ => Err("no input file".to_string())
+    }
+}
+
+fn is_json(path: &str) -> bool {
+    path.to_lowercase().ends_with(".json")
+}
+
+fn count_of(value: &str, name: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(format!("invalid {}: {}", name, value))
+    }
+}
+
+fn parse_waveform<I: Iterator<Item=String>>(mut args: I)
+ -> Result<Command, String> {
+
+    let (mut input, mut output, mut ascii) = (None, None, false);
+    let (mut zooms, mut bits) = (vec![DEFAULT_ZOOM], DEFAULT_BITS);
+    let (mut width, mut height) = WAVEFORM_SIZE;
+
+    while let Some(arg) = args.next() {
+
+        match arg.as_str() {
+
+            "-h" | "--help" => return Ok(Command::Help),
+
+            "-z" | "--zoom" => {
+                let value = try!(value_of(&mut args, &arg));
+                zooms = try!(value
+                    .split(',')
+                    .map(|zoom| match zoom.parse::<u32>() {
+                        Ok(zoom) if zoom > 0 => Ok(zoom),
+                        _ => Err(format!("invalid zoom: {}", value))
+                    })
+                    .collect());
+            },
+
+            "-b" | "--bits" => {
+                let value = try!(value_of(&mut args, &arg));
+                bits = match value.as_str() {
+                    "8" => 8,
+                    "16" => 16,
+                    _ => return Err(format!("invalid bits: {}", value))
+                };
+            },
+
+            "-o" | "--output" => {
+                let path = try!(value_of(&mut args, &arg));
+                if !is_json(&path) && !path.to_lowercase().ends_with(".dat") {
+                    return Err(format!("unknown peaks format: {}", path));
+                }
+                output = Some(path);
+            },
+
+            "--width" =>
+                width = try!(count_of(&try!(value_of(&mut args, &arg)),
+                    "width")),
+
+            "--height" =>
+                height = try!(count_of(&try!(value_of(&mut args, &arg)),
+                    "height")),
+
+            "--ascii" => ascii = true,
+
+            opt if opt.starts_with("-") =>
+                return Err(format!("unknown option: {}", opt)),
+
+            _ if input.is_some() =>
+                return Err("a single input file is expected".to_string()),
+
+            _ => input = Some(arg)
+        }
+    }
+
+    match input {
+        Some(input) => Ok(Command::Waveform(WaveformOptions {
+            input: input,
+            output: output,
+            zooms: zooms,
+            bits: bits,
+            width: width,
+            height: height,
+            ascii: ascii
+        })),
+        _ => Err("no input file".to_string())
+    }
+}
+
+fn parse_generate<I: Iterator<Item=String>>(mut args: I)
+ -> Result<Command, String> {
+
+    let (mut device, mut output) = (None, None);
+    let (mut signal, mut format) = (Signal::Sine(DEFAULT_FREQUENCY),
+        SampleFormat::S16_LE);
+    let (mut rate, mut channels) = (DEFAULT_RATE, DEFAULT_CHANNELS);
+    let mut duration = Duration::from_secs(DEFAULT_SECONDS);
+    let mut level = DEFAULT_LEVEL;
+
+    while let Some(arg) = args.next() {
+
+        match arg.as_str() {
+
+            "-h" | "--help" => return Ok(Command::Help),
+
+            "-s" | "--signal" => {
+                let value = try!(value_of(&mut args, &arg));
+                signal = match Signal::parse(&value) {
+                    Ok(signal) => signal,
+                    _ => return Err(format!("invalid signal: {}", value))
+                };
+            },
+
+            "-f" | "--format" => {
+                let name = try!(value_of(&mut args, &arg));
+                format = match SampleFormat::from_name(&name) {
+                    Some(format) if WaveFormat::from_sample_format(format, 1,
+                        1).is_some() => format,
+                    _ => return Err(format!("unknown format: {}", name))
+                };
+            },
+
+            "-r" | "--rate" => {
+                let value = try!(value_of(&mut args, &arg));
+                rate = match value.parse::<u32>() {
+                    Ok(rate) if rate > 0 => rate,
+                    _ => return Err(format!("invalid rate: {}", value))
+                };
+            },
+
+            "-c" | "--channels" => {
+                let value = try!(value_of(&mut args, &arg));
+                channels = match value.parse::<u16>() {
+                    Ok(channels) if channels > 0 => channels,
+                    _ => return Err(format!("invalid channels: {}", value))
+                };
+            },
+
+            "-t" | "--time" =>
+                duration = try!(duration_of(&try!(value_of(&mut args,
+                    &arg)))),
+
+            "-l" | "--level" => {
+                let value = try!(value_of(&mut args, &arg));
+                level = match parse_gain(&value) {
+                    Some(gain) => gain,
+                    _ => return Err(format!("invalid level: {}", value))
+                };
+            },
+
+            "-d" | "--device" =>
+                device = Some(try!(value_of(&mut args, &arg))),
+
+            "-o" | "--output" =>
+                output = Some(try!(value_of(&mut args, &arg))),
+
+            opt => return Err(format!("unknown option: {}", opt))
+        }
+    }
+
+    Ok(Command::Generate(GenerateOptions {
+        generator: Generator::new(signal)
+            .format(format)
+            .rate(rate)
+            .channels(channels)
+            .duration(duration)
+            .level(level),
+        device: device,
+        output: output
+    }))
+}
+
+pub fn parse_args<I: Iterator<Item=String>>(args: I)
+ -> Result<Command, String> {
+
+    let mut args = args.peekable();
+    let first = args.peek().cloned();
+
+    match first.as_ref().map(|arg| arg.as_str()) {
+        Some(ANALYZE) => {
+            args.next();
+            return parse_analyze(args);
+        },
+        Some(SPECTRUM) => {
+            args.next();
+            return parse_spectrum(args);
+        },
+        Some(WAVEFORM) => {
+            args.next();
+            return parse_waveform(args);
+        },
+        Some(TRIM) => {
+            args.next();
+            return parse_trim(args);
+        },
+        Some(GENERATE) => {
+            args.next();
+            return parse_generate(args);
+        },
+        _ => ()
+    }
+
+    let (mut inputs, mut output, mut format) = (Vec::new(), None, None);
+    let (mut device, mut rate, mut quality) = (None, None, None);
+    let (mut channels, mut mix, mut dither) = (None, None, None);
+    let (mut volume, mut mixer, mut list_controls) = (None, None, false);
+    let mut fades : Option<Fades> = None;
+    let mut eq = Vec::new();
+    let (mut target, mut ceiling, mut meter) = (None, None, false);
+    let mut spectrum = false;
+    let (mut skip_silence, mut silence) = (false, Detection::default());
+
+    while let Some(arg) = args.next() {
+
+        match arg.as_str() {
+
+            "-h" | "--help" => return Ok(Command::Help),
+
+            "-l" | "--list-devices" => return Ok(Command::ListDevices),
+
+            "--list-controls" => list_controls = true,
+
+            "-M" | "--mixer" =>
+                mixer = Some(try!(value_of(&mut args, &arg))),
+
+            "-d" | "--device" =>
+                device = Some(try!(value_of(&mut args, &arg))),
+
+            "-c" | "--channels" => {
+                let value = try!(value_of(&mut args, &arg));
+                channels = match value.parse::<u16>() {
+                    Ok(channels) if channels > 0 => Some(channels),
+                    _ => return Err(format!("invalid channels: {}", value))
+                };
+            },
+
+            "-m" | "--mix" => {
+                let value = try!(value_of(&mut args, &arg));
+                mix = match Matrix::parse(&value) {
+                    Ok(matrix) => Some(matrix),
+                    _ => return Err(format!("invalid mix: {}", value))
+                };
+            },
+
+            "-r" | "--rate" => {
+                let value = try!(value_of(&mut args, &arg));
+                rate = match value.parse::<u32>() {
+                    Ok(rate) if rate > 0 => Some(rate),
+                    _ => return Err(format!("invalid rate: {}", value))
+                };
+            },
+
+            "-q" | "--quality" => {
+                let name = try!(value_of(&mut args, &arg));
+                quality = match Quality::from_name(&name) {
+                    Some(quality) => Some(quality),
+                    _ => return Err(format!("unknown quality: {}", name))
+                };
+            },
+
+            "-v" | "--volume" => {
+                let value = try!(value_of(&mut args, &arg));
+                volume = match parse_gain(&value) {
+                    Some(gain) => Some(gain),
+                    _ => return Err(format!("invalid volume: {}", value))
+                };
+            },
+
+            "-D" | "--dither" => {
+                let name = try!(value_of(&mut args, &arg));
+                dither = match Dither::from_name(&name) {
+                    Some(dither) => Some(dither),
+                    _ => return Err(format!("unknown dither: {}", name))
+                };
+            },
+
+            "-F" | "--fade" => {
+                let value = try!(value_of(&mut args, &arg));
+                let duration = try!(duration_of(&value));
+                let mut changed = fades.unwrap_or(Fades::default());
+                changed.fade_in = duration;
+                changed.fade_out = duration;
+                fades = Some(changed);
+            },
+
+            "-X" | "--crossfade" => {
+                let value = try!(value_of(&mut args, &arg));
+                let mut changed = fades.unwrap_or(Fades::default());
+                changed.crossfade = try!(duration_of(&value));
+                fades = Some(changed);
+            },
+
+            "--curve" => {
+                let name = try!(value_of(&mut args, &arg));
+                let mut changed = fades.unwrap_or(Fades::default());
+                changed.curve = match Curve::from_name(&name) {
+                    Some(curve) => curve,
+                    _ => return Err(format!("unknown curve: {}", name))
+                };
+                fades = Some(changed);
+            },
+
+            "-e" | "--eq" => {
+                let value = try!(value_of(&mut args, &arg));
+                match Band::parse(&value) {
+                    Ok(band) => eq.push(band),
+                    _ => return Err(format!("invalid band: {}", value))
+                }
+            },
+
+            "-N" | "--normalize" => {
+                let value = try!(value_of(&mut args, &arg));
+                target = match (value.as_str(), value.parse::<f64>()) {
+                    ("ebu", _) => Some(DEFAULT_TARGET),
+                    (_, Ok(lufs)) if lufs.is_finite() && lufs < 0.0 =>
+                        Some(lufs),
+                    _ => return Err(format!("invalid loudness: {}", value))
+                };
+            },
+
+            "--ceiling" => {
+                let value = try!(value_of(&mut args, &arg));
+                ceiling = match value.parse::<f64>() {
+                    Ok(dbtp) if dbtp.is_finite() && dbtp <= 0.0 => Some(dbtp),
+                    _ => return Err(format!("invalid ceiling: {}", value))
+                };
+            },
+
+            "--meter" => meter = true,
+
+            "--spectrum" => spectrum = true,
+
+            "--skip-silence" => skip_silence = true,
+
+            "--silence-threshold" =>
+                silence.threshold = try!(threshold_of(&try!(value_of(
+                    &mut args, &arg)))),
+
+            "--silence-duration" =>
+                silence.duration = try!(duration_of(&try!(value_of(
+                    &mut args, &arg)))),
+
+            "--silence-summed" => silence.mode = Mode::Summed,
+
+            "-o" | "--output" =>
+                output = Some(try!(value_of(&mut args, &arg))),
+
+            "-f" | "--format" => {
+                let name = try!(value_of(&mut args, &arg));
+                format = match SampleFormat::from_name(&name) {
+                    Some(format) => Some(format),
+                    _ => return Err(format!("unknown format: {}", name))
+                };
+            },
+
+            // a lone `-` is a path, not an option
+            opt if opt.starts_with("-") && opt != STDOUT_PATH =>
+                return Err(format!("unknown option: {}", opt)),
+
+            _ => inputs.push(arg)
+        }
+    }
+
+    if list_controls {
+        return Ok(Command::ListControls(card_of(device
+            .as_ref()
+            .map(|device| device.as_str())
+            .unwrap_or(DEFAULT_DEVICE))));
+    }
+
+    match inputs.is_empty() {
+        false => Ok(Command::Play(Options {
+            inputs: inputs,
+            output: output,
+            format: format,
+            device: device,
+            channels: channels,
+            mix: mix,
+            rate: rate,
+            quality: quality,
+            volume: volume,
+            mixer: mixer,
+            dither: dither,
+            fades: fades,
+            eq: eq,
+            loudness: target.map(|target|
+                (target, ceiling.unwrap_or(DEFAULT_CEILING))),
+            meter: meter,
+            spectrum: spectrum,
+            silence: match skip_silence {
+                true => Some(silence),
+                _ => None
+            }
+        })),
+        _ => Err("no input file".to_string())
+    }
+}
+
+// the format closest to what the file holds that the output can take
+fn default_format(format: &WaveFormat, to_device: bool) -> SampleFormat {
+    match (format.encoding(), to_device) {
+        (Ok(Encoding::Pcm), true) =>
+            playback_format(format.bits_per_sample as u8),
+        (_, true) => playback_format(16),
+        // adpcm is decoded to 16 bits
+        _ => format.sample_format()
+            .unwrap_or(SampleFormat::S16_LE)
+    }
+}
+
+// the format of the first file that can be read
+fn first_wave(paths: &[String]) -> Option<WaveFormat> {
+    paths
+        .iter()
+        .filter_map(|path| WaveReader::open(path).ok())
+        .map(|reader| reader.format().clone())
+        .next()
+}
+
+fn first_format(paths: &[String]) -> Option<SampleFormat> {
+    first_wave(paths)
+        .map(|wave| default_format(&wave, false))
+}
+
+// playlists are replaced with their entries. entries that can't be
+// played at all, and playlists that can't be read, fail right away.
+fn expand(inputs: &[String]) -> (Vec<String>, Vec<TrackResult>) {
+
+    let (mut paths, mut failed) = (Vec::new(), Vec::new());
+
+    for input in inputs {
+
+        if !playlist::is_playlist(input) {
+            paths.push(input.clone());
+            continue;
+        }
+
+        let entries = match playlist::load(input) {
+            Ok(entries) => entries,
+            Err(err) => {
+                failed.push(TrackResult {
+                    path: input.clone(),
+                    result: Err(err)
+                });
+                continue;
+            }
+        };
+
+        for entry in entries {
+            match entry.location {
+                Location::File(_) => paths.push(entry.path()),
+                Location::Url(_) => failed.push(TrackResult {
+                    path: format!("{}: {}", input, entry.path()),
+                    result: Err(IOError::new(IOErrorKind::InvalidInput,
+                        "streams are not supported"))
+                })
+            }
+        }
+    }
+
+    (paths, failed)
+}
+
+pub fn run(options: Options) -> IOResult<Vec<TrackResult>> {
+
+    let (paths, mut results) = expand(&options.inputs);
+    let unmixed = options.channels.is_none() && options.mix.is_none();
+    let mut queue = Queue::new();
+
+    for path in paths.iter() {
+        queue.push(path.clone());
+    }
+
+    if let Some(rate) = options.rate {
+        queue.resample_to(rate, options.quality.unwrap_or(Quality::default()));
+    }
+
+    if let Some(dither) = options.dither {
+        queue.dither(dither);
+    }
+
+    if let Some(gain) = options.volume {
+        queue.volume(gain);
+    }
+
+    if let Some(fades) = options.fades {
+        queue.fades(fades);
+    }
+
+    queue.equalizer(options.eq);
+
+    if let Some((target, ceiling)) = options.loudness {
+        queue.normalize(target, ceiling);
+    }
+
+    if let Some(detection) = options.silence {
+        queue.skip_silence(detection);
+    }
+
+    if let Some(channels) = options.channels {
+        queue.mix_to(channels);
+    }
+
+    if let Some(matrix) = options.mix {
+        queue.route(matrix);
+    }
+
+    let levels = match options.meter {
+        true => {
+            let (tx, rx) = channel();
+            queue.meter(tx);
+            Some(rx)
+        },
+        _ => None
+    };
+
+    let columns = match options.spectrum {
+        true => {
+            let (tx, rx) = channel();
+            queue.spectrum(tx, Stft::new(DEFAULT_SIZE, 1));
+            Some(rx)
+        },
+        _ => None
+    };
+
+    let done = Arc::new(AtomicBool::new(false));
+    let display = match options.meter || options.spectrum {
+        true => {
+            let done = done.clone();
+            Some(spawn(move || show(levels, columns, done)))
+        },
+        _ => None
+    };
+
+    let played = match options.output {
+
+        Some(ref path) => {
+
+            // a pipe carries a single format, the first file that can be
+            // read decides it
+            let format = options.format
+                .or(first_format(&paths))
+                .unwrap_or(SampleFormat::S16_LE);
+
+            match path == STDOUT_PATH {
+                true => queue.play(PipeSink::stdout(format), |_| format),
+                _ => {
+                    let f = try!(File::create(path));
+                    queue.play(PipeSink::new(f, format), |_| format)
+                }
+            }
+        },
+
+        _ => {
+            let name = options.device
+                .unwrap_or(DEFAULT_DEVICE.to_string());
+
+            // a device that can't be probed is tried with the formats
+            // playback_format() gives and the channels of the files
+            let caps = device::capabilities(&name, Direction::Playback)
+                .ok();
+            let available = caps
+                .as_ref()
+                .map(|caps| caps.formats.clone())
+                .unwrap_or(Vec::new());
+
+            // the channels of the first file, as near as the device takes
+            if let (true, Some(caps), Some(wave)) = (unmixed, caps,
+                first_wave(&paths)) {
+                let channels = wave.channels as u32;
+                if !caps.supports_channels(channels) {
+                    queue.mix_to(channels
+                        .max(caps.min_channels)
+                        .min(caps.max_channels) as u16);
+                }
+            }
+
+            let format = match options.format {
+                Some(format) if available.is_empty() ||
+                    available.contains(&format) => Some(format),
+                Some(_) => return Err(IOError::new(IOErrorKind::InvalidInput,
+                    "the format can't be played on the device")),
+                _ => first_format(&paths)
+                    .and_then(|source| best_format(source, &available))
+            };
+
+            if let Some(ref control) = options.mixer {
+                let mixer = try!(Mixer::open(&card_of(&name)));
+                queue.hardware_volume(Box::new(try!(MixerVolume::new(mixer,
+                    control))));
+            }
+
+            let writer = try!(NonBlockingSoundPcmPlaybackWriter::open(name));
+
+            match format {
+                // every file is converted into the one device format
+                Some(format) => queue.play(writer, move |_| format),
+                _ => queue.play(writer, |wave| default_format(wave, true))
+            }
+        }
+    };
+
+    done.store(true, Ordering::SeqCst);
+    if let Some(display) = display {
+        display.join().unwrap();
+    }
+
+    results.extend(try!(played));
+    Ok(results)
+}
+
+// the newest of what has come on `rx`, and whether more can come
+fn latest<T>(rx: &Option<Receiver<T>>) -> (Option<T>, bool) {
+
+    let rx = match *rx {
+        Some(ref rx) => rx,
+        _ => return (None, false)
+    };
+
+    let mut last = None;
+
+    loop {
+        match rx.try_recv() {
+            Ok(value) => last = Some(value),
+            Err(TryRecvError::Empty) => return (last, true),
+            _ => return (last, false)
+        }
+    }
+}
+
+// draws the levels and the spectrum over each other on a line of
+// stderr, until the playback is done
+fn show(levels: Option<Receiver<Levels>>,
+    columns: Option<Receiver<Column>>,
+    done: Arc<AtomicBool>) {
+
+    let mut stderr = io::stderr();
+    let (mut meter, mut spectrum) = (String::new(), String::new());
+    let mut shown = false;
+
+    loop {
+
+        let (level, levels_open) = latest(&levels);
+        let (column, columns_open) = latest(&columns);
+        let changed = level.is_some() || column.is_some();
+
+        if let Some(level) = level {
+            meter = meter::render(&level, METER_WIDTH);
+        }
+
+        if let Some(column) = column {
+            spectrum = format!("[{}]", spectrum::render(&column,
+                SPECTRUM_WIDTH));
+        }
+
+        if changed {
+            let gap = match meter.is_empty() || spectrum.is_empty() {
+                true => "",
+                _ => " "
+            };
+            write!(stderr, "\r{}{}{}", meter, gap, spectrum).unwrap();
+            shown = true;
+        }
+
+        if !(levels_open || columns_open) || done.load(Ordering::SeqCst) {
+            break;
+        }
+
+        sleep(Duration::from_millis(PERIOD_MILLIS / 2));
+    }
+
+    if shown {
+        writeln!(stderr, "").unwrap();
+    }
+}
+
+// tells about the files that couldn't be played
+fn report(results: &[TrackResult]) -> i32 {
+
+    let mut stderr = io::stderr();
+    let mut status = 0;
+
+    for track in results {
+        if let Err(ref err) = track.result {
+            writeln!(stderr, "wave-player: {}: {}", track.path, err).unwrap();
+            status = 1;
+        }
+    }
+
+    status
+}
+
+// reports every file, those that can't be read on stderr
+fn analyze_files(options: &AnalyzeOptions) -> i32 {
+
+    let mut stderr = io::stderr();
+    let mut status = 0;
+
+    for path in options.inputs.iter() {
+        match (analyze::analyze(path, options.silence), options.json) {
+            (Ok(analysis), true) =>
+                println!("{}", analyze::to_json(path, &analysis)),
+            (Ok(analysis), _) =>
+                print!("{}", analyze::to_text(path, &analysis)),
+            (Err(err), _) => {
+                writeln!(stderr, "wave-player: {}: {}", path, err).unwrap();
+                status = 1;
+            }
+        }
+    }
+
+    status
+}
+
+// the average spectrum on stdout, or the spectrogram in a file
+fn spectrum_of(options: &SpectrumOptions) -> IOResult<()> {
+
+    let stft = Stft::new(options.size, 1)
+        .window(options.window)
+        .overlap(options.overlap);
+    let spectrogram = try!(spectrum::spectrogram(&options.input, &stft));
+
+    match options.output {
+        Some(ref path) => {
+            let export = match Export::from_path(path) {
+                Some(export) => export,
+                _ => return Err(IOError::new(IOErrorKind::InvalidInput,
+                    "unknown image format"))
+            };
+            let mut output = BufWriter::new(try!(File::create(path)));
+            export.write(&mut output, &spectrogram)
+        },
+        _ => spectrum::write_average_csv(&mut io::stdout(), &spectrogram)
+    }
+}
+
+// `path` with the zoom put in ahead of its extension
+fn zoom_path(path: &str, zoom: u32) -> String {
+    let name = path.rfind('/').map_or(0, |slash| slash + 1);
+    match path[name..].rfind('.') {
+        Some(dot) => format!("{}-{}{}", &path[..name + dot], zoom,
+            &path[name + dot..]),
+        _ => format!("{}-{}", path, zoom)
+    }
+}
+
+// the signal in a wave file, or played on a device
+fn generate(options: &GenerateOptions) -> IOResult<Vec<TrackResult>> {
+
+    let generator = &options.generator;
+
+    match options.output {
+
+        Some(ref path) if path == STDOUT_PATH => {
+            let stdout = io::stdout();
+            try!(copy(&mut try!(generator.wave()), &mut stdout.lock()));
+            Ok(Vec::new())
+        },
+
+        Some(ref path) => {
+            let mut output = BufWriter::new(try!(File::create(path)));
+            try!(copy(&mut try!(generator.wave()), &mut output));
+            try!(output.flush());
+            Ok(Vec::new())
+        },
+
+        _ => {
+            let name = options.device
+                .clone()
+                .unwrap_or(DEFAULT_DEVICE.to_string());
+
+            // a device that can't be probed is tried with the signal's
+            // own format
+            let available = device::capabilities(&name, Direction::Playback)
+                .map(|caps| caps.formats)
+                .unwrap_or(Vec::new());
+            let format = best_format(generator.sample_format(), &available)
+                .unwrap_or(generator.sample_format());
+
+            let mut queue = Queue::new();
+            queue.push_generator(generator.clone());
+
+            let writer = try!(
+                NonBlockingSoundPcmPlaybackWriter::open(name));
+            queue.play(writer, move |_| format)
+        }
+    }
+}
+
+// the peaks in files, or a drawing on stdout
+fn waveform_of(options: &WaveformOptions) -> IOResult<()> {
+
+    let path = match options.output {
+        Some(ref path) => path,
+        _ => {
+            // as many pixels as there are columns of dots
+            let columns = match options.ascii {
+                true => options.width,
+                _ => options.width * 2
+            } as u64;
+            let frames = try!(WaveReader::open(&options.input))
+                .header()
+                .frames();
+            let zoom = ((frames + columns - 1) / columns).max(1);
+
+            let peaks = try!(waveform::scan(&options.input, &[zoom as u32],
+                DEFAULT_BITS));
+            let drawing = match options.ascii {
+                true => waveform::render_ascii(&peaks[0], options.width,
+                    options.height),
+                _ => waveform::render_braille(&peaks[0], options.width,
+                    options.height)
+            };
+
+            println!("{}", drawing);
+            return Ok(());
+        }
+    };
+
+    let levels = try!(waveform::scan(&options.input, &options.zooms,
+        options.bits));
+
+    for peaks in levels {
+
+        let path = match options.zooms.len() {
+            1 => path.clone(),
+            _ => zoom_path(path, peaks.samples_per_pixel)
+        };
+
+        let mut output = BufWriter::new(try!(File::create(&path)));
+        try!(match is_json(&path) {
+            true => writeln!(output, "{}", peaks.to_json()),
+            _ => peaks.write_dat(&mut output)
+        });
+    }
+
+    Ok(())
+}
+
+fn list_devices() -> IOResult<()> {
+
+    for hint in try!(device::hints()) {
+
+        let direction = match (hint.playback, hint.capture) {
+            (true, true) => "playback/capture",
+            (true, _) => "playback",
+            _ => "capture"
+        };
+
+        // descriptions come in two lines, card and device
+        let description = hint.description
+            .as_ref()
+            .map(|desc| desc.replace("\n", ", "))
+            .unwrap_or(String::new());
+
+        println!("{:<32} {:<17} {}", hint.name, direction, description);
+    }
+
+    Ok(())
+}
+
+fn list_controls(card: &str) -> IOResult<()> {
+
+    let mut mixer = try!(Mixer::open(card));
+
+    for control in try!(mixer.controls()) {
+
+        let volume = control.volume
+            .map(|volume| format!("{:3.0}%", volume.fraction() * 100.0))
+            .unwrap_or(String::new());
+        let db = control.decibels()
+            .map(|db| format!("{:.2} dB", db))
+            .unwrap_or(String::new());
+        let switch = match control.switch {
+            Some(true) => "on",
+            Some(false) => "off",
+            _ => ""
+        };
+
+        println!("{:<24} {:>4} {:>10} {}", control.name, volume, db, switch);
+    }
+
+    Ok(())
+}
+
+pub fn main() -> i32 {
+
+    let mut stderr = io::stderr();
+
+    match parse_args(env::args().skip(1)) {
+
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            0
+        },
+
+        Ok(Command::ListDevices) => match list_devices() {
+            Ok(_) => 0,
+            Err(err) => {
+                writeln!(stderr, "wave-player: {}", err).unwrap();
+                1
+            }
+        },
+
+        Ok(Command::ListControls(card)) => match list_controls(&card) {
+            Ok(_) => 0,
+            Err(err) => {
+                writeln!(stderr, "wave-player: {}", err).unwrap();
+                1
+            }
+        },
+
+        Ok(Command::Analyze(options)) => analyze_files(&options),
+
+        Ok(Command::Generate(options)) => match generate(&options) {
+            Ok(results) => report(&results),
+            Err(err) => {
+                writeln!(stderr, "wave-player: {}", err).unwrap();
+                1
+            }
+        },
+
+        Ok(Command::Trim(options)) => match silence::trim(&options.input,
+            &options.output, options.silence, options.all) {
+            Ok(_) => 0,
+            Err(err) => {
+                writeln!(stderr, "wave-player: {}: {}", options.input, err)
+                    .unwrap();
+                1
+            }
+        },
+
+        Ok(Command::Waveform(options)) => match waveform_of(&options) {
+            Ok(_) => 0,
+            Err(err) => {
+                writeln!(stderr, "wave-player: {}: {}", options.input, err)
+                    .unwrap();
+                1
+            }
+        },
+
+        Ok(Command::Spectrum(options)) => match spectrum_of(&options) {
+            Ok(_) => 0,
+            Err(err) => {
+                writeln!(stderr, "wave-player: {}: {}", options.input, err)
+                    .unwrap();
+                1
+            }
+        },
+
+        Ok(Command::Play(options)) => match run(options) {
+            Ok(results) => report(&results),
+            Err(err) => {
+                writeln!(stderr, "wave-player: {}", err).unwrap();
+                1
+            }
+        },
+
+        Err(msg) => {
+            writeln!(stderr, "wave-player: {}\n\n{}", msg, USAGE).unwrap();
+            2
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::Duration;
+
+    use super::*;
+    use super::{ expand, zoom_path };
+    use convert::Dither;
+    use eq::{ Band, DEFAULT_Q, FilterKind };
+    use fade::{ Curve, Fades };
+    use fixtures::TempDir;
+    use generator::{ Generator, Signal };
+    use resample::Quality;
+    use sample::SampleFormat;
+    use silence::{ Detection, Mode };
+    use spectrum::{ DEFAULT_OVERLAP, DEFAULT_SIZE, Window };
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace()
+            .map(|arg| arg.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn zoom_path_test() {
+        assert_eq!("out-256.dat", zoom_path("out.dat", 256));
+        assert_eq!("a.b/out-16.json", zoom_path("a.b/out.json", 16));
+        assert_eq!("a.b/out-16", zoom_path("a.b/out", 16));
+    }
+
+    #[test]
+    fn parse_play_test() {
+
+        assert_eq!(Ok(Command::Play(Options {
+                inputs: vec!["a.wav".to_string()],
+                output: Some("-".to_string()),
+                format: Some(SampleFormat::FLOAT_LE),
+                device: None,
+                channels: None,
+                mix: None,
+                rate: None,
+                quality: None,
+                volume: None,
+                mixer: None,
+                dither: None,
+                fades: None,
+                eq: Vec::new(),
+                loudness: None,
+                meter: false,
+                spectrum: false,
+                silence: None
+            })),
+            parse_args(args("--format f32le -o - a.wav").into_iter()));
+
+        match parse_args(args("-d hw:1,0 a.wav").into_iter()) {
+            Ok(Command::Play(options)) =>
+                assert_eq!(Some("hw:1,0".to_string()), options.device),
+            _ => panic!("play command is expected")
+        }
+
+        match parse_args(args("a.wav b.wav").into_iter()) {
+            Ok(Command::Play(options)) =>
+                assert_eq!(vec!["a.wav", "b.wav"], options.inputs),
+            _ => panic!("play command is expected")
+        }
+
+        // anywhere but first it is a file
+        match parse_args(args("a.wav analyze").into_iter()) {
+            Ok(Command::Play(options)) =>
+                assert_eq!(vec!["a.wav", "analyze"], options.inputs),
+            _ => panic!("play command is expected")
+        }
+
+        assert_eq!(Ok(Command::ListDevices),
+            parse_args(args("--list-devices").into_iter()));
+        assert_eq!(Ok(Command::Help),
+            parse_args(args("a.wav -h").into_iter()));
+
+        assert!(parse_args(args("--format s17le a.wav").into_iter()).is_err());
+        assert!(parse_args(args("-o").into_iter()).is_err());
+        assert!(parse_args(args("-o out.pcm").into_iter()).is_err());
+        assert!(parse_args(args("--bogus a.wav").into_iter()).is_err());
+    }
+
+    #[test]
+    fn parse_resample_test() {
+
+        match parse_args(args("-r 48000 -q high a.wav").into_iter()) {
+            Ok(Command::Play(options)) => {
+                assert_eq!(Some(48000), options.rate);
+                assert_eq!(Some(Quality::High), options.quality);
+            },
+            _ => panic!("play command is expected")
+        }
+
+        assert!(parse_args(args("-r 0 a.wav").into_iter()).is_err());
+        assert!(parse_args(args("-q best a.wav").into_iter()).is_err());
+    }
+
+    #[test]
+    fn parse_mix_test() {
+
+        match parse_args(args("-c 1 --mix 0,1;1,0 a.wav").into_iter()) {
+            Ok(Command::Play(options)) => {
+                assert_eq!(Some(1), options.channels);
+                assert_eq!(Some(2), options.mix.map(|mix| mix.outputs()));
+            },
+            _ => panic!("play command is expected")
+        }
+
+        assert!(parse_args(args("-c 0 a.wav").into_iter()).is_err());
+        assert!(parse_args(args("-m 1,0;1 a.wav").into_iter()).is_err());
+    }
+
+    #[test]
+    fn parse_volume_test() {
+
+        match parse_args(args("-v -6db a.wav").into_iter()) {
+            Ok(Command::Play(options)) =>
+                assert!((options.volume.unwrap() - 0.501).abs() < 0.001),
+            _ => panic!("play command is expected")
+        }
+
+        match parse_args(args("-M Master a.wav").into_iter()) {
+            Ok(Command::Play(options)) =>
+                assert_eq!(Some("Master".to_string()), options.mixer),
+            _ => panic!("play command is expected")
+        }
+
+        assert_eq!(Ok(Command::ListControls("hw:1".to_string())),
+            parse_args(args("--list-controls -d plughw:1,0").into_iter()));
+
+        assert!(parse_args(args("-v loud a.wav").into_iter()).is_err());
+    }
+
+    #[test]
+    fn parse_dither_test() {
+
+        match parse_args(args("--dither shaped a.wav").into_iter()) {
+            Ok(Command::Play(options)) =>
+                assert_eq!(Some(Dither::Shaped), options.dither),
+            _ => panic!("play command is expected")
+        }
+
+        assert!(parse_args(args("-D white a.wav").into_iter()).is_err());
+    }
+
+    #[test]
+    fn parse_fades_test() {
+
+        match parse_args(args("-F 50 -X 2000 --curve linear a.wav").into_iter()) {
+            Ok(Command::Play(options)) =>
+                assert_eq!(Some(Fades {
+                        fade_in: Duration::from_millis(50),
+                        fade_out: Duration::from_millis(50),
+                        crossfade: Duration::from_secs(2),
+                        curve: Curve::Linear
+                    }),
+                    options.fades),
+            _ => panic!("play command is expected")
+        }
+
+        assert!(parse_args(args("--fade 1s a.wav").into_iter()).is_err());
+        assert!(parse_args(args("--curve log a.wav").into_iter()).is_err());
+    }
+
+    #[test]
+    fn parse_eq_test() {
+
+        match parse_args(args("-e hp:80 --eq peak:1000:-3:2 a.wav").into_iter()) {
+            Ok(Command::Play(options)) =>
+                assert_eq!(vec![Band::new(FilterKind::HighPass, 80.0, 0.0,
+                                    DEFAULT_Q),
+                                Band::new(FilterKind::Peaking, 1000.0, -3.0,
+                                    2.0)],
+                    options.eq),
+            _ => panic!("play command is expected")
+        }
+
+        assert!(parse_args(args("--eq tilt:1000 a.wav").into_iter()).is_err());
+    }
+
+    #[test]
+    fn parse_loudness_test() {
+
+        match parse_args(args("-N ebu a.wav").into_iter()) {
+            Ok(Command::Play(options)) =>
+                assert_eq!(Some((-23.0, -1.0)), options.loudness),
+            _ => panic!("play command is expected")
+        }
+
+        match parse_args(args("--ceiling -2 --normalize -16 a.wav").into_iter()) {
+            Ok(Command::Play(options)) =>
+                assert_eq!(Some((-16.0, -2.0)), options.loudness),
+            _ => panic!("play command is expected")
+        }
+
+        assert!(parse_args(args("-N loud a.wav").into_iter()).is_err());
+    }
+
+    #[test]
+    fn parse_meter_test() {
+        match parse_args(args("--meter --spectrum a.wav").into_iter()) {
+            Ok(Command::Play(options)) =>
+                assert!(options.meter && options.spectrum),
+            _ => panic!("play command is expected")
+        }
+    }
+
+    #[test]
+    fn parse_silence_test() {
+
+        match parse_args(args("--skip-silence --silence-threshold -45 a.wav")
+            .into_iter()) {
+            Ok(Command::Play(options)) =>
+                assert_eq!(Some(Detection {
+                        threshold: -45.0,
+                        ..Detection::default()
+                    }),
+                    options.silence),
+            _ => panic!("play command is expected")
+        }
+
+        // the detection is set up without being used
+        match parse_args(args("--silence-summed a.wav").into_iter()) {
+            Ok(Command::Play(options)) => assert_eq!(None, options.silence),
+            _ => panic!("play command is expected")
+        }
+    }
+
+    #[test]
+    fn parse_spectrum_test() {
+
+        assert_eq!(Ok(Command::Spectrum(SpectrumOptions {
+                input: "a.wav".to_string(),
+                output: Some("a.png".to_string()),
+                size: 512,
+                window: Window::Blackman,
+                overlap: 0.75
+            })),
+            parse_args(args("spectrum -s 512 -w blackman --overlap 0.75 \
+                             -o a.png a.wav").into_iter()));
+
+        match parse_args(args("spectrum a.wav").into_iter()) {
+            Ok(Command::Spectrum(options)) => {
+                assert_eq!(None, options.output);
+                assert_eq!((DEFAULT_SIZE, Window::Hann, DEFAULT_OVERLAP),
+                    (options.size, options.window, options.overlap));
+            },
+            _ => panic!("spectrum command is expected")
+        }
+
+        assert!(parse_args(args("spectrum -s 1000 a.wav").into_iter()).is_err());
+        assert!(parse_args(args("spectrum --overlap 1 a.wav").into_iter())
+            .is_err());
+        assert!(parse_args(args("spectrum -o a.jpg a.wav").into_iter()).is_err());
+        assert!(parse_args(args("spectrum a.wav b.wav").into_iter()).is_err());
+    }
+
+    #[test]
+    fn parse_waveform_test() {
+
+        assert_eq!(Ok(Command::Waveform(WaveformOptions {
+                input: "a.wav".to_string(),
+                output: Some("a.json".to_string()),
+                zooms: vec![256, 1024],
+                bits: 8,
+                width: 80,
+                height: 4,
+                ascii: true
+            })),
+            parse_args(args("waveform -z 256,1024 -b 8 -o a.json --height 4 \
+                             --ascii a.wav").into_iter()));
+
+        assert!(parse_args(args("waveform -z 0 a.wav").into_iter()).is_err());
+        assert!(parse_args(args("waveform -b 24 a.wav").into_iter()).is_err());
+        assert!(parse_args(args("waveform -o a.png a.wav").into_iter()).is_err());
+        assert!(parse_args(args("waveform --width 0 a.wav").into_iter())
+            .is_err());
+    }
+
+    #[test]
+    fn parse_analyze_test() {
+
+        assert_eq!(Ok(Command::Analyze(AnalyzeOptions {
+                inputs: vec!["a.wav".to_string(), "b.wav".to_string()],
+                json: true,
+                silence: Detection::digital()
+            })),
+            parse_args(args("analyze a.wav --json b.wav").into_iter()));
+
+        match parse_args(args("analyze -t -50 -d 200 --summed a.wav")
+            .into_iter()) {
+            Ok(Command::Analyze(options)) =>
+                assert_eq!(Detection {
+                        threshold: -50.0,
+                        duration: Duration::from_millis(200),
+                        mode: Mode::Summed
+                    },
+                    options.silence),
+            _ => panic!("analyze command is expected")
+        }
+
+        assert!(parse_args(args("analyze").into_iter()).is_err());
+        assert!(parse_args(args("analyze -o - a.wav").into_iter()).is_err());
+        assert!(parse_args(args("analyze -t 3 a.wav").into_iter()).is_err());
+    }
+
+    #[test]
+    fn parse_trim_test() {
+
+        assert_eq!(Ok(Command::Trim(TrimOptions {
+                input: "a.wav".to_string(),
+                output: "b.wav".to_string(),
+                silence: Detection {
+                    threshold: -40.0,
+                    ..Detection::default()
+                },
+                all: true
+            })),
+            parse_args(args("trim -t -40 --all -o b.wav a.wav").into_iter()));
+
+        assert!(parse_args(args("trim a.wav").into_iter()).is_err());
+        assert!(parse_args(args("trim -o b.wav").into_iter()).is_err());
+        assert!(parse_args(args("trim -d 1s -o b.wav a.wav").into_iter())
+            .is_err());
+    }
+
+    #[test]
+    fn parse_generate_test() {
+
+        assert_eq!(Ok(Command::Generate(GenerateOptions {
+                generator: Generator::new(Signal::Sweep(20.0, 20000.0))
+                    .format(SampleFormat::S24_3LE)
+                    .rate(96000)
+                    .channels(1)
+                    .duration(Duration::from_millis(1500))
+                    .level(0.25),
+                device: None,
+                output: Some("a.wav".to_string())
+            })),
+            parse_args(args("generate -f s24_3le -r 96000 -c 1 -t 1500 \
+                             -l 0.25 -s sweep -o a.wav").into_iter()));
+
+        match parse_args(args("generate -d hw:1,0").into_iter()) {
+            Ok(Command::Generate(options)) => {
+                assert_eq!(Signal::Sine(1000.0), options.generator.signal());
+                assert_eq!(Some("hw:1,0".to_string()), options.device);
+            },
+            _ => panic!("generate command is expected")
+        }
+
+        assert!(parse_args(args("generate -s sine:0").into_iter()).is_err());
+        assert!(parse_args(args("generate -f s16be").into_iter()).is_err());
+        assert!(parse_args(args("generate a.wav").into_iter()).is_err());
+    }
+
+    #[test]
+    fn expand_test() {
+
+        let dir = TempDir::new("cli_expand_test");
+        let list = dir.write("list.m3u",
+            b"#EXTM3U\na.wav\nhttp://example.com/live\n");
+
+        let (paths, failed) = expand(&args(&format!("x.wav {} missing.pls",
+            list)));
+
+        // files in a playlist are found next to it
+        assert_eq!(vec!["x.wav".to_string(), dir.path("a.wav")], paths);
+        assert_eq!(2, failed.len());
+        assert_eq!(format!("{}: http://example.com/live", list),
+            failed[0].path);
+        assert_eq!("missing.pls", failed[1].path);
+    }
+}
@@ -0,0 +1,311 @@
+use sample::SampleFormat;
+
+// xorshift64*, enough for dither and test signals and the same on
+// every run for a given seed.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state : u64
+}
+
+impl Rng {
+
+    pub fn new(seed: u64) -> Self {
+        Rng {
+            state: match seed {
+                0 => 0x9e37_79b9_7f4a_7c15,
+                seed => seed
+            }
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // uniform in [0.0, 1.0)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+const DITHER_SEED : u64 = 0x5eed;
+
+// what is added before a sample is rounded to fewer bits. tpdf dither
+// spreads the rounding error into white noise of about 1 lsb; Shaped
+// also feeds the error back so that the noise moves up in frequency,
+// where it is heard less.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dither {
+    None,
+    Tpdf,
+    Shaped
+}
+
+impl Default for Dither {
+    fn default() -> Self {
+        Dither::Tpdf
+    }
+}
+
+impl Dither {
+    pub fn from_name(name: &str) -> Option<Dither> {
+        match name.to_lowercase().as_str() {
+            "none" => Some(Dither::None),
+            "tpdf" => Some(Dither::Tpdf),
+            "shaped" => Some(Dither::Shaped),
+            _ => None
+        }
+    }
+}
+
+// converts interleaved samples between any two sample formats. samples
+// are dithered only when an integer format with fewer bits than the
+// source is written or they have been scaled on the way, every other
+// conversion is exact or rounds once.
+pub struct Converter {
+    from     : SampleFormat,
+    to       : SampleFormat,
+    channels : usize,
+    dither   : Dither,
+    exact    : bool,
+    rng      : Rng,
+    errors   : Vec<f64>,
+    channel  : usize
+}
+
+impl Converter {
+
+    pub fn new(from: SampleFormat, to: SampleFormat, channels: usize)
+     -> Self {
+        Converter {
+            from: from,
+            to: to,
+            channels: channels.max(1),
+            dither: Dither::default(),
+            exact: true,
+            rng: Rng::new(DITHER_SEED),
+            errors: vec![0.0; channels.max(1)],
+            channel: 0
+        }
+    }
+
+    pub fn dither(mut self, dither: Dither) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    pub fn from(&self) -> SampleFormat {
+        self.from
+    }
+
+    pub fn to(&self) -> SampleFormat {
+        self.to
+    }
+
+    // samples that have been scaled are no longer on the grid of their
+    // source format, and are dithered into any integer format
+    pub fn set_requantize(&mut self, requantize: bool) {
+        self.exact = !requantize;
+    }
+
+    pub fn reduces_depth(&self) -> bool {
+        !self.to.is_float() && (self.from.is_float() ||
+            self.to.width() < self.from.width())
+    }
+
+    fn quantize(&mut self, sample: f64) -> f64 {
+
+        let scale = (1u64 << (self.to.width() - 1)) as f64;
+        let ch = self.channel;
+        self.channel = (self.channel + 1) % self.channels;
+
+        let target = match self.dither {
+            Dither::Shaped => sample * scale - self.errors[ch],
+            _ => sample * scale
+        };
+
+        // the sum of two uniform values has a triangular distribution
+        // spanning 2 lsb
+        let noise = self.rng.next_f64() - self.rng.next_f64();
+
+        let value = (target + noise)
+            .round()
+            .max(-scale)
+            .min(scale - 1.0);
+
+        // the error is taken back out of the next sample of the channel
+        self.errors[ch] = value - target;
+        value / scale
+    }
+
+    // samples in [-1.0, 1.0) into bytes of the target format
+    pub fn encode(&mut self, samples: &[f64]) -> Vec<u8> {
+
+        if self.dither == Dither::None || self.to.is_float() ||
+            (self.exact && !self.reduces_depth()) {
+            return self.to.encode_samples(samples);
+        }
+
+        let quantized = samples
+            .iter()
+            .map(|sample| self.quantize(*sample))
+            .collect::<Vec<_>>();
+
+        self.to.encode_samples(&quantized)
+    }
+
+    // bytes of the source format into bytes of the target format
+    pub fn convert(&mut self, input: &[u8]) -> Vec<u8> {
+        let samples = self.from.decode_samples(input);
+        self.encode(&samples)
+    }
+}
+
+// what a sample format is best played as, out of what a device takes.
+// the format itself, or else one that keeps every bit, of the same kind
+// (integer or float) and as narrow as possible, or else the widest one.
+// signed and native endian formats win ties.
+pub fn best_format(source: SampleFormat, available: &[SampleFormat])
+ -> Option<SampleFormat> {
+
+    if available.contains(&source) {
+        return Some(source);
+    }
+
+    let native_big_endian = cfg!(target_endian = "big");
+
+    available
+        .iter()
+        .min_by_key(|format| {
+            let lossy = format.width() < source.width() ||
+                (source.is_float() && !format.is_float());
+            let width = format.width() as i64;
+            (lossy,
+             format.is_float() != source.is_float(),
+             match lossy { true => -width, _ => width },
+             !format.is_signed(),
+             format.is_big_endian() != native_big_endian)
+        })
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::f64::consts::PI;
+
+    use super::*;
+    use sample::SampleFormat;
+    use sample::SampleFormat::*;
+
+    #[test]
+    fn convert_test() {
+
+        let samples = [0.0, 0.5, -0.5, -1.0, 0.25, -0.125, 0.99];
+        let bytes = S24_3LE.encode_samples(&samples);
+
+        // everything that doesn't lose bits comes back as it was, the
+        // rest within half an lsb
+        for format in SampleFormat::all() {
+
+            let mut to = Converter::new(S24_3LE, *format, 1)
+                .dither(Dither::None);
+            let mut back = Converter::new(*format, S24_3LE, 1)
+                .dither(Dither::None);
+            let converted = to.convert(&bytes);
+
+            assert_eq!(samples.len() * format.physical_bytes(),
+                converted.len());
+
+            let lsb = match format.is_float() {
+                true => 0.0,
+                _ => 1.0 / (1u64 << (format.width() - 1)) as f64
+            };
+
+            for (sample, expected) in S24_3LE
+                .decode_samples(&back.convert(&converted))
+                .iter()
+                .zip(samples.iter()) {
+                assert!((sample - expected).abs() <= lsb / 2.0 + 1e-7,
+                    "{}: {} {}", format.name(), sample, expected);
+            }
+        }
+
+        // signedness and byte order only
+        let mut converter = Converter::new(S16_LE, U16_BE, 1);
+        assert_eq!(vec![0xc0, 0x00, 0x80, 0x00],
+            converter.convert(&[0x00, 0x40, 0x00, 0x00]));
+    }
+
+    #[test]
+    fn tpdf_test() {
+
+        // a level of a third of a 16 bit lsb
+        let level = 1.0 / 3.0 / 32768.0;
+        let samples = vec![level; 48000];
+
+        let plain = S16_LE.decode_samples(&Converter::new(S24_3LE, S16_LE, 1)
+            .dither(Dither::None)
+            .encode(&samples));
+        assert!(plain.iter().all(|sample| *sample == 0.0));
+
+        // dither makes it come through on average
+        let dithered = S16_LE.decode_samples(&Converter::new(S24_3LE, S16_LE, 1)
+            .encode(&samples));
+        let mean = dithered.iter().fold(0.0, |sum, s| sum + s) /
+            dithered.len() as f64;
+        assert!((mean - level).abs() < level * 0.1);
+
+        // and never more than a couple of lsb off
+        for sample in &dithered {
+            assert!((sample - level).abs() <= 2.0 / 32768.0);
+        }
+    }
+
+    // the error power left after a moving average, a crude low pass
+    fn low_frequency_error(dither: Dither) -> f64 {
+
+        let samples = (0..48000)
+            .map(|n| 0.3 * (2.0 * PI * 440.0 * n as f64 / 48000.0).sin())
+            .collect::<Vec<_>>();
+
+        let output = S16_LE.decode_samples(&Converter::new(FLOAT_LE, S16_LE, 1)
+            .dither(dither)
+            .encode(&samples));
+
+        let errors = output
+            .iter()
+            .zip(samples.iter())
+            .map(|(out, sample)| out - sample)
+            .collect::<Vec<_>>();
+
+        errors
+            .windows(16)
+            .map(|window| window.iter().fold(0.0, |sum, e| sum + e) / 16.0)
+            .fold(0.0, |sum, e| sum + e * e)
+    }
+
+    #[test]
+    fn noise_shaping_test() {
+        assert!(low_frequency_error(Dither::Shaped) <
+            low_frequency_error(Dither::Tpdf) / 4.0);
+    }
+
+    #[test]
+    fn best_format_test() {
+
+        let device = [S16_LE, S32_LE, S24_3LE];
+
+        assert_eq!(Some(S24_3LE), best_format(S24_3LE, &device));
+        assert_eq!(Some(S24_3LE), best_format(S24_LE, &device));
+        assert_eq!(Some(S32_LE), best_format(S24_LE, &[S16_LE, S32_LE]));
+        assert_eq!(Some(S16_LE), best_format(U8, &device));
+        // floats lose their headroom in any integer format
+        assert_eq!(Some(S32_LE), best_format(FLOAT_LE, &device));
+        assert_eq!(Some(S16_LE), best_format(S24_3LE, &[U8, S16_LE]));
+        assert_eq!(None, best_format(S16_LE, &[]));
+    }
+}
@@ -0,0 +1,374 @@
+extern crate libc;
+
+use std::ffi::{ CStr, CString };
+use std::ptr;
+
+use io::*;
+use sample::SampleFormat;
+use sp_io::*;
+
+#[allow(non_camel_case_types)]
+type snd_pcm_hw_params_t = libc::c_void;
+
+#[link(name = "asound")]
+extern "C" {
+
+    fn snd_device_name_hint(card: i32,
+        iface: *const libc::c_char,
+        hints: *mut *mut *mut libc::c_void) -> i32;
+
+    fn snd_device_name_get_hint(hint: *const libc::c_void,
+        id: *const libc::c_char) -> *mut libc::c_char;
+
+    fn snd_device_name_free_hint(hints: *mut *mut libc::c_void) -> i32;
+
+    fn snd_pcm_open(pcm: *mut *mut snd_pcm_t,
+        name: *const libc::c_char,
+        stream: snd_pcm_stream_t,
+        mode: i32) -> i32;
+
+    fn snd_pcm_close(pcm: *mut snd_pcm_t) -> i32;
+
+    fn snd_pcm_hw_params_malloc(params: *mut *mut snd_pcm_hw_params_t) -> i32;
+
+    fn snd_pcm_hw_params_free(params: *mut snd_pcm_hw_params_t);
+
+    fn snd_pcm_hw_params_any(pcm: *mut snd_pcm_t,
+        params: *mut snd_pcm_hw_params_t) -> i32;
+
+    fn snd_pcm_hw_params_test_format(pcm: *mut snd_pcm_t,
+        params: *mut snd_pcm_hw_params_t,
+        format: snd_pcm_format_t) -> i32;
+
+    fn snd_pcm_hw_params_test_rate(pcm: *mut snd_pcm_t,
+        params: *mut snd_pcm_hw_params_t,
+        rate: u32,
+        dir: i32) -> i32;
+
+    fn snd_pcm_hw_params_get_rate_min(params: *const snd_pcm_hw_params_t,
+        rate: *mut u32,
+        dir: *mut i32) -> i32;
+
+    fn snd_pcm_hw_params_get_rate_max(params: *const snd_pcm_hw_params_t,
+        rate: *mut u32,
+        dir: *mut i32) -> i32;
+
+    fn snd_pcm_hw_params_get_channels_min(params: *const snd_pcm_hw_params_t,
+        channels: *mut u32) -> i32;
+
+    fn snd_pcm_hw_params_get_channels_max(params: *const snd_pcm_hw_params_t,
+        channels: *mut u32) -> i32;
+}
+
+fn snd_result(res: i32) -> IOResult<()> {
+    match res {
+        res if res >= 0 => Ok(()),
+        errnum => Err(IOError::new(IO_ERROR,
+            snd_pcm_error(errnum).unwrap_or("unknown error")))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Playback,
+    Capture
+}
+
+impl Direction {
+    fn to_snd(self) -> snd_pcm_stream_t {
+        match self {
+            Direction::Playback => SND_PCM_STREAM_PLAYBACK,
+            Direction::Capture => SND_PCM_STREAM_CAPTURE
+        }
+    }
+}
+
+// a pcm device as alsa announces it
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceHint {
+    pub name        : String,
+    pub description : Option<String>,
+    pub playback    : bool,
+    pub capture     : bool
+}
+
+impl DeviceHint {
+
+    pub fn supports(&self, direction: Direction) -> bool {
+        match direction {
+            Direction::Playback => self.playback,
+            Direction::Capture => self.capture
+        }
+    }
+}
+
+// a missing IOID hint means the device works both ways
+fn directions(ioid: Option<&str>) -> (bool, bool) {
+    match ioid {
+        Some("Output") => (true, false),
+        Some("Input") => (false, true),
+        _ => (true, true)
+    }
+}
+
+unsafe fn get_hint(hint: *const libc::c_void, id: &str) -> Option<String> {
+
+    let id = CString::new(id)
+        .unwrap();
+
+    let value = snd_device_name_get_hint(hint, id.as_ptr());
+
+    match value.is_null() {
+        true => None,
+        _ => {
+            let st = CStr::from_ptr(value)
+                .to_string_lossy()
+                .into_owned();
+            libc::free(value as *mut libc::c_void);
+            Some(st)
+        }
+    }
+}
+
+// every pcm device alsa knows of, on all cards and from the configuration
+pub fn hints() -> IOResult<Vec<DeviceHint>> {
+
+    let iface = CString::new("pcm")
+        .unwrap();
+
+    let mut raw : *mut *mut libc::c_void = ptr::null_mut();
+
+    try!(snd_result(unsafe {
+        snd_device_name_hint(-1, iface.as_ptr(), &mut raw)
+    }));
+
+    let mut hints = Vec::new();
+
+    unsafe {
+
+        let mut n = raw;
+
+        while !(*n).is_null() {
+
+            if let Some(name) = get_hint(*n, "NAME") {
+
+                let ioid = get_hint(*n, "IOID");
+                let (playback, capture) = directions(ioid
+                    .as_ref()
+                    .map(|s| s.as_str()));
+
+                hints.push(DeviceHint {
+                    name: name,
+                    description: get_hint(*n, "DESC"),
+                    playback: playback,
+                    capture: capture
+                });
+            }
+
+            n = n.offset(1);
+        }
+
+        snd_device_name_free_hint(raw);
+    }
+
+    Ok(hints)
+}
+
+pub fn playback_devices() -> IOResult<Vec<DeviceHint>> {
+    hints()
+        .map(|hints| hints
+            .into_iter()
+            .filter(|hint| hint.playback)
+            .collect())
+}
+
+pub const STANDARD_RATES : [u32; 13] = [
+    8000, 11025, 16000, 22050, 32000, 44100, 48000,
+    64000, 88200, 96000, 176400, 192000, 384000
+];
+
+// what a device accepts in the given direction
+#[derive(Clone, Debug, PartialEq)]
+pub struct Capabilities {
+    pub formats      : Vec<SampleFormat>,
+    pub min_rate     : u32,
+    pub max_rate     : u32,
+    pub rates        : Vec<u32>,
+    pub min_channels : u32,
+    pub max_channels : u32
+}
+
+impl Capabilities {
+
+    pub fn supports_format(&self, format: SampleFormat) -> bool {
+        self.formats.contains(&format)
+    }
+
+    pub fn supports_rate(&self, rate: u32) -> bool {
+        self.min_rate <= rate && rate <= self.max_rate
+    }
+
+    pub fn supports_channels(&self, channels: u32) -> bool {
+        self.min_channels <= channels && channels <= self.max_channels
+    }
+}
+
+// keeps a probed device and its parameter space until they are released
+struct Probe {
+    pcm    : *mut snd_pcm_t,
+    params : *mut snd_pcm_hw_params_t
+}
+
+impl Probe {
+
+    fn open(name: &str, direction: Direction) -> IOResult<Probe> {
+
+        let cname = match CString::new(name) {
+            Ok(cname) => cname,
+            _ => return Err(IOError::new(IOErrorKind::InvalidInput,
+                "device name contains a nul byte"))
+        };
+
+        let mut probe = Probe {
+            pcm: ptr::null_mut(),
+            params: ptr::null_mut()
+        };
+
+        unsafe {
+            try!(snd_result(snd_pcm_open(&mut probe.pcm,
+                cname.as_ptr(),
+                direction.to_snd(),
+                SND_PCM_NONBLOCK)));
+            try!(snd_result(snd_pcm_hw_params_malloc(&mut probe.params)));
+            try!(snd_result(snd_pcm_hw_params_any(probe.pcm,
+                probe.params)));
+        }
+
+        Ok(probe)
+    }
+
+    fn capabilities(&self) -> IOResult<Capabilities> {
+
+        let (mut min_rate, mut max_rate, mut dir) = (0, 0, 0);
+        let (mut min_channels, mut max_channels) = (0, 0);
+
+        unsafe {
+            try!(snd_result(snd_pcm_hw_params_get_rate_min(self.params,
+                &mut min_rate, &mut dir)));
+            try!(snd_result(snd_pcm_hw_params_get_rate_max(self.params,
+                &mut max_rate, &mut dir)));
+            try!(snd_result(snd_pcm_hw_params_get_channels_min(self.params,
+                &mut min_channels)));
+            try!(snd_result(snd_pcm_hw_params_get_channels_max(self.params,
+                &mut max_channels)));
+        }
+
+        let formats = SampleFormat::all()
+            .iter()
+            .filter(|format| unsafe {
+                snd_pcm_hw_params_test_format(self.pcm,
+                    self.params,
+                    format.to_snd()) == 0
+            })
+            .cloned()
+            .collect();
+
+        let rates = STANDARD_RATES
+            .iter()
+            .filter(|rate| unsafe {
+                snd_pcm_hw_params_test_rate(self.pcm,
+                    self.params,
+                    **rate,
+                    0) == 0
+            })
+            .cloned()
+            .collect();
+
+        Ok(Capabilities {
+            formats: formats,
+            min_rate: min_rate,
+            max_rate: max_rate,
+            rates: rates,
+            min_channels: min_channels,
+            max_channels: max_channels
+        })
+    }
+}
+
+impl Drop for Probe {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.params.is_null() {
+                snd_pcm_hw_params_free(self.params);
+            }
+            if !self.pcm.is_null() {
+                snd_pcm_close(self.pcm);
+            }
+        }
+    }
+}
+
+// opens the device for a moment to ask what it can do
+pub fn capabilities(name: &str, direction: Direction)
+ -> IOResult<Capabilities> {
+    Probe::open(name, direction)
+        .and_then(|probe| probe.capabilities())
+}
+
+pub fn open_playback(name: String)
+ -> IOResult<NonBlockingSoundPcmPlaybackWriter> {
+    NonBlockingSoundPcmPlaybackWriter::open(name)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use super::directions;
+
+    #[test]
+    fn directions_test() {
+        assert_eq!((true, false), directions(Some("Output")));
+        assert_eq!((false, true), directions(Some("Input")));
+        assert_eq!((true, true), directions(None));
+    }
+
+    #[test]
+    fn capabilities_test() {
+
+        let caps = Capabilities {
+            formats: vec![SampleFormat::S16_LE],
+            min_rate: 8000,
+            max_rate: 48000,
+            rates: vec![8000, 44100, 48000],
+            min_channels: 1,
+            max_channels: 2
+        };
+
+        assert!(caps.supports_format(SampleFormat::S16_LE));
+        assert!(!caps.supports_format(SampleFormat::FLOAT_LE));
+        assert!(caps.supports_rate(44100));
+        assert!(!caps.supports_rate(96000));
+        assert!(caps.supports_channels(2));
+        assert!(!caps.supports_channels(6));
+    }
+
+    #[test]
+    fn unknown_device_test() {
+        assert!(capabilities("no such device", Direction::Playback)
+            .is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "optional")]
+    fn hints_test() {
+
+        let hints = hints().unwrap();
+        assert!(hints.iter().any(|hint| hint.name == "default"));
+
+        let caps = capabilities("default", Direction::Playback)
+            .unwrap();
+        assert!(!caps.formats.is_empty());
+        assert!(caps.min_channels >= 1);
+    }
+}
@@ -0,0 +1,516 @@
+use std::any::Any;
+use std::f64::consts::PI;
+use std::ops::{ Add, Mul, Sub };
+
+use chain::Processor;
+use io::*;
+
+// the q of a butterworth second order section, a flat passband
+pub const DEFAULT_Q : f64 = 0.7071067811865476;
+
+const INVALID_BAND : &'static str = "invalid equaliser band";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterKind {
+    LowShelf,
+    HighShelf,
+    Peaking,
+    LowPass,
+    HighPass,
+    Notch
+}
+
+impl FilterKind {
+
+    pub fn from_name(name: &str) -> Option<FilterKind> {
+        match name.to_lowercase().as_str() {
+            "lowshelf" | "low-shelf" | "ls" => Some(FilterKind::LowShelf),
+            "highshelf" | "high-shelf" | "hs" => Some(FilterKind::HighShelf),
+            "peaking" | "peak" | "bell" => Some(FilterKind::Peaking),
+            "lowpass" | "low-pass" | "lp" => Some(FilterKind::LowPass),
+            "highpass" | "high-pass" | "hp" => Some(FilterKind::HighPass),
+            "notch" => Some(FilterKind::Notch),
+            _ => None
+        }
+    }
+
+    // whether the gain of a band changes anything
+    pub fn has_gain(self) -> bool {
+        match self {
+            FilterKind::LowShelf | FilterKind::HighShelf |
+            FilterKind::Peaking => true,
+            _ => false
+        }
+    }
+}
+
+// a filter of an equaliser. the frequency is in Hz, the gain in dB; q is
+// the width of a peak or notch and the steepness of a shelf or a pass.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Band {
+    pub kind      : FilterKind,
+    pub frequency : f64,
+    pub gain      : f64,
+    pub q         : f64
+}
+
+impl Band {
+
+    pub fn new(kind: FilterKind, frequency: f64, gain: f64, q: f64) -> Self {
+        Band {
+            kind: kind,
+            frequency: frequency,
+            gain: gain,
+            q: q
+        }
+    }
+
+    // "kind:frequency[:gain[:q]]", e.g. "peak:1000:-3:2" or "hp:80".
+    // the gain of a pass or a notch is left empty or out, "lp:8000::1".
+    pub fn parse(st: &str) -> IOResult<Band> {
+
+        let fields = st.split(':')
+            .map(|field| field.trim())
+            .collect::<Vec<_>>();
+
+        if fields.len() < 2 || fields.len() > 4 {
+            return Err(IOError::new(IOErrorKind::InvalidInput, INVALID_BAND));
+        }
+
+        let kind = match FilterKind::from_name(fields[0]) {
+            Some(kind) => kind,
+            _ => return Err(IOError::new(IOErrorKind::InvalidInput,
+                INVALID_BAND))
+        };
+
+        let number = |index: usize, default: f64| -> IOResult<f64> {
+            match fields.get(index) {
+                Some(field) if !field.is_empty() => field
+                    .to_lowercase()
+                    .trim_right_matches("db")
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| IOError::new(IOErrorKind::InvalidInput,
+                        INVALID_BAND)),
+                _ => Ok(default)
+            }
+        };
+
+        let frequency = try!(number(1, 0.0));
+        let gain = try!(number(2, 0.0));
+        let q = try!(number(3, DEFAULT_Q));
+
+        if !(frequency > 0.0) || !(q > 0.0) || !gain.is_finite() ||
+            !frequency.is_finite() || !q.is_finite() {
+            return Err(IOError::new(IOErrorKind::InvalidInput, INVALID_BAND));
+        }
+
+        if gain != 0.0 && !kind.has_gain() {
+            return Err(IOError::new(IOErrorKind::InvalidInput, INVALID_BAND));
+        }
+
+        Ok(Band::new(kind, frequency, gain, q))
+    }
+
+    // the coefficients at `rate`, after the audio eq cookbook of
+    // robert bristow-johnson
+    pub fn coefficients(&self, rate: u32) -> Coefficients {
+
+        // at or past nyquist a filter falls apart
+        let frequency = self.frequency.min(rate as f64 * 0.499);
+        let w0 = 2.0 * PI * frequency / rate as f64;
+        let (sin, cos) = (w0.sin(), w0.cos());
+        let alpha = sin / (2.0 * self.q);
+        let a = 10.0f64.powf(self.gain / 40.0);
+        let root = 2.0 * a.sqrt() * alpha;
+
+        let (b0, b1, b2, a0, a1, a2) = match self.kind {
+            FilterKind::LowPass => ((1.0 - cos) / 2.0,
+                1.0 - cos,
+                (1.0 - cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha),
+            FilterKind::HighPass => ((1.0 + cos) / 2.0,
+                -(1.0 + cos),
+                (1.0 + cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha),
+            FilterKind::Notch => (1.0,
+                -2.0 * cos,
+                1.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha),
+            FilterKind::Peaking => (1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a),
+            FilterKind::LowShelf => (a * ((a + 1.0) - (a - 1.0) * cos + root),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - root),
+                (a + 1.0) + (a - 1.0) * cos + root,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - root),
+            FilterKind::HighShelf => (a * ((a + 1.0) + (a - 1.0) * cos + root),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - root),
+                (a + 1.0) - (a - 1.0) * cos + root,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - root)
+        };
+
+        Coefficients {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0
+        }
+    }
+}
+
+// a biquad normalized so that a0 is 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Coefficients {
+    pub b0 : f64,
+    pub b1 : f64,
+    pub b2 : f64,
+    pub a1 : f64,
+    pub a2 : f64
+}
+
+impl Coefficients {
+
+    // the gain at `frequency`, as a ratio of amplitudes
+    pub fn magnitude(&self, frequency: f64, rate: u32) -> f64 {
+
+        let w = 2.0 * PI * frequency / rate as f64;
+        let (cos1, sin1) = (w.cos(), w.sin());
+        let (cos2, sin2) = ((2.0 * w).cos(), (2.0 * w).sin());
+
+        // the polynomials in e^-jw
+        let (num_re, num_im) = (self.b0 + self.b1 * cos1 + self.b2 * cos2,
+            -self.b1 * sin1 - self.b2 * sin2);
+        let (den_re, den_im) = (1.0 + self.a1 * cos1 + self.a2 * cos2,
+            -self.a1 * sin1 - self.a2 * sin2);
+
+        ((num_re * num_re + num_im * num_im) /
+            (den_re * den_re + den_im * den_im)).sqrt()
+    }
+
+    pub fn magnitude_db(&self, frequency: f64, rate: u32) -> f64 {
+        20.0 * self.magnitude(frequency, rate).log10()
+    }
+}
+
+// what a filter can run in
+pub trait Real: Copy + Add<Output=Self> + Sub<Output=Self> + Mul<Output=Self> {
+    fn from_f64(value: f64) -> Self;
+    fn to_f64(self) -> f64;
+}
+
+impl Real for f32 {
+
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl Real for f64 {
+
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+// a biquad in transposed direct form ii with a state for every channel
+#[derive(Clone, Debug)]
+pub struct Biquad<T: Real> {
+    b0    : T,
+    b1    : T,
+    b2    : T,
+    a1    : T,
+    a2    : T,
+    state : Vec<(T, T)>
+}
+
+impl<T: Real> Biquad<T> {
+
+    pub fn new(coefficients: Coefficients, channels: usize) -> Self {
+        let zero = T::from_f64(0.0);
+        Biquad {
+            b0: T::from_f64(coefficients.b0),
+            b1: T::from_f64(coefficients.b1),
+            b2: T::from_f64(coefficients.b2),
+            a1: T::from_f64(coefficients.a1),
+            a2: T::from_f64(coefficients.a2),
+            state: vec![(zero, zero); channels]
+        }
+    }
+
+    pub fn reset(&mut self) {
+        let zero = T::from_f64(0.0);
+        for state in self.state.iter_mut() {
+            *state = (zero, zero);
+        }
+    }
+
+    pub fn run(&mut self, channel: usize, input: T) -> T {
+        let (s1, s2) = self.state[channel];
+        let output = self.b0 * input + s1;
+        self.state[channel] = (self.b1 * input - self.a1 * output + s2,
+            self.b2 * input - self.a2 * output);
+        output
+    }
+
+    // a trailing partial frame is left as it is
+    pub fn process(&mut self, samples: &mut [T]) {
+        let channels = self.state.len();
+        for frame in samples.chunks_mut(channels.max(1)) {
+            if frame.len() < channels {
+                break;
+            }
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                *sample = self.run(channel, *sample);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Precision {
+    Single,
+    Double
+}
+
+impl Default for Precision {
+    fn default() -> Self {
+        Precision::Double
+    }
+}
+
+// bands applied one after another to every channel of the frames
+pub struct Equalizer {
+    bands     : Vec<Band>,
+    precision : Precision,
+    single    : Vec<Biquad<f32>>,
+    double    : Vec<Biquad<f64>>
+}
+
+impl Equalizer {
+
+    pub fn new(bands: Vec<Band>) -> Self {
+        Equalizer {
+            bands: bands,
+            precision: Precision::default(),
+            single: Vec::new(),
+            double: Vec::new()
+        }
+    }
+
+    pub fn precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    pub fn bands(&self) -> &[Band] {
+        &self.bands
+    }
+
+    // the gain of all the bands at `frequency`, in dB
+    pub fn response_db(&self, frequency: f64, rate: u32) -> f64 {
+        self.bands
+            .iter()
+            .map(|band| band.coefficients(rate).magnitude_db(frequency, rate))
+            .fold(0.0, |sum, db| sum + db)
+    }
+}
+
+impl Processor for Equalizer {
+
+    fn configure(&mut self, channels: u16, rate: u32) -> (u16, u32) {
+
+        self.single.clear();
+        self.double.clear();
+
+        // nothing to filter until there is a rate
+        if rate > 0 {
+            for band in self.bands.iter() {
+                let coefficients = band.coefficients(rate);
+                match self.precision {
+                    Precision::Single => self.single
+                        .push(Biquad::new(coefficients, channels as usize)),
+                    Precision::Double => self.double
+                        .push(Biquad::new(coefficients, channels as usize))
+                }
+            }
+        }
+
+        (channels, rate)
+    }
+
+    fn process(&mut self, mut samples: Vec<f64>) -> Vec<f64> {
+
+        match self.precision {
+            Precision::Double => {
+                for filter in self.double.iter_mut() {
+                    filter.process(&mut samples);
+                }
+            },
+            Precision::Single => {
+                let mut single = samples.iter()
+                    .map(|sample| *sample as f32)
+                    .collect::<Vec<_>>();
+                for filter in self.single.iter_mut() {
+                    filter.process(&mut single);
+                }
+                samples = single.iter()
+                    .map(|sample| *sample as f64)
+                    .collect();
+            }
+        }
+
+        samples
+    }
+
+    fn reset(&mut self) {
+        for filter in self.single.iter_mut() {
+            filter.reset();
+        }
+        for filter in self.double.iter_mut() {
+            filter.reset();
+        }
+    }
+
+    fn as_any(&mut self) -> &mut Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::f64::consts::PI;
+
+    use super::*;
+    use chain::Processor;
+
+    const RATE : u32 = 48000;
+
+    // the amplitude a sine at `frequency` comes out with once the
+    // filter has settled
+    fn measure(equalizer: &mut Equalizer, frequency: f64) -> f64 {
+
+        equalizer.configure(1, RATE);
+
+        let samples = (0..RATE as usize)
+            .map(|n| (2.0 * PI * frequency * n as f64 / RATE as f64).sin())
+            .collect::<Vec<_>>();
+
+        let output = equalizer.process(samples);
+
+        // the rms of the second half, times root two
+        let settled = &output[output.len() / 2..];
+        (settled.iter().fold(0.0, |sum, sample| sum + sample * sample) /
+            settled.len() as f64).sqrt() * 2.0f64.sqrt()
+    }
+
+    #[test]
+    fn response_test() {
+
+        // a peak has its gain at its frequency
+        let peak = Band::new(FilterKind::Peaking, 1000.0, 6.0, 1.0)
+            .coefficients(RATE);
+        assert!((peak.magnitude_db(1000.0, RATE) - 6.0).abs() < 1e-9);
+        assert!(peak.magnitude_db(20.0, RATE).abs() < 0.05);
+
+        // a butterworth pass is 3 dB down at its frequency
+        let low = Band::new(FilterKind::LowPass, 1000.0, 0.0, DEFAULT_Q)
+            .coefficients(RATE);
+        assert!((low.magnitude(0.0, RATE) - 1.0).abs() < 1e-9);
+        assert!((low.magnitude_db(1000.0, RATE) + 3.0103).abs() < 1e-3);
+        assert!(low.magnitude(24000.0, RATE) < 1e-9);
+
+        let high = Band::new(FilterKind::HighPass, 1000.0, 0.0, DEFAULT_Q)
+            .coefficients(RATE);
+        assert!(high.magnitude(0.0, RATE) < 1e-9);
+        assert!((high.magnitude_db(1000.0, RATE) + 3.0103).abs() < 1e-3);
+        assert!((high.magnitude(24000.0, RATE) - 1.0).abs() < 1e-9);
+
+        let notch = Band::new(FilterKind::Notch, 1000.0, 0.0, 4.0)
+            .coefficients(RATE);
+        assert!(notch.magnitude(1000.0, RATE) < 1e-9);
+        assert!((notch.magnitude(0.0, RATE) - 1.0).abs() < 1e-9);
+
+        // shelves have their gain at one end and none at the other
+        let low = Band::new(FilterKind::LowShelf, 200.0, -6.0, DEFAULT_Q)
+            .coefficients(RATE);
+        assert!((low.magnitude_db(0.0, RATE) + 6.0).abs() < 1e-9);
+        assert!(low.magnitude_db(24000.0, RATE).abs() < 1e-9);
+        assert!((low.magnitude_db(200.0, RATE) + 3.0).abs() < 1e-3);
+
+        let high = Band::new(FilterKind::HighShelf, 8000.0, 4.0, DEFAULT_Q)
+            .coefficients(RATE);
+        assert!(high.magnitude_db(0.0, RATE).abs() < 1e-9);
+        assert!((high.magnitude_db(24000.0, RATE) - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn equalizer_test() {
+
+        let bands = vec![Band::new(FilterKind::Peaking, 1000.0, -9.0, 2.0),
+                         Band::new(FilterKind::HighPass, 50.0, 0.0, DEFAULT_Q)];
+
+        // a sine comes out as the response says it should, in either
+        // precision
+        for precision in [Precision::Double, Precision::Single].iter() {
+            let mut equalizer = Equalizer::new(bands.clone())
+                .precision(*precision);
+            for frequency in [100.0, 1000.0, 1500.0, 10000.0].iter() {
+                let expected = equalizer.response_db(*frequency, RATE);
+                let measured = 20.0 * measure(&mut equalizer, *frequency).log10();
+                assert!((measured - expected).abs() < 0.01);
+            }
+        }
+
+        // stereo channels are filtered apart
+        let mut equalizer = Equalizer::new(vec![
+            Band::new(FilterKind::LowPass, 100.0, 0.0, DEFAULT_Q)]);
+        equalizer.configure(2, RATE);
+        let output = equalizer.process(vec![1.0, 0.0, 1.0, 0.0]);
+        assert!(output[0] > 0.0 && output[2] > output[0]);
+        assert_eq!(0.0, output[1]);
+        assert_eq!(0.0, output[3]);
+    }
+
+    #[test]
+    fn parse_test() {
+
+        assert_eq!(Band::new(FilterKind::Peaking, 1000.0, -3.0, 2.0),
+            Band::parse("peak:1000:-3:2").unwrap());
+        assert_eq!(Band::new(FilterKind::HighPass, 80.0, 0.0, DEFAULT_Q),
+            Band::parse("hp:80").unwrap());
+        assert_eq!(Band::new(FilterKind::LowPass, 8000.0, 0.0, 1.0),
+            Band::parse("lowpass:8000::1").unwrap());
+        assert_eq!(Band::new(FilterKind::LowShelf, 100.0, 4.5, DEFAULT_Q),
+            Band::parse("ls:100:4.5 dB").unwrap());
+
+        assert!(Band::parse("peak").is_err());
+        assert!(Band::parse("tilt:1000").is_err());
+        assert!(Band::parse("peak:-10:3").is_err());
+        assert!(Band::parse("peak:1000:3:0").is_err());
+        assert!(Band::parse("notch:1000:3").is_err());
+    }
+}
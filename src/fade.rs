@@ -0,0 +1,257 @@
+use std::any::Any;
+use std::f64::consts::FRAC_PI_2;
+use std::time::Duration;
+
+use chain::Processor;
+
+// the shape of a fade. fading in and out with the same curve over the
+// same frames keeps the sum of the gains at 1 for Linear and the sum
+// of their squares at 1 for EqualPower, which keeps the loudness of
+// uncorrelated material level through a crossfade.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Curve {
+    Linear,
+    EqualPower
+}
+
+impl Default for Curve {
+    fn default() -> Self {
+        Curve::EqualPower
+    }
+}
+
+impl Curve {
+
+    pub fn from_name(name: &str) -> Option<Curve> {
+        match name.to_lowercase().as_str() {
+            "linear" => Some(Curve::Linear),
+            "equal-power" | "equal_power" => Some(Curve::EqualPower),
+            _ => None
+        }
+    }
+
+    // the gain of a fade in that has gone `x` of the way, 0.0 to 1.0
+    pub fn gain(self, x: f64) -> f64 {
+        let x = x.max(0.0).min(1.0);
+        match self {
+            Curve::Linear => x,
+            Curve::EqualPower => (x * FRAC_PI_2).sin()
+        }
+    }
+}
+
+// how long fades take. zero is no fade.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fades {
+    pub fade_in   : Duration,
+    pub fade_out  : Duration,
+    pub crossfade : Duration,
+    pub curve     : Curve
+}
+
+impl Default for Fades {
+    fn default() -> Self {
+        Fades {
+            fade_in: Duration::new(0, 0),
+            fade_out: Duration::new(0, 0),
+            crossfade: Duration::new(0, 0),
+            curve: Curve::default()
+        }
+    }
+}
+
+pub fn frames(duration: Duration, rate: u32) -> usize {
+    (duration.as_secs() * rate as u64 +
+        duration.subsec_nanos() as u64 * rate as u64 / 1_000_000_000) as usize
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Direction {
+    In,
+    Out
+}
+
+// an envelope over a number of frames. every channel of a frame gets the
+// same gain; a fade in leaves what follows it as it is, a fade out
+// silences it.
+#[derive(Clone, Debug)]
+pub struct Fade {
+    curve     : Curve,
+    direction : Direction,
+    frames    : usize,
+    position  : usize,
+    channels  : usize
+}
+
+impl Fade {
+
+    pub fn fade_in(curve: Curve, frames: usize) -> Self {
+        Fade {
+            curve: curve,
+            direction: Direction::In,
+            frames: frames,
+            position: 0,
+            channels: 1
+        }
+    }
+
+    pub fn fade_out(curve: Curve, frames: usize) -> Self {
+        Fade {
+            curve: curve,
+            direction: Direction::Out,
+            frames: frames,
+            position: 0,
+            channels: 1
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.position >= self.frames
+    }
+
+    pub fn is_fade_out(&self) -> bool {
+        self.direction == Direction::Out
+    }
+
+    // the gain of a frame, taken at its middle
+    pub fn gain_at(&self, frame: usize) -> f64 {
+
+        let x = match frame < self.frames {
+            true => (frame as f64 + 0.5) / self.frames as f64,
+            _ => 1.0
+        };
+
+        match self.direction {
+            Direction::In => self.curve.gain(x),
+            Direction::Out => self.curve.gain(1.0 - x)
+        }
+    }
+
+    pub fn process(&mut self, samples: &mut [f64], channels: usize) {
+
+        if self.is_done() && self.direction == Direction::In {
+            return;
+        }
+
+        for frame in samples.chunks_mut(channels.max(1)) {
+
+            let gain = self.gain_at(self.position);
+
+            for sample in frame.iter_mut() {
+                *sample *= gain;
+            }
+
+            self.position += 1;
+        }
+    }
+}
+
+impl Processor for Fade {
+
+    fn configure(&mut self, channels: u16, rate: u32) -> (u16, u32) {
+        self.channels = channels as usize;
+        (channels, rate)
+    }
+
+    fn process(&mut self, mut samples: Vec<f64>) -> Vec<f64> {
+        let channels = self.channels;
+        Fade::process(self, &mut samples, channels);
+        samples
+    }
+
+    fn as_any(&mut self) -> &mut Any {
+        self
+    }
+}
+
+// the end of one track faded out over the start of the next, frame by
+// frame. the longer of the two goes on past the end of the shorter.
+pub fn crossfade(tail: &[f64], head: &[f64], channels: usize, curve: Curve)
+ -> Vec<f64> {
+
+    let channels = channels.max(1);
+    let frames = tail.len().max(head.len()) / channels;
+
+    let mut out = tail.to_vec();
+    let mut head = head.to_vec();
+
+    Fade::fade_out(curve, tail.len() / channels)
+        .process(&mut out, channels);
+    Fade::fade_in(curve, head.len() / channels)
+        .process(&mut head, channels);
+
+    out.resize(frames * channels, 0.0);
+
+    for (sample, other) in out.iter_mut().zip(head.iter()) {
+        *sample += *other;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn curve_test() {
+        assert_eq!(0.0, Curve::Linear.gain(0.0));
+        assert_eq!(0.25, Curve::Linear.gain(0.25));
+        assert!((Curve::EqualPower.gain(0.5) - 0.5f64.sqrt()).abs() < 1e-12);
+        assert_eq!(1.0, Curve::EqualPower.gain(2.0));
+        assert_eq!(Some(Curve::EqualPower), Curve::from_name("equal-power"));
+        assert_eq!(None, Curve::from_name("log"));
+    }
+
+    #[test]
+    fn fade_test() {
+
+        // 4 stereo frames of fade in, then 2 untouched
+        let mut fade = Fade::fade_in(Curve::Linear, 4);
+        let mut samples = vec![1.0; 12];
+        fade.process(&mut samples, 2);
+
+        assert_eq!(vec![0.125, 0.125, 0.375, 0.375, 0.625, 0.625,
+                        0.875, 0.875, 1.0, 1.0, 1.0, 1.0],
+            samples);
+        assert!(fade.is_done());
+
+        // a fade out split over two blocks ends in silence
+        let mut fade = Fade::fade_out(Curve::Linear, 4);
+        let mut first = vec![1.0; 2];
+        let mut second = vec![1.0; 4];
+        fade.process(&mut first, 1);
+        fade.process(&mut second, 1);
+
+        assert_eq!(vec![0.875, 0.625], first);
+        assert_eq!(vec![0.375, 0.125, 0.0, 0.0], second);
+
+        assert_eq!(4410, frames(Duration::from_millis(100), 44100));
+    }
+
+    #[test]
+    fn crossfade_test() {
+
+        // a constant level stays constant through a linear crossfade
+        let mixed = crossfade(&[1.0; 8], &[1.0; 8], 2, Curve::Linear);
+        assert_eq!(8, mixed.len());
+        for sample in &mixed {
+            assert!((sample - 1.0).abs() < 1e-12);
+        }
+
+        // the power of two equal-power fades adds up to one
+        let out = Fade::fade_out(Curve::EqualPower, 16);
+        let into = Fade::fade_in(Curve::EqualPower, 16);
+        for frame in 0..16 {
+            let power = out.gain_at(frame).powi(2) + into.gain_at(frame).powi(2);
+            assert!((power - 1.0).abs() < 1e-12);
+        }
+
+        // a short head is faded in over its own length
+        let mixed = crossfade(&[1.0; 4], &[2.0; 2], 1, Curve::Linear);
+        assert_eq!(vec![0.875 + 0.5, 0.625 + 1.5, 0.375, 0.125], mixed);
+    }
+}
@@ -1,10 +1,12 @@
 use std;
 use std::fs::File;
+use std::io::{ Seek, SeekFrom };
 use std::sync::mpsc::*;
 use std::thread::{ JoinHandle, sleep, spawn };
 use std::time::Duration;
 
 use io::*;
+use wav::{ SeekPoint, SeekTarget, WaveHeader };
 
 pub type FileIOCallbackRet = ();
 
@@ -16,6 +18,7 @@ define_io!(FileIO,
 
 pub enum FileIORequest {
     Read(usize),
+    Seek(SeekTarget),
     Close
 }
 
@@ -23,6 +26,7 @@ unsafe impl Send for FileIORequest {}
 
 pub enum FileIOResponse {
     Read(ReadBuffer<File>),
+    Sought(SeekPoint),
     Failed(IOError),
     Closed,
     Timeout
@@ -292,26 +296,41 @@ fn handle_fio_request(mut f: File, req: FileIORequest) -> FileIOResponse {
                 _ => FileIOResponse::Read(buf)
             }
         },
+        FileIORequest::Seek(target) => match seek_wave(&mut f, target) {
+            Ok(point) => FileIOResponse::Sought(point),
+            Err(e) => FileIOResponse::Failed(e)
+        },
         FileIORequest::Close => FileIOResponse::Closed
     }
 }
 
+// the header is read again on every seek, so the handler can stay
+// stateless. reads go on from the block the target is in.
+fn seek_wave(f: &mut File, target: SeekTarget) -> IOResult<SeekPoint> {
+    let header = try!(WaveHeader::parse(f));
+    let point = header.seek_point(target);
+    try!(f.seek(SeekFrom::Start(header.data_offset + point.offset)));
+    Ok(point)
+}
+
 #[cfg(test)]
 mod tests {
 
-    use std::fs::File; 
+    use std::fs::File;
     use std::str::from_utf8;
     use std::sync::mpsc::{ channel, TryRecvError };
     use std::thread::{ sleep, spawn };
     use std::time::Duration;
 
+    use fixtures::{ TempDir, pcm, sample_file };
+    use sample::SampleFormat;
     use io::*;
     use super::*;
     use super::{ handle_fio_request, Worker };
+    use wav::{ SeekPoint, SeekTarget };
 
     const RIFF            : &'static str = "RIFF";
     const WAVE            : &'static str = "WAVE";
-    const WAVE_FILE_PATH  : &'static str = "/usr/share/sounds/k3b_success1.wav";
     const RIFF_FIELD_SIZE : usize = 4;
 
     const ERROR_MESSAGE_1 : &'static str = "read response is expected";
@@ -320,7 +339,8 @@ mod tests {
     #[test]
     fn handler_test() {
 
-        let f = File::open(WAVE_FILE_PATH)
+        let dir = TempDir::new("fio_handler_test");
+        let f = File::open(sample_file(&dir))
             .unwrap();
         
         let res = handle_fio_request(f
@@ -351,6 +371,9 @@ mod tests {
     #[test]
     fn worker_test() {
 
+        let dir = TempDir::new("fio_worker_test");
+        let path = sample_file(&dir);
+
         let (req_tx, req_rx) = channel::<FileIORequest>();          
         let (res_tx, res_rx) = channel::<FileIOResponse>();
 
@@ -363,7 +386,7 @@ mod tests {
                 req_rx, 
                 timer);
 
-            let f = File::open(WAVE_FILE_PATH)
+            let f = File::open(path)
                 .unwrap();
 
             let handler = Box::new(handle_fio_request);
@@ -408,8 +431,8 @@ mod tests {
     #[test]
     fn file_io_test() {
 
-        let path = WAVE_FILE_PATH
-            .to_string();
+        let dir = TempDir::new("fio_file_io_test");
+        let path = sample_file(&dir);
 
         let mut file_io = FileIO::new(path, 10, 0); 
 
@@ -457,4 +480,41 @@ mod tests {
             }
         } 
     }
+
+    #[test]
+    fn seek_test() {
+
+        // 16 bit stereo at 8000 Hz, 4 frames numbered by their bytes
+        let wave = pcm(SampleFormat::S16_LE, 2, 8000, &[0, 0, 0, 0,
+            1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3]);
+
+        let dir = TempDir::new("fio_seek_test");
+        let f = File::open(dir.write("seek.wav", &wave))
+            .unwrap();
+
+        let targets = vec![
+            (SeekTarget::Frame(2), SeekPoint { offset: 8, frame: 2 }),
+            (SeekTarget::Byte(7), SeekPoint { offset: 4, frame: 1 }),
+            (SeekTarget::Time(Duration::new(0, 375_000)),
+             SeekPoint { offset: 12, frame: 3 })
+        ];
+
+        for (target, expected) in targets {
+
+            match handle_fio_request(f.try_clone().unwrap(),
+                FileIORequest::Seek(target)) {
+                FileIOResponse::Sought(point) => assert_eq!(expected, point),
+                _ => panic!("sought response is expected")
+            }
+
+            match handle_fio_request(f.try_clone().unwrap(),
+                FileIORequest::Read(4)) {
+                FileIOResponse::Read(buf) => {
+                    let frame = expected.frame as u8;
+                    assert_eq!(&[frame; 4], unsafe { buf.load() });
+                },
+                _ => panic!(ERROR_MESSAGE_1)
+            }
+        }
+    }
 }
@@ -0,0 +1,408 @@
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
+
+use generator::{ Generator, Signal };
+use io::*;
+use sample::SampleFormat;
+use wav::{ WAVE_FORMAT_EXTENSIBLE, WAVE_FORMAT_IMA_ADPCM, WaveFormat };
+
+// the sub format guid of an extensible fmt chunk, after its first two
+// bytes
+const GUID_TAIL : [u8; 14] = [0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80,
+                              0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71];
+
+// bytes of an ima adpcm block for each channel
+const ADPCM_BLOCK_SIZE : u16 = 256;
+
+// a directory of its own in the temp directory, removed with what is in
+// it once dropped
+pub struct TempDir {
+    path : PathBuf
+}
+
+impl TempDir {
+
+    pub fn new(name: &str) -> TempDir {
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.subsec_nanos())
+            .unwrap_or(0);
+
+        let mut count = 0;
+
+        loop {
+            let path = env::temp_dir().join(format!("wave-player-{}-{}-{}",
+                name, nanos, count));
+            match fs::create_dir(&path) {
+                Ok(_) => return TempDir { path: path },
+                Err(ref err) if err.kind() == IOErrorKind::AlreadyExists =>
+                    count += 1,
+                Err(err) => panic!("failed to create a temp dir: {}", err)
+            }
+        }
+    }
+
+    // where a file of the given name goes in the directory
+    pub fn path(&self, name: &str) -> String {
+        self.path
+            .join(name)
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    pub fn write(&self, name: &str, bytes: &[u8]) -> String {
+        let path = self.path(name);
+        File::create(&path)
+            .unwrap()
+            .write_all(bytes)
+            .unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+// a wave file and the frames a reader finds in it, None for one it has
+// to turn down
+pub struct Fixture {
+    pub name   : &'static str,
+    pub bytes  : Vec<u8>,
+    pub frames : Option<u64>
+}
+
+pub fn chunk(id: &[u8], body: &[u8]) -> Vec<u8> {
+    let mut buf = id.to_vec();
+    buf.extend_from_slice(&le_u32(body.len() as u32));
+    buf.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        buf.push(0);
+    }
+    buf
+}
+
+pub fn riff(chunks: &[Vec<u8>]) -> Vec<u8> {
+    let body = chunks.concat();
+    let mut buf = b"RIFF".to_vec();
+    buf.extend_from_slice(&le_u32(body.len() as u32 + 4));
+    buf.extend_from_slice(b"WAVE");
+    buf.extend(body);
+    buf
+}
+
+fn le_u16(value: u16) -> [u8; 2] {
+    [value as u8, (value >> 8) as u8]
+}
+
+fn le_u32(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
+
+// a 1 kHz sine at half of full scale, as long as `frames` and a second
+// more
+pub fn sine(channels: u16, rate: u32, frames: u64) -> Generator {
+    Generator::new(Signal::Sine(1000.0))
+        .rate(rate)
+        .channels(channels)
+        .duration(Duration::from_secs(frames / rate as u64 + 1))
+}
+
+// a wave file of `frames` frames of the sine in `format`
+pub fn wave(format: SampleFormat, channels: u16, rate: u32, frames: u64)
+ -> Vec<u8> {
+    let samples = sine(channels, rate, frames).samples(0, frames as usize);
+    wave_of(format, channels, rate, &samples)
+}
+
+// a wave file of interleaved `samples` in `format`
+pub fn wave_of(format: SampleFormat, channels: u16, rate: u32,
+    samples: &[f64]) -> Vec<u8> {
+    pcm(format, channels, rate, &format.encode_samples(samples))
+}
+
+// a wave file of `data` as it is, laid out in `format`
+pub fn pcm(format: SampleFormat, channels: u16, rate: u32, data: &[u8])
+ -> Vec<u8> {
+    riff(&[fmt_chunk(format, channels, rate), chunk(b"data", data)])
+}
+
+// a plain fmt chunk for samples in `format`
+pub fn fmt_chunk(format: SampleFormat, channels: u16, rate: u32) -> Vec<u8> {
+    let fmt = WaveFormat::from_sample_format(format, channels, rate)
+        .unwrap();
+    chunk(b"fmt ", &fmt.to_bytes())
+}
+
+// a second of 16 bit stereo at 8000 Hz, written to `dir` for tests that
+// need some wave file to read
+pub fn sample_file(dir: &TempDir) -> String {
+    dir.write("sample.wav", &wave(SampleFormat::S16_LE, 2, 8000, 8000))
+}
+
+// the same 24 bit samples behind an extensible fmt chunk
+fn extensible(channels: u16, rate: u32, frames: u64) -> Vec<u8> {
+
+    let format = SampleFormat::S24_3LE;
+    let plain = WaveFormat::from_sample_format(format, channels, rate)
+        .unwrap();
+
+    let mut fmt = plain.to_bytes();
+    fmt[0..2].copy_from_slice(&le_u16(WAVE_FORMAT_EXTENSIBLE));
+    fmt.extend_from_slice(&le_u16(22));
+    fmt.extend_from_slice(&le_u16(24));
+    fmt.extend_from_slice(&le_u32((1u32 << channels) - 1));
+    fmt.extend_from_slice(&le_u16(plain.format_tag));
+    fmt.extend_from_slice(&GUID_TAIL);
+
+    let samples = sine(channels, rate, frames).samples(0, frames as usize);
+    riff(&[chunk(b"fmt ", &fmt),
+           chunk(b"data", &format.encode_samples(&samples))])
+}
+
+// `blocks` blocks of ima adpcm, each starting from silence and rising
+fn ima_adpcm(channels: u16, rate: u32, blocks: u64) -> (Vec<u8>, u64) {
+
+    let block_align = ADPCM_BLOCK_SIZE * channels;
+    let samples_per_block = (ADPCM_BLOCK_SIZE - 4) * 2 + 1;
+
+    let mut fmt = Vec::new();
+    fmt.extend_from_slice(&le_u16(WAVE_FORMAT_IMA_ADPCM));
+    fmt.extend_from_slice(&le_u16(channels));
+    fmt.extend_from_slice(&le_u32(rate));
+    fmt.extend_from_slice(&le_u32(rate * block_align as u32 /
+        samples_per_block as u32));
+    fmt.extend_from_slice(&le_u16(block_align));
+    fmt.extend_from_slice(&le_u16(4));
+    fmt.extend_from_slice(&le_u16(2));
+    fmt.extend_from_slice(&le_u16(samples_per_block));
+
+    // a header for each channel, then nibbles of small steps
+    let mut data = Vec::new();
+    for _ in 0..blocks {
+        for _ in 0..channels {
+            data.extend_from_slice(&[0, 0, 0, 0]);
+        }
+        for n in 0..(block_align - 4 * channels) {
+            data.push(match n % 2 { 0 => 0x12, _ => 0x9a });
+        }
+    }
+
+    let frames = blocks * samples_per_block as u64;
+    (riff(&[chunk(b"fmt ", &fmt), chunk(b"fact", &le_u32(frames as u32)),
+            chunk(b"data", &data)]),
+     frames)
+}
+
+// a file of every format the reader takes
+pub fn every_format(channels: u16, rate: u32, frames: u64) -> Vec<Fixture> {
+
+    let formats = [("u8", SampleFormat::U8),
+                   ("s16", SampleFormat::S16_LE),
+                   ("s24", SampleFormat::S24_3LE),
+                   ("s32", SampleFormat::S32_LE),
+                   ("f32", SampleFormat::FLOAT_LE),
+                   ("f64", SampleFormat::FLOAT64_LE)];
+
+    let mut fixtures = formats
+        .iter()
+        .map(|&(name, format)| Fixture {
+            name: name,
+            bytes: wave(format, channels, rate, frames),
+            frames: Some(frames)
+        })
+        .collect::<Vec<_>>();
+
+    fixtures.push(Fixture {
+        name: "s24-extensible",
+        bytes: extensible(channels, rate, frames),
+        frames: Some(frames)
+    });
+
+    let (bytes, adpcm_frames) = ima_adpcm(channels, rate, 2);
+    fixtures.push(Fixture {
+        name: "ima-adpcm",
+        bytes: bytes,
+        frames: Some(adpcm_frames)
+    });
+
+    fixtures
+}
+
+// files that are broken in the ways files out there are, 16 bit stereo
+// at 8000 Hz of 4 frames when they can be read at all
+pub fn malformed() -> Vec<Fixture> {
+
+    let good = wave(SampleFormat::S16_LE, 2, 8000, 4);
+    let fmt = good[12..36].to_vec();
+    let data = good[36..].to_vec();
+
+    let fixture = |name, bytes, frames| Fixture {
+        name: name,
+        bytes: bytes,
+        frames: frames
+    };
+
+    // sizes a writer never came back to fill in
+    let mut unfixed = good.clone();
+    unfixed[4..8].copy_from_slice(&[0, 0, 0, 0]);
+    unfixed[40..44].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+
+    let mut unpadded = riff(&[fmt.clone(), b"LIST\x03\x00\x00\x00abc".to_vec(),
+                              data.clone()]);
+    let size = unpadded.len() as u32 - 8;
+    unpadded[4..8].copy_from_slice(&le_u32(size));
+
+    let mut no_channels = good.clone();
+    no_channels[22..24].copy_from_slice(&[0, 0]);
+
+    // mpeg layer 3
+    let mut unsupported = good.clone();
+    unsupported[20..22].copy_from_slice(&le_u16(0x55));
+
+    let mut misaligned = good.clone();
+    misaligned[32..34].copy_from_slice(&le_u16(3));
+
+    // ima adpcm blocks no larger than their headers, with no samples per
+    // block to go by
+    let (mut headers_only, _) = ima_adpcm(1, 8000, 1);
+    headers_only[32..34].copy_from_slice(&le_u16(4));
+    headers_only[38..40].copy_from_slice(&le_u16(0));
+
+    let mut rifx = good.clone();
+    rifx[0..4].copy_from_slice(b"RIFX");
+
+    // a data chunk that says it is longer than what is left, and one
+    // with half a frame at its end
+    let mut partial = good.clone();
+    partial.extend_from_slice(&[1, 1]);
+    partial[40..44].copy_from_slice(&le_u32(18));
+
+    vec![
+        fixture("truncated-riff", good[..10].to_vec(), None),
+        fixture("truncated-fmt", good[..24].to_vec(), None),
+        fixture("no-data", riff(&[fmt.clone()]), None),
+        fixture("no-fmt", riff(&[data.clone()]), None),
+        fixture("short-data", good[..good.len() - 8].to_vec(), Some(2)),
+        fixture("unfixed-sizes", unfixed, Some(4)),
+        fixture("odd-chunk", riff(&[fmt.clone(), chunk(b"LIST", b"abc"),
+            data.clone()]), Some(4)),
+        fixture("odd-chunk-unpadded", unpadded, None),
+        fixture("partial-frame", partial, Some(4)),
+        fixture("zero-channels", no_channels, None),
+        fixture("unsupported-format", unsupported, None),
+        fixture("misaligned-blocks", misaligned, None),
+        fixture("ima-adpcm-headers-only", headers_only, None),
+        fixture("rifx", rifx, None)
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::Cursor;
+    use std::path::Path;
+
+    use super::*;
+    use analyze::analyze;
+    use sample::SampleFormat;
+    use silence::Detection;
+    use wav::WaveReader;
+
+    #[test]
+    fn temp_dir_test() {
+
+        let path = {
+            let dir = TempDir::new("fixtures_temp_dir_test");
+            let other = TempDir::new("fixtures_temp_dir_test");
+            assert!(dir.path("a") != other.path("a"));
+
+            let path = dir.write("a.wav", b"RIFF");
+            assert!(Path::new(&path).exists());
+            path
+        };
+
+        assert!(!Path::new(&path).exists());
+    }
+
+    #[test]
+    fn every_format_test() {
+
+        let dir = TempDir::new("fixtures_every_format_test");
+        let expected = sine(2, 8000, 100).samples(0, 100);
+
+        for fixture in every_format(2, 8000, 100) {
+
+            let path = dir.write(&format!("{}.wav", fixture.name),
+                &fixture.bytes);
+            let mut reader = WaveReader::open(&path).unwrap();
+            let frames = fixture.frames.unwrap();
+
+            assert_eq!(frames, reader.header().frames());
+
+            let samples = reader.read_frames(frames as usize).unwrap();
+            assert_eq!(frames as usize * 2, samples.len());
+
+            // the sine comes back as near as the bits allow. adpcm
+            // holds a signal of its own.
+            let step = match fixture.name {
+                "u8" => Some(1.0 / 64.0),
+                "ima-adpcm" => None,
+                _ => Some(1.0 / 32768.0)
+            };
+            if let Some(step) = step {
+                for (sample, expected) in samples.iter().zip(expected.iter()) {
+                    assert!((sample - expected).abs() <= step, "{}",
+                        fixture.name);
+                }
+            }
+
+            // and through a file worker
+            assert_eq!(frames, analyze(&path, Detection::digital())
+                .unwrap()
+                .frames);
+        }
+    }
+
+    #[test]
+    fn malformed_test() {
+
+        for fixture in malformed() {
+
+            let mut reader = match (WaveReader::new(Cursor::new(fixture.bytes)),
+                fixture.frames) {
+                (Ok(reader), Some(_)) => reader,
+                (Err(_), None) => continue,
+                (Ok(_), None) => panic!("{} is read", fixture.name),
+                (Err(err), _) => panic!("{} fails: {}", fixture.name, err)
+            };
+
+            let frames = fixture.frames.unwrap();
+            let mut read = 0;
+            loop {
+                let samples = reader.read_frames(3).unwrap();
+                if samples.is_empty() {
+                    break;
+                }
+                read += samples.len() as u64 / 2;
+            }
+
+            assert_eq!((fixture.name, frames), (fixture.name, read));
+        }
+
+        // and the good file they are made from is read whole
+        let good = wave(SampleFormat::S16_LE, 2, 8000, 4);
+        assert_eq!(4, WaveReader::new(Cursor::new(good))
+            .unwrap()
+            .header()
+            .frames());
+    }
+}
@@ -0,0 +1,529 @@
+use std::cmp::min;
+use std::f64::consts::PI;
+use std::io::{ Read, Seek, SeekFrom };
+use std::time::Duration;
+
+use io::*;
+use sample::SampleFormat;
+use wav::{ WaveFormat, header_bytes };
+
+pub const DEFAULT_FREQUENCY : f64 = 1000.0;
+pub const DEFAULT_SWEEP     : (f64, f64) = (20.0, 20000.0);
+pub const DEFAULT_RATE      : u32 = 48000;
+pub const DEFAULT_CHANNELS  : u16 = 2;
+pub const DEFAULT_SECONDS   : u64 = 5;
+
+// half of full scale, -6 dBFS
+pub const DEFAULT_LEVEL : f64 = 0.5;
+
+const NOISE_SEED : u64 = 0x5eed;
+
+// pink noise is the sum of rows of white noise, each held for twice as
+// many frames as the one before
+const PINK_ROWS : u32 = 16;
+
+// frames made for a read at most
+const FRAMES_PER_READ : u64 = 4096;
+
+const INVALID_SIGNAL : &'static str = "invalid signal";
+
+// what a generator makes. frequencies are in hz, impulses come so many
+// a second, and a sweep rises or falls exponentially over the whole
+// length of the signal. pink noise, a sum of white noises, stays well
+// under the level.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Signal {
+    Sine(f64),
+    Square(f64),
+    Sawtooth(f64),
+    WhiteNoise,
+    PinkNoise,
+    Sweep(f64, f64),
+    Impulse(f64)
+}
+
+fn frequency_of(field: Option<&&str>, default: f64) -> IOResult<f64> {
+    match field.map(|field| field.parse::<f64>()) {
+        None => Ok(default),
+        Some(Ok(hz)) if hz.is_finite() && hz > 0.0 => Ok(hz),
+        _ => Err(IOError::new(IOErrorKind::InvalidInput, INVALID_SIGNAL))
+    }
+}
+
+impl Signal {
+
+    // name[:hz] for sine, square and saw, white, pink, sweep[:from:to]
+    // and impulse[:per second]
+    pub fn parse(st: &str) -> IOResult<Signal> {
+
+        let fields = st.split(':')
+            .map(|field| field.trim())
+            .collect::<Vec<_>>();
+
+        let name = fields[0].to_lowercase();
+        let most = match name.as_str() {
+            "sweep" => 3,
+            "white" | "pink" => 1,
+            _ => 2
+        };
+
+        if fields.len() > most {
+            return Err(IOError::new(IOErrorKind::InvalidInput,
+                INVALID_SIGNAL));
+        }
+
+        let first = fields.get(1);
+
+        match name.as_str() {
+            "sine" => frequency_of(first, DEFAULT_FREQUENCY)
+                .map(Signal::Sine),
+            "square" => frequency_of(first, DEFAULT_FREQUENCY)
+                .map(Signal::Square),
+            "saw" | "sawtooth" => frequency_of(first, DEFAULT_FREQUENCY)
+                .map(Signal::Sawtooth),
+            "white" => Ok(Signal::WhiteNoise),
+            "pink" => Ok(Signal::PinkNoise),
+            "sweep" => {
+                let from = try!(frequency_of(first, DEFAULT_SWEEP.0));
+                let to = try!(frequency_of(fields.get(2), DEFAULT_SWEEP.1));
+                Ok(Signal::Sweep(from, to))
+            },
+            "impulse" => frequency_of(first, 1.0)
+                .map(Signal::Impulse),
+            _ => Err(IOError::new(IOErrorKind::InvalidInput,
+                INVALID_SIGNAL))
+        }
+    }
+
+    // the way it is parsed
+    pub fn name(&self) -> String {
+        match *self {
+            Signal::Sine(hz) => format!("sine:{}", hz),
+            Signal::Square(hz) => format!("square:{}", hz),
+            Signal::Sawtooth(hz) => format!("saw:{}", hz),
+            Signal::WhiteNoise => "white".to_string(),
+            Signal::PinkNoise => "pink".to_string(),
+            Signal::Sweep(from, to) => format!("sweep:{}:{}", from, to),
+            Signal::Impulse(rate) => format!("impulse:{}", rate)
+        }
+    }
+}
+
+// uniform in [-1.0, 1.0), the same for the same key. splitmix64.
+fn white(key: u64) -> f64 {
+    let mut z = key.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+}
+
+// a signal of a given length, format, rate and channels. any frame can
+// be made on its own, so that the wave it makes can be read from
+// anywhere, and the noise of a seed is always the same.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Generator {
+    signal   : Signal,
+    format   : SampleFormat,
+    rate     : u32,
+    channels : u16,
+    duration : Duration,
+    level    : f64,
+    seed     : u64
+}
+
+impl Generator {
+
+    pub fn new(signal: Signal) -> Self {
+        Generator {
+            signal: signal,
+            format: SampleFormat::S16_LE,
+            rate: DEFAULT_RATE,
+            channels: DEFAULT_CHANNELS,
+            duration: Duration::from_secs(DEFAULT_SECONDS),
+            level: DEFAULT_LEVEL,
+            seed: NOISE_SEED
+        }
+    }
+
+    pub fn format(mut self, format: SampleFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn rate(mut self, rate: u32) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    pub fn channels(mut self, channels: u16) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    // the peak, linear
+    pub fn level(mut self, level: f64) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn signal(&self) -> Signal {
+        self.signal
+    }
+
+    pub fn sample_format(&self) -> SampleFormat {
+        self.format
+    }
+
+    pub fn name(&self) -> String {
+        self.signal.name()
+    }
+
+    pub fn wave_format(&self) -> IOResult<WaveFormat> {
+        match WaveFormat::from_sample_format(self.format, self.channels,
+            self.rate) {
+            Some(_) if self.channels == 0 || self.rate == 0 =>
+                Err(IOError::new(IOErrorKind::InvalidInput,
+                    "a signal needs a rate and a channel")),
+            Some(format) => Ok(format),
+            _ => Err(IOError::new(IOErrorKind::InvalidInput,
+                "the format can't be written to a wave file"))
+        }
+    }
+
+    pub fn frames(&self) -> u64 {
+        let rate = self.rate as u64;
+        self.duration.as_secs() * rate +
+            self.duration.subsec_nanos() as u64 * rate / 1_000_000_000
+    }
+
+    pub fn length(&self) -> Duration {
+        self.duration
+    }
+
+    // where a tone of `hz` is in its cycle, in [0.0, 1.0)
+    fn phase(&self, hz: f64, frame: u64) -> f64 {
+        (frame as f64 * hz / self.rate as f64).fract()
+    }
+
+    fn noise(&self, channel: u16, row: u32, index: u64) -> f64 {
+        white(self.seed ^ (channel as u64) << 56 ^ (row as u64) << 48 ^
+            index)
+    }
+
+    // a sample of a channel, in [-level, level]
+    pub fn sample(&self, frame: u64, channel: u16) -> f64 {
+
+        let value = match self.signal {
+
+            Signal::Sine(hz) => (2.0 * PI * self.phase(hz, frame)).sin(),
+
+            Signal::Square(hz) => match self.phase(hz, frame) < 0.5 {
+                true => 1.0,
+                _ => -1.0
+            },
+
+            Signal::Sawtooth(hz) => 2.0 * self.phase(hz, frame) - 1.0,
+
+            Signal::WhiteNoise => self.noise(channel, 0, frame),
+
+            // voss-mccartney, a row taking a new value every 2^row
+            // frames, with white noise on top
+            Signal::PinkNoise => (0..PINK_ROWS)
+                .fold(self.noise(channel, PINK_ROWS, frame), |sum, row|
+                    sum + self.noise(channel, row, frame >> row)) /
+                (PINK_ROWS + 1) as f64,
+
+            // the phase of f0 * (f1 / f0)^(t / T), integrated over t
+            Signal::Sweep(from, to) => {
+                let length = self.frames().max(1) as f64 /
+                    self.rate as f64;
+                let time = frame as f64 / self.rate as f64;
+                let ratio = (to / from).ln();
+                let cycles = match ratio.abs() < 1e-9 {
+                    true => from * time,
+                    _ => from * length / ratio *
+                        ((time / length * ratio).exp() - 1.0)
+                };
+                (2.0 * PI * cycles.fract()).sin()
+            },
+
+            Signal::Impulse(per_second) => {
+                let period = (self.rate as f64 / per_second)
+                    .round()
+                    .max(1.0) as u64;
+                match frame % period {
+                    0 => 1.0,
+                    _ => 0.0
+                }
+            }
+        };
+
+        value * self.level
+    }
+
+    // interleaved samples of up to `frames` frames from `from` on
+    pub fn samples(&self, from: u64, frames: usize) -> Vec<f64> {
+
+        let end = min(from + frames as u64, self.frames());
+        let mut samples = Vec::with_capacity(
+            end.saturating_sub(from) as usize * self.channels as usize);
+
+        for frame in from..end {
+            for channel in 0..self.channels {
+                samples.push(self.sample(frame, channel));
+            }
+        }
+
+        samples
+    }
+
+    // a wave file of the signal, made as it is read
+    pub fn wave(&self) -> IOResult<Wave> {
+
+        let format = try!(self.wave_format());
+        let data_size = self.frames() * format.block_align as u64;
+
+        if data_size > u32::max_value() as u64 - 64 {
+            return Err(IOError::new(IOErrorKind::InvalidInput,
+                "the signal is too long for a wave file"));
+        }
+
+        let header = header_bytes(&format, data_size as u32);
+        let size = header.len() as u64 + data_size;
+
+        Ok(Wave {
+            generator: self.clone(),
+            frame_size: format.block_align as u64,
+            header: header,
+            size: size,
+            position: 0
+        })
+    }
+
+    // the whole wave file in memory
+    pub fn to_bytes(&self) -> IOResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        try!(try!(self.wave()).read_to_end(&mut bytes));
+        Ok(bytes)
+    }
+}
+
+// the bytes of a wave file of a generated signal
+pub struct Wave {
+    generator  : Generator,
+    frame_size : u64,
+    header     : Vec<u8>,
+    size       : u64,
+    position   : u64
+}
+
+impl Read for Wave {
+
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+
+        if self.position >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let header_size = self.header.len() as u64;
+
+        if self.position < header_size {
+            let start = self.position as usize;
+            let n = min(self.header.len() - start, buf.len());
+            buf[..n].copy_from_slice(&self.header[start..start + n]);
+            self.position += n as u64;
+            return Ok(n);
+        }
+
+        // the frames the read falls in, a partial one at either end
+        let offset = self.position - header_size;
+        let frame = offset / self.frame_size;
+        let skip = (offset % self.frame_size) as usize;
+        let frames = min((skip as u64 + buf.len() as u64 +
+            self.frame_size - 1) / self.frame_size, FRAMES_PER_READ);
+
+        let bytes = self.generator.format.encode_samples(
+            &self.generator.samples(frame, frames as usize));
+        let n = min(bytes.len() - skip, buf.len());
+
+        buf[..n].copy_from_slice(&bytes[skip..skip + n]);
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for Wave {
+
+    fn seek(&mut self, pos: SeekFrom) -> IOResult<u64> {
+
+        let position = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(offset) => self.size as i64 + offset,
+            SeekFrom::Current(offset) => self.position as i64 + offset
+        };
+
+        if position < 0 {
+            return Err(IOError::new(IOErrorKind::InvalidInput,
+                "seek to before the start"));
+        }
+
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::{ Cursor, Read, Seek, SeekFrom };
+    use std::time::Duration;
+
+    use super::*;
+    use sample::SampleFormat;
+    use wav::{ SeekTarget, WaveReader };
+
+    #[test]
+    fn parse_test() {
+
+        assert_eq!(Signal::Sine(440.0), Signal::parse("sine:440").unwrap());
+        assert_eq!(Signal::Square(DEFAULT_FREQUENCY),
+            Signal::parse("square").unwrap());
+        assert_eq!(Signal::Sweep(100.0, 10.0),
+            Signal::parse("sweep:100:10").unwrap());
+        assert_eq!(Signal::Impulse(1.0), Signal::parse("impulse").unwrap());
+        assert_eq!(Signal::PinkNoise, Signal::parse("pink").unwrap());
+
+        assert!(Signal::parse("sine:0").is_err());
+        assert!(Signal::parse("saw:loud").is_err());
+        assert!(Signal::parse("white:1").is_err());
+        assert!(Signal::parse("triangle").is_err());
+
+        for spec in ["saw:220", "sweep:20:20000", "white"].iter() {
+            assert_eq!(*spec, Signal::parse(spec).unwrap().name());
+        }
+    }
+
+    #[test]
+    fn signal_test() {
+
+        // 8 frames a cycle
+        let tone = |signal| Generator::new(signal)
+            .rate(8000)
+            .level(1.0);
+
+        let sine = tone(Signal::Sine(1000.0));
+        assert!((sine.sample(2, 0) - 1.0).abs() < 1e-12);
+        assert!((sine.sample(6, 1) + 1.0).abs() < 1e-12);
+
+        let square = tone(Signal::Square(1000.0));
+        assert_eq!((1.0, -1.0), (square.sample(3, 0), square.sample(4, 0)));
+
+        let saw = tone(Signal::Sawtooth(1000.0));
+        assert_eq!((-1.0, 0.5), (saw.sample(8, 0), saw.sample(6, 0)));
+
+        let impulse = tone(Signal::Impulse(2000.0));
+        assert_eq!(vec![1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0],
+            impulse.samples(0, 5));
+
+        // a sweep starts on its first frequency and ends near its last,
+        // crossing zero about twice as often
+        let sweep = tone(Signal::Sweep(1000.0, 2000.0))
+            .duration(Duration::from_secs(1));
+        assert!((sweep.sample(2, 0) - 1.0).abs() < 1e-3);
+
+        let crossings = |from| sweep
+            .samples(from, 800)
+            .chunks(2)
+            .map(|frame| frame[0])
+            .collect::<Vec<_>>()
+            .windows(2)
+            .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+            .count();
+        assert!(crossings(7200) > crossings(0) * 17 / 10);
+    }
+
+    #[test]
+    fn noise_test() {
+
+        let white = Generator::new(Signal::WhiteNoise)
+            .rate(8000)
+            .channels(1)
+            .level(1.0)
+            .duration(Duration::from_secs(1));
+        let samples = white.samples(0, 8000);
+
+        // uniform, with no dc
+        let mean = samples.iter().fold(0.0, |sum, s| sum + s) / 8000.0;
+        let square = samples.iter().fold(0.0, |sum, s| sum + s * s) / 8000.0;
+        assert!(mean.abs() < 0.03);
+        assert!((square - 1.0 / 3.0).abs() < 0.03);
+        assert!(samples.iter().all(|s| *s >= -1.0 && *s < 1.0));
+
+        // the same seed makes the same noise, another makes another
+        assert_eq!(samples, white.samples(0, 8000));
+        assert!(samples != white.clone().seed(1).samples(0, 8000));
+
+        // pink noise changes less from frame to frame than white noise
+        let pink = Generator::new(Signal::PinkNoise)
+            .rate(8000)
+            .channels(1)
+            .level(1.0)
+            .duration(Duration::from_secs(1));
+        let difference = |samples: &[f64]| samples
+            .windows(2)
+            .fold(0.0, |sum, pair| sum + (pair[1] - pair[0]).abs()) /
+            samples.iter().fold(0.0, |sum, s| sum + s.abs());
+        assert!(difference(&pink.samples(0, 8000)) * 2.0 <
+            difference(&samples));
+    }
+
+    #[test]
+    fn wave_test() {
+
+        let generator = Generator::new(Signal::Square(1000.0))
+            .format(SampleFormat::S16_LE)
+            .rate(8000)
+            .channels(2)
+            .duration(Duration::from_millis(10));
+
+        let bytes = generator.to_bytes().unwrap();
+        assert_eq!(44 + 80 * 4, bytes.len());
+
+        // read back as any wave file
+        let mut reader = WaveReader::new(Cursor::new(bytes.clone()))
+            .unwrap();
+        assert_eq!(80, reader.header().frames());
+        assert_eq!(generator.samples(0, 80), reader.read_frames(80).unwrap());
+
+        // and read straight from the generator, from anywhere
+        let mut wave = generator.wave().unwrap();
+        let mut part = [0u8; 7];
+        wave.seek(SeekFrom::Start(43)).unwrap();
+        wave.read_exact(&mut part).unwrap();
+        assert_eq!(&bytes[43..50], &part);
+
+        let mut reader = WaveReader::new(generator.wave().unwrap()).unwrap();
+        reader.seek(SeekTarget::Frame(78)).unwrap();
+        assert_eq!(generator.samples(78, 2), reader.read_frames(10).unwrap());
+
+        assert!(generator.clone()
+            .format(SampleFormat::S16_BE)
+            .wave()
+            .is_err());
+        assert!(generator.clone()
+            .channels(0)
+            .wave()
+            .is_err());
+    }
+}
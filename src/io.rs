@@ -227,6 +227,14 @@ impl Timer {
         self.start.is_some()
     }
 
+    // counts the timeout from now again
+    pub fn restart(&mut self) {
+        match self.start {
+            Some(_) => self.start = Some(Instant::now()),
+            _ => panic!("has not started yet.")
+        }
+    }
+
     pub fn is_timeout(self) -> bool {
         match self.start {
             Some(instant) => instant.elapsed() > self.timeout,
@@ -250,23 +258,23 @@ pub trait Loop<SendMsg, RecvMsg, Ret> {
 mod test {
 
     use super::*;
+    use fixtures::{ TempDir, sample_file };
     use std::fs::File;
     use std::io::Read;
-    use std::process::Command;
     use std::str::from_utf8;
     use std::thread;
     use std::time::Duration;
 
     const RIFF : &'static str = "RIFF";
-    const FILEPATH : &'static str = "/usr/share/sounds/k3b_error1.wav";
     const BUFSIZE : usize = 4;
     const BUFALIGN : usize = 1;
     const OUTPUT_FILE : &'static str = "out";
 
     #[test]
     fn buffer_test() {
-      
-        let mut f = File::open(FILEPATH).unwrap();
+
+        let dir = TempDir::new("io_buffer_test");
+        let mut f = File::open(sample_file(&dir)).unwrap();
         let mut rbuf = ReadBuffer::<File>::new(BUFSIZE);
 
         rbuf.read(&mut f).unwrap();
@@ -284,17 +292,14 @@ mod test {
             BUFALIGN);
         assert_eq!(4usize, wbuf.size());
 
-        let mut out = File::create(OUTPUT_FILE).unwrap();
+        let output = dir.path(OUTPUT_FILE);
+        let mut out = File::create(&output).unwrap();
         wbuf.write(&mut out).unwrap();
 
-        let mut ifstream = File::open(OUTPUT_FILE).unwrap();
+        let mut ifstream = File::open(&output).unwrap();
         let mut st = String::new();
         ifstream.read_to_string(&mut st).unwrap(); 
         assert_eq!(RIFF, st.as_str());
-
-        Command::new("rm").arg(OUTPUT_FILE)
-            .output()
-            .unwrap();
     }
 
     #[test]
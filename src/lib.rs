@@ -3,5 +3,30 @@
 
 #[macro_use]
 mod io;
+mod analyze;
+mod chain;
+pub mod cli;
+mod convert;
+mod device;
+mod eq;
+mod fade;
 mod fio;
+#[cfg(test)]
+mod fixtures;
+mod generator;
+mod loudness;
+mod meter;
+mod mix;
+mod mixer;
+mod player;
+mod playlist;
+mod queue;
+mod resample;
+mod sample;
+mod silence;
+mod sink;
 mod sp_io;
+mod spectrum;
+mod volume;
+mod wav;
+mod waveform;
@@ -0,0 +1,753 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::f64;
+use std::f64::consts::PI;
+use std::io::{ Read, Seek };
+
+use chain::Processor;
+use eq::{ Biquad, Coefficients };
+use io::*;
+use mix::*;
+use player::FRAMES_PER_WRITE;
+use volume::{ Volume, db_to_linear };
+use wav::{ SeekTarget, WaveReader };
+
+// the level programmes are normalised to by ebu r128, in LUFS
+pub const DEFAULT_TARGET  : f64 = -23.0;
+// the highest true peak a normalised programme may reach, in dBTP
+pub const DEFAULT_CEILING : f64 = -1.0;
+// quiet tracks are turned up no more than this, in dB
+pub const MAX_GAIN        : f64 = 20.0;
+
+const ABSOLUTE_GATE : f64 = -70.0;
+const RELATIVE_GATE : f64 = -10.0;
+const RANGE_GATE    : f64 = -20.0;
+
+// blocks are measured in steps of 100 ms, a momentary block is 400 ms
+// and a short-term one 3 s
+const STEPS_PER_SECOND : u32 = 10;
+const MOMENTARY_STEPS  : usize = 4;
+const SHORT_TERM_STEPS : usize = 30;
+
+// taps of each phase of the true peak interpolator
+const PEAK_TAPS : usize = 12;
+
+// the limiter looks this far ahead and recovers over the release time
+const LOOKAHEAD_MILLIS : u32 = 5;
+const RELEASE_MILLIS   : u32 = 100;
+
+// the two stages of the k-weighting filter of bs.1770 at `rate`: a
+// shelf for the head and a high pass, derived from their analogue
+// prototypes so that any rate gets the response of the 48 kHz filter
+pub fn k_weighting(rate: u32) -> (Coefficients, Coefficients) {
+
+    let (frequency, gain, q) = (1681.974450955533, 3.999843853973347,
+        0.7071752369554196);
+    let k = (PI * frequency / rate as f64).tan();
+    let vh = 10.0f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+
+    let shelf = Coefficients {
+        b0: (vh + vb * k / q + k * k) / a0,
+        b1: 2.0 * (k * k - vh) / a0,
+        b2: (vh - vb * k / q + k * k) / a0,
+        a1: 2.0 * (k * k - 1.0) / a0,
+        a2: (1.0 - k / q + k * k) / a0
+    };
+
+    let (frequency, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * frequency / rate as f64).tan();
+    let a0 = 1.0 + k / q + k * k;
+
+    let pass = Coefficients {
+        b0: 1.0,
+        b1: -2.0,
+        b2: 1.0,
+        a1: 2.0 * (k * k - 1.0) / a0,
+        a2: (1.0 - k / q + k * k) / a0
+    };
+
+    (shelf, pass)
+}
+
+// surround channels count for more, the lfe not at all
+fn channel_weight(speaker: u32) -> f64 {
+    match speaker {
+        SPEAKER_LOW_FREQUENCY => 0.0,
+        SPEAKER_BACK_LEFT | SPEAKER_BACK_RIGHT | SPEAKER_BACK_CENTER |
+        SPEAKER_SIDE_LEFT | SPEAKER_SIDE_RIGHT => 1.41,
+        _ => 1.0
+    }
+}
+
+// the loudness of a mean square, weighted and summed over the channels
+fn lufs(energy: f64) -> f64 {
+    match energy > 0.0 {
+        true => -0.691 + 10.0 * energy.log10(),
+        _ => f64::NEG_INFINITY
+    }
+}
+
+fn to_db(amplitude: f64) -> f64 {
+    match amplitude > 0.0 {
+        true => 20.0 * amplitude.log10(),
+        _ => f64::NEG_INFINITY
+    }
+}
+
+// the mean of the energies above the absolute gate and `relative` below
+// the mean of those
+fn gated(energies: &[f64], relative: f64) -> Vec<f64> {
+
+    let loud = energies.iter()
+        .cloned()
+        .filter(|energy| lufs(*energy) > ABSOLUTE_GATE)
+        .collect::<Vec<_>>();
+
+    if loud.is_empty() {
+        return loud;
+    }
+
+    let gate = lufs(mean(&loud)) + relative;
+
+    loud.into_iter()
+        .filter(|energy| lufs(*energy) > gate)
+        .collect()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().fold(0.0, |sum, value| sum + value) / values.len() as f64
+}
+
+// the peak between samples, found by oversampling each channel through a
+// windowed sinc. the peak of a frame comes out `latency()` frames later.
+pub struct TruePeak {
+    phases  : Vec<Vec<f64>>,
+    history : Vec<Vec<f64>>,
+    next    : usize
+}
+
+impl TruePeak {
+
+    pub fn new(channels: usize, rate: u32) -> Self {
+
+        // anything at 192 kHz or more is taken as it is
+        let factor = match rate {
+            rate if rate < 96000 => 4,
+            rate if rate < 192000 => 2,
+            _ => 1
+        };
+
+        let length = factor * PEAK_TAPS;
+        let centre = (length / 2) as f64;
+
+        let prototype = (0..length)
+            .map(|n| {
+                let x = (n as f64 - centre) / factor as f64;
+                let sinc = match x == 0.0 {
+                    true => 1.0,
+                    _ => (PI * x).sin() / (PI * x)
+                };
+                let phase = 2.0 * PI * n as f64 / length as f64;
+                sinc * (0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos())
+            })
+            .collect::<Vec<_>>();
+
+        // each phase reversed, to run over the history oldest first
+        let phases = (0..factor)
+            .map(|phase| (0..PEAK_TAPS)
+                .rev()
+                .map(|tap| prototype[tap * factor + phase])
+                .collect())
+            .collect();
+
+        TruePeak {
+            phases: phases,
+            history: vec![vec![0.0; 2 * PEAK_TAPS]; channels],
+            next: 0
+        }
+    }
+
+    pub fn latency() -> usize {
+        PEAK_TAPS / 2
+    }
+
+    pub fn reset(&mut self) {
+        for history in self.history.iter_mut() {
+            for sample in history.iter_mut() {
+                *sample = 0.0;
+            }
+        }
+    }
+
+    // the highest absolute value of any channel between the frame
+    // `latency()` frames back and the one after it
+    pub fn push(&mut self, frame: &[f64]) -> f64 {
+
+        let mut peak = 0.0f64;
+        let next = self.next;
+
+        for (history, sample) in self.history.iter_mut().zip(frame.iter()) {
+
+            // written twice, so that the last taps are always in a row
+            history[next] = *sample;
+            history[next + PEAK_TAPS] = *sample;
+            let window = &history[next + 1..next + 1 + PEAK_TAPS];
+
+            for phase in self.phases.iter() {
+                let value = phase.iter()
+                    .zip(window.iter())
+                    .fold(0.0, |sum, (tap, sample)| sum + tap * sample);
+                peak = peak.max(value.abs());
+            }
+        }
+
+        self.next = (next + 1) % PEAK_TAPS;
+        peak
+    }
+}
+
+// what a scan of a whole programme finds. levels are in LUFS, the range
+// in LU and the peaks in dBFS and dBTP.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Measurement {
+    pub integrated  : f64,
+    pub range       : f64,
+    pub true_peak   : f64,
+    pub sample_peak : f64
+}
+
+// loudness after itu-r bs.1770-4 and ebu tech 3341 and 3342, fed with
+// interleaved frames as they come
+pub struct Meter {
+    channels    : usize,
+    weights     : Vec<f64>,
+    shelf       : Biquad<f64>,
+    pass        : Biquad<f64>,
+    step        : usize,
+    position    : usize,
+    energy      : f64,
+    steps       : VecDeque<f64>,
+    blocks      : Vec<f64>,
+    short_terms : Vec<f64>,
+    detector    : TruePeak,
+    true_peak   : f64,
+    sample_peak : f64
+}
+
+impl Meter {
+
+    // `mask` is the channel mask of the file, 0 for the usual layout
+    pub fn new(channels: u16, rate: u32, mask: u32) -> Self {
+
+        let (shelf, pass) = k_weighting(rate);
+
+        Meter {
+            channels: channels as usize,
+            weights: speakers(mask, channels)
+                .into_iter()
+                .map(channel_weight)
+                .collect(),
+            shelf: Biquad::new(shelf, channels as usize),
+            pass: Biquad::new(pass, channels as usize),
+            step: (rate / STEPS_PER_SECOND).max(1) as usize,
+            position: 0,
+            energy: 0.0,
+            steps: VecDeque::new(),
+            blocks: Vec::new(),
+            short_terms: Vec::new(),
+            detector: TruePeak::new(channels as usize, rate),
+            true_peak: 0.0,
+            sample_peak: 0.0
+        }
+    }
+
+    // a trailing partial frame is ignored
+    pub fn push(&mut self, samples: &[f64]) {
+
+        for frame in samples.chunks(self.channels.max(1)) {
+
+            if frame.len() < self.channels {
+                break;
+            }
+
+            self.true_peak = self.true_peak.max(self.detector.push(frame));
+
+            for (channel, sample) in frame.iter().enumerate() {
+                self.sample_peak = self.sample_peak.max(sample.abs());
+                let weighted = self.shelf.run(channel, *sample);
+                let weighted = self.pass.run(channel, weighted);
+                self.energy += self.weights[channel] * weighted * weighted;
+            }
+
+            self.position += 1;
+
+            if self.position == self.step {
+                self.end_step();
+            }
+        }
+    }
+
+    fn end_step(&mut self) {
+
+        self.steps.push_back(self.energy);
+        self.energy = 0.0;
+        self.position = 0;
+
+        if self.steps.len() > SHORT_TERM_STEPS {
+            self.steps.pop_front();
+        }
+
+        if let Some(block) = self.block(MOMENTARY_STEPS) {
+            self.blocks.push(block);
+        }
+
+        if let Some(block) = self.block(SHORT_TERM_STEPS) {
+            self.short_terms.push(block);
+        }
+    }
+
+    // the mean square of the last `steps` steps
+    fn block(&self, steps: usize) -> Option<f64> {
+
+        if self.steps.len() < steps {
+            return None;
+        }
+
+        let sum = self.steps
+            .iter()
+            .rev()
+            .take(steps)
+            .fold(0.0, |sum, energy| sum + energy);
+
+        Some(sum / (steps * self.step) as f64)
+    }
+
+    // the last 400 ms
+    pub fn momentary(&self) -> f64 {
+        self.block(MOMENTARY_STEPS)
+            .map(lufs)
+            .unwrap_or(f64::NEG_INFINITY)
+    }
+
+    // the last 3 s
+    pub fn short_term(&self) -> f64 {
+        self.block(SHORT_TERM_STEPS)
+            .map(lufs)
+            .unwrap_or(f64::NEG_INFINITY)
+    }
+
+    pub fn integrated(&self) -> f64 {
+        match gated(&self.blocks, RELATIVE_GATE) {
+            ref blocks if blocks.is_empty() => f64::NEG_INFINITY,
+            blocks => lufs(mean(&blocks))
+        }
+    }
+
+    // the spread of the short-term loudness, from its 10th to its 95th
+    // percentile
+    pub fn range(&self) -> f64 {
+
+        let mut levels = gated(&self.short_terms, RANGE_GATE)
+            .into_iter()
+            .map(lufs)
+            .filter(|level| level.is_finite())
+            .collect::<Vec<_>>();
+
+        if levels.is_empty() {
+            return 0.0;
+        }
+
+        levels.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let at = |percentile: f64| {
+            levels[((levels.len() - 1) as f64 * percentile).round() as usize]
+        };
+
+        at(0.95) - at(0.10)
+    }
+
+    pub fn true_peak(&self) -> f64 {
+        to_db(self.true_peak)
+    }
+
+    pub fn sample_peak(&self) -> f64 {
+        to_db(self.sample_peak)
+    }
+
+    pub fn measurement(&self) -> Measurement {
+        Measurement {
+            integrated: self.integrated(),
+            range: self.range(),
+            true_peak: self.true_peak(),
+            sample_peak: self.sample_peak()
+        }
+    }
+}
+
+// measures the whole data chunk of a file. the reader is left at the
+// start of the data.
+pub fn scan<R: Read + Seek>(reader: &mut WaveReader<R>)
+ -> IOResult<Measurement> {
+
+    let (channels, rate, mask) = {
+        let format = reader.format();
+        (format.channels, format.sample_rate, format.channel_mask)
+    };
+
+    let mut meter = Meter::new(channels, rate, mask);
+
+    try!(reader.seek(SeekTarget::Frame(0)));
+
+    loop {
+        let samples = try!(reader.read_frames(FRAMES_PER_WRITE));
+        if samples.is_empty() {
+            break;
+        }
+        meter.push(&samples);
+    }
+
+    try!(reader.seek(SeekTarget::Frame(0)));
+    Ok(meter.measurement())
+}
+
+// keeps the true peak of the frames under a ceiling. the gain is cut
+// ahead of a peak and let back up over the release time, smoothed so
+// that it never jumps. frames come out `lookahead` later.
+pub struct Limiter {
+    ceiling   : f64,
+    channels  : usize,
+    lookahead : usize,
+    release   : f64,
+    detector  : TruePeak,
+    delay     : VecDeque<f64>,
+    required  : VecDeque<f64>,
+    minimum   : VecDeque<(usize, f64)>,
+    smoothing : VecDeque<f64>,
+    sum       : f64,
+    gain      : f64,
+    frame     : usize
+}
+
+impl Limiter {
+
+    // `ceiling` is in dBTP
+    pub fn new(ceiling: f64) -> Self {
+        Limiter {
+            ceiling: db_to_linear(ceiling),
+            channels: 1,
+            lookahead: 1,
+            release: 0.0,
+            detector: TruePeak::new(1, 0),
+            delay: VecDeque::new(),
+            required: VecDeque::new(),
+            minimum: VecDeque::new(),
+            smoothing: VecDeque::new(),
+            sum: 0.0,
+            gain: 1.0,
+            frame: 0
+        }
+    }
+
+    fn push(&mut self, frame: &[f64], output: &mut Vec<f64>) {
+
+        // the gain the frame `latency()` back needs to stay under
+        let peak = self.detector.push(frame);
+        let required = match peak > self.ceiling {
+            true => self.ceiling / peak,
+            _ => 1.0
+        };
+
+        // the lowest of the window ahead
+        while self.minimum.back().map(|&(_, gain)| gain >= required)
+            .unwrap_or(false) {
+            self.minimum.pop_back();
+        }
+        self.minimum.push_back((self.frame, required));
+        while self.minimum.front().map(|&(at, _)| at + self.lookahead <= self.frame)
+            .unwrap_or(false) {
+            self.minimum.pop_front();
+        }
+        let lowest = self.minimum.front().map(|&(_, gain)| gain).unwrap_or(1.0);
+
+        self.gain = lowest.min(self.gain + (1.0 - self.gain) * self.release);
+
+        self.smoothing.push_back(self.gain);
+        self.sum += self.gain;
+        if self.smoothing.len() > self.lookahead {
+            self.sum -= self.smoothing.pop_front().unwrap_or(0.0);
+        }
+
+        self.required.push_back(required);
+        self.delay.extend(frame.iter().cloned());
+        self.frame += 1;
+
+        let delay = self.lookahead - 1 + TruePeak::latency();
+
+        if self.delay.len() > delay * self.channels {
+
+            // the smoothed gain is never above what the frame needs,
+            // unless the window hasn't filled since the start
+            let needed = self.required.pop_front().unwrap_or(1.0);
+            let gain = (self.sum / self.lookahead as f64).min(needed);
+
+            for _ in 0..self.channels {
+                let sample = self.delay.pop_front().unwrap_or(0.0);
+                output.push(sample * gain);
+            }
+        }
+    }
+}
+
+impl Processor for Limiter {
+
+    fn configure(&mut self, channels: u16, rate: u32) -> (u16, u32) {
+        self.channels = (channels as usize).max(1);
+        self.lookahead = (rate * LOOKAHEAD_MILLIS / 1000).max(1) as usize;
+        self.release = match rate {
+            0 => 1.0,
+            rate => 1.0 - (-1.0 / (rate as f64 * RELEASE_MILLIS as f64 /
+                1000.0)).exp()
+        };
+        self.detector = TruePeak::new(self.channels, rate);
+        self.reset();
+        (channels, rate)
+    }
+
+    fn process(&mut self, samples: Vec<f64>) -> Vec<f64> {
+
+        let mut output = Vec::with_capacity(samples.len());
+
+        for frame in samples.chunks(self.channels) {
+            if frame.len() == self.channels {
+                self.push(frame, &mut output);
+            }
+        }
+
+        output
+    }
+
+    // the frames still in the lookahead, after which the limiter
+    // starts over
+    fn flush(&mut self) -> Vec<f64> {
+
+        let held = self.delay.len() / self.channels;
+        let silence = vec![0.0; self.channels];
+        let mut output = Vec::with_capacity(held * self.channels);
+
+        while output.len() < held * self.channels {
+            self.push(&silence, &mut output);
+        }
+
+        self.reset();
+        output
+    }
+
+    fn reset(&mut self) {
+        self.detector.reset();
+        self.delay.clear();
+        self.required.clear();
+        self.minimum.clear();
+        self.smoothing.clear();
+        self.sum = 0.0;
+        self.gain = 1.0;
+        self.frame = 0;
+    }
+
+    fn as_any(&mut self) -> &mut Any {
+        self
+    }
+}
+
+// turns each track up or down to a target loudness and keeps the peaks
+// that makes under a ceiling
+pub struct Normalizer {
+    target  : f64,
+    gain    : Volume,
+    limiter : Limiter
+}
+
+impl Normalizer {
+
+    // `target` is in LUFS and `ceiling` in dBTP
+    pub fn new(target: f64, ceiling: f64) -> Self {
+        Normalizer {
+            target: target,
+            gain: Volume::new(),
+            limiter: Limiter::new(ceiling)
+        }
+    }
+
+    // sets the gain for a track measured at `loudness`. silence is left
+    // as it is.
+    pub fn set_loudness(&mut self, loudness: f64) {
+        let db = match loudness.is_finite() {
+            true => (self.target - loudness).min(MAX_GAIN),
+            _ => 0.0
+        };
+        self.gain.set_db(db);
+    }
+
+    pub fn gain_db(&self) -> f64 {
+        self.gain.db()
+    }
+}
+
+impl Processor for Normalizer {
+
+    fn configure(&mut self, channels: u16, rate: u32) -> (u16, u32) {
+        self.gain.configure(channels, rate);
+        self.limiter.configure(channels, rate)
+    }
+
+    fn process(&mut self, samples: Vec<f64>) -> Vec<f64> {
+        let samples = Processor::process(&mut self.gain, samples);
+        self.limiter.process(samples)
+    }
+
+    fn flush(&mut self) -> Vec<f64> {
+        self.limiter.flush()
+    }
+
+    fn reset(&mut self) {
+        self.limiter.reset();
+    }
+
+    fn as_any(&mut self) -> &mut Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::f64::consts::PI;
+
+    use super::*;
+    use chain::Processor;
+
+    const RATE : u32 = 48000;
+
+    // a stereo sine at `frequency`, `db` below full scale, starting at
+    // `phase`
+    fn sine(frequency: f64, db: f64, seconds: f64, phase: f64) -> Vec<f64> {
+        let amplitude = 10.0f64.powf(db / 20.0);
+        let frames = (seconds * RATE as f64) as usize;
+        let mut samples = Vec::with_capacity(frames * 2);
+        for n in 0..frames {
+            let sample = amplitude *
+                (2.0 * PI * frequency * n as f64 / RATE as f64 + phase).sin();
+            samples.push(sample);
+            samples.push(sample);
+        }
+        samples
+    }
+
+    #[test]
+    fn k_weighting_test() {
+
+        // the coefficients bs.1770 gives for 48 kHz
+        let (shelf, pass) = k_weighting(48000);
+        assert!((shelf.b0 - 1.53512485958697).abs() < 1e-9);
+        assert!((shelf.b1 + 2.69169618940638).abs() < 1e-9);
+        assert!((shelf.b2 - 1.19839281085285).abs() < 1e-9);
+        assert!((shelf.a1 + 1.69065929318241).abs() < 1e-9);
+        assert!((shelf.a2 - 0.73248077421585).abs() < 1e-9);
+        assert!((pass.a1 + 1.99004745483398).abs() < 1e-9);
+        assert!((pass.a2 - 0.99007225036621).abs() < 1e-9);
+
+        // and the same response at another rate
+        let (shelf, pass) = k_weighting(44100);
+        let db = shelf.magnitude_db(10000.0, 44100) +
+            pass.magnitude_db(10000.0, 44100);
+        assert!((db - 4.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn integrated_test() {
+
+        // a stereo sine at 1 kHz and -23 dBFS is -23 LUFS
+        let mut meter = Meter::new(2, RATE, 0);
+        meter.push(&sine(1000.0, -23.0, 5.0, 0.0));
+        assert!((meter.integrated() + 23.0).abs() < 0.1);
+        assert!((meter.momentary() + 23.0).abs() < 0.1);
+        assert!((meter.short_term() + 23.0).abs() < 0.1);
+
+        // quieter parts fall under the relative gate
+        let mut meter = Meter::new(2, RATE, 0);
+        meter.push(&sine(1000.0, -36.0, 5.0, 0.0));
+        meter.push(&sine(1000.0, -23.0, 20.0, 0.0));
+        meter.push(&sine(1000.0, -36.0, 5.0, 0.0));
+        assert!((meter.integrated() + 23.0).abs() < 0.1);
+
+        // silence has no loudness
+        let mut meter = Meter::new(1, RATE, 0);
+        meter.push(&vec![0.0; RATE as usize]);
+        assert_eq!(f64::NEG_INFINITY, meter.integrated());
+    }
+
+    #[test]
+    fn range_test() {
+
+        // ebu tech 3342 case 1, 10 LU
+        let mut meter = Meter::new(2, RATE, 0);
+        meter.push(&sine(1000.0, -20.0, 20.0, 0.0));
+        meter.push(&sine(1000.0, -30.0, 20.0, 0.0));
+        assert!((meter.range() - 10.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn true_peak_test() {
+
+        // a quarter of the rate at 45 degrees peaks between samples,
+        // 3 dB over them
+        let mut meter = Meter::new(2, RATE, 0);
+        meter.push(&sine(12000.0, 0.0, 0.1, PI / 4.0));
+        assert!((meter.sample_peak() + 3.01).abs() < 0.01);
+        assert!(meter.true_peak().abs() < 0.2);
+    }
+
+    #[test]
+    fn limiter_test() {
+
+        let mut limiter = Limiter::new(-1.0);
+        limiter.configure(2, RATE);
+
+        let input = sine(12000.0, 0.0, 0.5, PI / 4.0);
+        let mut output = limiter.process(input.clone());
+        output.extend(limiter.flush());
+        assert_eq!(input.len(), output.len());
+
+        let mut meter = Meter::new(2, RATE, 0);
+        meter.push(&output);
+        assert!(meter.true_peak() < -0.9);
+
+        // what is under the ceiling comes out as it went in
+        let input = sine(1000.0, -6.0, 0.1, 0.0);
+        let mut output = limiter.process(input.clone());
+        output.extend(limiter.flush());
+        assert_eq!(input, output);
+    }
+
+    #[test]
+    fn normalizer_test() {
+
+        let mut normalizer = Normalizer::new(DEFAULT_TARGET, DEFAULT_CEILING);
+        normalizer.configure(2, RATE);
+        normalizer.set_loudness(-13.0);
+        assert!((normalizer.gain_db() + 10.0).abs() < 1e-9);
+
+        let mut output = normalizer.process(sine(1000.0, -13.0, 2.0, 0.0));
+        output.extend(normalizer.flush());
+
+        let mut meter = Meter::new(2, RATE, 0);
+        meter.push(&output);
+        assert!((meter.integrated() - DEFAULT_TARGET).abs() < 0.1);
+
+        normalizer.set_loudness(f64::NEG_INFINITY);
+        assert_eq!(0.0, normalizer.gain_db());
+        normalizer.set_loudness(-80.0);
+        assert!((normalizer.gain_db() - MAX_GAIN).abs() < 1e-9);
+    }
+}
@@ -1,3 +1,7 @@
+extern crate wave_player2;
+
+use std::process::exit;
+
 fn main() {
-    println!("Hello, World!");
+    exit(wave_player2::cli::main());
 }
@@ -0,0 +1,349 @@
+use std::f64;
+
+use sample::SampleFormat;
+
+// how often levels are given, in time played
+pub const PERIOD_MILLIS : u64 = 50;
+
+// how long a peak is held, and how fast it falls after that
+const HOLD_MILLIS     : u64 = 1500;
+const FALL_DB_PER_SEC : f64 = 20.0;
+
+// the range of a rendered meter
+const FLOOR_DB : f64 = -60.0;
+
+fn to_db(level: f64) -> f64 {
+    match level > 0.0 {
+        true => 20.0 * level.log10(),
+        _ => f64::NEG_INFINITY
+    }
+}
+
+// the level of a channel over a period. levels are linear, 1.0 is full
+// scale; the peak hold is the highest peak of the last moments, and the
+// clips are counted from the start of the stream.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChannelLevel {
+    pub peak  : f64,
+    pub rms   : f64,
+    pub hold  : f64,
+    pub clips : u64
+}
+
+impl ChannelLevel {
+
+    pub fn peak_db(&self) -> f64 {
+        to_db(self.peak)
+    }
+
+    pub fn rms_db(&self) -> f64 {
+        to_db(self.rms)
+    }
+
+    pub fn hold_db(&self) -> f64 {
+        to_db(self.hold)
+    }
+}
+
+// the levels of every channel over a period, and how many frames had
+// been measured once it ended
+#[derive(Clone, Debug, PartialEq)]
+pub struct Levels {
+    pub channels : Vec<ChannelLevel>,
+    pub frames   : u64
+}
+
+impl Levels {
+
+    pub fn clips(&self) -> u64 {
+        self.channels
+            .iter()
+            .fold(0, |sum, channel| sum + channel.clips)
+    }
+}
+
+// the level a sample of `format` counts as clipped at, or below its
+// negative. the highest integer sample is a step short of 1.0.
+pub fn clip_level(format: SampleFormat) -> f64 {
+    match format.is_float() {
+        true => 1.0,
+        _ => 1.0 - 1.0 / (1u64 << (format.width() - 1)) as f64
+    }
+}
+
+// a held peak and the frames left before it starts to fall
+#[derive(Clone, Copy, Debug, Default)]
+struct Hold {
+    level : f64,
+    left  : u64
+}
+
+// measures encoded frames as they are written to a device. a frame
+// split between two writes is put back together.
+pub struct LevelMeter {
+    format    : SampleFormat,
+    channels  : usize,
+    period    : u64,
+    hold      : u64,
+    fall      : f64,
+    clip      : f64,
+    count     : u64,
+    frames    : u64,
+    peaks     : Vec<f64>,
+    squares   : Vec<f64>,
+    holds     : Vec<Hold>,
+    clips     : Vec<u64>,
+    partial   : Vec<u8>
+}
+
+impl LevelMeter {
+
+    pub fn new(format: SampleFormat, channels: u16, rate: u32) -> Self {
+
+        let channels = channels as usize;
+        let period = (rate as u64 * PERIOD_MILLIS / 1000).max(1);
+
+        LevelMeter {
+            format: format,
+            channels: channels,
+            period: period,
+            hold: rate as u64 * HOLD_MILLIS / 1000,
+            fall: 10.0f64.powf(-FALL_DB_PER_SEC * PERIOD_MILLIS as f64 /
+                1000.0 / 20.0),
+            clip: clip_level(format),
+            count: 0,
+            frames: 0,
+            peaks: vec![0.0; channels],
+            squares: vec![0.0; channels],
+            holds: vec![Hold::default(); channels],
+            clips: vec![0; channels],
+            partial: Vec::new()
+        }
+    }
+
+    pub fn format(&self) -> SampleFormat {
+        self.format
+    }
+
+    // starts the period over and forgets a partial frame, as after the
+    // queued frames have been dropped
+    pub fn reset(&mut self) {
+        self.count = 0;
+        self.partial.clear();
+        for channel in 0..self.channels {
+            self.peaks[channel] = 0.0;
+            self.squares[channel] = 0.0;
+        }
+    }
+
+    // the levels of every period `bytes` completes
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Levels> {
+
+        let frame_size = self.format.physical_bytes() * self.channels;
+
+        if frame_size == 0 {
+            return Vec::new();
+        }
+
+        let mut data = self.partial.split_off(0);
+        data.extend_from_slice(bytes);
+
+        let whole = data.len() / frame_size * frame_size;
+        self.partial = data[whole..].to_vec();
+
+        let samples = self.format.decode_samples(&data[..whole]);
+        self.push_samples(&samples)
+    }
+
+    pub fn push_samples(&mut self, samples: &[f64]) -> Vec<Levels> {
+
+        let mut levels = Vec::new();
+
+        for frame in samples.chunks(self.channels.max(1)) {
+
+            if frame.len() < self.channels {
+                break;
+            }
+
+            for (channel, sample) in frame.iter().enumerate() {
+
+                let level = sample.abs();
+
+                if *sample >= self.clip || *sample <= -1.0 {
+                    self.clips[channel] += 1;
+                }
+
+                self.peaks[channel] = self.peaks[channel].max(level);
+                self.squares[channel] += sample * sample;
+            }
+
+            self.count += 1;
+            self.frames += 1;
+
+            if self.count == self.period {
+                levels.push(self.end_period());
+            }
+        }
+
+        levels
+    }
+
+    fn end_period(&mut self) -> Levels {
+
+        let mut channels = Vec::with_capacity(self.channels);
+
+        for channel in 0..self.channels {
+
+            let peak = self.peaks[channel];
+            let hold = &mut self.holds[channel];
+
+            // a new peak is held, an old one waits and then falls
+            if peak >= hold.level {
+                hold.level = peak;
+                hold.left = self.hold;
+            } else if hold.left >= self.period {
+                hold.left -= self.period;
+            } else {
+                hold.left = 0;
+                hold.level = (hold.level * self.fall).max(peak);
+            }
+
+            channels.push(ChannelLevel {
+                peak: peak,
+                rms: (self.squares[channel] / self.count as f64).sqrt(),
+                hold: hold.level,
+                clips: self.clips[channel]
+            });
+
+            self.peaks[channel] = 0.0;
+            self.squares[channel] = 0.0;
+        }
+
+        self.count = 0;
+
+        Levels {
+            channels: channels,
+            frames: self.frames
+        }
+    }
+}
+
+// where a level falls on a meter `width` characters wide
+fn position(level: f64, width: usize) -> usize {
+    let db = to_db(level).max(FLOOR_DB);
+    ((db - FLOOR_DB) / -FLOOR_DB * width as f64).round() as usize
+}
+
+// a line of a bar for every channel, from -60 to 0 dBFS: `#` up to the
+// rms, `-` up to the peak and `|` at the peak hold, then the peak in dB.
+// clips are counted at the end once there are any.
+pub fn render(levels: &Levels, width: usize) -> String {
+
+    let mut line = String::new();
+
+    for (index, channel) in levels.channels.iter().enumerate() {
+
+        let rms = position(channel.rms, width);
+        let peak = position(channel.peak, width).max(rms);
+        let hold = position(channel.hold, width);
+
+        let bar = (0..width)
+            .map(|n| match n {
+                n if n < rms => '#',
+                n if n < peak => '-',
+                n if n + 1 == hold.max(1) && channel.hold > 0.0 => '|',
+                _ => ' '
+            })
+            .collect::<String>();
+
+        let db = match channel.peak_db() {
+            db if db < FLOOR_DB => "  -inf".to_string(),
+            db => format!("{:6.1}", db)
+        };
+
+        if index > 0 {
+            line.push(' ');
+        }
+        line.push_str(&format!("{} [{}]{}", index + 1, bar, db));
+    }
+
+    match levels.clips() {
+        0 => line,
+        clips => format!("{} clip {}", line, clips)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use sample::SampleFormat;
+
+    #[test]
+    fn level_test() {
+
+        // 400 frames make a period at 8000 Hz
+        let mut meter = LevelMeter::new(SampleFormat::S16_LE, 2, 8000);
+
+        let mut samples = Vec::new();
+        for n in 0..400 {
+            samples.push(match n % 2 { 0 => 0.5, _ => -0.5 });
+            samples.push(0.0);
+        }
+        let bytes = SampleFormat::S16_LE.encode_samples(&samples);
+
+        // split in the middle of a frame
+        assert!(meter.push(&bytes[..801]).is_empty());
+        let levels = meter.push(&bytes[801..]);
+
+        assert_eq!(1, levels.len());
+        assert_eq!(400, levels[0].frames);
+        assert_eq!(ChannelLevel { peak: 0.5, rms: 0.5, hold: 0.5, clips: 0 },
+            levels[0].channels[0]);
+        assert_eq!(ChannelLevel::default(), levels[0].channels[1]);
+        assert!((levels[0].channels[0].peak_db() + 6.02).abs() < 0.01);
+    }
+
+    #[test]
+    fn hold_test() {
+
+        let mut meter = LevelMeter::new(SampleFormat::FLOAT_LE, 1, 1000);
+
+        // 50 frames a period, held for 30 periods
+        let mut levels = meter.push_samples(&vec![1.0; 50]);
+        levels.extend(meter.push_samples(&vec![0.1; 50 * 31]));
+
+        assert_eq!(32, levels.len());
+        assert_eq!(1.0, levels[30].channels[0].hold);
+        assert!(levels[31].channels[0].hold < 1.0);
+        assert!(levels[31].channels[0].hold > 0.1);
+
+        // full scale counts as clipped
+        assert_eq!(50, levels[31].clips());
+    }
+
+    #[test]
+    fn clip_test() {
+
+        // a frame a period at 20 Hz
+        let mut meter = LevelMeter::new(SampleFormat::S16_LE, 1, 20);
+        let bytes = SampleFormat::S16_LE.encode_samples(&[1.5, -1.0, 0.99]);
+        let levels = meter.push(&bytes);
+        assert_eq!(2, levels[2].clips());
+    }
+
+    #[test]
+    fn render_test() {
+
+        let levels = Levels {
+            channels: vec![
+                ChannelLevel { peak: 1.0, rms: 0.1, hold: 1.0, clips: 0 },
+                ChannelLevel { peak: 0.0, rms: 0.0, hold: 0.001, clips: 3 }],
+            frames: 0
+        };
+
+        // -20 dB is two thirds of the way up
+        assert_eq!("1 [########----]   0.0 2 [|           ]  -inf clip 3",
+            render(&levels, 12));
+    }
+}
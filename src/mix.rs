@@ -0,0 +1,387 @@
+use std::any::Any;
+use std::f64::consts::FRAC_1_SQRT_2;
+
+use chain::Processor;
+use io::*;
+
+// speaker positions of the channel mask of WAVE_FORMAT_EXTENSIBLE.
+// channels are stored in the order of the bits set.
+pub const SPEAKER_FRONT_LEFT            : u32 = 0x1;
+pub const SPEAKER_FRONT_RIGHT           : u32 = 0x2;
+pub const SPEAKER_FRONT_CENTER          : u32 = 0x4;
+pub const SPEAKER_LOW_FREQUENCY         : u32 = 0x8;
+pub const SPEAKER_BACK_LEFT             : u32 = 0x10;
+pub const SPEAKER_BACK_RIGHT            : u32 = 0x20;
+pub const SPEAKER_FRONT_LEFT_OF_CENTER  : u32 = 0x40;
+pub const SPEAKER_FRONT_RIGHT_OF_CENTER : u32 = 0x80;
+pub const SPEAKER_BACK_CENTER           : u32 = 0x100;
+pub const SPEAKER_SIDE_LEFT             : u32 = 0x200;
+pub const SPEAKER_SIDE_RIGHT            : u32 = 0x400;
+
+// a channel without a position in the mask
+const SPEAKER_UNKNOWN : u32 = 0;
+
+const HALF : f64 = 0.5;
+
+// the usual layouts for files without a channel mask
+pub fn default_mask(channels: u16) -> u32 {
+    match channels {
+        1 => SPEAKER_FRONT_CENTER,
+        2 => 0x3,
+        3 => 0x7,
+        // quad
+        4 => 0x33,
+        5 => 0x37,
+        // 5.1, 6.1 and 7.1
+        6 => 0x3f,
+        7 => 0x70f,
+        8 => 0x63f,
+        _ => 0
+    }
+}
+
+// the position of each channel. channels beyond the bits of the mask
+// have no position.
+pub fn speakers(mask: u32, channels: u16) -> Vec<u32> {
+
+    let mask = match mask {
+        0 => default_mask(channels),
+        mask => mask
+    };
+
+    let mut speakers = (0..32)
+        .map(|bit| 1 << bit)
+        .filter(|speaker| mask & speaker != 0)
+        .take(channels as usize)
+        .collect::<Vec<u32>>();
+
+    speakers.resize(channels as usize, SPEAKER_UNKNOWN);
+    speakers
+}
+
+// where a speaker missing from the output goes, as alternatives tried in
+// order. the gains are those of the itu-r bs.775 down-mix equations:
+// centre and surrounds are mixed into the front pair at -3 db, and into
+// mono at 0 db and -6 db. the lfe channel is left out.
+fn fold(speaker: u32) -> Vec<Vec<(u32, f64)>> {
+
+    let (left, right) = (SPEAKER_FRONT_LEFT, SPEAKER_FRONT_RIGHT);
+    let center = SPEAKER_FRONT_CENTER;
+
+    match speaker {
+        SPEAKER_LOW_FREQUENCY => vec![],
+        SPEAKER_FRONT_LEFT => vec![vec![(center, FRAC_1_SQRT_2)]],
+        SPEAKER_FRONT_RIGHT => vec![vec![(center, FRAC_1_SQRT_2)]],
+        SPEAKER_FRONT_LEFT_OF_CENTER => vec![vec![(left, 1.0)],
+            vec![(center, FRAC_1_SQRT_2)]],
+        SPEAKER_FRONT_RIGHT_OF_CENTER => vec![vec![(right, 1.0)],
+            vec![(center, FRAC_1_SQRT_2)]],
+        SPEAKER_BACK_LEFT => vec![vec![(SPEAKER_SIDE_LEFT, 1.0)],
+            vec![(left, FRAC_1_SQRT_2)], vec![(center, HALF)]],
+        SPEAKER_SIDE_LEFT => vec![vec![(SPEAKER_BACK_LEFT, 1.0)],
+            vec![(left, FRAC_1_SQRT_2)], vec![(center, HALF)]],
+        SPEAKER_BACK_RIGHT => vec![vec![(SPEAKER_SIDE_RIGHT, 1.0)],
+            vec![(right, FRAC_1_SQRT_2)], vec![(center, HALF)]],
+        SPEAKER_SIDE_RIGHT => vec![vec![(SPEAKER_BACK_RIGHT, 1.0)],
+            vec![(right, FRAC_1_SQRT_2)], vec![(center, HALF)]],
+        SPEAKER_BACK_CENTER => vec![
+            vec![(SPEAKER_BACK_LEFT, FRAC_1_SQRT_2),
+                 (SPEAKER_BACK_RIGHT, FRAC_1_SQRT_2)],
+            vec![(SPEAKER_SIDE_LEFT, FRAC_1_SQRT_2),
+                 (SPEAKER_SIDE_RIGHT, FRAC_1_SQRT_2)],
+            vec![(left, HALF), (right, HALF)],
+            vec![(center, HALF)]],
+        // the centre, and anything else without a place of its own
+        _ => vec![vec![(left, FRAC_1_SQRT_2), (right, FRAC_1_SQRT_2)],
+            vec![(center, 1.0)]]
+    }
+}
+
+// gains from every input channel to every output channel. processing
+// takes interleaved frames of `inputs` channels to frames of `outputs`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matrix {
+    inputs  : usize,
+    outputs : usize,
+    gains   : Vec<f64>
+}
+
+impl Matrix {
+
+    // all silent
+    pub fn new(inputs: usize, outputs: usize) -> Self {
+        Matrix {
+            inputs: inputs,
+            outputs: outputs,
+            gains: vec![0.0; inputs * outputs]
+        }
+    }
+
+    pub fn identity(channels: usize) -> Self {
+        let mut matrix = Matrix::new(channels, channels);
+        for ch in 0..channels {
+            matrix.set(ch, ch, 1.0);
+        }
+        matrix
+    }
+
+    // the standard mix of `channels` laid out as `mask` says into the
+    // default layout of `outputs`. mono is copied to every output but
+    // the lfe, other channels go to the same speaker or are folded down
+    // into the speakers there are. upmixed speakers stay silent.
+    pub fn standard(channels: u16, mask: u32, outputs: u16) -> Self {
+
+        if channels == outputs {
+            return Matrix::identity(channels as usize);
+        }
+
+        let inputs = speakers(mask, channels);
+        let layout = speakers(0, outputs);
+        let mut matrix = Matrix::new(inputs.len(), layout.len());
+
+        let place = |speaker: u32| layout
+            .iter()
+            .position(|out| *out == speaker && speaker != SPEAKER_UNKNOWN);
+
+        for (input, speaker) in inputs.iter().enumerate() {
+
+            if channels == 1 {
+                for (output, out) in layout.iter().enumerate() {
+                    if *out != SPEAKER_LOW_FREQUENCY {
+                        matrix.set(output, input, 1.0);
+                    }
+                }
+                continue;
+            }
+
+            if let Some(output) = place(*speaker) {
+                matrix.set(output, input, 1.0);
+                continue;
+            }
+
+            // the first alternative that has all its speakers
+            let targets = fold(*speaker)
+                .into_iter()
+                .filter_map(|targets| targets
+                    .iter()
+                    .map(|&(speaker, gain)| place(speaker)
+                        .map(|output| (output, gain)))
+                    .collect::<Option<Vec<_>>>())
+                .next();
+
+            for &(output, gain) in targets.iter().flat_map(|t| t.iter()) {
+                matrix.set(output, input, gain);
+            }
+        }
+
+        matrix
+    }
+
+    // rows of gains, one row for each output: "0.5,0.5" mixes stereo into
+    // mono, "0,1;1,0" swaps the channels of stereo
+    pub fn parse(st: &str) -> IOResult<Matrix> {
+
+        let invalid = || IOError::new(IOErrorKind::InvalidInput,
+            "invalid channel matrix");
+
+        let mut rows = Vec::new();
+
+        for row in st.split(';') {
+            let mut gains = Vec::new();
+            for gain in row.split(',') {
+                match gain.trim().parse::<f64>() {
+                    Ok(gain) if gain.is_finite() => gains.push(gain),
+                    _ => return Err(invalid())
+                }
+            }
+            rows.push(gains);
+        }
+
+        let inputs = rows[0].len();
+
+        if rows.iter().any(|row| row.len() != inputs) {
+            return Err(invalid());
+        }
+
+        Ok(Matrix {
+            inputs: inputs,
+            outputs: rows.len(),
+            gains: rows
+                .into_iter()
+                .flat_map(|row| row.into_iter())
+                .collect()
+        })
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    pub fn outputs(&self) -> usize {
+        self.outputs
+    }
+
+    pub fn gain(&self, output: usize, input: usize) -> f64 {
+        self.gains[output * self.inputs + input]
+    }
+
+    pub fn set(&mut self, output: usize, input: usize, gain: f64) {
+        self.gains[output * self.inputs + input] = gain;
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Matrix::identity(self.inputs)
+    }
+
+    // scaled down so that no output can go beyond full scale
+    pub fn normalized(mut self) -> Self {
+
+        let loudest = (0..self.outputs)
+            .map(|output| (0..self.inputs)
+                .fold(0.0, |sum, input| sum + self.gain(output, input).abs()))
+            .fold(0.0, f64::max);
+
+        if loudest > 1.0 {
+            for gain in self.gains.iter_mut() {
+                *gain /= loudest;
+            }
+        }
+
+        self
+    }
+
+    // a trailing partial frame is ignored
+    pub fn process(&self, samples: &[f64]) -> Vec<f64> {
+
+        let mut output = Vec::with_capacity(samples.len() / self.inputs.max(1) *
+            self.outputs);
+
+        for frame in samples.chunks(self.inputs) {
+
+            if frame.len() < self.inputs {
+                break;
+            }
+
+            for row in self.gains.chunks(self.inputs) {
+                output.push(row
+                    .iter()
+                    .zip(frame.iter())
+                    .fold(0.0, |sum, (gain, sample)| sum + gain * sample));
+            }
+        }
+
+        output
+    }
+}
+
+impl Processor for Matrix {
+
+    fn configure(&mut self, channels: u16, rate: u32) -> (u16, u32) {
+        (self.outputs as u16, rate)
+    }
+
+    fn process(&mut self, samples: Vec<f64>) -> Vec<f64> {
+        Matrix::process(self, &samples)
+    }
+
+    fn as_any(&mut self) -> &mut Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    use super::*;
+
+    fn rows(matrix: &Matrix) -> Vec<Vec<f64>> {
+        (0..matrix.outputs())
+            .map(|output| (0..matrix.inputs())
+                .map(|input| matrix.gain(output, input))
+                .collect())
+            .collect()
+    }
+
+    #[test]
+    fn speakers_test() {
+        assert_eq!(vec![SPEAKER_FRONT_LEFT, SPEAKER_FRONT_RIGHT],
+            speakers(0, 2));
+        // 5.1 with side surrounds
+        assert_eq!(vec![0x1, 0x2, 0x4, 0x8, 0x200, 0x400], speakers(0x60f, 6));
+        assert_eq!(vec![SPEAKER_FRONT_CENTER, 0, 0], speakers(0x4, 3));
+    }
+
+    #[test]
+    fn downmix_test() {
+
+        let h = FRAC_1_SQRT_2;
+
+        // l, r, c, lfe, ls, rs
+        let stereo = Matrix::standard(6, 0, 2);
+        assert_eq!(vec![vec![1.0, 0.0, h, 0.0, h, 0.0],
+                        vec![0.0, 1.0, h, 0.0, 0.0, h]],
+            rows(&stereo));
+
+        // side surrounds go to the same place
+        assert_eq!(stereo, Matrix::standard(6, 0x60f, 2));
+
+        assert_eq!(vec![vec![h, h]], rows(&Matrix::standard(2, 0, 1)));
+        assert_eq!(vec![vec![h, h, 1.0, 0.0, 0.5, 0.5]],
+            rows(&Matrix::standard(6, 0, 1)));
+    }
+
+    #[test]
+    fn upmix_test() {
+
+        assert_eq!(vec![vec![1.0], vec![1.0]],
+            rows(&Matrix::standard(1, 0, 2)));
+
+        // mono goes everywhere but the lfe
+        assert_eq!(vec![1.0, 1.0, 1.0, 0.0, 1.0, 1.0],
+            rows(&Matrix::standard(1, 0, 6))
+                .into_iter()
+                .map(|row| row[0])
+                .collect::<Vec<_>>());
+
+        // stereo stays in the front pair of 5.1
+        let surround = Matrix::standard(2, 0, 6);
+        assert_eq!(vec![1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            rows(&surround)
+                .into_iter()
+                .map(|row| row[0])
+                .collect::<Vec<_>>());
+
+        assert!(Matrix::standard(2, 0x600, 2).is_identity());
+    }
+
+    #[test]
+    fn process_test() {
+
+        let swap = Matrix::parse("0,1; 1,0").unwrap();
+        assert_eq!(vec![2.0, 1.0, 4.0, 3.0],
+            swap.process(&[1.0, 2.0, 3.0, 4.0, 5.0]));
+
+        let mono = Matrix::parse("0.5,0.5").unwrap();
+        assert_eq!((2, 1), (mono.inputs(), mono.outputs()));
+        assert_eq!(vec![0.5, -0.25], mono.process(&[0.5, 0.5, -0.5, 0.0]));
+
+        assert!(Matrix::parse("1,0;1").is_err());
+        assert!(Matrix::parse("1,x").is_err());
+        assert!(Matrix::parse("").is_err());
+    }
+
+    #[test]
+    fn normalized_test() {
+
+        // full scale in every channel stays within full scale
+        let matrix = Matrix::standard(6, 0, 2)
+            .normalized();
+        for sample in matrix.process(&[1.0; 6]) {
+            assert!((sample - 1.0).abs() < 1e-12);
+        }
+
+        assert_eq!(Matrix::identity(2), Matrix::identity(2).normalized());
+    }
+}
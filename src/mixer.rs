@@ -0,0 +1,638 @@
+extern crate libc;
+
+use std::ffi::{ CStr, CString };
+use std::ptr;
+use std::sync::{ Arc, Mutex };
+use std::sync::mpsc::{ Receiver, channel };
+use std::thread::spawn;
+use std::time::Duration;
+
+use io::*;
+use sp_io::snd_pcm_error;
+use volume::{ HardwareVolume, db_to_linear };
+
+#[allow(non_camel_case_types)]
+type snd_mixer_t = libc::c_void;
+
+#[allow(non_camel_case_types)]
+type snd_mixer_elem_t = libc::c_void;
+
+#[allow(non_camel_case_types)]
+type snd_mixer_selem_channel_id_t = i32;
+
+const SND_MIXER_SCHN_FRONT_LEFT : snd_mixer_selem_channel_id_t = 0;
+
+#[link(name = "asound")]
+extern "C" {
+
+    fn snd_mixer_attach(mixer: *mut snd_mixer_t,
+        name: *const libc::c_char) -> i32;
+
+    fn snd_mixer_close(mixer: *mut snd_mixer_t) -> i32;
+
+    fn snd_mixer_elem_next(elem: *mut snd_mixer_elem_t)
+     -> *mut snd_mixer_elem_t;
+
+    fn snd_mixer_first_elem(mixer: *mut snd_mixer_t) -> *mut snd_mixer_elem_t;
+
+    fn snd_mixer_handle_events(mixer: *mut snd_mixer_t) -> i32;
+
+    fn snd_mixer_load(mixer: *mut snd_mixer_t) -> i32;
+
+    fn snd_mixer_open(mixer: *mut *mut snd_mixer_t, mode: i32) -> i32;
+
+    fn snd_mixer_selem_get_index(elem: *mut snd_mixer_elem_t) -> u32;
+
+    fn snd_mixer_selem_get_name(elem: *mut snd_mixer_elem_t)
+     -> *const libc::c_char;
+
+    fn snd_mixer_selem_get_playback_dB(elem: *mut snd_mixer_elem_t,
+        channel: snd_mixer_selem_channel_id_t,
+        value: *mut libc::c_long) -> i32;
+
+    fn snd_mixer_selem_get_playback_dB_range(elem: *mut snd_mixer_elem_t,
+        min: *mut libc::c_long,
+        max: *mut libc::c_long) -> i32;
+
+    fn snd_mixer_selem_get_playback_switch(elem: *mut snd_mixer_elem_t,
+        channel: snd_mixer_selem_channel_id_t,
+        value: *mut i32) -> i32;
+
+    fn snd_mixer_selem_get_playback_volume(elem: *mut snd_mixer_elem_t,
+        channel: snd_mixer_selem_channel_id_t,
+        value: *mut libc::c_long) -> i32;
+
+    fn snd_mixer_selem_get_playback_volume_range(elem: *mut snd_mixer_elem_t,
+        min: *mut libc::c_long,
+        max: *mut libc::c_long) -> i32;
+
+    fn snd_mixer_selem_has_playback_switch(elem: *mut snd_mixer_elem_t) -> i32;
+
+    fn snd_mixer_selem_has_playback_volume(elem: *mut snd_mixer_elem_t) -> i32;
+
+    fn snd_mixer_selem_register(mixer: *mut snd_mixer_t,
+        options: *mut libc::c_void,
+        classp: *mut *mut libc::c_void) -> i32;
+
+    fn snd_mixer_selem_set_playback_dB_all(elem: *mut snd_mixer_elem_t,
+        value: libc::c_long,
+        dir: i32) -> i32;
+
+    fn snd_mixer_selem_set_playback_switch_all(elem: *mut snd_mixer_elem_t,
+        value: i32) -> i32;
+
+    fn snd_mixer_selem_set_playback_volume_all(elem: *mut snd_mixer_elem_t,
+        value: libc::c_long) -> i32;
+
+    fn snd_mixer_wait(mixer: *mut snd_mixer_t, timeout: i32) -> i32;
+}
+
+const NO_SUCH_CONTROL : &'static str = "no such mixer control";
+
+// how long a watcher waits for events at a time
+const WATCH_INTERVAL_MILLIS : u64 = 500;
+
+fn snd_result(res: i32) -> IOResult<i32> {
+    match res {
+        res if res >= 0 => Ok(res),
+        errnum => Err(IOError::new(IO_ERROR,
+            snd_pcm_error(errnum).unwrap_or("unknown error")))
+    }
+}
+
+// a value within its range. decibels are kept in hundredths, as alsa
+// keeps them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Level {
+    pub min   : i64,
+    pub max   : i64,
+    pub value : i64
+}
+
+impl Level {
+
+    // where the value is between min and max, from 0.0 to 1.0
+    pub fn fraction(&self) -> f64 {
+        match self.max > self.min {
+            true => (self.value - self.min) as f64 /
+                (self.max - self.min) as f64,
+            _ => 0.0
+        }
+    }
+}
+
+// a playback control of the simple mixer and its state. a control may
+// have a volume, a switch or both; the volume of the first channel
+// stands for all of them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Control {
+    pub name   : String,
+    pub index  : u32,
+    pub volume : Option<Level>,
+    pub db     : Option<Level>,
+    pub switch : Option<bool>
+}
+
+impl Control {
+
+    pub fn new(name: &str) -> Self {
+        Control {
+            name: name.to_string(),
+            index: 0,
+            volume: None,
+            db: None,
+            switch: None
+        }
+    }
+
+    pub fn decibels(&self) -> Option<f64> {
+        self.db.map(|db| db.value as f64 / 100.0)
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.switch == Some(false)
+    }
+}
+
+// what the player needs of a mixer, so that it can be replaced in tests
+pub trait MixerControl: Send {
+
+    fn controls(&mut self) -> IOResult<Vec<Control>>;
+
+    fn control(&mut self, name: &str) -> IOResult<Control> {
+        try!(self.controls())
+            .into_iter()
+            .find(|control| control.name == name)
+            .ok_or(IOError::new(IOErrorKind::NotFound, NO_SUCH_CONTROL))
+    }
+
+    // a raw value within the volume range
+    fn set_volume(&mut self, name: &str, value: i64) -> IOResult<()>;
+
+    fn set_db(&mut self, name: &str, db: f64) -> IOResult<()>;
+
+    fn set_switch(&mut self, name: &str, on: bool) -> IOResult<()>;
+}
+
+// the simple mixer of a card, "default" or "hw:0" for example
+pub struct Mixer {
+    handle : *mut snd_mixer_t,
+    card   : String
+}
+
+// a handle is only ever used by one thread at a time
+unsafe impl Send for Mixer {}
+
+impl Mixer {
+
+    pub fn open(card: &str) -> IOResult<Mixer> {
+
+        let ccard = match CString::new(card) {
+            Ok(ccard) => ccard,
+            _ => return Err(IOError::new(IOErrorKind::InvalidInput,
+                "card name contains a nul byte"))
+        };
+
+        let mut mixer = Mixer {
+            handle: ptr::null_mut(),
+            card: card.to_string()
+        };
+
+        unsafe {
+            try!(snd_result(snd_mixer_open(&mut mixer.handle, 0)));
+            try!(snd_result(snd_mixer_attach(mixer.handle, ccard.as_ptr())));
+            try!(snd_result(snd_mixer_selem_register(mixer.handle,
+                ptr::null_mut(),
+                ptr::null_mut())));
+            try!(snd_result(snd_mixer_load(mixer.handle)));
+        }
+
+        Ok(mixer)
+    }
+
+    pub fn card(&self) -> &str {
+        &self.card
+    }
+
+    fn elems(&self) -> Vec<*mut snd_mixer_elem_t> {
+
+        let mut elems = Vec::new();
+
+        unsafe {
+            let mut elem = snd_mixer_first_elem(self.handle);
+            while !elem.is_null() {
+                elems.push(elem);
+                elem = snd_mixer_elem_next(elem);
+            }
+        }
+
+        elems
+    }
+
+    fn find(&self, name: &str) -> IOResult<*mut snd_mixer_elem_t> {
+        self.elems()
+            .into_iter()
+            .find(|elem| unsafe { elem_name(*elem) } == name)
+            .ok_or(IOError::new(IOErrorKind::NotFound, NO_SUCH_CONTROL))
+    }
+
+    // waits for a change made by anyone, up to `timeout`, and takes it
+    // in. returns whether there was one.
+    pub fn wait(&mut self, timeout: Duration) -> IOResult<bool> {
+
+        let millis = timeout.as_secs() as i32 * 1000 +
+            (timeout.subsec_nanos() / 1_000_000) as i32;
+
+        unsafe {
+            match try!(snd_result(snd_mixer_wait(self.handle, millis))) {
+                0 => Ok(false),
+                _ => snd_result(snd_mixer_handle_events(self.handle))
+                    .map(|events| events > 0)
+            }
+        }
+    }
+}
+
+unsafe fn elem_name(elem: *mut snd_mixer_elem_t) -> String {
+    CStr::from_ptr(snd_mixer_selem_get_name(elem))
+        .to_string_lossy()
+        .into_owned()
+}
+
+unsafe fn elem_control(elem: *mut snd_mixer_elem_t) -> Control {
+
+    let mut control = Control::new(&elem_name(elem));
+    control.index = snd_mixer_selem_get_index(elem);
+
+    let level = |range: &Fn(*mut libc::c_long, *mut libc::c_long) -> i32,
+                 value: &Fn(*mut libc::c_long) -> i32| {
+        let (mut min, mut max, mut current) = (0, 0, 0);
+        match (range(&mut min, &mut max), value(&mut current)) {
+            (0, 0) => Some(Level {
+                min: min as i64,
+                max: max as i64,
+                value: current as i64
+            }),
+            _ => None
+        }
+    };
+
+    if snd_mixer_selem_has_playback_volume(elem) != 0 {
+        control.volume = level(
+            &|min, max| snd_mixer_selem_get_playback_volume_range(elem,
+                min, max),
+            &|value| snd_mixer_selem_get_playback_volume(elem,
+                SND_MIXER_SCHN_FRONT_LEFT, value));
+        control.db = level(
+            &|min, max| snd_mixer_selem_get_playback_dB_range(elem,
+                min, max),
+            &|value| snd_mixer_selem_get_playback_dB(elem,
+                SND_MIXER_SCHN_FRONT_LEFT, value));
+    }
+
+    if snd_mixer_selem_has_playback_switch(elem) != 0 {
+        let mut on = 0;
+        if snd_mixer_selem_get_playback_switch(elem,
+            SND_MIXER_SCHN_FRONT_LEFT, &mut on) == 0 {
+            control.switch = Some(on != 0);
+        }
+    }
+
+    control
+}
+
+impl MixerControl for Mixer {
+
+    // playback controls only
+    fn controls(&mut self) -> IOResult<Vec<Control>> {
+        Ok(self.elems()
+            .into_iter()
+            .map(|elem| unsafe { elem_control(elem) })
+            .filter(|control| control.volume.is_some() ||
+                control.switch.is_some())
+            .collect())
+    }
+
+    fn set_volume(&mut self, name: &str, value: i64) -> IOResult<()> {
+        let elem = try!(self.find(name));
+        unsafe {
+            snd_result(snd_mixer_selem_set_playback_volume_all(elem,
+                value as libc::c_long))
+                .map(|_| ())
+        }
+    }
+
+    // the nearest step at or below `db`
+    fn set_db(&mut self, name: &str, db: f64) -> IOResult<()> {
+        let elem = try!(self.find(name));
+        unsafe {
+            snd_result(snd_mixer_selem_set_playback_dB_all(elem,
+                (db * 100.0).round() as libc::c_long,
+                -1))
+                .map(|_| ())
+        }
+    }
+
+    fn set_switch(&mut self, name: &str, on: bool) -> IOResult<()> {
+        let elem = try!(self.find(name));
+        unsafe {
+            snd_result(snd_mixer_selem_set_playback_switch_all(elem,
+                on as i32))
+                .map(|_| ())
+        }
+    }
+}
+
+impl Drop for Mixer {
+    fn drop(&mut self) {
+        if !self.handle.is_null() {
+            unsafe {
+                snd_mixer_close(self.handle);
+            }
+        }
+    }
+}
+
+// controls of a card as they change, from a mixer of its own on another
+// thread. the watcher stops at the first change after the receiver has
+// been dropped.
+pub fn watch(card: &str) -> IOResult<Receiver<Control>> {
+
+    let mut mixer = try!(Mixer::open(card));
+    let mut known = try!(mixer.controls());
+    let (tx, rx) = channel();
+
+    spawn(move || {
+        let interval = Duration::from_millis(WATCH_INTERVAL_MILLIS);
+        loop {
+            let changed = match mixer.wait(interval) {
+                Ok(true) => match mixer.controls() {
+                    Ok(controls) => controls,
+                    _ => break
+                },
+                Ok(false) => Vec::new(),
+                _ => break
+            };
+
+            for control in changed.iter().filter(|c| !known.contains(c)) {
+                if tx.send(control.clone()).is_err() {
+                    return;
+                }
+            }
+
+            if !changed.is_empty() {
+                known = changed;
+            }
+        }
+    });
+
+    Ok(rx)
+}
+
+// the card a pcm device is on: plughw:1,0 is on hw:1, names without a
+// card number go to the default mixer
+pub fn card_of(device: &str) -> String {
+
+    let card = device
+        .find(':')
+        .map(|pos| &device[pos + 1..])
+        .and_then(|rest| rest.split(',').next())
+        .and_then(|card| card.trim_left_matches("CARD=").parse::<u32>().ok());
+
+    match card {
+        Some(card) => format!("hw:{}", card),
+        _ => "default".to_string()
+    }
+}
+
+// a mixer that keeps its controls in memory, shared between clones
+#[derive(Clone)]
+pub struct MemoryMixer {
+    controls : Arc<Mutex<Vec<Control>>>
+}
+
+impl MemoryMixer {
+
+    pub fn new(controls: Vec<Control>) -> Self {
+        MemoryMixer {
+            controls: Arc::new(Mutex::new(controls))
+        }
+    }
+
+    fn update<F>(&mut self, name: &str, f: F) -> IOResult<()>
+        where F: Fn(&mut Control) -> IOResult<()> {
+        let mut controls = self.controls
+            .lock()
+            .unwrap();
+        match controls.iter_mut().find(|control| control.name == name) {
+            Some(control) => f(control),
+            _ => Err(IOError::new(IOErrorKind::NotFound, NO_SUCH_CONTROL))
+        }
+    }
+}
+
+fn unsupported() -> IOError {
+    IOError::new(IOErrorKind::InvalidInput, "the control can't do that")
+}
+
+impl MixerControl for MemoryMixer {
+
+    fn controls(&mut self) -> IOResult<Vec<Control>> {
+        Ok(self.controls
+            .lock()
+            .unwrap()
+            .clone())
+    }
+
+    fn set_volume(&mut self, name: &str, value: i64) -> IOResult<()> {
+        self.update(name, |control| match control.volume {
+            Some(ref mut volume) => {
+                volume.value = value.max(volume.min).min(volume.max);
+                Ok(())
+            },
+            _ => Err(unsupported())
+        })
+    }
+
+    fn set_db(&mut self, name: &str, db: f64) -> IOResult<()> {
+        self.update(name, |control| match control.db {
+            Some(ref mut level) => {
+                level.value = ((db * 100.0) as i64)
+                    .max(level.min)
+                    .min(level.max);
+                Ok(())
+            },
+            _ => Err(unsupported())
+        })
+    }
+
+    fn set_switch(&mut self, name: &str, on: bool) -> IOResult<()> {
+        self.update(name, |control| match control.switch {
+            Some(_) => {
+                control.switch = Some(on);
+                Ok(())
+            },
+            _ => Err(unsupported())
+        })
+    }
+}
+
+// a mixer control as the volume of the player. controls without a
+// decibel scale are set along their raw range, and those without a
+// switch are muted by turning them all the way down.
+pub struct MixerVolume<M: MixerControl> {
+    mixer   : M,
+    name    : String,
+    control : Control,
+    db      : f64
+}
+
+impl<M: MixerControl> MixerVolume<M> {
+
+    pub fn new(mut mixer: M, name: &str) -> IOResult<MixerVolume<M>> {
+
+        let control = try!(mixer.control(name));
+
+        if control.volume.is_none() {
+            return Err(IOError::new(IOErrorKind::InvalidInput,
+                "the control has no volume"));
+        }
+
+        Ok(MixerVolume {
+            mixer: mixer,
+            name: name.to_string(),
+            db: control.decibels().unwrap_or(0.0),
+            control: control
+        })
+    }
+
+    pub fn control(&self) -> &Control {
+        &self.control
+    }
+
+    fn apply(&mut self, db: f64) -> IOResult<()> {
+        match (self.control.db, self.control.volume) {
+            (Some(_), _) => self.mixer.set_db(&self.name, db),
+            (_, Some(level)) => self.mixer.set_volume(&self.name, level.min +
+                ((level.max - level.min) as f64 * db_to_linear(db)).round() as i64),
+            _ => Err(unsupported())
+        }
+    }
+}
+
+impl<M: MixerControl> HardwareVolume for MixerVolume<M> {
+
+    fn set_db(&mut self, db: f64) -> IOResult<()> {
+        try!(self.apply(db));
+        self.db = db;
+        Ok(())
+    }
+
+    fn set_mute(&mut self, muted: bool) -> IOResult<()> {
+        let db = self.db;
+        match (self.control.switch, muted) {
+            (Some(_), _) => self.mixer.set_switch(&self.name, !muted),
+            (_, true) => match self.control.volume {
+                Some(level) => self.mixer.set_volume(&self.name, level.min),
+                _ => Err(unsupported())
+            },
+            _ => self.apply(db)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use volume::HardwareVolume;
+
+    fn controls() -> Vec<Control> {
+
+        let mut master = Control::new("Master");
+        master.volume = Some(Level { min: 0, max: 87, value: 60 });
+        master.db = Some(Level { min: -6525, max: 0, value: -2025 });
+        master.switch = Some(true);
+
+        let mut pcm = Control::new("PCM");
+        pcm.volume = Some(Level { min: 0, max: 255, value: 255 });
+
+        let mut mute = Control::new("Headphone");
+        mute.switch = Some(false);
+
+        vec![master, pcm, mute]
+    }
+
+    #[test]
+    fn memory_mixer_test() {
+
+        let mut mixer = MemoryMixer::new(controls());
+
+        assert_eq!(3, mixer.controls().unwrap().len());
+        assert_eq!(Some(-20.25), mixer.control("Master").unwrap().decibels());
+        assert!(mixer.control("Headphone").unwrap().is_muted());
+        assert!(mixer.control("Bass").is_err());
+
+        mixer.set_volume("PCM", 300).unwrap();
+        assert_eq!(255, mixer.control("PCM").unwrap().volume.unwrap().value);
+        assert!(mixer.set_switch("PCM", false).is_err());
+        assert!(mixer.set_db("Headphone", -3.0).is_err());
+
+        let level = Level { min: -100, max: 100, value: 50 };
+        assert_eq!(0.75, level.fraction());
+    }
+
+    #[test]
+    fn mixer_volume_test() {
+
+        let mixer = MemoryMixer::new(controls());
+
+        let mut master = MixerVolume::new(mixer.clone(), "Master").unwrap();
+        master.set_db(-6.0).unwrap();
+        master.set_mute(true).unwrap();
+
+        let control = mixer.clone().control("Master").unwrap();
+        assert_eq!(Some(-6.0), control.decibels());
+        assert!(control.is_muted());
+
+        // no decibels and no switch
+        let mut pcm = MixerVolume::new(mixer.clone(), "PCM").unwrap();
+        pcm.set_db(-6.0).unwrap();
+        assert_eq!(128, mixer.clone().control("PCM").unwrap().volume.unwrap().value);
+        pcm.set_mute(true).unwrap();
+        assert_eq!(0, mixer.clone().control("PCM").unwrap().volume.unwrap().value);
+        pcm.set_mute(false).unwrap();
+        assert_eq!(128, mixer.clone().control("PCM").unwrap().volume.unwrap().value);
+
+        assert!(MixerVolume::new(mixer.clone(), "Headphone").is_err());
+    }
+
+    #[test]
+    fn card_of_test() {
+        assert_eq!("hw:0", card_of("plughw:0,0"));
+        assert_eq!("hw:1", card_of("hw:CARD=1,DEV=0"));
+        assert_eq!("default", card_of("default"));
+        assert_eq!("default", card_of("sysdefault:CARD=PCH"));
+    }
+
+    #[test]
+    fn unknown_card_test() {
+        assert!(Mixer::open("no such card").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "optional")]
+    fn mixer_test() {
+
+        let mut mixer = Mixer::open("default").unwrap();
+        let controls = mixer.controls().unwrap();
+        assert!(!controls.is_empty());
+
+        // setting a control to what it is changes nothing
+        for control in controls {
+            if let Some(volume) = control.volume {
+                mixer.set_volume(&control.name, volume.value).unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,929 @@
+use std::io::{ Read, Seek };
+use std::sync::mpsc::{ Receiver, Sender, channel };
+use std::thread::sleep;
+use std::time::Duration;
+
+use chain::{ Chain, Pass, Processor };
+use convert::{ Converter, Dither };
+use eq::{ Band, Equalizer };
+use loudness::Normalizer;
+use meter::Levels;
+use fade::{ Fade, Fades, frames };
+use io::*;
+use mix::Matrix;
+use resample::{ Quality, Resampler };
+use sample::SampleFormat;
+use sink::Sink;
+use sp_io::{ Format, SoundPcmIO, SoundPcmIORequest, SoundPcmIOResponse };
+use spectrum::{ Column, Stft, Tap };
+use volume::{ HardwareVolume, Volume, db_to_linear, linear_to_db };
+use wav::{ SeekTarget, WaveFormat, WaveReader };
+
+pub const FRAMES_PER_WRITE : usize = 4096;
+const WRITE_ALIGNMENT      : usize = 1;
+pub const IDLE_TIMEOUT     : u64 = 10;
+
+// the stages of the processing chain of an output, in the order they
+// run. other processors go in before the volume.
+pub const FADE      : &'static str = "fade";
+pub const MIX       : &'static str = "mix";
+pub const RESAMPLE  : &'static str = "resample";
+pub const EQ        : &'static str = "eq";
+pub const NORMALIZE : &'static str = "normalize";
+pub const VOLUME    : &'static str = "volume";
+// taps what is played, after the volume
+pub const SPECTRUM  : &'static str = "spectrum";
+
+fn expect_response<S: Sink + Send + 'static>(sp_io: &SoundPcmIO<S>)
+ -> IOResult<SoundPcmIOResponse> {
+    match sp_io.recv() {
+        Ok(SoundPcmIOResponse::Failed(err)) => Err(err),
+        Ok(SoundPcmIOResponse::Timeout) => Err(IOError::new(IO_ERROR,
+            "timeout")),
+        Ok(res) => Ok(res),
+        Err(err) => io_error(err)
+    }
+}
+
+// a pcm worker fed with tracks through a chain of processors, which
+// tracks in the same format follow each other on without a gap.
+pub struct Output<S: Sink + Send + 'static> {
+    sp_io     : SoundPcmIO<S>,
+    // the channels, rate and format the sink has been set up with
+    params    : Option<(u16, u32, SampleFormat)>,
+    // the channels, rate and channel mask of the tracks the processors
+    // are set up for, and the format they are played in. a track laid
+    // out the same goes on through the same processors, so that what
+    // they hold back carries over into it.
+    stream    : Option<(u16, u32, u32, SampleFormat)>,
+    written   : usize,
+    track     : (u16, u32),
+    fades     : Fades,
+    // fades first, on the frames of the track as they come in, and the
+    // volume last, before the samples are encoded
+    chain     : Chain,
+    channels  : Option<u16>,
+    route     : Option<Matrix>,
+    rate      : Option<u32>,
+    quality   : Quality,
+    dither    : Dither,
+    converter : Option<Converter>,
+    gain      : f64,
+    muted     : bool,
+    hardware  : Option<Box<HardwareVolume>>
+}
+
+impl<S: Sink + Send + 'static> Output<S> {
+
+    // the worker gives up once no request has come for `timeout`, unless
+    // the sink is paused.
+    pub fn start(sink: S, timeout: Duration) -> IOResult<Output<S>> {
+
+        let mut sp_io = SoundPcmIO::new(sink,
+            timeout.as_secs(),
+            timeout.subsec_nanos());
+
+        try!(sp_io.start());
+
+        let mut chain = Chain::new();
+        chain.set(FADE, Box::new(Pass));
+        chain.set(MIX, Box::new(Pass));
+        chain.set(RESAMPLE, Box::new(Pass));
+        chain.set(VOLUME, Box::new(Volume::new()));
+
+        Ok(Output {
+            sp_io: sp_io,
+            params: None,
+            stream: None,
+            written: 0,
+            track: (0, 0),
+            fades: Fades::default(),
+            chain: chain,
+            channels: None,
+            route: None,
+            rate: None,
+            quality: Quality::default(),
+            dither: Dither::default(),
+            converter: None,
+            gain: 1.0,
+            muted: false,
+            hardware: None
+        })
+    }
+
+    // runs the sink with `channels` whatever the tracks have, mixed the
+    // standard way
+    pub fn mix_to(&mut self, channels: u16) {
+        self.channels = Some(channels);
+    }
+
+    // mixes every track through `matrix`, which has to take as many
+    // channels as the tracks have
+    pub fn route(&mut self, matrix: Matrix) {
+        self.route = Some(matrix);
+    }
+
+    // runs the sink at `rate` whatever the rate of the tracks is
+    pub fn resample_to(&mut self, rate: u32, quality: Quality) {
+        self.rate = Some(rate);
+        self.quality = quality;
+    }
+
+    // tracks with more bits than the output format are dithered down to
+    // it. takes effect from the next track configured.
+    pub fn set_dither(&mut self, dither: Dither) {
+        self.dither = dither;
+    }
+
+    pub fn set_fades(&mut self, fades: Fades) {
+        self.fades = fades;
+    }
+
+    // the processors the samples go through, which can be changed
+    // between writes
+    pub fn chain(&mut self) -> &mut Chain {
+        &mut self.chain
+    }
+
+    // filters the output through `bands` from now on, or not at all
+    // when there are none. a running stream changes over smoothly.
+    pub fn set_equalizer(&mut self, bands: Vec<Band>) -> IOResult<()> {
+        match (bands.is_empty(), self.chain.contains(EQ)) {
+            (true, true) => self.chain.remove(EQ),
+            (true, false) => Ok(()),
+            (false, true) => self.chain.swap(EQ, Box::new(Equalizer::new(bands))),
+            (false, false) => {
+                // the equaliser goes ahead of the limiter
+                let before = match self.chain.contains(NORMALIZE) {
+                    true => NORMALIZE,
+                    _ => VOLUME
+                };
+                self.chain.insert(EQ,
+                    Box::new(Equalizer::new(bands)),
+                    Some(before))
+            }
+        }
+    }
+
+    // turns tracks to `target` LUFS with a limiter at `ceiling` dBTP,
+    // once they have been measured with set_loudness()
+    pub fn set_normalization(&mut self, target: f64, ceiling: f64)
+     -> IOResult<()> {
+        let normalizer = Box::new(Normalizer::new(target, ceiling));
+        match self.chain.contains(NORMALIZE) {
+            true => self.chain.swap(NORMALIZE, normalizer),
+            _ => self.chain.insert(NORMALIZE, normalizer, Some(VOLUME))
+        }
+    }
+
+    // sends a column of the spectrum of what is played to `tx` every hop
+    // of `stft`
+    pub fn tap_spectrum(&mut self, tx: Sender<Column>, stft: Stft) {
+        self.chain.set(SPECTRUM, Box::new(Tap::new(tx, stft)));
+    }
+
+    // the integrated loudness of the track being played, in LUFS
+    pub fn set_loudness(&mut self, loudness: f64) {
+        if let Some(normalizer) = self.chain.get_mut::<Normalizer>(NORMALIZE) {
+            normalizer.set_loudness(loudness);
+        }
+    }
+
+    fn volume_stage(&mut self) -> &mut Volume {
+        self.chain
+            .get_mut::<Volume>(VOLUME)
+            .expect("the volume stage is missing")
+    }
+
+    // fades the next frames written in, or lets them through as they
+    // are when there is no fade in. ends a fade out.
+    pub fn fade_in(&mut self) {
+        let (_, rate) = self.track;
+        let fade : Box<Processor> = match frames(self.fades.fade_in, rate) {
+            0 => Box::new(Pass),
+            frames => Box::new(Fade::fade_in(self.fades.curve, frames))
+        };
+        self.chain.set(FADE, fade);
+    }
+
+    // the frames of the track a fade out takes
+    pub fn fade_out_frames(&self) -> usize {
+        let (_, rate) = self.track;
+        frames(self.fades.fade_out, rate)
+    }
+
+    // writes `tail`, the frames that would have come next, fading out
+    // over them. anything written after it is silent until fade_in().
+    pub fn fade_out(&mut self, tail: &[f64]) -> IOResult<usize> {
+        let (channels, _) = self.track;
+        self.chain.set(FADE, Box::new(Fade::fade_out(self.fades.curve,
+            tail.len() / (channels as usize).max(1))));
+        self.write(tail)
+    }
+
+    // fades out over `tail` and pauses the sink once the fade has been
+    // played, rather than stopping the sound where it is
+    pub fn pause(&mut self, tail: &[f64]) -> IOResult<()> {
+
+        try!(self.fade_out(tail));
+
+        // silence behind the fade keeps the sink running until it
+        // pauses, so that it doesn't underrun
+        let silence = vec![0.0; tail.len()];
+        try!(self.write(&silence));
+
+        let margin = self.fades.fade_out;
+
+        loop {
+            let position = match try!(self.request(SoundPcmIORequest::Position)) {
+                SoundPcmIOResponse::Position(position) => position,
+                _ => panic!("unexpected response type")
+            };
+
+            let queued = position.queued();
+
+            if queued <= margin {
+                break;
+            }
+
+            sleep(queued - margin);
+        }
+
+        match try!(self.request(SoundPcmIORequest::Pause)) {
+            SoundPcmIOResponse::Paused => Ok(()),
+            _ => panic!("unexpected response type")
+        }
+    }
+
+    // resumes the sink and fades back in
+    pub fn resume(&mut self) -> IOResult<()> {
+        match try!(self.request(SoundPcmIORequest::Resume)) {
+            SoundPcmIOResponse::Resumed => {
+                self.fade_in();
+                Ok(())
+            },
+            _ => panic!("unexpected response type")
+        }
+    }
+
+    fn request(&self, req: SoundPcmIORequest<S>)
+     -> IOResult<SoundPcmIOResponse> {
+        match self.sp_io.send(req) {
+            Ok(_) => expect_response(&self.sp_io),
+            _ => Err(IOError::new(IOErrorKind::BrokenPipe,
+                "the pcm worker has stopped"))
+        }
+    }
+
+    // whether a track in `wave` format played in `format` goes on from
+    // the last one without anything being set up again
+    pub fn continues(&self, wave: &WaveFormat, format: SampleFormat) -> bool {
+        self.stream == Some((wave.channels, wave.sample_rate,
+            wave.channel_mask, format))
+    }
+
+    // sets the sink up for a track in `wave` format to be played in
+    // `format`, unless it already is. what has been written in the old
+    // params is played out before the stream is set up again.
+    // returns whether the params have changed.
+    pub fn configure(&mut self, wave: &WaveFormat, format: SampleFormat)
+     -> IOResult<bool> {
+
+        // adpcm is decoded to 16 bits
+        let source = wave.sample_format()
+            .unwrap_or(SampleFormat::S16_LE);
+
+        if self.continues(wave, format) {
+            let changed = self.converter
+                .as_ref()
+                .map(|converter| converter.from() != source)
+                .unwrap_or(true);
+            if changed {
+                let (channels, _) = self.chain.output();
+                self.converter = Some(Converter::new(source, format,
+                    channels as usize).dither(self.dither));
+            }
+            return Ok(false);
+        }
+
+        try!(self.end_track());
+
+        let starting = self.params.is_none();
+        self.track = (wave.channels, wave.sample_rate);
+
+        // the stream starts with a fade in
+        if starting {
+            self.fade_in();
+        }
+
+        let mixer : Box<Processor> = match (&self.route, self.channels) {
+            (&Some(ref route), _) if route.inputs() != wave.channels as usize =>
+                return Err(IOError::new(IOErrorKind::InvalidInput,
+                    "the channel matrix doesn't fit the track")),
+            (&Some(ref route), _) => Box::new(route.clone()),
+            (_, Some(channels)) if channels != wave.channels =>
+                Box::new(Matrix::standard(wave.channels,
+                    wave.channel_mask,
+                    channels).normalized()),
+            _ => Box::new(Pass)
+        };
+
+        let channels = match (&self.route, self.channels) {
+            (&Some(ref route), _) => route.outputs() as u16,
+            (_, Some(channels)) => channels,
+            _ => wave.channels
+        };
+        let sample_rate = wave.sample_rate;
+
+        let resampler : Box<Processor> = match self.rate {
+            Some(rate) if rate != sample_rate =>
+                Box::new(Resampler::new(sample_rate,
+                    rate,
+                    channels as usize,
+                    self.quality)),
+            _ => Box::new(Pass)
+        };
+
+        self.chain.set(MIX, mixer);
+        self.chain.set(RESAMPLE, resampler);
+
+        let (channels, rate) = self.chain.configure(wave.channels, sample_rate);
+
+        self.converter = Some(Converter::new(source, format, channels as usize)
+            .dither(self.dither));
+
+        let params = (channels, rate, format);
+        let stream = (wave.channels, wave.sample_rate, wave.channel_mask,
+            format);
+
+        match self.params {
+            Some(current) if current == params => {
+                self.stream = Some(stream);
+                return Ok(false);
+            },
+            Some(_) => try!(self.drain()),
+            _ => ()
+        }
+
+        // nothing goes on from a stream that fails to be set up
+        self.stream = None;
+
+        // the sink is opened in the format the samples are converted into
+        try!(self.request(SoundPcmIORequest::SetSampleFormat(format)));
+        try!(self.request(SoundPcmIORequest::SetParams(Format::new(channels,
+            rate as usize,
+            format.width() as u16))));
+
+        self.params = Some(params);
+        self.stream = Some(stream);
+        Ok(true)
+    }
+
+    // returns the number of bytes the sink accepted
+    pub fn write(&mut self, samples: &[f64]) -> IOResult<usize> {
+
+        if self.params.is_none() {
+            return Err(IOError::new(IO_ERROR,
+                "output has not been configured"));
+        }
+
+        // samples off the grid of the output format are dithered
+        // whatever its width
+        let gained = !self.volume_stage().is_unity();
+        let samples = self.chain.process(samples.to_vec());
+
+        self.write_out(samples, gained)
+    }
+
+    // writes out what the processors have held back of the track.
+    // returns the number of bytes the sink accepted.
+    pub fn end_track(&mut self) -> IOResult<usize> {
+
+        if self.params.is_none() {
+            return Ok(0);
+        }
+
+        let gained = !self.volume_stage().is_unity();
+        let tail = self.chain.flush();
+        self.write_out(tail, gained)
+    }
+
+    fn write_out(&mut self, samples: Vec<f64>, gained: bool)
+     -> IOResult<usize> {
+
+        let format = match self.params {
+            Some((_, _, format)) => format,
+            _ => return Err(IOError::new(IO_ERROR,
+                "output has not been configured"))
+        };
+
+        if samples.is_empty() {
+            return Ok(0);
+        }
+
+        let bytes = match self.converter {
+            Some(ref mut converter) => {
+                converter.set_requantize(gained);
+                converter.encode(&samples)
+            },
+            _ => format.encode_samples(&samples)
+        };
+
+        match try!(self.request(SoundPcmIORequest::Write(WriteBuffer::new(
+            &bytes,
+            WRITE_ALIGNMENT)))) {
+            SoundPcmIOResponse::Written(n) => {
+                self.written += n;
+                Ok(n)
+            },
+            _ => panic!("unexpected response type")
+        }
+    }
+
+    // the device's own control is used in place of scaling the samples
+    pub fn set_hardware_volume(&mut self, hardware: Box<HardwareVolume>) {
+        self.hardware = Some(hardware);
+        self.chain.set(VOLUME, Box::new(Volume::new()));
+    }
+
+    // the linear gain asked for last, whether the samples or the device
+    // is turned down by it
+    pub fn volume(&self) -> f64 {
+        self.gain
+    }
+
+    pub fn set_volume(&mut self, gain: f64) -> IOResult<()> {
+
+        match self.hardware {
+            Some(ref mut hardware) => try!(hardware.set_db(linear_to_db(gain))),
+            _ => self.volume_stage().set_linear(gain)
+        }
+
+        self.gain = gain;
+        Ok(())
+    }
+
+    pub fn set_volume_db(&mut self, db: f64) -> IOResult<()> {
+        self.set_volume(db_to_linear(db))
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn set_mute(&mut self, muted: bool) -> IOResult<()> {
+
+        match (&mut self.hardware, muted) {
+            (&mut Some(ref mut hardware), _) => try!(hardware.set_mute(muted)),
+            (_, true) => self.volume_stage().mute(),
+            _ => self.volume_stage().unmute()
+        }
+
+        self.muted = muted;
+        Ok(())
+    }
+
+    pub fn drain(&mut self) -> IOResult<()> {
+        match try!(self.request(SoundPcmIORequest::Drain)) {
+            SoundPcmIOResponse::Drained => Ok(()),
+            _ => panic!("unexpected response type")
+        }
+    }
+
+    // a sink has to drop what it has queued for the playback to jump at once
+    pub fn drop_queued(&mut self) -> IOResult<()> {
+
+        self.chain.reset();
+
+        match try!(self.request(SoundPcmIORequest::Drop)) {
+            SoundPcmIOResponse::Dropped => Ok(()),
+            _ => panic!("unexpected response type")
+        }
+    }
+
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    // the levels of what is written are sent to `tx` every period, for
+    // as long as it is there to receive them
+    pub fn subscribe(&mut self, tx: Sender<Levels>) -> IOResult<()> {
+        match try!(self.request(SoundPcmIORequest::Subscribe(tx))) {
+            SoundPcmIOResponse::Subscribed => Ok(()),
+            _ => panic!("unexpected response type")
+        }
+    }
+
+    // plays out what is left and stops the worker.
+    // returns the number of bytes written in all.
+    pub fn finish(mut self) -> IOResult<usize> {
+        try!(self.end_track());
+        if self.params.is_some() {
+            try!(self.drain());
+        }
+        try!(self.sp_io.stop());
+        Ok(self.written)
+    }
+}
+
+// what can be asked of a playback while it is going on
+pub enum PlayerRequest {
+    Seek(SeekTarget),
+    // a linear gain
+    Volume(f64),
+    Mute(bool),
+    // the bands of the equaliser, none to turn it off
+    Equalizer(Vec<Band>),
+    Stop
+}
+
+// decodes the data chunk, converts it into `format` and feeds it to the
+// sink through a pcm worker, the same way a sound card is fed.
+// returns the number of bytes the sink accepted.
+pub fn play<R, S>(reader: &mut WaveReader<R>, sink: S, format: SampleFormat)
+ -> IOResult<usize>
+    where R: Read + Seek, S: Sink + Send + 'static {
+    let (_tx, rx) = channel();
+    play_with(reader, sink, format, Fades::default(), &rx)
+}
+
+// jumps to `target` at once, dropping what has been queued. the
+// sound goes on from where it is dropped for a fade out, if there is one.
+fn seek<R, S>(output: &mut Output<S>,
+    reader: &mut WaveReader<R>,
+    target: SeekTarget) -> IOResult<()>
+    where R: Read + Seek, S: Sink + Send + 'static {
+
+    try!(output.drop_queued());
+
+    if output.fade_out_frames() > 0 && output.written() > 0 {
+        let tail = try!(reader.read_frames(output.fade_out_frames()));
+        try!(output.fade_out(&tail));
+    }
+
+    try!(reader.seek(target));
+    output.fade_in();
+    Ok(())
+}
+
+// plays like play() does with fades, taking requests between writes
+pub fn play_with<R, S>(reader: &mut WaveReader<R>,
+    sink: S,
+    format: SampleFormat,
+    fades: Fades,
+    requests: &Receiver<PlayerRequest>) -> IOResult<usize>
+    where R: Read + Seek, S: Sink + Send + 'static {
+
+    let wave = reader.header().format.clone();
+
+    let mut output = try!(Output::start(sink,
+        Duration::from_secs(IDLE_TIMEOUT)));
+
+    output.set_fades(fades);
+    try!(output.configure(&wave, format));
+
+    'playback: loop {
+
+        while let Ok(req) = requests.try_recv() {
+
+            // what is playing fades out after what has been queued
+            // when it stops, in place of being cut off
+            let fading = output.fade_out_frames() > 0 && output.written() > 0;
+
+            match req {
+                PlayerRequest::Seek(target) =>
+                    try!(seek(&mut output, reader, target)),
+                PlayerRequest::Volume(gain) => try!(output.set_volume(gain)),
+                PlayerRequest::Mute(muted) => try!(output.set_mute(muted)),
+                PlayerRequest::Equalizer(bands) =>
+                    try!(output.set_equalizer(bands)),
+                PlayerRequest::Stop if fading => {
+                    let tail = try!(reader.read_frames(output.fade_out_frames()));
+                    try!(output.fade_out(&tail));
+                    break 'playback;
+                },
+                PlayerRequest::Stop => {
+                    try!(output.drop_queued());
+                    break 'playback;
+                }
+            }
+        }
+
+        let samples = try!(reader.read_frames(FRAMES_PER_WRITE));
+
+        if samples.is_empty() {
+            break;
+        }
+
+        try!(output.write(&samples));
+    }
+
+    try!(output.end_track());
+    output.finish()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::Cursor;
+    use std::sync::{ Arc, Mutex };
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    use super::*;
+    use eq::{ Band, DEFAULT_Q, FilterKind };
+    use fade::{ Curve, Fades };
+    use fixtures::wave_of;
+    use sample::SampleFormat;
+    use sink::*;
+    use volume::HardwareVolume;
+    use wav::{ SeekTarget, WaveReader };
+
+    // 16 bit stereo at 8000 Hz, 2 frames
+    fn wave() -> Vec<u8> {
+        wave_of(SampleFormat::S16_LE, 2, 8000, &[0.0, 0.5, -0.5, 0.0])
+    }
+
+    #[test]
+    fn play_into_pipe_test() {
+
+        let mut reader = WaveReader::new(Cursor::new(wave()))
+            .unwrap();
+
+        let sink = MemorySink::memory();
+        let (output, log) = (sink.output().clone(), sink.log());
+
+        // 4 samples of 4 bytes
+        assert_eq!(16, play(&mut reader, sink, SampleFormat::S32_LE)
+            .unwrap());
+
+        assert_eq!(vec![0, 0, 0, 0, 0, 0, 0, 0x40,
+                        0, 0, 0, 0xc0, 0, 0, 0, 0],
+            output.bytes());
+        assert_eq!(Some(SinkParams { bits: 32, rate: 8000, channels: 2,
+                                     format: SampleFormat::S32_LE }),
+            log.params());
+    }
+
+    #[test]
+    fn play_in_format_test() {
+
+        let mut reader = WaveReader::new(Cursor::new(wave()))
+            .unwrap();
+
+        let sink = MemorySink::memory();
+        let (output, log) = (sink.output().clone(), sink.log());
+
+        // the sink takes packed 24 bit frames rather than 4 byte ones
+        assert_eq!(12, play(&mut reader, sink, SampleFormat::S24_3LE)
+            .unwrap());
+
+        assert_eq!(vec![0, 0, 0, 0, 0, 0x40, 0, 0, 0xc0, 0, 0, 0],
+            output.bytes());
+        assert_eq!(Some(SinkParams { bits: 24, rate: 8000, channels: 2,
+                                     format: SampleFormat::S24_3LE }),
+            log.params());
+    }
+
+    #[test]
+    fn play_float_test() {
+
+        let mut reader = WaveReader::new(Cursor::new(wave()))
+            .unwrap();
+
+        let output = MemoryOutput::new();
+        let sink = PipeSink::new(output.clone(), SampleFormat::FLOAT_LE);
+
+        assert_eq!(16, play(&mut reader, sink, SampleFormat::FLOAT_LE)
+            .unwrap());
+        assert_eq!(vec![0.0, 0.5, -0.5, 0.0],
+            SampleFormat::FLOAT_LE.decode_samples(&output.bytes()));
+    }
+
+    #[test]
+    fn seek_test() {
+
+        let mut reader = WaveReader::new(Cursor::new(wave()))
+            .unwrap();
+
+        let sink = MemorySink::memory();
+        let (output, log) = (sink.output().clone(), sink.log());
+
+        let (tx, rx) = channel();
+        tx.send(PlayerRequest::Seek(SeekTarget::Frame(1)))
+            .unwrap();
+
+        assert_eq!(8, play_with(&mut reader, sink, SampleFormat::S32_LE,
+            Fades::default(), &rx)
+            .unwrap());
+        assert_eq!(vec![0, 0, 0, 0xc0, 0, 0, 0, 0], output.bytes());
+
+        match log.events()[1] {
+            SinkEvent::Drop { .. } => (),
+            _ => panic!("drop event is expected")
+        }
+    }
+
+    #[test]
+    fn seek_with_fades_test() {
+
+        // 16 bit mono at 8000 Hz, 8 frames
+        let mut reader = WaveReader::new(Cursor::new(wave_of(
+            SampleFormat::S16_LE, 1, 8000, &[0.5; 8])))
+            .unwrap();
+        let wave = reader.format().clone();
+
+        let sink = MemorySink::memory();
+        let log = sink.log();
+
+        let mut out = Output::start(sink, Duration::from_secs(IDLE_TIMEOUT))
+            .unwrap();
+
+        // 2 frames each way
+        out.set_fades(Fades {
+            fade_in: Duration::new(0, 250_000),
+            fade_out: Duration::new(0, 250_000),
+            crossfade: Duration::new(0, 0),
+            curve: Curve::Linear
+        });
+        out.configure(&wave, SampleFormat::S16_LE).unwrap();
+
+        let samples = reader.read_frames(4).unwrap();
+        out.write(&samples).unwrap();
+        seek(&mut out, &mut reader, SeekTarget::Frame(0)).unwrap();
+        out.finish().unwrap();
+
+        // what has been queued is dropped before the fade out is written
+        let events = log.events();
+        match (&events[1], &events[2], &events[3]) {
+            (&SinkEvent::Write { len: 8, .. },
+             &SinkEvent::Drop { .. },
+             &SinkEvent::Write { len: 4, .. }) => (),
+            _ => panic!("unexpected events {:?}", &events[1..4])
+        }
+    }
+
+    #[test]
+    fn stop_test() {
+
+        let mut reader = WaveReader::new(Cursor::new(wave()))
+            .unwrap();
+
+        let (tx, rx) = channel();
+        tx.send(PlayerRequest::Stop)
+            .unwrap();
+
+        assert_eq!(0, play_with(&mut reader, MemorySink::memory(),
+            SampleFormat::S16_LE, Fades::default(), &rx).unwrap());
+    }
+
+    struct MockVolume(Arc<Mutex<Vec<(f64, bool)>>>);
+
+    impl HardwareVolume for MockVolume {
+        fn set_db(&mut self, db: f64) -> IOResult<()> {
+            self.0.lock().unwrap().push((db, false));
+            Ok(())
+        }
+        fn set_mute(&mut self, muted: bool) -> IOResult<()> {
+            self.0.lock().unwrap().push((0.0, muted));
+            Ok(())
+        }
+    }
+
+    // plays the test wave in float at the given volume
+    fn play_at(gain: f64, hardware: Option<MockVolume>) -> Vec<f64> {
+
+        let reader = WaveReader::new(Cursor::new(wave()))
+            .unwrap();
+        let (output, wave) = (MemoryOutput::new(), reader.format().clone());
+        let sink = PipeSink::new(output.clone(), SampleFormat::FLOAT_LE);
+
+        let mut out = Output::start(sink, Duration::from_secs(IDLE_TIMEOUT))
+            .unwrap();
+        if let Some(hardware) = hardware {
+            out.set_hardware_volume(Box::new(hardware));
+        }
+
+        // before the rate is known the gain is set at once
+        out.set_volume(gain).unwrap();
+        out.configure(&wave, SampleFormat::FLOAT_LE).unwrap();
+        out.write(&[0.0, 0.5, -0.5, 0.0]).unwrap();
+        out.finish().unwrap();
+
+        SampleFormat::FLOAT_LE.decode_samples(&output.bytes())
+    }
+
+    #[test]
+    fn equalizer_test() {
+
+        let reader = WaveReader::new(Cursor::new(wave()))
+            .unwrap();
+        let (output, wave) = (MemoryOutput::new(), reader.format().clone());
+        let sink = PipeSink::new(output.clone(), SampleFormat::FLOAT_LE);
+
+        let mut out = Output::start(sink, Duration::from_secs(IDLE_TIMEOUT))
+            .unwrap();
+
+        // a shelf takes everything well below it down by its gain
+        out.set_equalizer(vec![Band::new(FilterKind::LowShelf, 1000.0, -6.0,
+            DEFAULT_Q)]).unwrap();
+        out.configure(&wave, SampleFormat::FLOAT_LE).unwrap();
+        assert_eq!(vec![FADE, MIX, RESAMPLE, EQ, VOLUME], out.chain().names());
+
+        out.write(&[1.0; 8000]).unwrap();
+
+        // and lets it through again once it has faded out
+        out.set_equalizer(Vec::new()).unwrap();
+        out.write(&[1.0; 2000]).unwrap();
+        assert!(!out.chain().contains(EQ));
+        out.finish().unwrap();
+
+        let samples = SampleFormat::FLOAT_LE.decode_samples(&output.bytes());
+        assert_eq!(10000, samples.len());
+        assert!((samples[7999] - 0.501).abs() < 0.001);
+        assert_eq!(1.0, samples[9999]);
+        assert!(samples[8000..].windows(2).all(|pair| pair[1] >= pair[0] - 1e-6));
+    }
+
+    #[test]
+    fn fade_test() {
+
+        let reader = WaveReader::new(Cursor::new(wave()))
+            .unwrap();
+        let (output, wave) = (MemoryOutput::new(), reader.format().clone());
+        let sink = PipeSink::new(output.clone(), SampleFormat::FLOAT_LE);
+
+        let mut out = Output::start(sink, Duration::from_secs(IDLE_TIMEOUT))
+            .unwrap();
+
+        // 2 frames at 8000 Hz each way
+        out.set_fades(Fades {
+            fade_in: Duration::new(0, 250_000),
+            fade_out: Duration::new(0, 250_000),
+            crossfade: Duration::new(0, 0),
+            curve: Curve::Linear
+        });
+        out.configure(&wave, SampleFormat::FLOAT_LE).unwrap();
+        assert_eq!(2, out.fade_out_frames());
+
+        out.write(&[1.0; 6]).unwrap();
+        out.fade_out(&[1.0; 4]).unwrap();
+        out.write(&[1.0; 2]).unwrap();
+        out.finish().unwrap();
+
+        assert_eq!(vec![0.25, 0.25, 0.75, 0.75, 1.0, 1.0,
+                        0.75, 0.75, 0.25, 0.25, 0.0, 0.0],
+            SampleFormat::FLOAT_LE.decode_samples(&output.bytes()));
+    }
+
+    #[test]
+    fn meter_test() {
+
+        let reader = WaveReader::new(Cursor::new(wave()))
+            .unwrap();
+        let (output, wave) = (MemoryOutput::new(), reader.format().clone());
+        let sink = PipeSink::new(output.clone(), SampleFormat::S16_LE);
+
+        let mut out = Output::start(sink, Duration::from_secs(IDLE_TIMEOUT))
+            .unwrap();
+
+        let (tx, rx) = channel();
+        out.subscribe(tx).unwrap();
+        out.configure(&wave, SampleFormat::S16_LE).unwrap();
+
+        // a period is 400 frames at 8000 Hz
+        let mut samples = Vec::new();
+        for _ in 0..500 {
+            samples.push(0.5);
+            samples.push(-1.0);
+        }
+        out.write(&samples).unwrap();
+        out.finish().unwrap();
+
+        // levels are sent before the write is answered
+        let mut levels = Vec::new();
+        while let Ok(level) = rx.try_recv() {
+            levels.push(level);
+        }
+        assert_eq!(1, levels.len());
+        assert_eq!(400, levels[0].frames);
+        assert_eq!(0.5, levels[0].channels[0].peak);
+        assert_eq!(1.0, levels[0].channels[1].rms);
+        assert_eq!(400, levels[0].clips());
+    }
+
+    #[test]
+    fn volume_test() {
+
+        assert_eq!(vec![0.0, 0.25, -0.25, 0.0], play_at(0.5, None));
+
+        // the device turns itself down instead
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        assert_eq!(vec![0.0, 0.5, -0.5, 0.0],
+            play_at(0.5, Some(MockVolume(calls.clone()))));
+
+        let calls = calls.lock().unwrap();
+        assert_eq!(1, calls.len());
+        assert!((calls[0].0 + 6.02).abs() < 0.01);
+    }
+}
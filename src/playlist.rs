@@ -0,0 +1,408 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{ Path, PathBuf };
+use std::time::Duration;
+
+use io::*;
+
+const EXTM3U : &'static str = "#EXTM3U";
+const EXTINF : &'static str = "#EXTINF:";
+const PLS_SECTION : &'static str = "[playlist]";
+const FILE_URI : &'static str = "file://";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Location {
+    File(PathBuf),
+    // streams are listed in playlists but can't be played from here
+    Url(String)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub location : Location,
+    pub title    : Option<String>,
+    pub duration : Option<Duration>
+}
+
+impl Entry {
+
+    fn new(location: Location) -> Self {
+        Entry {
+            location: location,
+            title: None,
+            duration: None
+        }
+    }
+
+    pub fn path(&self) -> String {
+        match self.location {
+            Location::File(ref path) => path
+                .to_string_lossy()
+                .into_owned(),
+            Location::Url(ref url) => url.clone()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlaylistFormat {
+    M3u,
+    M3u8,
+    Pls
+}
+
+impl PlaylistFormat {
+
+    // playlists are told from other files by their extension only
+    pub fn of(path: &str) -> Option<PlaylistFormat> {
+        let ext = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+
+        match ext.as_ref().map(|ext| ext.as_str()) {
+            Some("m3u") => Some(PlaylistFormat::M3u),
+            Some("m3u8") => Some(PlaylistFormat::M3u8),
+            Some("pls") => Some(PlaylistFormat::Pls),
+            _ => None
+        }
+    }
+}
+
+pub fn is_playlist(path: &str) -> bool {
+    PlaylistFormat::of(path).is_some()
+}
+
+// m3u8 and pls are utf-8, plain m3u is usually latin-1 but is taken as
+// utf-8 when it happens to be valid.
+fn decode_text(bytes: Vec<u8>, format: PlaylistFormat) -> String {
+
+    match (format, String::from_utf8(bytes)) {
+        (_, Ok(text)) => text,
+        (PlaylistFormat::M3u, Err(err)) => err.into_bytes()
+            .iter()
+            .map(|byte| *byte as char)
+            .collect(),
+        (_, Err(err)) => String::from_utf8_lossy(&err.into_bytes())
+            .into_owned()
+    }
+}
+
+// lines of a playlist without the byte order mark and surrounding blanks
+fn lines<'a>(text: &'a str) -> Box<Iterator<Item=&'a str> + 'a> {
+    Box::new(text
+        .trim_left_matches('\u{feff}')
+        .lines()
+        .map(|line| line.trim()))
+}
+
+fn hex_digit(byte: u8) -> Option<u8> {
+    (byte as char)
+        .to_digit(16)
+        .map(|digit| digit as u8)
+}
+
+fn percent_decode(st: &str) -> String {
+
+    let bytes = st.as_bytes();
+    let (mut decoded, mut i) = (Vec::new(), 0);
+
+    while i < bytes.len() {
+        let hex = match bytes[i] == b'%' && i + 2 < bytes.len() {
+            true => match (hex_digit(bytes[i + 1]), hex_digit(bytes[i + 2])) {
+                (Some(high), Some(low)) => Some(high << 4 | low),
+                _ => None
+            },
+            _ => None
+        };
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded)
+        .into_owned()
+}
+
+// relative paths are relative to the directory the playlist is in
+fn locate(entry: &str, base: &Path) -> Location {
+
+    if entry.starts_with(FILE_URI) {
+        // the host part of file://host/path is dropped
+        let rest = &entry[FILE_URI.len()..];
+        let path = match rest.find('/') {
+            Some(start) => &rest[start..],
+            _ => rest
+        };
+        return Location::File(PathBuf::from(percent_decode(path)));
+    }
+
+    if entry.contains("://") {
+        return Location::Url(entry.to_string());
+    }
+
+    let path = Path::new(entry);
+
+    match path.is_absolute() {
+        true => Location::File(path.to_path_buf()),
+        _ => Location::File(base.join(path))
+    }
+}
+
+fn parse_seconds(st: &str) -> Option<Duration> {
+    match st.trim().parse::<f64>() {
+        Ok(secs) if secs >= 0.0 => Some(Duration::new(secs as u64,
+            (secs.fract() * 1e9) as u32)),
+        _ => None
+    }
+}
+
+// #EXTINF:<seconds> [attributes],<title>
+// the title starts at the first comma outside of quoted attributes.
+fn parse_extinf(info: &str) -> (Option<Duration>, Option<String>) {
+
+    let mut quoted = false;
+    let comma = info
+        .char_indices()
+        .filter(|&(_, ch)| {
+            if ch == '"' {
+                quoted = !quoted;
+            }
+            ch == ',' && !quoted
+        })
+        .map(|(pos, _)| pos)
+        .next();
+
+    let (head, title) = match comma {
+        Some(pos) => match info[pos + 1..].trim() {
+            "" => (&info[..pos], None),
+            title => (&info[..pos], Some(title.to_string()))
+        },
+        _ => (info, None)
+    };
+
+    let duration = head
+        .split_whitespace()
+        .next()
+        .and_then(parse_seconds);
+
+    (duration, title)
+}
+
+pub fn parse_m3u(text: &str, base: &Path) -> Vec<Entry> {
+
+    let mut entries = Vec::new();
+    let mut info = None;
+
+    for line in lines(text) {
+
+        if line.is_empty() || line == EXTM3U {
+            continue;
+        }
+
+        if line.starts_with(EXTINF) {
+            info = Some(parse_extinf(&line[EXTINF.len()..]));
+            continue;
+        }
+
+        // any other directive or comment
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let mut entry = Entry::new(locate(line, base));
+
+        if let Some((duration, title)) = info.take() {
+            entry.duration = duration;
+            entry.title = title;
+        }
+
+        entries.push(entry);
+    }
+
+    entries
+}
+
+// [playlist] with FileN, TitleN and LengthN keys, ordered by N
+pub fn parse_pls(text: &str, base: &Path) -> IOResult<Vec<Entry>> {
+
+    let mut lines = lines(text)
+        .filter(|line| !line.is_empty() && !line.starts_with(';'));
+
+    match lines.next() {
+        Some(section) if section.to_lowercase() == PLS_SECTION => (),
+        _ => return Err(IOError::new(IOErrorKind::InvalidData,
+            "not a pls playlist"))
+    }
+
+    let mut fields = BTreeMap::new();
+
+    for line in lines {
+
+        let (key, value) = match line.find('=') {
+            Some(pos) => (line[..pos].trim().to_lowercase(),
+                line[pos + 1..].trim()),
+            _ => continue
+        };
+
+        let split = key
+            .find(|ch: char| ch.is_digit(10))
+            .unwrap_or(key.len());
+
+        let index = match key[split..].parse::<u32>() {
+            Ok(index) => index,
+            _ => continue
+        };
+
+        let field = fields
+            .entry(index)
+            .or_insert((None, None, None));
+
+        match &key[..split] {
+            "file" => field.0 = Some(locate(value, base)),
+            "title" => field.1 = Some(value.to_string()),
+            "length" => field.2 = parse_seconds(value),
+            _ => ()
+        }
+    }
+
+    Ok(fields
+        .into_iter()
+        .filter_map(|(_, (location, title, duration))| location
+            .map(|location| Entry {
+                location: location,
+                title: title,
+                duration: duration
+            }))
+        .collect())
+}
+
+pub fn load(path: &str) -> IOResult<Vec<Entry>> {
+
+    let format = match PlaylistFormat::of(path) {
+        Some(format) => format,
+        _ => return Err(IOError::new(IOErrorKind::InvalidInput,
+            "not a playlist"))
+    };
+
+    let mut bytes = Vec::new();
+    try!(File::open(path)
+        .and_then(|mut f| f.read_to_end(&mut bytes)));
+
+    let text = decode_text(bytes, format);
+    let base = Path::new(path)
+        .parent()
+        .unwrap_or(Path::new(""));
+
+    match format {
+        PlaylistFormat::Pls => parse_pls(&text, base),
+        _ => Ok(parse_m3u(&text, base))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::path::{ Path, PathBuf };
+    use std::time::Duration;
+
+    use super::*;
+    use fixtures::TempDir;
+
+    fn file(path: &str) -> Location {
+        Location::File(PathBuf::from(path))
+    }
+
+    #[test]
+    fn m3u_test() {
+
+        let text = "\u{feff}#EXTM3U\r\n\
+            #EXTINF:123,Artist - Take 1\r\n\
+            take1.wav\r\n\
+            \r\n\
+            # a comment\r\n\
+            #EXTINF:-1 group=\"a,b\",Take 2\r\n\
+            /abs/take2.wav\r\n\
+            http://example.com/stream\r\n\
+            file:///tmp/take%203.wav\r\n";
+
+        let entries = parse_m3u(text, Path::new("/lists"));
+
+        assert_eq!(4, entries.len());
+
+        assert_eq!(Entry {
+                location: file("/lists/take1.wav"),
+                title: Some("Artist - Take 1".to_string()),
+                duration: Some(Duration::from_secs(123))
+            },
+            entries[0]);
+
+        // -1 is how m3u says the length isn't known
+        assert_eq!(Entry {
+                location: file("/abs/take2.wav"),
+                title: Some("Take 2".to_string()),
+                duration: None
+            },
+            entries[1]);
+
+        assert_eq!(Location::Url("http://example.com/stream".to_string()),
+            entries[2].location);
+        assert_eq!(None, entries[2].title);
+        assert_eq!(file("/tmp/take 3.wav"), entries[3].location);
+    }
+
+    #[test]
+    fn pls_test() {
+
+        let text = "[playlist]\n\
+            File2=b.wav\n\
+            Title2=Second\n\
+            File1=/abs/a.wav\n\
+            Length1=12.5\n\
+            Title3=no file\n\
+            NumberOfEntries=2\n\
+            Version=2\n";
+
+        let entries = parse_pls(text, Path::new("dir"))
+            .unwrap();
+
+        assert_eq!(vec![
+                Entry {
+                    location: file("/abs/a.wav"),
+                    title: None,
+                    duration: Some(Duration::from_millis(12500))
+                },
+                Entry {
+                    location: file("dir/b.wav"),
+                    title: Some("Second".to_string()),
+                    duration: None
+                }
+            ],
+            entries);
+
+        assert!(parse_pls("File1=a.wav", Path::new("")).is_err());
+    }
+
+    #[test]
+    fn load_test() {
+
+        let dir = TempDir::new("playlist_load_test");
+
+        // latin-1, which isn't valid utf-8
+        let entries = load(&dir.write("list.m3u", b"caf\xe9.wav\n"));
+
+        assert_eq!(file(&dir.path("café.wav")), entries.unwrap()[0].location);
+
+        assert!(is_playlist("a/B.M3U8"));
+        assert!(!is_playlist("a.wav"));
+        assert!(load("a.wav").is_err());
+    }
+}
@@ -0,0 +1,790 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{ Read, Seek };
+use std::sync::mpsc::Sender;
+use std::thread::{ JoinHandle, spawn };
+use std::time::Duration;
+
+use convert::Dither;
+use eq::Band;
+use fade::{ Fades, crossfade, frames };
+use generator::Generator;
+use io::*;
+use loudness::{ Measurement, scan };
+use meter::Levels;
+use mix::Matrix;
+use player::{ FRAMES_PER_WRITE, IDLE_TIMEOUT, Output };
+use resample::Quality;
+use sample::SampleFormat;
+use silence;
+use silence::Detection;
+use sink::Sink;
+use spectrum::{ Column, Stft };
+use volume::HardwareVolume;
+use wav::{ SeekTarget, WaveFormat, WaveReader };
+
+// what a track is decoded from, a file or a wave made in memory
+trait Source : Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> Source for T {}
+
+// an entry of a queue: a file, or a signal made as it is played
+#[derive(Clone, Debug)]
+pub enum Entry {
+    File(String),
+    Generated(Generator)
+}
+
+impl Entry {
+
+    pub fn name(&self) -> String {
+        match *self {
+            Entry::File(ref path) => path.clone(),
+            Entry::Generated(ref generator) => generator.name()
+        }
+    }
+
+    fn open(&self) -> IOResult<WaveReader<Box<Source>>> {
+        let source : Box<Source> = match *self {
+            Entry::File(ref path) => Box::new(try!(File::open(path))),
+            Entry::Generated(ref generator) =>
+                Box::new(try!(generator.wave()))
+        };
+        WaveReader::new(source)
+    }
+
+    // the length of an entry that can be opened
+    fn length(&self) -> Option<Duration> {
+        match *self {
+            Entry::File(ref path) => WaveReader::open(path)
+                .ok()
+                .map(|reader| reader.header().duration()),
+            Entry::Generated(ref generator) => Some(generator.length())
+        }
+    }
+}
+
+// a file opened ahead of its turn with its first frames decoded,
+// so that it can start the moment the previous one ends. a file to be
+// normalised is measured first, and one to be played without the
+// silence at its ends is searched for it.
+pub struct Track {
+    path     : String,
+    reader   : WaveReader<Box<Source>>,
+    first    : Vec<f64>,
+    loudness : Option<Measurement>,
+    skip     : u64,
+    left     : Option<u64>
+}
+
+impl Track {
+
+    pub fn load(entry: Entry, measure: bool, silence: Option<Detection>)
+     -> IOResult<Track> {
+
+        let mut reader = try!(entry.open());
+
+        let loudness = match measure {
+            true => Some(try!(scan(&mut reader))),
+            _ => None
+        };
+
+        // decoding starts at the block the sound starts in, the frames
+        // ahead of it are skipped
+        let (skip, left) = match silence {
+            Some(detection) => {
+                let (spans, frames) = try!(silence::scan(&mut reader,
+                    detection));
+                let (start, end) = silence::audible(&spans, frames);
+                let point = try!(reader.seek(SeekTarget::Frame(start)));
+                (start - point.frame, Some(end - point.frame))
+            },
+            _ => (0, None)
+        };
+
+        let mut track = Track {
+            path: entry.name(),
+            reader: reader,
+            first: Vec::new(),
+            loudness: loudness,
+            skip: skip,
+            left: left
+        };
+
+        track.first = try!(track.read(FRAMES_PER_WRITE));
+        Ok(track)
+    }
+
+    // decoded frames, empty at the end of the file or of its sound
+    fn read(&mut self, max_frames: usize) -> IOResult<Vec<f64>> {
+
+        let channels = self.reader.format().channels.max(1) as u64;
+        let mut samples = try!(self.reader.read_frames(max_frames));
+
+        if let Some(left) = self.left {
+            let keep = (left * channels).min(samples.len() as u64);
+            samples.truncate(keep as usize);
+            self.left = Some(left - keep / channels);
+        }
+
+        let skip = (self.skip * channels).min(samples.len() as u64);
+        samples.drain(..skip as usize);
+        self.skip -= skip / channels;
+
+        Ok(samples)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn format(&self) -> &WaveFormat {
+        self.reader.format()
+    }
+
+    pub fn loudness(&self) -> Option<Measurement> {
+        self.loudness
+    }
+}
+
+fn preload(entry: Entry, measure: bool, silence: Option<Detection>)
+ -> (String, JoinHandle<IOResult<Track>>) {
+    (entry.name(), spawn(move || Track::load(entry, measure, silence)))
+}
+
+// how a single entry of a queue went. a file that can't be opened or
+// decoded fails on its own, the rest of the queue is played anyway.
+#[derive(Debug)]
+pub struct TrackResult {
+    pub path   : String,
+    pub result : IOResult<usize>
+}
+
+// the end of a file held back to be crossfaded into the next one, and
+// the entry of the queue it belongs to. files are crossfaded only when
+// the output goes on from one to the other, so that they can be mixed
+// frame by frame.
+struct Tail {
+    samples : Vec<f64>,
+    index   : usize
+}
+
+// files played one after another on a single stream. the stream is set
+// up again only between files whose channels, rate or output format
+// differ, so files in the same format follow each other without a gap
+// through the same processors, or crossfade into each other when a
+// crossfade is set.
+pub struct Queue {
+    entries   : VecDeque<Entry>,
+    channels  : Option<u16>,
+    route     : Option<Matrix>,
+    resample  : Option<(u32, Quality)>,
+    dither    : Dither,
+    volume    : f64,
+    hardware  : Option<Box<HardwareVolume>>,
+    fades     : Fades,
+    bands     : Vec<Band>,
+    normalize : Option<(f64, f64)>,
+    silence   : Option<Detection>,
+    levels    : Option<Sender<Levels>>,
+    spectrum  : Option<(Sender<Column>, Stft)>
+}
+
+impl Queue {
+
+    pub fn new() -> Self {
+        Queue {
+            entries: VecDeque::new(),
+            channels: None,
+            route: None,
+            resample: None,
+            dither: Dither::default(),
+            volume: 1.0,
+            hardware: None,
+            fades: Fades::default(),
+            bands: Vec::new(),
+            normalize: None,
+            silence: None,
+            levels: None,
+            spectrum: None
+        }
+    }
+
+    // plays every file with `channels`, as a device that takes no other
+    // number of channels needs
+    pub fn mix_to(&mut self, channels: u16) {
+        self.channels = Some(channels);
+    }
+
+    // mixes every file through `matrix`
+    pub fn route(&mut self, matrix: Matrix) {
+        self.route = Some(matrix);
+    }
+
+    // plays every file at `rate`, so that only the number of channels or
+    // the output format can make the stream be set up again
+    pub fn resample_to(&mut self, rate: u32, quality: Quality) {
+        self.resample = Some((rate, quality));
+    }
+
+    // how files with more bits than their output format are reduced
+    pub fn dither(&mut self, dither: Dither) {
+        self.dither = dither;
+    }
+
+    // a linear gain for every file
+    pub fn volume(&mut self, gain: f64) {
+        self.volume = gain;
+    }
+
+    // turns the device down in place of the samples
+    pub fn hardware_volume(&mut self, hardware: Box<HardwareVolume>) {
+        self.hardware = Some(hardware);
+    }
+
+    // the crossfade goes between files, fading in and out goes at the
+    // start of the queue
+    pub fn fades(&mut self, fades: Fades) {
+        self.fades = fades;
+    }
+
+    // filters every file through an equaliser of `bands`
+    pub fn equalizer(&mut self, bands: Vec<Band>) {
+        self.bands = bands;
+    }
+
+    // turns every file to `target` LUFS with a limiter at `ceiling`
+    // dBTP. files are measured as they are opened.
+    pub fn normalize(&mut self, target: f64, ceiling: f64) {
+        self.normalize = Some((target, ceiling));
+    }
+
+    // leaves out the silence at the start and end of every file. files
+    // are searched for it as they are opened.
+    pub fn skip_silence(&mut self, detection: Detection) {
+        self.silence = Some(detection);
+    }
+
+    // sends the levels of what is played to `tx` as it goes
+    pub fn meter(&mut self, tx: Sender<Levels>) {
+        self.levels = Some(tx);
+    }
+
+    // sends the spectrum of what is played to `tx` as it goes
+    pub fn spectrum(&mut self, tx: Sender<Column>, stft: Stft) {
+        self.spectrum = Some((tx, stft));
+    }
+
+    pub fn push(&mut self, path: String) {
+        self.entries.push_back(Entry::File(path));
+    }
+
+    // plays a signal as if it were a file
+    pub fn push_generator(&mut self, generator: Generator) {
+        self.entries.push_back(Entry::Generated(generator));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // the length of the entries that can be opened
+    pub fn duration(&self) -> Duration {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.length())
+            .fold(Duration::new(0, 0), |sum, length| sum + length)
+    }
+
+    // `choose` picks the output format for each file.
+    // the next file is opened while the current one is being played.
+    pub fn play<S, F>(mut self, sink: S, choose: F)
+     -> IOResult<Vec<TrackResult>>
+        where S: Sink + Send + 'static, F: Fn(&WaveFormat) -> SampleFormat {
+
+        let mut output = try!(Output::start(sink,
+            Duration::from_secs(IDLE_TIMEOUT)));
+
+        if let Some(tx) = self.levels.take() {
+            try!(output.subscribe(tx));
+        }
+
+        if let Some((tx, stft)) = self.spectrum.take() {
+            output.tap_spectrum(tx, stft);
+        }
+
+        if let Some((rate, quality)) = self.resample {
+            output.resample_to(rate, quality);
+        }
+
+        output.set_dither(self.dither);
+
+        if let Some(hardware) = self.hardware.take() {
+            output.set_hardware_volume(hardware);
+        }
+
+        try!(output.set_volume(self.volume));
+
+        if let Some(channels) = self.channels {
+            output.mix_to(channels);
+        }
+
+        if let Some(matrix) = self.route.take() {
+            output.route(matrix);
+        }
+
+        output.set_fades(self.fades);
+        if let Some((target, ceiling)) = self.normalize {
+            try!(output.set_normalization(target, ceiling));
+        }
+
+        try!(output.set_equalizer(self.bands.clone()));
+
+        let (measure, silence) = (self.normalize.is_some(), self.silence);
+        let mut results = Vec::new();
+        let mut tail = None;
+        let mut last = None;
+        let mut next = self.entries
+            .pop_front()
+            .map(|entry| preload(entry, measure, silence));
+
+        while let Some((path, handle)) = next.take() {
+
+            let loaded = match handle.join() {
+                Ok(loaded) => loaded,
+                _ => Err(IOError::new(IO_ERROR, "failed to load the file"))
+            };
+
+            next = self.entries
+                .pop_front()
+                .map(|entry| preload(entry, measure, silence));
+
+            let result = match loaded {
+                Ok(track) => {
+                    let format = choose(track.format());
+                    if !output.continues(track.format(), format) {
+                        end_track(&mut output, tail.take(), last,
+                            &mut results);
+                    }
+                    last = Some(results.len());
+                    play_track(&mut output, track, format, &self.fades,
+                        &mut tail, &mut results)
+                },
+                Err(err) => Err(err)
+            };
+
+            results.push(TrackResult {
+                path: path,
+                result: result
+            });
+        }
+
+        end_track(&mut output, tail, last, &mut results);
+        try!(output.finish());
+        Ok(results)
+    }
+}
+
+// plays out the end of the file played last, before the stream moves
+// on to something else: the end held back for a crossfade, as it is,
+// and what the processors still hold of the file
+fn end_track<S>(output: &mut Output<S>,
+    tail: Option<Tail>,
+    last: Option<usize>,
+    results: &mut Vec<TrackResult>) where S: Sink + Send + 'static {
+
+    let index = match last {
+        Some(index) => index,
+        _ => return
+    };
+
+    let samples = tail
+        .map(|tail| tail.samples)
+        .unwrap_or(Vec::new());
+
+    let written = output
+        .write(&samples)
+        .and_then(|n| output.end_track().map(|tail| n + tail));
+
+    credit(results, index, written);
+}
+
+// adds what has been written of the file at `index` to its result,
+// unless it has already failed
+fn credit(results: &mut Vec<TrackResult>, index: usize,
+    written: IOResult<usize>) {
+    let updated = match (results[index].result.as_ref(), written) {
+        (Err(_), _) => return,
+        (Ok(n), Ok(written)) => Ok(*n + written),
+        (_, Err(err)) => Err(err)
+    };
+    results[index].result = updated;
+}
+
+// plays a file, crossfaded from `tail` when there is one. with a
+// crossfade set, the end of the file is left in `tail` for the next one.
+// the file goes in `results` next, the bytes of a crossfade count for
+// both the files in it.
+fn play_track<S>(output: &mut Output<S>,
+    mut track: Track,
+    format: SampleFormat,
+    fades: &Fades,
+    tail: &mut Option<Tail>,
+    results: &mut Vec<TrackResult>) -> IOResult<usize>
+    where S: Sink + Send + 'static {
+
+    let wave = track.format().clone();
+    let channels = wave.channels as usize;
+    let hold = frames(fades.crossfade, wave.sample_rate) * channels;
+
+    let mut written = 0;
+    let mut pending = track.first.split_off(0);
+
+    // the overlap is played the way the previous file was, and the
+    // output is set up for this one once it is out
+    if let Some(previous) = tail.take() {
+
+        while pending.len() < previous.samples.len() {
+            let more = try!(track.read(FRAMES_PER_WRITE));
+            if more.is_empty() {
+                break;
+            }
+            pending.extend(more);
+        }
+
+        let split = previous.samples.len().min(pending.len());
+        let rest = pending.split_off(split);
+        let mixed = crossfade(&previous.samples, &pending, channels,
+            fades.curve);
+
+        let overlap = try!(output.write(&mixed));
+        credit(results, previous.index, Ok(overlap));
+        written += overlap;
+        pending = rest;
+    }
+
+    try!(output.configure(&wave, format));
+
+    if let Some(loudness) = track.loudness() {
+        output.set_loudness(loudness.integrated);
+    }
+
+    loop {
+
+        if pending.len() > hold {
+            let keep = pending.len() - hold;
+            let held = pending.split_off(keep);
+            written += try!(output.write(&pending));
+            pending = held;
+        }
+
+        let more = try!(track.read(FRAMES_PER_WRITE));
+
+        if more.is_empty() {
+            break;
+        }
+
+        pending.extend(more);
+    }
+
+    // what the processors hold back is played out with the start of the
+    // next file, or once the stream moves on
+    if hold > 0 {
+        *tail = Some(Tail {
+            samples: pending,
+            index: results.len()
+        });
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::Duration;
+
+    use super::*;
+    use fade::{ Curve, Fades };
+    use fixtures::{ TempDir, sine, wave_of };
+    use generator::{ Generator, Signal };
+    use loudness::{ DEFAULT_CEILING, DEFAULT_TARGET, Meter };
+    use resample::{ Quality, Resampler };
+    use sample::SampleFormat;
+    use silence::Detection;
+    use sink::*;
+
+    // 16 bit stereo, 2 frames at the given rate
+    fn create(dir: &TempDir, name: &str, rate: u32) -> String {
+        dir.write(name, &wave_of(SampleFormat::S16_LE, 2, rate,
+            &[0.0, 0.5, -0.5, 0.0]))
+    }
+
+    // 16 bit stereo, a second of 1 kHz at 8000 Hz and `db` below full
+    // scale
+    fn tone(dir: &TempDir, name: &str, db: f64) -> String {
+        let samples = Generator::new(Signal::Sine(1000.0))
+            .rate(8000)
+            .channels(2)
+            .level(10.0f64.powf(db / 20.0))
+            .duration(Duration::from_secs(1))
+            .samples(0, 8000);
+        dir.write(name, &wave_of(SampleFormat::S16_LE, 2, 8000, &samples))
+    }
+
+    #[test]
+    fn queue_test() {
+
+        let dir = TempDir::new("queue_test");
+        let paths = [create(&dir, "1.wav", 8000),
+                     create(&dir, "2.wav", 8000),
+                     create(&dir, "3.wav", 16000)];
+        let missing = dir.path("no_such_file.wav");
+
+        let mut queue = Queue::new();
+        queue.push(paths[0].clone());
+        queue.push(missing.clone());
+        queue.push(paths[1].clone());
+        queue.push(paths[2].clone());
+
+        let sink = MemorySink::memory();
+        let (output, log) = (sink.output().clone(), sink.log());
+
+        let results = queue.play(sink, |_| SampleFormat::S16_LE)
+            .unwrap();
+
+        assert_eq!(4, results.len());
+        assert_eq!(8, *results[0].result.as_ref().unwrap());
+        assert!(results[1].result.is_err());
+        assert_eq!(missing, results[1].path);
+        assert_eq!(24, output.bytes().len());
+
+        // the stream is set up again only for the change of rate, after
+        // the first two files have been played out
+        let events = log.events();
+        let kinds = events
+            .iter()
+            .map(|event| match *event {
+                SinkEvent::SetParams { .. } => "set",
+                SinkEvent::Write { .. } => "write",
+                SinkEvent::Drain { .. } => "drain",
+                _ => "other"
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(vec!["set", "write", "write", "drain", "set", "write",
+                        "drain"],
+            kinds);
+    }
+
+    #[test]
+    fn resample_test() {
+
+        let dir = TempDir::new("queue_resample_test");
+
+        let mut queue = Queue::new();
+        queue.push(create(&dir, "1.wav", 8000));
+        queue.push(create(&dir, "2.wav", 16000));
+        queue.resample_to(16000, Quality::Linear);
+
+        let sink = MemorySink::memory();
+        let (output, log) = (sink.output().clone(), sink.log());
+
+        let results = queue.play(sink, |_| SampleFormat::S16_LE)
+            .unwrap();
+
+        // 2 frames at 8000 Hz make 4 at 16000 Hz
+        assert_eq!(16, *results[0].result.as_ref().unwrap());
+        assert_eq!(8, *results[1].result.as_ref().unwrap());
+        assert_eq!(24, output.bytes().len());
+
+        // a single stream at the common rate
+        let sets = log.events()
+            .iter()
+            .filter(|event| match **event {
+                SinkEvent::SetParams { .. } => true,
+                _ => false
+            })
+            .count();
+        assert_eq!(1, sets);
+        assert_eq!(16000, log.params().unwrap().rate);
+    }
+
+    #[test]
+    fn gapless_resample_test() {
+
+        let dir = TempDir::new("queue_gapless_resample_test");
+        let samples = sine(2, 8000, 2000).samples(0, 2000);
+
+        // a sine cut in two, the second half going on where the first
+        // one stops
+        let (first, second) = samples.split_at(2000);
+
+        let mut queue = Queue::new();
+        queue.push(dir.write("1.wav", &wave_of(SampleFormat::S16_LE, 2, 8000,
+            first)));
+        queue.push(dir.write("2.wav", &wave_of(SampleFormat::S16_LE, 2, 8000,
+            second)));
+        queue.resample_to(16000, Quality::Linear);
+
+        let sink = MemorySink::memory();
+        let output = sink.output().clone();
+
+        let results = queue.play(sink, |_| SampleFormat::S16_LE)
+            .unwrap();
+
+        // the two files come out as the whole sine resampled at once
+        let mut resampler = Resampler::new(8000, 16000, 2, Quality::Linear);
+        let mut expected = resampler.process(&samples);
+        expected.extend(resampler.flush());
+
+        let played = SampleFormat::S16_LE.decode_samples(&output.bytes());
+        assert_eq!(expected.len(), played.len());
+        for (sample, expected) in played.iter().zip(expected.iter()) {
+            assert!((sample - expected).abs() <= 2.0 / 32768.0);
+        }
+
+        let written = results
+            .iter()
+            .fold(0, |sum, result| sum + *result.result.as_ref().unwrap());
+        assert_eq!(output.bytes().len(), written);
+    }
+
+    #[test]
+    fn crossfade_test() {
+
+        let dir = TempDir::new("queue_crossfade_test");
+
+        let mut queue = Queue::new();
+        queue.push(create(&dir, "1.wav", 8000));
+        queue.push(create(&dir, "2.wav", 8000));
+        queue.push(create(&dir, "3.wav", 16000));
+
+        // the whole of each file at 8000 Hz
+        queue.fades(Fades {
+            crossfade: Duration::new(0, 250_000),
+            curve: Curve::Linear,
+            ..Fades::default()
+        });
+
+        let sink = MemorySink::memory();
+        let output = sink.output().clone();
+
+        let results = queue.play(sink, |_| SampleFormat::S16_LE)
+            .unwrap();
+
+        // the first two files overlap entirely and both count the bytes
+        // of the overlap, the last one can't be crossfaded at another rate
+        let written = results
+            .iter()
+            .map(|result| *result.result.as_ref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![8, 8, 8], written);
+        assert_eq!(vec![0.0, 0.5, -0.5, 0.0, 0.0, 0.5, -0.5, 0.0],
+            SampleFormat::S16_LE.decode_samples(&output.bytes()));
+    }
+
+    #[test]
+    fn normalize_test() {
+
+        let dir = TempDir::new("queue_normalize_test");
+
+        let mut queue = Queue::new();
+        queue.push(tone(&dir, "1.wav", -10.0));
+        queue.push(tone(&dir, "2.wav", -30.0));
+        queue.normalize(DEFAULT_TARGET, DEFAULT_CEILING);
+
+        let sink = MemorySink::memory();
+        let output = sink.output().clone();
+
+        let results = queue.play(sink, |_| SampleFormat::S16_LE)
+            .unwrap();
+
+        assert_eq!(32000, *results[0].result.as_ref().unwrap());
+        assert_eq!(32000, *results[1].result.as_ref().unwrap());
+
+        // both files come out at the target, away from where they meet
+        let samples = SampleFormat::S16_LE.decode_samples(&output.bytes());
+        for file in 0..2 {
+            let mut meter = Meter::new(2, 8000, 0);
+            let start = file * 16000;
+            meter.push(&samples[start + 2000..start + 14000]);
+            assert!((meter.integrated() - DEFAULT_TARGET).abs() < 0.2);
+        }
+    }
+
+    #[test]
+    fn mix_test() {
+
+        let dir = TempDir::new("queue_mix_test");
+
+        let mut queue = Queue::new();
+        queue.push(create(&dir, "mix.wav", 8000));
+        queue.mix_to(1);
+
+        let sink = MemorySink::memory();
+        let (output, log) = (sink.output().clone(), sink.log());
+
+        let results = queue.play(sink, |_| SampleFormat::S16_LE)
+            .unwrap();
+
+        // both channels at -3 db, scaled back to full scale
+        assert_eq!(4, *results[0].result.as_ref().unwrap());
+        assert_eq!(vec![0.25, -0.25],
+            SampleFormat::S16_LE.decode_samples(&output.bytes()));
+        assert_eq!(1, log.params().unwrap().channels);
+    }
+
+    #[test]
+    fn skip_silence_test() {
+
+        let dir = TempDir::new("queue_skip_silence_test");
+
+        // a frame is enough to count as silence at 8000 Hz
+        let mut queue = Queue::new();
+        queue.push(create(&dir, "silence.wav", 8000));
+        queue.skip_silence(Detection {
+            duration: Duration::new(0, 125_000),
+            ..Detection::default()
+        });
+
+        let sink = MemorySink::memory();
+        let output = sink.output().clone();
+
+        let results = queue.play(sink, |_| SampleFormat::S16_LE)
+            .unwrap();
+
+        assert_eq!(8, *results[0].result.as_ref().unwrap());
+        assert_eq!(vec![0.0, 0.5, -0.5, 0.0],
+            SampleFormat::S16_LE.decode_samples(&output.bytes()));
+    }
+
+    #[test]
+    fn generator_test() {
+
+        let generator = Generator::new(Signal::Square(1000.0))
+            .rate(8000)
+            .channels(1)
+            .duration(Duration::from_millis(10));
+
+        let mut queue = Queue::new();
+        queue.push_generator(generator.clone());
+        assert_eq!(Duration::from_millis(10), queue.duration());
+
+        let sink = MemorySink::memory();
+        let (output, log) = (sink.output().clone(), sink.log());
+
+        let results = queue.play(sink, |_| SampleFormat::S16_LE)
+            .unwrap();
+
+        assert_eq!("square:1000", results[0].path);
+        assert_eq!(160, *results[0].result.as_ref().unwrap());
+        assert_eq!(generator.samples(0, 80),
+            SampleFormat::S16_LE.decode_samples(&output.bytes()));
+        assert_eq!(8000, log.params().unwrap().rate);
+    }
+}
@@ -0,0 +1,415 @@
+use std::any::Any;
+use std::f64::consts::PI;
+
+use chain::Processor;
+
+// how the resampler filters. the sinc qualities differ in filter length,
+// stopband attenuation and how close to nyquist the passband reaches;
+// Linear just interpolates between neighbouring frames and aliases.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quality {
+    Linear,
+    Low,
+    Medium,
+    High
+}
+
+impl Default for Quality {
+    fn default() -> Self {
+        Quality::Medium
+    }
+}
+
+impl Quality {
+
+    pub fn from_name(name: &str) -> Option<Quality> {
+        match name.to_lowercase().as_str() {
+            "linear" => Some(Quality::Linear),
+            "low" => Some(Quality::Low),
+            "medium" => Some(Quality::Medium),
+            "high" => Some(Quality::High),
+            _ => None
+        }
+    }
+
+    // zero crossings on each side of the sinc, kaiser beta and the
+    // cutoff relative to the lower nyquist frequency
+    fn design(self) -> (f64, f64, f64) {
+        match self {
+            Quality::Linear => (1.0, 0.0, 1.0),
+            Quality::Low => (8.0, 6.0, 0.85),
+            Quality::Medium => (16.0, 8.0, 0.9),
+            Quality::High => (32.0, 10.0, 0.95)
+        }
+    }
+}
+
+// ratios with a large numerator use the nearest of this many phases
+const MAX_PHASES : u64 = 1024;
+
+fn gcd(a: u64, b: u64) -> u64 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b)
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    match x == 0.0 {
+        true => 1.0,
+        _ => (PI * x).sin() / (PI * x)
+    }
+}
+
+// modified bessel function of the first kind, order 0
+fn bessel_i0(x: f64) -> f64 {
+
+    let (mut sum, mut term, mut k) = (1.0, 1.0, 1.0);
+
+    while term > sum * 1e-12 {
+        term *= (x / (2.0 * k)) * (x / (2.0 * k));
+        sum += term;
+        k += 1.0;
+    }
+
+    sum
+}
+
+fn kaiser(x: f64, beta: f64) -> f64 {
+    match x.abs() <= 1.0 {
+        true => bessel_i0(beta * (1.0 - x * x).sqrt()) / bessel_i0(beta),
+        _ => 0.0
+    }
+}
+
+// a polyphase filter: for every phase, the taps applied to the input
+// frames around an output frame that falls that far between two of them.
+struct Filter {
+    phases : u64,
+    taps   : usize,
+    table  : Vec<f64>
+}
+
+impl Filter {
+
+    fn new(up: u64, down: u64, quality: Quality) -> Filter {
+
+        let (zero_crossings, beta, rolloff) = quality.design();
+        let phases = up.min(MAX_PHASES);
+
+        // downsampling has to cut below the output nyquist frequency,
+        // which makes the filter longer in input frames
+        let cutoff = match quality {
+            Quality::Linear => 1.0,
+            _ => rolloff * (up as f64 / down as f64).min(1.0)
+        };
+
+        let width = zero_crossings / cutoff;
+        let half = width.ceil() as usize;
+        let taps = 2 * half;
+
+        let kernel = |u: f64| match quality {
+            Quality::Linear => (1.0 - u.abs()).max(0.0),
+            _ => cutoff * sinc(cutoff * u) * kaiser(u / width, beta)
+        };
+
+        let mut table = Vec::with_capacity((phases as usize + 1) * taps);
+
+        // one more row than phases for positions rounded up to a whole frame
+        for q in 0..phases + 1 {
+
+            let fraction = q as f64 / phases as f64;
+            let row = (0..taps)
+                .map(|k| kernel(fraction + (half - 1) as f64 - k as f64))
+                .collect::<Vec<_>>();
+
+            // every phase passes dc as it is
+            let sum = row.iter().fold(0.0, |sum, c| sum + c);
+            table.extend(row.iter().map(|c| c / sum));
+        }
+
+        Filter {
+            phases: phases,
+            taps: taps,
+            table: table
+        }
+    }
+
+    fn row(&self, phase: u64, up: u64) -> &[f64] {
+        let q = ((phase * self.phases + up / 2) / up) as usize;
+        &self.table[q * self.taps..(q + 1) * self.taps]
+    }
+}
+
+// converts interleaved samples from one rate to another. input can come
+// in pieces of any size; flush() gives the rest once the input has ended,
+// so that n input frames always make n * to / from output frames.
+pub struct Resampler {
+    from     : u32,
+    to       : u32,
+    channels : usize,
+    up       : u64,
+    down     : u64,
+    filter   : Filter,
+    buffer   : Vec<f64>,
+    next     : u64,
+    pushed   : u64,
+    produced : u64
+}
+
+impl Resampler {
+
+    pub fn new(from: u32, to: u32, channels: usize, quality: Quality)
+     -> Resampler {
+
+        if from == 0 || to == 0 || channels == 0 {
+            panic!("invalid resampler params");
+        }
+
+        let divisor = gcd(from as u64, to as u64);
+        let (up, down) = (to as u64 / divisor, from as u64 / divisor);
+
+        let mut resampler = Resampler {
+            from: from,
+            to: to,
+            channels: channels,
+            up: up,
+            down: down,
+            filter: Filter::new(up, down, quality),
+            buffer: Vec::new(),
+            next: 0,
+            pushed: 0,
+            produced: 0
+        };
+
+        resampler.reset();
+        resampler
+    }
+
+    pub fn from(&self) -> u32 {
+        self.from
+    }
+
+    pub fn to(&self) -> u32 {
+        self.to
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    // forgets all input, as after a seek
+    pub fn reset(&mut self) {
+        // the first output frame is centred on the first input frame
+        let padding = self.filter.taps / 2 - 1;
+        self.buffer = vec![0.0; padding * self.channels];
+        self.next = 0;
+        self.pushed = 0;
+        self.produced = 0;
+    }
+
+    // a trailing partial frame is ignored
+    pub fn process(&mut self, input: &[f64]) -> Vec<f64> {
+        let frames = input.len() / self.channels;
+        self.buffer.extend_from_slice(&input[..frames * self.channels]);
+        self.pushed += frames as u64;
+        self.run(None)
+    }
+
+    // the frames still held back by the filter, after which the
+    // resampler starts over
+    pub fn flush(&mut self) -> Vec<f64> {
+
+        let zeros = self.filter.taps / 2 * self.channels;
+        let len = self.buffer.len();
+        self.buffer.resize(len + zeros, 0.0);
+
+        let expected = (self.pushed * self.up + self.down - 1) / self.down;
+        let output = self.run(Some(expected));
+
+        self.reset();
+        output
+    }
+
+    fn run(&mut self, limit: Option<u64>) -> Vec<f64> {
+
+        let (channels, taps) = (self.channels, self.filter.taps);
+        let frames = self.buffer.len() / channels;
+        let mut output = Vec::new();
+
+        loop {
+
+            match limit {
+                Some(limit) if self.produced >= limit => break,
+                _ => ()
+            }
+
+            let first = (self.next / self.up) as usize;
+
+            if first + taps > frames {
+                break;
+            }
+
+            let row = self.filter.row(self.next % self.up, self.up);
+            let window = &self.buffer[first * channels..];
+
+            for ch in 0..channels {
+                output.push(row
+                    .iter()
+                    .enumerate()
+                    .fold(0.0, |sum, (k, c)| sum + c * window[k * channels + ch]));
+            }
+
+            self.next += self.down;
+            self.produced += 1;
+        }
+
+        // frames before the next output frame aren't needed any more
+        let consumed = ((self.next / self.up) as usize).min(frames);
+        self.buffer.drain(..consumed * channels);
+        self.next -= consumed as u64 * self.up;
+
+        output
+    }
+}
+
+impl Processor for Resampler {
+
+    fn configure(&mut self, channels: u16, rate: u32) -> (u16, u32) {
+        (self.channels as u16, self.to)
+    }
+
+    fn process(&mut self, samples: Vec<f64>) -> Vec<f64> {
+        Resampler::process(self, &samples)
+    }
+
+    fn flush(&mut self) -> Vec<f64> {
+        Resampler::flush(self)
+    }
+
+    fn reset(&mut self) {
+        Resampler::reset(self)
+    }
+
+    fn as_any(&mut self) -> &mut Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::f64::consts::PI;
+
+    use super::*;
+
+    fn sine(freq: f64, rate: u32, frames: usize) -> Vec<f64> {
+        (0..frames)
+            .map(|n| 0.5 * (2.0 * PI * freq * n as f64 / rate as f64).sin())
+            .collect()
+    }
+
+    // log spaced steps between two frequencies
+    fn sweep(from: f64, to: f64, steps: usize) -> Vec<f64> {
+        (0..steps)
+            .map(|n| from * (to / from).powf(n as f64 / (steps - 1) as f64))
+            .collect()
+    }
+
+    fn resample(input: &[f64], from: u32, to: u32, quality: Quality)
+     -> Vec<f64> {
+        let mut resampler = Resampler::new(from, to, 1, quality);
+        let mut output = Vec::new();
+        // pieces of odd sizes, as a reader would hand them over
+        for piece in input.chunks(1000) {
+            output.extend(resampler.process(piece));
+        }
+        output.extend(resampler.flush());
+        output
+    }
+
+    // the amplitude of a tone, away from the edges
+    fn gain(output: &[f64]) -> f64 {
+        let middle = &output[output.len() / 4..output.len() * 3 / 4];
+        let power = middle.iter().fold(0.0, |sum, s| sum + s * s) /
+            middle.len() as f64;
+        power.sqrt() * 2.0f64.sqrt() / 0.5
+    }
+
+    #[test]
+    fn length_test() {
+        for &(from, to) in [(44100, 48000), (96000, 44100), (8000, 8000),
+                            (44100, 48001)].iter() {
+            let output = resample(&vec![0.0; from as usize], from, to,
+                Quality::Low);
+            assert_eq!(to as usize, output.len());
+        }
+    }
+
+    #[test]
+    fn dc_test() {
+        for quality in [Quality::Linear, Quality::Medium].iter() {
+            let output = resample(&vec![0.25; 4410], 44100, 48000, *quality);
+            for sample in &output[100..output.len() - 100] {
+                assert!((sample - 0.25).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn passband_test() {
+
+        for freq in sweep(50.0, 18000.0, 8) {
+
+            let input = sine(freq, 44100, 22050);
+            let output = resample(&input, 44100, 48000, Quality::High);
+
+            // within 0.05 db
+            assert!((gain(&output) - 1.0).abs() < 0.006,
+                "{} Hz: {}", freq, gain(&output));
+        }
+    }
+
+    #[test]
+    fn stopband_test() {
+
+        // everything above the output nyquist frequency has to go,
+        // or it folds back into the audible band
+        for freq in sweep(25000.0, 45000.0, 6) {
+
+            let input = sine(freq, 96000, 48000);
+            let output = resample(&input, 96000, 44100, Quality::Medium);
+
+            // below -60 db
+            assert!(gain(&output) < 0.001, "{} Hz: {}", freq, gain(&output));
+        }
+
+        // a tone in the passband survives the downsampling
+        let output = resample(&sine(1000.0, 96000, 48000), 96000, 44100,
+            Quality::Medium);
+        assert!((gain(&output) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn interleaved_test() {
+
+        let left = sine(440.0, 44100, 4410);
+        let right = sine(1000.0, 44100, 4410);
+        let input = left
+            .iter()
+            .zip(right.iter())
+            .flat_map(|(l, r)| vec![*l, *r])
+            .collect::<Vec<_>>();
+
+        let mut resampler = Resampler::new(44100, 48000, 2, Quality::Low);
+        let mut output = resampler.process(&input);
+        output.extend(resampler.flush());
+
+        let left_out = resample(&left, 44100, 48000, Quality::Low);
+
+        assert_eq!(2 * left_out.len(), output.len());
+        for (n, sample) in left_out.iter().enumerate() {
+            assert!((sample - output[2 * n]).abs() < 1e-12);
+        }
+    }
+}
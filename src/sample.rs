@@ -0,0 +1,325 @@
+use sp_io::*;
+
+// linear sample formats, named after their alsa counterparts.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    S8         = SND_PCM_FORMAT_S8 as isize,
+    U8         = SND_PCM_FORMAT_U8 as isize,
+    S16_LE     = SND_PCM_FORMAT_S16_LE as isize,
+    S16_BE     = SND_PCM_FORMAT_S16_BE as isize,
+    U16_LE     = SND_PCM_FORMAT_U16_LE as isize,
+    U16_BE     = SND_PCM_FORMAT_U16_BE as isize,
+    S24_LE     = SND_PCM_FORMAT_S24_LE as isize,
+    S24_BE     = SND_PCM_FORMAT_S24_BE as isize,
+    U24_LE     = SND_PCM_FORMAT_U24_LE as isize,
+    U24_BE     = SND_PCM_FORMAT_U24_BE as isize,
+    S32_LE     = SND_PCM_FORMAT_S32_LE as isize,
+    S32_BE     = SND_PCM_FORMAT_S32_BE as isize,
+    U32_LE     = SND_PCM_FORMAT_U32_LE as isize,
+    U32_BE     = SND_PCM_FORMAT_U32_BE as isize,
+    FLOAT_LE   = SND_PCM_FORMAT_FLOAT_LE as isize,
+    FLOAT_BE   = SND_PCM_FORMAT_FLOAT_BE as isize,
+    FLOAT64_LE = SND_PCM_FORMAT_FLOAT64_LE as isize,
+    FLOAT64_BE = SND_PCM_FORMAT_FLOAT64_BE as isize,
+    S24_3LE    = SND_PCM_FORMAT_S24_3LE as isize,
+    S24_3BE    = SND_PCM_FORMAT_S24_3BE as isize,
+    U24_3LE    = SND_PCM_FORMAT_U24_3LE as isize,
+    U24_3BE    = SND_PCM_FORMAT_U24_3BE as isize,
+    S20_3LE    = SND_PCM_FORMAT_S20_3LE as isize,
+    S20_3BE    = SND_PCM_FORMAT_S20_3BE as isize,
+    U20_3LE    = SND_PCM_FORMAT_U20_3LE as isize,
+    U20_3BE    = SND_PCM_FORMAT_U20_3BE as isize,
+    S18_3LE    = SND_PCM_FORMAT_S18_3LE as isize,
+    S18_3BE    = SND_PCM_FORMAT_S18_3BE as isize,
+    U18_3LE    = SND_PCM_FORMAT_U18_3LE as isize,
+    U18_3BE    = SND_PCM_FORMAT_U18_3BE as isize
+}
+
+use self::SampleFormat::*;
+
+const ALL_FORMATS : [SampleFormat; 30] = [
+    S8, U8, S16_LE, S16_BE, U16_LE, U16_BE, S24_LE, S24_BE, U24_LE, U24_BE,
+    S32_LE, S32_BE, U32_LE, U32_BE, FLOAT_LE, FLOAT_BE, FLOAT64_LE, FLOAT64_BE,
+    S24_3LE, S24_3BE, U24_3LE, U24_3BE, S20_3LE, S20_3BE, U20_3LE, U20_3BE,
+    S18_3LE, S18_3BE, U18_3LE, U18_3BE
+];
+
+// short names commonly used by other tools (sox, ffmpeg).
+const ALIASES : [(&'static str, SampleFormat); 4] = [
+    ("F32LE", FLOAT_LE),
+    ("F32BE", FLOAT_BE),
+    ("F64LE", FLOAT64_LE),
+    ("F64BE", FLOAT64_BE)
+];
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| *c != '_' && *c != '-')
+        .flat_map(|c| c.to_uppercase())
+        .collect()
+}
+
+impl SampleFormat {
+
+    pub fn all() -> &'static [SampleFormat] {
+        &ALL_FORMATS
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            S8 => "S8", U8 => "U8",
+            S16_LE => "S16_LE", S16_BE => "S16_BE",
+            U16_LE => "U16_LE", U16_BE => "U16_BE",
+            S24_LE => "S24_LE", S24_BE => "S24_BE",
+            U24_LE => "U24_LE", U24_BE => "U24_BE",
+            S32_LE => "S32_LE", S32_BE => "S32_BE",
+            U32_LE => "U32_LE", U32_BE => "U32_BE",
+            FLOAT_LE => "FLOAT_LE", FLOAT_BE => "FLOAT_BE",
+            FLOAT64_LE => "FLOAT64_LE", FLOAT64_BE => "FLOAT64_BE",
+            S24_3LE => "S24_3LE", S24_3BE => "S24_3BE",
+            U24_3LE => "U24_3LE", U24_3BE => "U24_3BE",
+            S20_3LE => "S20_3LE", S20_3BE => "S20_3BE",
+            U20_3LE => "U20_3LE", U20_3BE => "U20_3BE",
+            S18_3LE => "S18_3LE", S18_3BE => "S18_3BE",
+            U18_3LE => "U18_3LE", U18_3BE => "U18_3BE"
+        }
+    }
+
+    // accepts alsa names in any case with or without underscores
+    // ("S16_LE", "s16le"), and the f32le/f64le style aliases.
+    pub fn from_name(name: &str) -> Option<SampleFormat> {
+
+        let name = normalize(name);
+
+        ALL_FORMATS
+            .iter()
+            .find(|format| normalize(format.name()) == name)
+            .or(ALIASES
+                .iter()
+                .find(|&&(alias, _)| alias == name)
+                .map(|&(_, ref format)| format))
+            .cloned()
+    }
+
+    pub fn to_snd(self) -> snd_pcm_format_t {
+        self as snd_pcm_format_t
+    }
+
+    pub fn from_snd(format: snd_pcm_format_t) -> Option<SampleFormat> {
+        ALL_FORMATS
+            .iter()
+            .find(|f| f.to_snd() == format)
+            .cloned()
+    }
+
+    // significant bits of a sample
+    pub fn width(self) -> u32 {
+        match self {
+            S8 | U8 => 8,
+            S16_LE | S16_BE | U16_LE | U16_BE => 16,
+            S18_3LE | S18_3BE | U18_3LE | U18_3BE => 18,
+            S20_3LE | S20_3BE | U20_3LE | U20_3BE => 20,
+            S24_LE | S24_BE | U24_LE | U24_BE |
+            S24_3LE | S24_3BE | U24_3LE | U24_3BE => 24,
+            S32_LE | S32_BE | U32_LE | U32_BE | FLOAT_LE | FLOAT_BE => 32,
+            FLOAT64_LE | FLOAT64_BE => 64
+        }
+    }
+
+    // bytes a sample occupies in a buffer
+    pub fn physical_bytes(self) -> usize {
+        match self {
+            S8 | U8 => 1,
+            S16_LE | S16_BE | U16_LE | U16_BE => 2,
+            S24_3LE | S24_3BE | U24_3LE | U24_3BE |
+            S20_3LE | S20_3BE | U20_3LE | U20_3BE |
+            S18_3LE | S18_3BE | U18_3LE | U18_3BE => 3,
+            FLOAT64_LE | FLOAT64_BE => 8,
+            _ => 4
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        match self {
+            FLOAT_LE | FLOAT_BE | FLOAT64_LE | FLOAT64_BE => true,
+            _ => false
+        }
+    }
+
+    pub fn is_signed(self) -> bool {
+        match self {
+            U8 | U16_LE | U16_BE | U24_LE | U24_BE | U32_LE | U32_BE |
+            U24_3LE | U24_3BE | U20_3LE | U20_3BE | U18_3LE | U18_3BE => false,
+            _ => true
+        }
+    }
+
+    pub fn is_big_endian(self) -> bool {
+        match self {
+            S16_BE | U16_BE | S24_BE | U24_BE | S32_BE | U32_BE |
+            FLOAT_BE | FLOAT64_BE | S24_3BE | U24_3BE | S20_3BE | U20_3BE |
+            S18_3BE | U18_3BE => true,
+            _ => false
+        }
+    }
+
+    fn load(self, bytes: &[u8]) -> u64 {
+
+        let n = self.physical_bytes();
+
+        (0..n).fold(0u64, |acc, i| {
+            let byte = match self.is_big_endian() {
+                true => bytes[i],
+                _ => bytes[n - 1 - i]
+            };
+            acc << 8 | byte as u64
+        })
+    }
+
+    fn store(self, value: u64, bytes: &mut [u8]) {
+
+        let n = self.physical_bytes();
+
+        for i in 0..n {
+            let byte = (value >> (8 * i)) as u8;
+            match self.is_big_endian() {
+                true => bytes[n - 1 - i] = byte,
+                _ => bytes[i] = byte
+            }
+        }
+    }
+
+    // a sample in [-1.0, 1.0) from the head of `bytes`
+    pub fn decode(self, bytes: &[u8]) -> f64 {
+
+        let raw = self.load(bytes);
+
+        match self {
+            FLOAT_LE | FLOAT_BE => f32::from_bits(raw as u32) as f64,
+            FLOAT64_LE | FLOAT64_BE => f64::from_bits(raw),
+            _ => {
+                let width = self.width();
+                let raw = raw & (!0u64 >> (64 - width));
+                let scale = (1u64 << (width - 1)) as f64;
+
+                match self.is_signed() {
+                    true => {
+                        // sign extension from the significant bits
+                        let shift = 64 - width;
+                        ((raw << shift) as i64 >> shift) as f64 / scale
+                    },
+                    _ => (raw as f64 - scale) / scale
+                }
+            }
+        }
+    }
+
+    // integer formats are rounded and clipped to their range
+    pub fn encode(self, sample: f64, bytes: &mut [u8]) {
+
+        let raw = match self {
+            FLOAT_LE | FLOAT_BE => (sample as f32).to_bits() as u64,
+            FLOAT64_LE | FLOAT64_BE => sample.to_bits(),
+            _ => {
+                let scale = (1u64 << (self.width() - 1)) as f64;
+                let value = (sample * scale)
+                    .round()
+                    .max(-scale)
+                    .min(scale - 1.0) as i64;
+
+                match self.is_signed() {
+                    true => value as u64,
+                    _ => (value + scale as i64) as u64
+                }
+            }
+        };
+
+        self.store(raw, bytes);
+    }
+
+    pub fn decode_samples(self, bytes: &[u8]) -> Vec<f64> {
+        bytes
+            .chunks(self.physical_bytes())
+            .filter(|chunk| chunk.len() == self.physical_bytes())
+            .map(|chunk| self.decode(chunk))
+            .collect()
+    }
+
+    pub fn encode_samples(self, samples: &[f64]) -> Vec<u8> {
+
+        let n = self.physical_bytes();
+        let mut bytes = vec![0u8; samples.len() * n];
+
+        for (sample, chunk) in samples
+            .iter()
+            .zip(bytes.chunks_mut(n)) {
+            self.encode(*sample, chunk);
+        }
+
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn name_test() {
+
+        assert_eq!(Some(S16_LE), SampleFormat::from_name("s16le"));
+        assert_eq!(Some(S16_LE), SampleFormat::from_name("S16_LE"));
+        assert_eq!(Some(FLOAT_LE), SampleFormat::from_name("f32le"));
+        assert_eq!(Some(FLOAT64_BE), SampleFormat::from_name("float64-be"));
+        assert_eq!(Some(S24_3LE), SampleFormat::from_name("s24_3le"));
+        assert_eq!(None, SampleFormat::from_name("s17le"));
+
+        for format in SampleFormat::all() {
+            assert_eq!(Some(*format), SampleFormat::from_name(format.name()));
+            assert_eq!(Some(*format), SampleFormat::from_snd(format.to_snd()));
+        }
+    }
+
+    #[test]
+    fn encode_test() {
+
+        let mut bytes = [0u8; 4];
+
+        S16_LE.encode(0.5, &mut bytes);
+        assert_eq!([0x00, 0x40], bytes[..2]);
+
+        S16_BE.encode(-1.0, &mut bytes);
+        assert_eq!([0x80, 0x00], bytes[..2]);
+
+        // clipped to the largest positive value
+        S16_LE.encode(2.0, &mut bytes);
+        assert_eq!([0xff, 0x7f], bytes[..2]);
+
+        U8.encode(0.0, &mut bytes);
+        assert_eq!(0x80, bytes[0]);
+
+        S24_3LE.encode(-0.5, &mut bytes);
+        assert_eq!([0x00, 0x00, 0xc0], bytes[..3]);
+
+        S24_LE.encode(-0.5, &mut bytes);
+        assert_eq!([0x00, 0x00, 0xc0, 0xff], bytes);
+
+        FLOAT_LE.encode(0.25, &mut bytes);
+        assert_eq!([0x00, 0x00, 0x80, 0x3e], bytes);
+    }
+
+    #[test]
+    fn round_trip_test() {
+
+        let samples = [0.0, 0.5, -0.5, -1.0, 0.25, -0.125];
+
+        for format in SampleFormat::all() {
+            let bytes = format.encode_samples(&samples);
+            assert_eq!(samples.len() * format.physical_bytes(), bytes.len());
+            assert_eq!(samples.to_vec(), format.decode_samples(&bytes),
+                "{}", format.name());
+        }
+    }
+}
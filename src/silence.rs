@@ -0,0 +1,372 @@
+use std::f64;
+use std::fs::File;
+use std::io::{ BufWriter, Read, Seek, Write };
+use std::path::Path;
+use std::time::Duration;
+
+use io::*;
+use player::FRAMES_PER_WRITE;
+use wav::{ self, SeekTarget, WaveReader };
+
+pub const DEFAULT_THRESHOLD : f64 = -60.0;
+pub const DEFAULT_MILLIS    : u64 = 500;
+
+// digital silence is looked for over shorter stretches
+pub const DIGITAL_MILLIS : u64 = 100;
+
+// whether every channel has to be quiet for a frame to be silent, or
+// the channels mixed down
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Channels,
+    Summed
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::Channels
+    }
+}
+
+// what counts as silence: frames at or under `threshold` dBFS for
+// `duration` or longer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Detection {
+    pub threshold : f64,
+    pub duration  : Duration,
+    pub mode      : Mode
+}
+
+impl Default for Detection {
+    fn default() -> Self {
+        Detection {
+            threshold: DEFAULT_THRESHOLD,
+            duration: Duration::from_millis(DEFAULT_MILLIS),
+            mode: Mode::default()
+        }
+    }
+}
+
+impl Detection {
+
+    // samples that are exactly zero
+    pub fn digital() -> Self {
+        Detection {
+            threshold: f64::NEG_INFINITY,
+            duration: Duration::from_millis(DIGITAL_MILLIS),
+            mode: Mode::Channels
+        }
+    }
+}
+
+// a run of silent frames
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    pub start  : u64,
+    pub frames : u64
+}
+
+impl Span {
+    pub fn end(&self) -> u64 {
+        self.start + self.frames
+    }
+}
+
+// finds the silent spans of frames as they come
+pub struct Detector {
+    level       : f64,
+    min_frames  : u64,
+    mode        : Mode,
+    channels    : usize,
+    frames      : u64,
+    silent_from : Option<u64>,
+    spans       : Vec<Span>
+}
+
+impl Detector {
+
+    pub fn new(detection: Detection, channels: u16, rate: u32) -> Self {
+
+        let duration = detection.duration;
+        let min_frames = duration.as_secs() * rate as u64 +
+            duration.subsec_nanos() as u64 * rate as u64 / 1_000_000_000;
+
+        Detector {
+            level: 10.0f64.powf(detection.threshold / 20.0),
+            min_frames: min_frames.max(1),
+            mode: detection.mode,
+            channels: (channels as usize).max(1),
+            frames: 0,
+            silent_from: None,
+            spans: Vec::new()
+        }
+    }
+
+    fn is_silent(&self, frame: &[f64]) -> bool {
+        match self.mode {
+            Mode::Channels => frame
+                .iter()
+                .all(|sample| sample.abs() <= self.level),
+            Mode::Summed => (frame.iter().fold(0.0, |sum, s| sum + s) /
+                frame.len() as f64).abs() <= self.level
+        }
+    }
+
+    // interleaved samples, whole frames of them
+    pub fn push(&mut self, samples: &[f64]) {
+
+        for frame in samples.chunks(self.channels) {
+
+            match (self.is_silent(frame), self.silent_from) {
+                (true, None) => self.silent_from = Some(self.frames),
+                (false, Some(start)) => {
+                    self.end_span(start);
+                    self.silent_from = None;
+                },
+                _ => ()
+            }
+
+            self.frames += 1;
+        }
+    }
+
+    fn end_span(&mut self, start: u64) {
+        let frames = self.frames - start;
+        if frames >= self.min_frames {
+            self.spans.push(Span { start: start, frames: frames });
+        }
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn finish(mut self) -> Vec<Span> {
+        if let Some(start) = self.silent_from.take() {
+            self.end_span(start);
+        }
+        self.spans
+    }
+}
+
+// the frames from the end of a leading span to the start of a trailing
+// one. a file that is silent through is left empty.
+pub fn audible(spans: &[Span], frames: u64) -> (u64, u64) {
+
+    let start = match spans.first() {
+        Some(span) if span.start == 0 => span.end(),
+        _ => 0
+    };
+
+    let end = match spans.last() {
+        Some(span) if span.end() == frames => span.start,
+        _ => frames
+    };
+
+    match start < end {
+        true => (start, end),
+        _ => (0, 0)
+    }
+}
+
+// the frames between the spans
+pub fn sounding(spans: &[Span], frames: u64) -> Vec<(u64, u64)> {
+
+    let mut ranges = Vec::new();
+    let mut start = 0;
+
+    for span in spans {
+        if span.start > start {
+            ranges.push((start, span.start));
+        }
+        start = span.end();
+    }
+
+    if frames > start {
+        ranges.push((start, frames));
+    }
+
+    ranges
+}
+
+// looks through the whole data chunk of a file for its silent spans.
+// returns them and the frames of the file. the reader is left at the
+// start of the data.
+pub fn scan<R: Read + Seek>(reader: &mut WaveReader<R>, detection: Detection)
+ -> IOResult<(Vec<Span>, u64)> {
+
+    let (channels, rate) = (reader.format().channels,
+        reader.format().sample_rate);
+    let mut detector = Detector::new(detection, channels, rate);
+
+    try!(reader.seek(SeekTarget::Frame(0)));
+
+    loop {
+        let samples = try!(reader.read_frames(FRAMES_PER_WRITE));
+        if samples.is_empty() {
+            break;
+        }
+        detector.push(&samples);
+    }
+
+    try!(reader.seek(SeekTarget::Frame(0)));
+
+    let frames = detector.frames();
+    Ok((detector.finish(), frames))
+}
+
+// writes the file at `input` to `output` without its leading and
+// trailing silence, or without any silent span at all when `inner` is
+// set. the other chunks of the file are kept. returns the frames of the
+// file and the frames written.
+pub fn trim(input: &str, output: &str, detection: Detection, inner: bool)
+ -> IOResult<(u64, u64)> {
+
+    if Path::new(input) == Path::new(output) {
+        return Err(IOError::new(IOErrorKind::InvalidInput,
+            "cannot trim a file in place"));
+    }
+
+    let mut reader = try!(WaveReader::open(input));
+    let (spans, frames) = try!(scan(&mut reader, detection));
+
+    let ranges = match inner {
+        true => sounding(&spans, frames),
+        _ => vec![audible(&spans, frames)]
+    };
+
+    let mut file = try!(File::open(input));
+    let mut writer = BufWriter::new(try!(File::create(output)));
+    let written = try!(wav::rewrite(&mut file, reader.header(), &ranges,
+        &mut writer));
+    try!(writer.flush());
+
+    Ok((frames, written))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs::File;
+    use std::io::{ Cursor, Read };
+    use std::time::Duration;
+
+    use super::*;
+    use fixtures::{ TempDir, chunk, fmt_chunk, pcm, riff };
+    use sample::SampleFormat;
+    use wav::WaveReader;
+
+    #[test]
+    fn detector_test() {
+
+        // 10 frames a second, silent at -40 dB for 5 frames or more
+        let detection = Detection {
+            threshold: -40.0,
+            duration: Duration::from_millis(500),
+            mode: Mode::Channels
+        };
+        let mut detector = Detector::new(detection, 2, 10);
+
+        let mut samples = vec![0.001; 12];
+        samples.extend(vec![0.5, 0.0, 0.001, 0.001]);
+        samples.extend(vec![0.0; 6]);
+        samples.extend(vec![0.1, -0.1]);
+        samples.extend(vec![0.0; 10]);
+
+        detector.push(&samples[..6]);
+        detector.push(&samples[6..]);
+        assert_eq!(17, detector.frames());
+
+        // the 4 quiet frames from 7 are too short a span
+        let spans = detector.finish();
+        assert_eq!(vec![Span { start: 0, frames: 6 },
+                        Span { start: 12, frames: 5 }],
+            spans);
+        assert_eq!((6, 12), audible(&spans, 17));
+        assert_eq!(vec![(6, 12)], sounding(&spans, 17));
+
+        // the frame at 11 cancels out once mixed down
+        let mut detector = Detector::new(Detection {
+            mode: Mode::Summed,
+            ..detection
+        }, 2, 10);
+        detector.push(&samples);
+        assert_eq!(vec![Span { start: 0, frames: 6 },
+                        Span { start: 7, frames: 10 }],
+            detector.finish());
+
+        // nothing but exact zeros is digitally silent
+        let mut detector = Detector::new(Detection::digital(), 1, 100);
+        detector.push(&[0.0; 10]);
+        detector.push(&[1e-9]);
+        detector.push(&[0.0; 9]);
+        assert_eq!(vec![Span { start: 0, frames: 10 }], detector.finish());
+    }
+
+    #[test]
+    fn audible_test() {
+
+        let spans = vec![Span { start: 2, frames: 3 },
+                         Span { start: 6, frames: 2 }];
+        assert_eq!((0, 10), audible(&spans, 10));
+        assert_eq!(vec![(0, 2), (5, 6), (8, 10)], sounding(&spans, 10));
+
+        let spans = vec![Span { start: 0, frames: 10 }];
+        assert_eq!((0, 0), audible(&spans, 10));
+        assert!(sounding(&spans, 10).is_empty());
+    }
+
+    #[test]
+    fn scan_test() {
+
+        // 16 bit mono at 8000 Hz: 1 frame of sound after 4 of silence
+        let wave = pcm(SampleFormat::S16_LE, 1, 8000,
+            &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0x40]);
+
+        let mut reader = WaveReader::new(Cursor::new(wave)).unwrap();
+        let detection = Detection {
+            duration: Duration::new(0, 250_000),
+            ..Detection::digital()
+        };
+
+        assert_eq!((vec![Span { start: 0, frames: 4 }], 5),
+            scan(&mut reader, detection).unwrap());
+        assert_eq!(vec![0.0, 0.0], reader.read_frames(2).unwrap());
+    }
+
+    #[test]
+    fn trim_test() {
+
+        let dir = TempDir::new("silence_trim_test");
+
+        // 16 bit mono at 8000 Hz: 2 frames of silence on either side of
+        // sound, then a LIST chunk
+        let input = dir.write("in.wav", &riff(&[
+            fmt_chunk(SampleFormat::S16_LE, 1, 8000),
+            chunk(b"data", &[0, 0, 0, 0, 0, 0x40, 0, 0xc0, 0, 0, 0, 0]),
+            chunk(b"LIST", b"INFO")
+        ]));
+        let path = dir.path("out.wav");
+
+        let detection = Detection {
+            duration: Duration::new(0, 250_000),
+            ..Detection::default()
+        };
+        let trimmed = trim(&input, &path, detection, false);
+        let in_place = trim(&input, &input, detection, false);
+
+        let mut output = Vec::new();
+        File::open(&path)
+            .unwrap()
+            .read_to_end(&mut output)
+            .unwrap();
+
+        assert_eq!((6, 2), trimmed.unwrap());
+        assert!(in_place.is_err());
+
+        assert_eq!(b"RIFF\x34\x00\x00\x00WAVE", &output[..12]);
+        assert_eq!(b"data\x04\x00\x00\x00\x00\x40\x00\xc0",
+            &output[36..48]);
+        assert_eq!(b"LIST\x04\x00\x00\x00INFO", &output[48..]);
+    }
+}
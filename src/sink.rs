@@ -0,0 +1,951 @@
+use std::fs::File;
+use std::io;
+use std::sync::{ Arc, Mutex };
+use std::thread::sleep;
+use std::time::{ Duration, Instant };
+
+use io::*;
+use sample::SampleFormat;
+use sp_io::playback_format;
+
+// anything playback audio can be sent to. the alsa writer is one of them,
+// the recording sinks below stand in for a sound card in tests.
+// wave_bits is the number of significant bits of the samples written.
+pub trait Sink: io::Write {
+
+    fn set_params(&mut self,
+        wave_bits: u8,
+        wave_rates: u32,
+        wave_channels: u8) -> io::Result<()>;
+
+    // how far playback has come since the params have been set
+    fn position(&mut self) -> io::Result<Position>;
+
+    // the sample format the stream is opened in when the bits set up next
+    // match its width. sinks with a fixed format have nothing to choose.
+    fn use_format(&mut self, format: SampleFormat) {
+    }
+
+    // frames written but not played yet
+    fn delay(&mut self) -> io::Result<u64> {
+        self.position()
+            .map(|position| position.delay)
+    }
+
+    // sinks that can't underrun have nothing to count
+    fn stats(&self) -> XrunStats {
+        XrunStats::default()
+    }
+
+    fn set_recovery(&mut self, policy: RecoveryPolicy) {
+    }
+
+    fn pause(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn resume(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    // waits until everything written has been played
+    fn drain(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+
+    // throws away what has been written but not played yet
+    fn drop_queued(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// what happened to a stream since it has been opened
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct XrunStats {
+    pub xruns      : u64,
+    pub suspends   : u64,
+    pub recoveries : u64,
+    pub failures   : u64
+}
+
+// frames are counted from the last set_params. silence written on
+// recovery isn't counted and dropped frames are taken back, so that
+// played() is the part of the stream that has actually been heard.
+// timestamp is when the figures were taken, on the sink's own clock.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Position {
+    pub written   : u64,
+    pub delay     : u64,
+    pub rate      : u32,
+    pub timestamp : Option<Duration>
+}
+
+impl Position {
+
+    pub fn played(&self) -> u64 {
+        self.written
+            .saturating_sub(self.delay)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        duration_of(self.played(), self.rate)
+    }
+
+    // how long what is queued takes to play
+    pub fn queued(&self) -> Duration {
+        duration_of(self.delay, self.rate)
+    }
+}
+
+fn duration_of(frames: u64, rate: u32) -> Duration {
+    match rate as u64 {
+        0 => Duration::new(0, 0),
+        rate => Duration::new(frames / rate,
+            ((frames % rate) * 1_000_000_000 / rate) as u32)
+    }
+}
+
+// what a sink does when the device runs out of data or gets suspended.
+// the stream is recovered so that it can be written again, except with
+// Fail: the write reports the error and the stream stays stopped until
+// the params are set again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RecoveryPolicy {
+    Recover,
+    RecoverWithSilence(Duration),
+    Fail
+}
+
+impl Default for RecoveryPolicy {
+    fn default() -> Self {
+        RecoveryPolicy::Recover
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SinkParams {
+    pub bits     : u8,
+    pub rate     : u32,
+    pub channels : u8,
+    pub format   : SampleFormat
+}
+
+impl SinkParams {
+
+    pub fn frame_size(&self) -> usize {
+        self.format.physical_bytes() * self.channels as usize
+    }
+
+    // how long the given amount of bytes takes to be played
+    pub fn duration_of(&self, bytes: usize) -> Duration {
+
+        let frames = (bytes / self.frame_size()) as u64;
+        let rate = self.rate as u64;
+
+        Duration::new(frames / rate,
+            ((frames % rate) * 1_000_000_000 / rate) as u32)
+    }
+
+    // whole frames played in the given duration, in bytes
+    pub fn bytes_for(&self, duration: Duration) -> usize {
+
+        let rate = self.rate as u64;
+        let frames = duration.as_secs() * rate +
+            duration.subsec_nanos() as u64 * rate / 1_000_000_000;
+
+        frames as usize * self.frame_size()
+    }
+}
+
+// `at` fields are measured from the creation of the sink.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SinkEvent {
+    SetParams { params: SinkParams, at: Duration },
+    Write { offset: usize, len: usize, at: Duration },
+    Pause { at: Duration },
+    Resume { at: Duration },
+    Drain { at: Duration },
+    Drop { at: Duration },
+    Xrun { at: Duration }
+}
+
+#[derive(Clone)]
+pub struct SinkLog {
+    inner : Arc<Mutex<Vec<SinkEvent>>>
+}
+
+impl SinkLog {
+
+    fn new() -> Self {
+        SinkLog {
+            inner: Arc::new(Mutex::new(Vec::new()))
+        }
+    }
+
+    fn push(&self, event: SinkEvent) {
+        self.inner
+            .lock()
+            .unwrap()
+            .push(event);
+    }
+
+    pub fn events(&self) -> Vec<SinkEvent> {
+        self.inner
+            .lock()
+            .unwrap()
+            .clone()
+    }
+
+    // the params in effect at the end of the log
+    pub fn params(&self) -> Option<SinkParams> {
+        self.events()
+            .iter()
+            .rev()
+            .filter_map(|event| match *event {
+                SinkEvent::SetParams { params, .. } => Some(params),
+                _ => None
+            })
+            .next()
+    }
+
+    pub fn written(&self) -> usize {
+        self.events()
+            .iter()
+            .map(|event| match *event {
+                SinkEvent::Write { len, .. } => len,
+                _ => 0
+            })
+            .sum()
+    }
+}
+
+// shared byte storage, so that recorded data can still be inspected
+// after the sink has been moved into a worker thread.
+#[derive(Clone)]
+pub struct MemoryOutput {
+    inner : Arc<Mutex<Vec<u8>>>
+}
+
+impl MemoryOutput {
+
+    pub fn new() -> Self {
+        MemoryOutput {
+            inner: Arc::new(Mutex::new(Vec::new()))
+        }
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        self.inner
+            .lock()
+            .unwrap()
+            .clone()
+    }
+}
+
+impl io::Write for MemoryOutput {
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner
+            .lock()
+            .unwrap()
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+const PARAMS_NOT_SET : &'static str = "params have not been set";
+const FRAME_NOT_ALIGNED : &'static str = "buffer is not aligned to frames";
+
+// records exactly what would have been sent to a device.
+pub struct RecordingSink<W: io::Write> {
+    output   : W,
+    origin   : Instant,
+    params   : Option<SinkParams>,
+    format   : Option<SampleFormat>,
+    offset   : usize,
+    frames   : u64,
+    log      : SinkLog,
+    realtime : bool,
+    deadline : Option<Instant>,
+    paused   : Option<Duration>,
+    recovery : RecoveryPolicy,
+    stats    : XrunStats
+}
+
+pub type MemorySink = RecordingSink<MemoryOutput>;
+pub type FileSink = RecordingSink<File>;
+
+impl<W: io::Write> RecordingSink<W> {
+
+    pub fn new(output: W) -> Self {
+        RecordingSink {
+            output: output,
+            origin: Instant::now(),
+            params: None,
+            format: None,
+            offset: 0,
+            frames: 0,
+            log: SinkLog::new(),
+            realtime: false,
+            deadline: None,
+            paused: None,
+            recovery: RecoveryPolicy::default(),
+            stats: XrunStats::default()
+        }
+    }
+
+    // makes the sink play in real time like a device does, so that data
+    // coming in after the queued data has run out is an underrun.
+    pub fn realtime(mut self) -> Self {
+        self.realtime = true;
+        self
+    }
+
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+
+    pub fn log(&self) -> SinkLog {
+        self.log.clone()
+    }
+
+    fn record(&mut self, buf: &[u8]) -> io::Result<()> {
+
+        try!(self.output.write_all(buf));
+
+        self.log.push(SinkEvent::Write {
+            offset: self.offset,
+            len: buf.len(),
+            at: self.origin.elapsed()
+        });
+        self.offset += buf.len();
+        Ok(())
+    }
+
+    // what is left to play of the queued data
+    fn queued(&self) -> Duration {
+        match (self.paused, self.deadline) {
+            (Some(left), _) => left,
+            (_, Some(deadline)) => {
+                let now = Instant::now();
+                match deadline > now {
+                    true => deadline - now,
+                    _ => Duration::new(0, 0)
+                }
+            },
+            _ => Duration::new(0, 0)
+        }
+    }
+
+    fn queued_frames(&self) -> u64 {
+        match self.params {
+            Some(params) if self.realtime =>
+                (params.bytes_for(self.queued()) / params.frame_size()) as u64,
+            _ => 0
+        }
+    }
+
+    fn check_underrun(&mut self, params: SinkParams) -> io::Result<()> {
+
+        match self.deadline {
+            Some(deadline) if Instant::now() > deadline => (),
+            _ => return Ok(())
+        }
+
+        self.stats.xruns += 1;
+        self.log.push(SinkEvent::Xrun {
+            at: self.origin.elapsed()
+        });
+
+        if let RecoveryPolicy::Fail = self.recovery {
+            return Err(IOError::new(IOErrorKind::BrokenPipe, "underrun"));
+        }
+
+        self.deadline = None;
+        self.stats.recoveries += 1;
+
+        match self.recovery {
+
+            RecoveryPolicy::RecoverWithSilence(duration) => {
+                let frames = params.bytes_for(duration) /
+                    params.frame_size() * params.channels as usize;
+                let silence = params.format
+                    .encode_samples(&vec![0.0; frames]);
+                // the silence is played ahead of what comes next
+                self.deadline = Some(Instant::now() + duration);
+                self.record(&silence)
+            },
+
+            _ => Ok(())
+        }
+    }
+}
+
+impl MemorySink {
+    pub fn memory() -> Self {
+        RecordingSink::new(MemoryOutput::new())
+    }
+}
+
+impl FileSink {
+    pub fn create(path: &str) -> io::Result<Self> {
+        File::create(path)
+            .map(RecordingSink::new)
+    }
+}
+
+impl<W: io::Write> Sink for RecordingSink<W> {
+
+    fn set_params(&mut self,
+        wave_bits: u8,
+        wave_rates: u32,
+        wave_channels: u8) -> io::Result<()> {
+
+        match wave_bits {
+            8 | 16 | 24 | 32 => (),
+            _ => return Err(IOError::new(IOErrorKind::InvalidInput,
+                "unsupported wave bits"))
+        }
+
+        if wave_rates == 0 || wave_channels == 0 {
+            return Err(IOError::new(IOErrorKind::InvalidInput,
+                "invalid params"));
+        }
+
+        // 24 bit samples are sent in 4 bytes, as the alsa writer does,
+        // unless a packed format has been asked for
+        let format = match self.format {
+            Some(format) if format.width() == wave_bits as u32 => format,
+            _ => playback_format(wave_bits)
+        };
+
+        let params = SinkParams {
+            bits: wave_bits,
+            rate: wave_rates,
+            channels: wave_channels,
+            format: format
+        };
+
+        self.params = Some(params);
+        self.frames = 0;
+        self.deadline = None;
+        self.log.push(SinkEvent::SetParams {
+            params: params,
+            at: self.origin.elapsed()
+        });
+        Ok(())
+    }
+
+    // a sink that doesn't play in real time plays everything at once
+    fn position(&mut self) -> io::Result<Position> {
+
+        let rate = match self.params {
+            Some(params) => params.rate,
+            _ => return Err(IOError::new(IO_ERROR, PARAMS_NOT_SET))
+        };
+
+        Ok(Position {
+            written: self.frames,
+            delay: self.queued_frames().min(self.frames),
+            rate: rate,
+            timestamp: Some(self.origin.elapsed())
+        })
+    }
+
+    fn use_format(&mut self, format: SampleFormat) {
+        self.format = Some(format);
+    }
+
+    fn stats(&self) -> XrunStats {
+        self.stats
+    }
+
+    fn set_recovery(&mut self, policy: RecoveryPolicy) {
+        self.recovery = policy;
+    }
+
+    fn pause(&mut self) -> io::Result<()> {
+        if self.paused.is_none() {
+            self.paused = Some(self.queued());
+            self.deadline = None;
+            self.log.push(SinkEvent::Pause {
+                at: self.origin.elapsed()
+            });
+        }
+        Ok(())
+    }
+
+    fn resume(&mut self) -> io::Result<()> {
+        if let Some(left) = self.paused.take() {
+            self.deadline = Some(Instant::now() + left);
+            self.log.push(SinkEvent::Resume {
+                at: self.origin.elapsed()
+            });
+        }
+        Ok(())
+    }
+
+    fn drain(&mut self) -> io::Result<()> {
+
+        try!(self.resume());
+        try!(self.output.flush());
+
+        if self.realtime {
+            sleep(self.queued());
+        }
+
+        self.deadline = None;
+        self.log.push(SinkEvent::Drain {
+            at: self.origin.elapsed()
+        });
+        Ok(())
+    }
+
+    fn drop_queued(&mut self) -> io::Result<()> {
+        self.frames -= self.queued_frames().min(self.frames);
+        self.deadline = None;
+        self.paused = self.paused.map(|_| Duration::new(0, 0));
+        self.log.push(SinkEvent::Drop {
+            at: self.origin.elapsed()
+        });
+        Ok(())
+    }
+}
+
+impl<W: io::Write> io::Write for RecordingSink<W> {
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+
+        let params = match self.params {
+            Some(params) => params,
+            _ => return Err(IOError::new(IO_ERROR, PARAMS_NOT_SET))
+        };
+
+        if buf.len() % params.frame_size() != 0 {
+            return Err(IOError::new(IOErrorKind::InvalidInput,
+                FRAME_NOT_ALIGNED));
+        }
+
+        // a paused device just queues what it gets
+        if self.realtime && self.paused.is_none() {
+            try!(self.check_underrun(params));
+        }
+
+        try!(self.record(buf));
+        self.frames += (buf.len() / params.frame_size()) as u64;
+
+        if self.realtime {
+            let queued = self.queued() + params.duration_of(buf.len());
+            match self.paused {
+                Some(_) => self.paused = Some(queued),
+                _ => self.deadline = Some(Instant::now() + queued)
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.drain()
+    }
+}
+
+const FORMAT_MISMATCH : &'static str = "params do not match the output format";
+
+// writes already converted pcm to stdout, a pipe or any other writer.
+pub struct PipeSink<W: io::Write> {
+    output : W,
+    format : SampleFormat,
+    params : Option<SinkParams>,
+    frames : u64
+}
+
+impl<W: io::Write> PipeSink<W> {
+
+    pub fn new(output: W, format: SampleFormat) -> Self {
+        PipeSink {
+            output: output,
+            format: format,
+            params: None,
+            frames: 0
+        }
+    }
+
+    pub fn format(&self) -> SampleFormat {
+        self.format
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+
+    fn frame_size(&self) -> Option<usize> {
+        self.params
+            .map(|params| params.frame_size())
+    }
+}
+
+impl PipeSink<io::Stdout> {
+    pub fn stdout(format: SampleFormat) -> Self {
+        PipeSink::new(io::stdout(), format)
+    }
+}
+
+impl<W: io::Write> Sink for PipeSink<W> {
+
+    fn set_params(&mut self,
+        wave_bits: u8,
+        wave_rates: u32,
+        wave_channels: u8) -> io::Result<()> {
+
+        if wave_bits as u32 != self.format.width() {
+            return Err(IOError::new(IOErrorKind::InvalidInput,
+                FORMAT_MISMATCH));
+        }
+
+        if wave_rates == 0 || wave_channels == 0 {
+            return Err(IOError::new(IOErrorKind::InvalidInput,
+                "invalid params"));
+        }
+
+        self.params = Some(SinkParams {
+            bits: wave_bits,
+            rate: wave_rates,
+            channels: wave_channels,
+            format: self.format
+        });
+        self.frames = 0;
+        Ok(())
+    }
+
+    // whatever reads the pipe sets the pace, nothing is known to be queued
+    fn position(&mut self) -> io::Result<Position> {
+        match self.params {
+            Some(params) => Ok(Position {
+                written: self.frames,
+                delay: 0,
+                rate: params.rate,
+                timestamp: None
+            }),
+            _ => Err(IOError::new(IO_ERROR, PARAMS_NOT_SET))
+        }
+    }
+}
+
+impl<W: io::Write> io::Write for PipeSink<W> {
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+
+        let frame_size = match self.frame_size() {
+            Some(frame_size) => frame_size,
+            _ => return Err(IOError::new(IO_ERROR, PARAMS_NOT_SET))
+        };
+
+        if buf.len() % frame_size != 0 {
+            return Err(IOError::new(IOErrorKind::InvalidInput,
+                FRAME_NOT_ALIGNED));
+        }
+
+        try!(self.output.write_all(buf));
+        self.frames += (buf.len() / frame_size) as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs::File;
+    use std::io::{ Read, Write };
+    use std::thread::sleep;
+    use std::time::{ Duration, Instant };
+
+    use super::*;
+    use fixtures::TempDir;
+    use sample::SampleFormat;
+
+    #[test]
+    fn memory_sink_test() {
+
+        let mut sink = MemorySink::memory();
+        let (output, log) = (sink.output().clone(), sink.log());
+
+        assert!(sink.write(&[0u8; 4]).is_err());
+
+        // no device takes 12 bit samples
+        assert_eq!(IOErrorKind::InvalidInput,
+            sink.set_params(12, 8000, 2).unwrap_err().kind());
+
+        sink.set_params(16, 8000, 2)
+            .unwrap();
+
+        // half a frame is not something a device would accept
+        assert!(sink.write(&[0u8; 2]).is_err());
+
+        assert_eq!(8, sink.write(&[1, 2, 3, 4, 5, 6, 7, 8]).unwrap());
+        assert_eq!(4, sink.write(&[9, 10, 11, 12]).unwrap());
+        sink.flush().unwrap();
+
+        assert_eq!((1..13).collect::<Vec<u8>>(), output.bytes());
+        assert_eq!(Some(SinkParams { bits: 16, rate: 8000, channels: 2,
+                                     format: SampleFormat::S16_LE }),
+            log.params());
+        assert_eq!(12, log.written());
+
+        let events = log.events();
+        assert_eq!(4, events.len());
+
+        match events[2] {
+            SinkEvent::Write { offset, len, .. } => {
+                assert_eq!(8, offset);
+                assert_eq!(4, len);
+            },
+            _ => panic!("write event is expected")
+        }
+
+        // flush waits for the device to play everything out
+        match events[3] {
+            SinkEvent::Drain { .. } => (),
+            _ => panic!("drain event is expected")
+        }
+    }
+
+    #[test]
+    fn file_sink_test() {
+
+        let dir = TempDir::new("sink_file_sink_test");
+        let path = dir.path("out");
+
+        {
+            let mut sink = FileSink::create(&path)
+                .unwrap();
+            sink.set_params(8, 8000, 1).unwrap();
+            sink.write(b"RIFF").unwrap();
+        }
+
+        let mut st = String::new();
+        File::open(&path)
+            .unwrap()
+            .read_to_string(&mut st)
+            .unwrap();
+        assert_eq!("RIFF", st.as_str());
+    }
+
+    #[test]
+    fn duration_test() {
+        let params = SinkParams { bits: 16, rate: 8000, channels: 2,
+                                  format: SampleFormat::S16_LE };
+        assert_eq!(Duration::from_millis(500), params.duration_of(16000));
+    }
+
+    #[test]
+    fn pipe_sink_test() {
+
+        let mut sink = PipeSink::new(Vec::new(), SampleFormat::S24_3LE);
+
+        // 24 bits are packed in 3 bytes, unlike the alsa writer
+        assert!(sink.set_params(16, 8000, 2).is_err());
+        sink.set_params(24, 8000, 2).unwrap();
+
+        assert!(sink.write(&[0u8; 4]).is_err());
+        assert_eq!(6, sink.write(&[1, 2, 3, 4, 5, 6]).unwrap());
+
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], sink.into_inner());
+    }
+
+    #[test]
+    fn use_format_test() {
+
+        let mut sink = MemorySink::memory();
+        let log = sink.log();
+
+        // packed 24 bit frames once the format is asked for
+        sink.use_format(SampleFormat::S24_3LE);
+        sink.set_params(24, 8000, 2).unwrap();
+        assert!(sink.write(&[0u8; 8]).is_err());
+        assert_eq!(6, sink.write(&[0u8; 6]).unwrap());
+        assert_eq!(Some(SinkParams { bits: 24, rate: 8000, channels: 2,
+                                     format: SampleFormat::S24_3LE }),
+            log.params());
+
+        // and the usual format for bits it doesn't fit
+        sink.set_params(16, 8000, 2).unwrap();
+        assert_eq!(Some(SampleFormat::S16_LE),
+            log.params().map(|params| params.format));
+    }
+
+    fn underrun(policy: RecoveryPolicy) -> (MemorySink, io::Result<usize>) {
+
+        let mut sink = MemorySink::memory()
+            .realtime();
+
+        sink.set_recovery(policy);
+        sink.set_params(16, 8000, 1).unwrap();
+
+        // 8 frames last a millisecond
+        sink.write(&[1u8; 16]).unwrap();
+        sleep(Duration::from_millis(20));
+        let res = sink.write(&[1u8; 16]);
+
+        (sink, res)
+    }
+
+    #[test]
+    fn underrun_test() {
+
+        let (sink, res) = underrun(RecoveryPolicy::Recover);
+
+        assert_eq!(16, res.unwrap());
+        assert_eq!(XrunStats { xruns: 1, suspends: 0, recoveries: 1,
+                               failures: 0 },
+            sink.stats());
+        assert_eq!(vec![1u8; 32], sink.output().bytes());
+
+        match sink.log().events()[2] {
+            SinkEvent::Xrun { .. } => (),
+            _ => panic!("xrun event is expected")
+        }
+    }
+
+    #[test]
+    fn underrun_with_silence_test() {
+
+        let (sink, res) = underrun(RecoveryPolicy::RecoverWithSilence(
+            Duration::from_millis(10)));
+
+        assert_eq!(16, res.unwrap());
+
+        // 80 frames of silence between the two writes
+        let mut expected = vec![1u8; 16];
+        expected.extend_from_slice(&[0u8; 160]);
+        expected.extend_from_slice(&[1u8; 16]);
+        assert_eq!(expected, sink.output().bytes());
+
+        // and the data queued behind it
+        assert!(sink.queued() > Duration::from_millis(5));
+    }
+
+    #[test]
+    fn underrun_failure_test() {
+
+        let (mut sink, res) = underrun(RecoveryPolicy::Fail);
+
+        assert_eq!(IOErrorKind::BrokenPipe, res.unwrap_err().kind());
+        assert_eq!(XrunStats { xruns: 1, suspends: 0, recoveries: 0,
+                               failures: 0 },
+            sink.stats());
+
+        // the stream stays stopped until it is set up again
+        assert!(sink.write(&[1u8; 16]).is_err());
+        sink.set_params(16, 8000, 1).unwrap();
+        assert_eq!(16, sink.write(&[1u8; 16]).unwrap());
+        assert_eq!(0, sink.stats().recoveries);
+    }
+
+    #[test]
+    fn pause_test() {
+
+        let mut sink = MemorySink::memory()
+            .realtime();
+
+        sink.set_params(16, 8000, 1).unwrap();
+        sink.write(&[1u8; 160]).unwrap();
+        sink.pause().unwrap();
+
+        // a paused device doesn't run out of data
+        sleep(Duration::from_millis(30));
+        sink.write(&[1u8; 16]).unwrap();
+        sink.resume().unwrap();
+        sink.write(&[1u8; 16]).unwrap();
+
+        assert_eq!(0, sink.stats().xruns);
+        assert_eq!(192, sink.log().written());
+    }
+
+    #[test]
+    fn drain_test() {
+
+        let mut sink = MemorySink::memory()
+            .realtime();
+
+        sink.set_params(16, 8000, 1).unwrap();
+
+        // 20 milliseconds of data
+        let start = Instant::now();
+        sink.write(&[1u8; 320]).unwrap();
+        sink.drain().unwrap();
+
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn drop_test() {
+
+        let mut sink = MemorySink::memory()
+            .realtime();
+
+        sink.set_params(16, 8000, 1).unwrap();
+
+        // a whole second, which drain would wait for
+        sink.write(&[1u8; 16000]).unwrap();
+        sink.drop_queued().unwrap();
+
+        let start = Instant::now();
+        sink.drain().unwrap();
+        assert!(start.elapsed() < Duration::from_millis(500));
+
+        match sink.log().events()[2] {
+            SinkEvent::Drop { .. } => (),
+            _ => panic!("drop event is expected")
+        }
+    }
+
+    #[test]
+    fn position_test() {
+
+        let mut sink = MemorySink::memory();
+
+        assert!(sink.position().is_err());
+        sink.set_params(16, 8000, 2).unwrap();
+        sink.write(&[0u8; 32]).unwrap();
+
+        // nothing is held back without a clock
+        let position = sink.position().unwrap();
+        assert_eq!((8, 0), (position.written, position.delay));
+
+        let mut sink = MemorySink::memory()
+            .realtime();
+
+        sink.set_params(16, 8000, 1).unwrap();
+
+        // a second of data, and 80 frames of silence that aren't counted
+        // as written but are played ahead of it
+        sink.set_recovery(RecoveryPolicy::RecoverWithSilence(
+            Duration::from_millis(10)));
+        sink.write(&[1u8; 16]).unwrap();
+        sleep(Duration::from_millis(20));
+        sink.write(&[1u8; 16000]).unwrap();
+
+        let position = sink.position().unwrap();
+        assert_eq!(8008, position.written);
+        assert!(position.delay > 7000 && position.delay <= 8008);
+        assert_eq!(position.delay, sink.delay().unwrap());
+        assert!(position.elapsed() < Duration::from_millis(200));
+
+        // what has been dropped has never been played
+        sink.drop_queued().unwrap();
+
+        let position = sink.position().unwrap();
+        assert_eq!(0, position.delay);
+        assert!(position.written < 1008);
+        assert_eq!(position.written, position.played());
+    }
+}
@@ -3,109 +3,117 @@ extern crate libc;
 use std;
 use std::io;
 use std::ffi::{ CStr, CString };
+use std::mem;
 use std::mem::size_of;
+use std::ptr;
 use std::ptr::{ Unique, copy_nonoverlapping };
+use std::sync::mpsc::*;
+use std::thread::{ JoinHandle, sleep, spawn };
+use std::time::Duration;
 
 use io::*;
+use meter::{ LevelMeter, Levels };
+use sample::SampleFormat;
+use sink::{ Position, RecoveryPolicy, Sink, XrunStats };
 
 #[allow(non_camel_case_types)]
-type snd_pcm_t = libc::c_void;
+pub type snd_pcm_t = libc::c_void;
 
 #[allow(non_camel_case_types)]
-type snd_pcm_stream_t = i32;
+pub type snd_pcm_stream_t = i32;
 
-const SND_PCM_STREAM_PLAYBACK : snd_pcm_stream_t = 0;
-const SND_PCM_STREAM_CAPTURE  : snd_pcm_stream_t = 1;
+pub const SND_PCM_STREAM_PLAYBACK : snd_pcm_stream_t = 0;
+pub const SND_PCM_STREAM_CAPTURE  : snd_pcm_stream_t = 1;
 
 #[allow(non_camel_case_types)]
-type snd_pcm_format_t = i32;
-
-const SND_PCM_FORMAT_UNKNOWN            : snd_pcm_format_t = -1;
-const SND_PCM_FORMAT_S8                 : snd_pcm_format_t = 0;
-const SND_PCM_FORMAT_U8                 : snd_pcm_format_t = 1; 
-const	SND_PCM_FORMAT_S16_LE             : snd_pcm_format_t = 2;
-const SND_PCM_FORMAT_S16_BE             : snd_pcm_format_t = 3;
-const SND_PCM_FORMAT_U16_LE             : snd_pcm_format_t = 4;
-const SND_PCM_FORMAT_U16_BE             : snd_pcm_format_t = 5;
-const SND_PCM_FORMAT_S24_LE             : snd_pcm_format_t = 6;
-const SND_PCM_FORMAT_S24_BE             : snd_pcm_format_t = 7;
-const SND_PCM_FORMAT_U24_LE             : snd_pcm_format_t = 8;
-const SND_PCM_FORMAT_U24_BE             : snd_pcm_format_t = 9;
-const SND_PCM_FORMAT_S32_LE             : snd_pcm_format_t = 10;
-const SND_PCM_FORMAT_S32_BE             : snd_pcm_format_t = 11;
-const SND_PCM_FORMAT_U32_LE             : snd_pcm_format_t = 12;
-const SND_PCM_FORMAT_U32_BE             : snd_pcm_format_t = 13;
-const SND_PCM_FORMAT_FLOAT_LE           : snd_pcm_format_t = 14;
-const	SND_PCM_FORMAT_FLOAT_BE           : snd_pcm_format_t = 15;
-const SND_PCM_FORMAT_FLOAT64_LE         : snd_pcm_format_t = 16;
-const SND_PCM_FORMAT_FLOAT64_BE         : snd_pcm_format_t = 17;
-const SND_PCM_FORMAT_IEC958_SUBFRAME_LE : snd_pcm_format_t = 18;
-const	SND_PCM_FORMAT_IEC958_SUBFRAME_BE : snd_pcm_format_t = 19;
-const	SND_PCM_FORMAT_MU_LAW             : snd_pcm_format_t = 20;
-const SND_PCM_FORMAT_A_LAW              : snd_pcm_format_t = 21;
-const SND_PCM_FORMAT_IMA_ADPCM          : snd_pcm_format_t = 22;
-const SND_PCM_FORMAT_MPEG               : snd_pcm_format_t = 23;
-const	SND_PCM_FORMAT_GSM                : snd_pcm_format_t = 24;
-const SND_PCM_FORMAT_SPECIAL            : snd_pcm_format_t = 31;
-const SND_PCM_FORMAT_S24_3LE            : snd_pcm_format_t = 32;
-const SND_PCM_FORMAT_S24_3BE            : snd_pcm_format_t = 33;
-const SND_PCM_FORMAT_U24_3LE            : snd_pcm_format_t = 34;
-const SND_PCM_FORMAT_U24_3BE            : snd_pcm_format_t = 35;
-const SND_PCM_FORMAT_S20_3LE            : snd_pcm_format_t = 36;
-const SND_PCM_FORMAT_S20_3BE            : snd_pcm_format_t = 37;
-const SND_PCM_FORMAT_U20_3LE            : snd_pcm_format_t = 38;
-const SND_PCM_FORMAT_U20_3BE            : snd_pcm_format_t = 39;
-const SND_PCM_FORMAT_S18_3LE            : snd_pcm_format_t = 40;
-const SND_PCM_FORMAT_S18_3BE            : snd_pcm_format_t = 41;
-const SND_PCM_FORMAT_U18_3LE            : snd_pcm_format_t = 42;
-const SND_PCM_FORMAT_U18_3BE            : snd_pcm_format_t = 43;
-const SND_PCM_FORMAT_G723_24            : snd_pcm_format_t = 44;
-const SND_PCM_FORMAT_G723_24_1B         : snd_pcm_format_t = 45;
-const SND_PCM_FORMAT_G723_40            : snd_pcm_format_t = 46;
-const SND_PCM_FORMAT_G723_40_1B         : snd_pcm_format_t = 47;
-const SND_PCM_FORMAT_DSD_U8             : snd_pcm_format_t = 48;
-const SND_PCM_FORMAT_DSD_U16_LE         : snd_pcm_format_t = 49;
-const SND_PCM_FORMAT_DSD_U32_LE         : snd_pcm_format_t = 50;
-const SND_PCM_FORMAT_DSD_U16_BE         : snd_pcm_format_t = 51;
-const SND_PCM_FORMAT_DSD_U32_BE         : snd_pcm_format_t = 52;
+pub type snd_pcm_format_t = i32;
+
+pub const SND_PCM_FORMAT_UNKNOWN            : snd_pcm_format_t = -1;
+pub const SND_PCM_FORMAT_S8                 : snd_pcm_format_t = 0;
+pub const SND_PCM_FORMAT_U8                 : snd_pcm_format_t = 1; 
+pub const SND_PCM_FORMAT_S16_LE             : snd_pcm_format_t = 2;
+pub const SND_PCM_FORMAT_S16_BE             : snd_pcm_format_t = 3;
+pub const SND_PCM_FORMAT_U16_LE             : snd_pcm_format_t = 4;
+pub const SND_PCM_FORMAT_U16_BE             : snd_pcm_format_t = 5;
+pub const SND_PCM_FORMAT_S24_LE             : snd_pcm_format_t = 6;
+pub const SND_PCM_FORMAT_S24_BE             : snd_pcm_format_t = 7;
+pub const SND_PCM_FORMAT_U24_LE             : snd_pcm_format_t = 8;
+pub const SND_PCM_FORMAT_U24_BE             : snd_pcm_format_t = 9;
+pub const SND_PCM_FORMAT_S32_LE             : snd_pcm_format_t = 10;
+pub const SND_PCM_FORMAT_S32_BE             : snd_pcm_format_t = 11;
+pub const SND_PCM_FORMAT_U32_LE             : snd_pcm_format_t = 12;
+pub const SND_PCM_FORMAT_U32_BE             : snd_pcm_format_t = 13;
+pub const SND_PCM_FORMAT_FLOAT_LE           : snd_pcm_format_t = 14;
+pub const SND_PCM_FORMAT_FLOAT_BE           : snd_pcm_format_t = 15;
+pub const SND_PCM_FORMAT_FLOAT64_LE         : snd_pcm_format_t = 16;
+pub const SND_PCM_FORMAT_FLOAT64_BE         : snd_pcm_format_t = 17;
+pub const SND_PCM_FORMAT_IEC958_SUBFRAME_LE : snd_pcm_format_t = 18;
+pub const SND_PCM_FORMAT_IEC958_SUBFRAME_BE : snd_pcm_format_t = 19;
+pub const SND_PCM_FORMAT_MU_LAW             : snd_pcm_format_t = 20;
+pub const SND_PCM_FORMAT_A_LAW              : snd_pcm_format_t = 21;
+pub const SND_PCM_FORMAT_IMA_ADPCM          : snd_pcm_format_t = 22;
+pub const SND_PCM_FORMAT_MPEG               : snd_pcm_format_t = 23;
+pub const SND_PCM_FORMAT_GSM                : snd_pcm_format_t = 24;
+pub const SND_PCM_FORMAT_SPECIAL            : snd_pcm_format_t = 31;
+pub const SND_PCM_FORMAT_S24_3LE            : snd_pcm_format_t = 32;
+pub const SND_PCM_FORMAT_S24_3BE            : snd_pcm_format_t = 33;
+pub const SND_PCM_FORMAT_U24_3LE            : snd_pcm_format_t = 34;
+pub const SND_PCM_FORMAT_U24_3BE            : snd_pcm_format_t = 35;
+pub const SND_PCM_FORMAT_S20_3LE            : snd_pcm_format_t = 36;
+pub const SND_PCM_FORMAT_S20_3BE            : snd_pcm_format_t = 37;
+pub const SND_PCM_FORMAT_U20_3LE            : snd_pcm_format_t = 38;
+pub const SND_PCM_FORMAT_U20_3BE            : snd_pcm_format_t = 39;
+pub const SND_PCM_FORMAT_S18_3LE            : snd_pcm_format_t = 40;
+pub const SND_PCM_FORMAT_S18_3BE            : snd_pcm_format_t = 41;
+pub const SND_PCM_FORMAT_U18_3LE            : snd_pcm_format_t = 42;
+pub const SND_PCM_FORMAT_U18_3BE            : snd_pcm_format_t = 43;
+pub const SND_PCM_FORMAT_G723_24            : snd_pcm_format_t = 44;
+pub const SND_PCM_FORMAT_G723_24_1B         : snd_pcm_format_t = 45;
+pub const SND_PCM_FORMAT_G723_40            : snd_pcm_format_t = 46;
+pub const SND_PCM_FORMAT_G723_40_1B         : snd_pcm_format_t = 47;
+pub const SND_PCM_FORMAT_DSD_U8             : snd_pcm_format_t = 48;
+pub const SND_PCM_FORMAT_DSD_U16_LE         : snd_pcm_format_t = 49;
+pub const SND_PCM_FORMAT_DSD_U32_LE         : snd_pcm_format_t = 50;
+pub const SND_PCM_FORMAT_DSD_U16_BE         : snd_pcm_format_t = 51;
+pub const SND_PCM_FORMAT_DSD_U32_BE         : snd_pcm_format_t = 52;
 
 #[cfg(target_endian = "little")]
-const SND_PCM_FORMAT_S16                : snd_pcm_format_t = SND_PCM_FORMAT_S16_LE;
+pub const SND_PCM_FORMAT_S16                : snd_pcm_format_t = SND_PCM_FORMAT_S16_LE;
 #[cfg(target_endian = "little")]
-const	SND_PCM_FORMAT_U16                : snd_pcm_format_t = SND_PCM_FORMAT_U16_LE;
+pub const SND_PCM_FORMAT_U16                : snd_pcm_format_t = SND_PCM_FORMAT_U16_LE;
 #[cfg(target_endian = "little")]
-const	SND_PCM_FORMAT_S24                : snd_pcm_format_t = SND_PCM_FORMAT_S24_LE;
+pub const SND_PCM_FORMAT_S24                : snd_pcm_format_t = SND_PCM_FORMAT_S24_LE;
 #[cfg(target_endian = "little")]
-const	SND_PCM_FORMAT_U24                : snd_pcm_format_t = SND_PCM_FORMAT_U24_LE;
+pub const SND_PCM_FORMAT_U24                : snd_pcm_format_t = SND_PCM_FORMAT_U24_LE;
 #[cfg(target_endian = "little")]
-const	SND_PCM_FORMAT_S32                : snd_pcm_format_t = SND_PCM_FORMAT_S32_LE;
+pub const SND_PCM_FORMAT_S32                : snd_pcm_format_t = SND_PCM_FORMAT_S32_LE;
 #[cfg(target_endian = "little")]
-const	SND_PCM_FORMAT_U32                : snd_pcm_format_t = SND_PCM_FORMAT_U32_LE;
+pub const SND_PCM_FORMAT_U32                : snd_pcm_format_t = SND_PCM_FORMAT_U32_LE;
 #[cfg(target_endian = "little")]
-const	SND_PCM_FORMAT_FLOAT              : snd_pcm_format_t = SND_PCM_FORMAT_FLOAT_LE;
+pub const SND_PCM_FORMAT_FLOAT              : snd_pcm_format_t = SND_PCM_FORMAT_FLOAT_LE;
 #[cfg(target_endian = "little")]
-const SND_PCM_FORMAT_FLOAT64            : snd_pcm_format_t = SND_PCM_FORMAT_FLOAT64_LE;
+pub const SND_PCM_FORMAT_FLOAT64            : snd_pcm_format_t = SND_PCM_FORMAT_FLOAT64_LE;
 #[cfg(target_endian = "little")]
-const SND_PCM_FORMAT_IEC958_SUBFRAME    : snd_pcm_format_t = SND_PCM_FORMAT_IEC958_SUBFRAME_LE;
+pub const SND_PCM_FORMAT_IEC958_SUBFRAME    : snd_pcm_format_t = SND_PCM_FORMAT_IEC958_SUBFRAME_LE;
 
 #[cfg(target_endian = "big")]
-const SND_PCM_FORMAT_S16                : snd_pcm_format_t = SND_PCM_FORMAT_S16_BE;
+pub const SND_PCM_FORMAT_S16                : snd_pcm_format_t = SND_PCM_FORMAT_S16_BE;
 #[cfg(target_endian = "big")]
-const	SND_PCM_FORMAT_U16                : snd_pcm_format_t = SND_PCM_FORMAT_U16_BE;
+pub const SND_PCM_FORMAT_U16                : snd_pcm_format_t = SND_PCM_FORMAT_U16_BE;
 #[cfg(target_endian = "big")]
-const	SND_PCM_FORMAT_S24                : snd_pcm_format_t = SND_PCM_FORMAT_S24_BE;
+pub const SND_PCM_FORMAT_S24                : snd_pcm_format_t = SND_PCM_FORMAT_S24_BE;
 #[cfg(target_endian = "big")]
-const	SND_PCM_FORMAT_U24                : snd_pcm_format_t = SND_PCM_FORMAT_U24_BE;
+pub const SND_PCM_FORMAT_U24                : snd_pcm_format_t = SND_PCM_FORMAT_U24_BE;
 #[cfg(target_endian = "big")]
-const	SND_PCM_FORMAT_S32                : snd_pcm_format_t = SND_PCM_FORMAT_S32_BE;
+pub const SND_PCM_FORMAT_S32                : snd_pcm_format_t = SND_PCM_FORMAT_S32_BE;
 #[cfg(target_endian = "big")]
-const	SND_PCM_FORMAT_U32                : snd_pcm_format_t = SND_PCM_FORMAT_U32_BE;
+pub const SND_PCM_FORMAT_U32                : snd_pcm_format_t = SND_PCM_FORMAT_U32_BE;
 #[cfg(target_endian = "big")]
-const	SND_PCM_FORMAT_FLOAT              : snd_pcm_format_t = SND_PCM_FORMAT_FLOAT_BE;
+pub const SND_PCM_FORMAT_FLOAT              : snd_pcm_format_t = SND_PCM_FORMAT_FLOAT_BE;
 #[cfg(target_endian = "big")]
-const SND_PCM_FORMAT_FLOAT64            : snd_pcm_format_t = SND_PCM_FORMAT_FLOAT64_BE;
+pub const SND_PCM_FORMAT_FLOAT64            : snd_pcm_format_t = SND_PCM_FORMAT_FLOAT64_BE;
 #[cfg(target_endian = "big")]
-const SND_PCM_FORMAT_IEC958_SUBFRAME    : snd_pcm_format_t = SND_PCM_FORMAT_IEC958_SUBFRAME_BE;
+pub const SND_PCM_FORMAT_IEC958_SUBFRAME    : snd_pcm_format_t = SND_PCM_FORMAT_IEC958_SUBFRAME_BE;
 
 #[allow(non_camel_case_types)]
 type snd_pcm_access_t = i32;
@@ -119,6 +127,31 @@ const SND_PCM_ACCESS_RW_NONINTERLEAVED    : snd_pcm_access_t = 4;
 #[allow(non_camel_case_types)]
 type snd_pcm_uframes_t = u64;
 
+#[allow(non_camel_case_types)]
+type snd_pcm_sframes_t = i64;
+
+#[allow(non_camel_case_types)]
+type snd_pcm_state_t = i32;
+
+const SND_PCM_STATE_OPEN         : snd_pcm_state_t = 0;
+const SND_PCM_STATE_SETUP        : snd_pcm_state_t = 1;
+const SND_PCM_STATE_PREPARED     : snd_pcm_state_t = 2;
+const SND_PCM_STATE_RUNNING      : snd_pcm_state_t = 3;
+const SND_PCM_STATE_XRUN         : snd_pcm_state_t = 4;
+const SND_PCM_STATE_DRAINING     : snd_pcm_state_t = 5;
+const SND_PCM_STATE_PAUSED       : snd_pcm_state_t = 6;
+const SND_PCM_STATE_SUSPENDED    : snd_pcm_state_t = 7;
+const SND_PCM_STATE_DISCONNECTED : snd_pcm_state_t = 8;
+
+#[allow(non_camel_case_types)]
+type snd_pcm_hw_params_t = libc::c_void;
+
+#[allow(non_camel_case_types)]
+type snd_pcm_status_t = libc::c_void;
+
+#[allow(non_camel_case_types)]
+type snd_htimestamp_t = libc::timespec;
+
 #[repr(C)]
 #[allow(non_camel_case_types)]
 struct snd_pcm_chmap_t {
@@ -131,15 +164,39 @@ extern "C" {
 
     fn snd_pcm_close(pcm: *mut snd_pcm_t) -> i32;
 
+    fn snd_pcm_delay(pcm: *mut snd_pcm_t,
+        delay: *mut snd_pcm_sframes_t) -> i32;
+
+    fn snd_pcm_drain(pcm: *mut snd_pcm_t) -> i32;
+
     fn snd_pcm_drop(pcm: *mut snd_pcm_t) -> i32;
 
     fn snd_pcm_get_chmap(pcm: *mut snd_pcm_t) -> *mut snd_pcm_chmap_t;
 
+    fn snd_pcm_get_params(pcm: *mut snd_pcm_t,
+        buffer_size: *mut snd_pcm_uframes_t,
+        period_size: *mut snd_pcm_uframes_t) -> i32;
+
+    fn snd_pcm_hw_params_can_pause(params: *const snd_pcm_hw_params_t) -> i32;
+
+    fn snd_pcm_hw_params_current(pcm: *mut snd_pcm_t,
+        params: *mut snd_pcm_hw_params_t) -> i32;
+
+    fn snd_pcm_hw_params_free(params: *mut snd_pcm_hw_params_t);
+
+    fn snd_pcm_hw_params_malloc(params: *mut *mut snd_pcm_hw_params_t) -> i32;
+
+    fn snd_pcm_nonblock(pcm: *mut snd_pcm_t, nonblock: i32) -> i32;
+
     fn snd_pcm_open(pcm: *mut *mut snd_pcm_t,
         name: *const libc::c_char,
         stream: snd_pcm_stream_t,
         mode: i32) -> i32;
 
+    fn snd_pcm_pause(pcm: *mut snd_pcm_t, enable: i32) -> i32;
+
+    fn snd_pcm_prepare(pcm: *mut snd_pcm_t) -> i32;
+
     fn snd_pcm_recover(pcm: *mut snd_pcm_t,
         err: i32,
         silent: i32) -> i32;
@@ -152,6 +209,24 @@ extern "C" {
         soft_resample: i32,
         latency: u32) -> i32; 
 
+    fn snd_pcm_state(pcm: *mut snd_pcm_t) -> snd_pcm_state_t;
+
+    fn snd_pcm_status(pcm: *mut snd_pcm_t,
+        status: *mut snd_pcm_status_t) -> i32;
+
+    fn snd_pcm_status_free(obj: *mut snd_pcm_status_t);
+
+    fn snd_pcm_status_get_delay(obj: *const snd_pcm_status_t)
+     -> snd_pcm_sframes_t;
+
+    fn snd_pcm_status_get_htstamp(obj: *const snd_pcm_status_t,
+        ptr: *mut snd_htimestamp_t);
+
+    fn snd_pcm_status_get_state(obj: *const snd_pcm_status_t)
+     -> snd_pcm_state_t;
+
+    fn snd_pcm_status_malloc(ptr: *mut *mut snd_pcm_status_t) -> i32;
+
     fn snd_pcm_writei(pcm: *mut snd_pcm_t,
         buffer: *const libc::c_void,
         size: usize) -> i32;  
@@ -163,25 +238,74 @@ type SoundPcm = snd_pcm_t;
 type SoundPcmPtr = Option<Unique<SoundPcm>>;
 
 pub struct NonBlockingSoundPcmPlaybackWriter {
-    inner : SoundPcmPtr
+    inner      : SoundPcmPtr,
+    name       : String,
+    frame_size : usize,
+    format     : Option<SampleFormat>,
+    preferred  : Option<SampleFormat>,
+    rate       : u32,
+    frames     : u64,
+    recovery   : RecoveryPolicy,
+    stats      : XrunStats,
+    pause      : PauseState
+}
+
+// devices that can't pause are paused by dropping what they have queued
+// and writing it again on resume, so the last buffer worth of written data
+// is kept around for them. data written while paused is held back.
+struct PauseState {
+    can_pause : bool,
+    paused    : bool,
+    hw_paused : bool,
+    capacity  : usize,
+    history   : Vec<u8>,
+    pending   : Vec<u8>
+}
+
+impl PauseState {
+
+    fn new() -> Self {
+        PauseState {
+            can_pause: false,
+            paused: false,
+            hw_paused: false,
+            capacity: 0,
+            history: Vec::new(),
+            pending: Vec::new()
+        }
+    }
+
+    fn remember(&mut self, buf: &[u8]) {
+
+        if self.can_pause {
+            return;
+        }
+
+        self.history.extend_from_slice(buf);
+
+        if self.history.len() > self.capacity {
+            let excess = self.history.len() - self.capacity;
+            self.history.drain(..excess);
+        }
+    }
 }
 
 const PLAYBACK_STREAM : snd_pcm_stream_t = SND_PCM_STREAM_PLAYBACK; 
 
-fn snd_pcm_error(errnum: i32) -> Result<&'static str, std::str::Utf8Error> {
+pub fn snd_pcm_error(errnum: i32) -> Result<&'static str, std::str::Utf8Error> {
     unsafe {
         CStr::from_ptr(snd_strerror(errnum))
             .to_str()
     }
 }
 
-const SND_PCM_NONBLOCK : i32 = 1;
-const SND_PCM_ASYNC    : i32 = 2;
+pub const SND_PCM_NONBLOCK : i32 = 1;
+pub const SND_PCM_ASYNC    : i32 = 2;
 
 impl NonBlockingSoundPcmPlaybackWriter {
 
     // write-only playback stream
-    pub fn create(path: &'static str) -> io::Result<Self> {
+    pub fn create(path: &str) -> io::Result<Self> {
 
         let mut raw_ptr : *mut SoundPcm = unsafe {
             std::mem::uninitialized()
@@ -204,7 +328,16 @@ impl NonBlockingSoundPcmPlaybackWriter {
 
             0 => unsafe {
                 Ok(NonBlockingSoundPcmPlaybackWriter {
-                    inner: Some(Unique::new(raw_ptr)) 
+                    inner: Some(Unique::new(raw_ptr)),
+                    name: path.to_string(),
+                    frame_size: 0,
+                    format: None,
+                    preferred: None,
+                    rate: 0,
+                    frames: 0,
+                    recovery: RecoveryPolicy::default(),
+                    stats: XrunStats::default(),
+                    pause: PauseState::new()
                 })
             },
 
@@ -212,6 +345,280 @@ impl NonBlockingSoundPcmPlaybackWriter {
                 ::from_raw_os_error(errnum))
         }    
     }
+
+    // opens a device by a name that may come from user input
+    pub fn open(name: String) -> io::Result<Self> {
+        Self::create(&name)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn pcm(&mut self) -> *mut snd_pcm_t {
+        match self.inner {
+            Some(ref mut inner) => unsafe {
+                inner.get_mut() as *mut snd_pcm_t
+            },
+            _ => panic!(NO_SND_PCM_PTR)
+        }
+    }
+
+    // writes whole frames until all of them are accepted or an error
+    // other than EAGAIN comes up, which is returned as is.
+    fn write_frames(&mut self, buf: &[u8]) -> Result<usize, i32> {
+
+        let (pcm, frame_size) = (self.pcm(), self.frame_size);
+        let (len, mut written) = (buf.len() / frame_size * frame_size, 0);
+
+        while written < len {
+
+            match unsafe {
+                snd_pcm_writei(pcm,
+                    buf.as_ptr()
+                       .offset(written as isize) as *const libc::c_void,
+                    (len - written) / frame_size)
+            } {
+                res if res >= 0 => written += res as usize * frame_size,
+                errnum if errnum == -EAGAIN => continue,
+                errnum => return Err(errnum)
+            }
+        }
+
+        Ok(written)
+    }
+
+    fn silence(&self, duration: Duration) -> Vec<u8> {
+
+        let format = self.format
+            .unwrap();
+        let channels = self.frame_size / format.physical_bytes();
+        let frames = duration.as_secs() as usize * self.rate as usize +
+            duration.subsec_nanos() as usize / 1000 * self.rate as usize /
+            1_000_000;
+
+        format.encode_samples(&vec![0.0; frames * channels])
+    }
+
+    // brings the stream back after an underrun or a suspend and applies
+    // the recovery policy.
+    fn recover(&mut self, errnum: i32) -> io::Result<()> {
+
+        match -errnum {
+            EPIPE => self.stats.xruns += 1,
+            ESTRPIPE => self.stats.suspends += 1,
+            _ => ()
+        }
+
+        // the stream is left stopped until it is set up again
+        if let RecoveryPolicy::Fail = self.recovery {
+            return Err(snd_error(errnum));
+        }
+
+        const SILENT : i32 = 1;
+
+        match unsafe { snd_pcm_recover(self.pcm(), errnum, SILENT) } {
+            0 => self.stats.recoveries += 1,
+            err => {
+                self.stats.failures += 1;
+                return Err(snd_error(err));
+            }
+        }
+
+        match self.recovery {
+
+            RecoveryPolicy::RecoverWithSilence(duration) => {
+                let silence = self.silence(duration);
+                self.write_frames(&silence)
+                    .map(|_| ())
+                    .map_err(snd_error)
+            },
+
+            _ => Ok(())
+        }
+    }
+
+    fn check(res: i32) -> io::Result<()> {
+        match res {
+            res if res >= 0 => Ok(()),
+            errnum => Err(snd_error(errnum))
+        }
+    }
+
+    fn can_pause(&mut self) -> bool {
+
+        let pcm = self.pcm();
+        let mut params : *mut snd_pcm_hw_params_t = ptr::null_mut();
+
+        unsafe {
+            match snd_pcm_hw_params_malloc(&mut params) {
+                0 => (),
+                _ => return false
+            }
+
+            let res = snd_pcm_hw_params_current(pcm, params) == 0 &&
+                snd_pcm_hw_params_can_pause(params) == 1;
+
+            snd_pcm_hw_params_free(params);
+            res
+        }
+    }
+
+    fn buffer_bytes(&mut self) -> usize {
+
+        let (mut buffer_size, mut period_size) = (0, 0);
+
+        match unsafe {
+            snd_pcm_get_params(self.pcm(), &mut buffer_size, &mut period_size)
+        } {
+            0 => buffer_size as usize * self.frame_size,
+            _ => 0
+        }
+    }
+
+    // frames written but not played yet
+    fn queued_frames(&mut self) -> usize {
+
+        let mut delay : snd_pcm_sframes_t = 0;
+
+        match unsafe { snd_pcm_delay(self.pcm(), &mut delay) } {
+            0 if delay > 0 => delay as usize,
+            _ => 0
+        }
+    }
+
+    // the state, delay and high resolution timestamp of the stream,
+    // all taken at the same moment
+    fn status(&mut self)
+     -> io::Result<(snd_pcm_state_t, snd_pcm_sframes_t, Duration)> {
+
+        let pcm = self.pcm();
+        let mut status : *mut snd_pcm_status_t = ptr::null_mut();
+
+        unsafe {
+            try!(Self::check(snd_pcm_status_malloc(&mut status)));
+
+            let res = snd_pcm_status(pcm, status);
+            let state = snd_pcm_status_get_state(status);
+            let delay = snd_pcm_status_get_delay(status);
+            let mut tstamp : snd_htimestamp_t = mem::zeroed();
+            snd_pcm_status_get_htstamp(status, &mut tstamp);
+
+            snd_pcm_status_free(status);
+            try!(Self::check(res));
+
+            Ok((state, delay, Duration::new(tstamp.tv_sec as u64,
+                tstamp.tv_nsec as u32)))
+        }
+    }
+
+    fn is_running(&mut self) -> bool {
+        unsafe { snd_pcm_state(self.pcm()) == SND_PCM_STATE_RUNNING }
+    }
+
+    // throws queued data away and makes the stream writable again
+    fn reset(&mut self) -> io::Result<()> {
+        let pcm = self.pcm();
+        unsafe {
+            try!(Self::check(snd_pcm_drop(pcm)));
+            Self::check(snd_pcm_prepare(pcm))
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pause.paused
+    }
+
+    fn pause_stream(&mut self) -> io::Result<()> {
+
+        if self.pause.paused {
+            return Ok(());
+        }
+
+        // a stream that hasn't started has nothing to stop
+        if self.is_running() {
+
+            match self.pause.can_pause {
+
+                true => {
+                    try!(Self::check(unsafe {
+                        snd_pcm_pause(self.pcm(), 1)
+                    }));
+                    self.pause.hw_paused = true;
+                },
+
+                _ => {
+                    let queued = self.queued_frames() * self.frame_size;
+                    let history = &self.pause.history;
+                    let start = history.len() - queued.min(history.len());
+
+                    // they are counted again when they are written back
+                    self.frames -= ((history.len() - start) /
+                        self.frame_size) as u64;
+
+                    let mut pending = history[start..].to_vec();
+                    pending.extend_from_slice(&self.pause.pending);
+                    self.pause.pending = pending;
+                    self.pause.history.clear();
+
+                    try!(self.reset());
+                }
+            }
+        }
+
+        self.pause.paused = true;
+        Ok(())
+    }
+
+    fn resume_stream(&mut self) -> io::Result<()> {
+
+        if !self.pause.paused {
+            return Ok(());
+        }
+
+        if self.pause.hw_paused {
+            try!(Self::check(unsafe { snd_pcm_pause(self.pcm(), 0) }));
+            self.pause.hw_paused = false;
+        }
+
+        self.pause.paused = false;
+
+        let pending = mem::replace(&mut self.pause.pending, Vec::new());
+        io::Write::write(self, &pending)
+            .map(|_| ())
+    }
+
+    fn drain_stream(&mut self) -> io::Result<()> {
+
+        try!(self.resume_stream());
+
+        let pcm = self.pcm();
+
+        // draining a non-blocking stream would just return EAGAIN
+        unsafe {
+            try!(Self::check(snd_pcm_nonblock(pcm, 0)));
+            let res = snd_pcm_drain(pcm);
+            try!(Self::check(snd_pcm_nonblock(pcm, 1)));
+            try!(Self::check(res));
+            try!(Self::check(snd_pcm_prepare(pcm)));
+        }
+
+        self.pause.history.clear();
+        Ok(())
+    }
+
+    fn drop_stream(&mut self) -> io::Result<()> {
+        let queued = self.queued_frames() as u64;
+        self.frames -= queued.min(self.frames);
+        self.pause.pending.clear();
+        self.pause.history.clear();
+        self.pause.hw_paused = false;
+        self.reset()
+    }
+}
+
+fn snd_error(errnum: i32) -> io::Error {
+    io::Error::from_raw_os_error(-errnum)
 }
 
 impl Drop for NonBlockingSoundPcmPlaybackWriter {
@@ -227,114 +634,179 @@ impl Drop for NonBlockingSoundPcmPlaybackWriter {
 }
 
 const NO_SND_PCM_PTR : &'static str = "no pcm pointer";
+const NO_PARAMS : &'static str = "params have not been set";
 const EAGAIN : i32 = libc::EAGAIN;
+const EPIPE : i32 = libc::EPIPE;
+const ESTRPIPE : i32 = libc::ESTRPIPE;
 
 impl io::Write for NonBlockingSoundPcmPlaybackWriter {
 
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
 
-        match self.inner {
+        let frame_size = match self.frame_size {
+            0 => return Err(IOError::new(IO_ERROR, NO_PARAMS)),
+            n => n
+        };
 
-            Some(ref mut inner) => unsafe {
+        // a trailing partial frame can't be written
+        let (len, mut written) = (buf.len() / frame_size * frame_size, 0);
 
-                let (pcm, len, mut written) = (inner.get_mut(),
-                    buf.len(),
-                    0);
-
-                while written < len {
-
-                    match snd_pcm_writei(pcm as *mut snd_pcm_t,
-                        buf.as_ptr()
-                           .offset(written as isize) as 
-                               *const libc::c_void,
-                        len - written) {
-
-                        res if res >= 0 => {
-                            // be supposed to channel length is one.
-                            written += res as usize;
-                        },
-
-                        errnum if errnum == -EAGAIN => continue,
-
-                        errnum => {
-                            match snd_pcm_recover(pcm as *mut snd_pcm_t,
-                                errnum as i32,
-                                0) {
-                                0 => return Err(io::Error
-                                    ::from_raw_os_error(errnum as i32)),
-                                err => panic!(snd_pcm_error(err))
-                            }
-                        }
-                    }
-                }
-              
-                Ok(written)
-            },
+        if self.pause.paused {
+            self.pause.pending.extend_from_slice(&buf[..len]);
+            return Ok(len);
+        }
 
-            _ => panic!(NO_SND_PCM_PTR)
+        while written < len {
+            match self.write_frames(&buf[written..len]) {
+                Ok(n) => {
+                    self.pause.remember(&buf[written..written + n]);
+                    self.frames += (n / frame_size) as u64;
+                    written += n;
+                },
+                Err(errnum) => try!(self.recover(errnum))
+            }
         }
+
+        Ok(written)
     }
 
+    // waits until everything written has been played
     fn flush(&mut self) -> io::Result<()> {
-
-        match self.inner {
-
-            Some(ref mut inner) => unsafe {
-
-                let pcm = inner.get_mut();
-
-                match snd_pcm_drop(pcm as *mut snd_pcm_t) {
-                    0 => Ok(()),
-                    errnum => Err(io::Error
-                        ::from_raw_os_error(errnum))
-                }
-            },
-
-            _ => panic!(NO_SND_PCM_PTR)
-        } 
+        self.drain_stream()
     }
 } 
 
-impl NonBlockingSoundPcmPlaybackWriter {
+// the sample format the writer opens the device with for the given bits
+pub fn playback_format(wave_bits: u8) -> SampleFormat {
+    let format = match wave_bits {
+        8  => SND_PCM_FORMAT_U8, 
+        16 => SND_PCM_FORMAT_S16, 
+        24 => SND_PCM_FORMAT_S24, 
+        32 => SND_PCM_FORMAT_S32, 
+        _  => panic!("unexpected wave bits")
+    };
+    SampleFormat::from_snd(format)
+        .unwrap()
+}
+
+impl Sink for NonBlockingSoundPcmPlaybackWriter {
 
     fn set_params(&mut self, 
         wave_bits: u8,
-        wave_rates: u16,
+        wave_rates: u32,
         wave_channels: u8) -> io::Result<()> {
         
         const ALLOW_RESAMPLING    : i32 = 1; 
         const ORDINARY_SAMLE_RATE : u32 = 480000;
 
+        let format = match self.preferred {
+            Some(format) if format.width() == wave_bits as u32 => format,
+            _ => playback_format(wave_bits)
+        };
+
         match self.inner {
           
             Some(ref mut inner) => unsafe {
                 
                 let pcm = inner.get_mut();        
 
-                let format = match wave_bits {
-                    8  => SND_PCM_FORMAT_U8, 
-                    16 => SND_PCM_FORMAT_S16, 
-                    24 => SND_PCM_FORMAT_S24, 
-                    32 => SND_PCM_FORMAT_S32, 
-                    _  => panic!("unexpected wave bits")
-                };
-
                 match snd_pcm_set_params(pcm as *mut snd_pcm_t,
-                    format,
+                    format.to_snd(),
                     SND_PCM_ACCESS_RW_INTERLEAVED,
                     wave_channels as u32,
-                    wave_rates as u32,
+                    wave_rates,
                     ALLOW_RESAMPLING,
                     ORDINARY_SAMLE_RATE) {
-                    0 => Ok(()),
-                    errnum => Err(io::Error
-                        ::from_raw_os_error(errnum))
+                    0 => {
+                        self.frame_size = format.physical_bytes() * 
+                            wave_channels as usize;
+                        self.format = Some(format);
+                        self.rate = wave_rates;
+                        self.frames = 0;
+                        self.pause = PauseState::new();
+                        self.pause.can_pause = self.can_pause();
+                        self.pause.capacity = self.buffer_bytes();
+                        Ok(())
+                    },
+                    errnum => Err(snd_error(errnum))
                 }
             },
 
             _ => panic!(NO_SND_PCM_PTR)
         } 
     }
+
+    // opens the stream in `format` rather than the one playback_format()
+    // gives whenever the bits set up match its width
+    fn use_format(&mut self, format: SampleFormat) {
+        self.preferred = Some(format);
+    }
+
+    // an underrun has played everything out, so a stream in the xrun
+    // state has nothing queued whatever the delay says.
+    fn position(&mut self) -> io::Result<Position> {
+
+        if self.frame_size == 0 {
+            return Err(IOError::new(IO_ERROR, NO_PARAMS));
+        }
+
+        let (state, delay, timestamp) = try!(self.status());
+
+        let delay = match state {
+            SND_PCM_STATE_XRUN => 0,
+            _ => delay.max(0) as u64
+        };
+
+        Ok(Position {
+            written: self.frames,
+            delay: delay.min(self.frames),
+            rate: self.rate,
+            timestamp: match timestamp.as_secs() == 0 &&
+                timestamp.subsec_nanos() == 0 {
+                true => None,
+                _ => Some(timestamp)
+            }
+        })
+    }
+
+    fn delay(&mut self) -> io::Result<u64> {
+
+        if self.frame_size == 0 {
+            return Err(IOError::new(IO_ERROR, NO_PARAMS));
+        }
+
+        let mut delay : snd_pcm_sframes_t = 0;
+
+        match unsafe { snd_pcm_delay(self.pcm(), &mut delay) } {
+            res if res == -EPIPE => Ok(0),
+            res => Self::check(res)
+                .map(|_| (delay.max(0) as u64).min(self.frames))
+        }
+    }
+
+    fn stats(&self) -> XrunStats {
+        self.stats
+    }
+
+    fn set_recovery(&mut self, policy: RecoveryPolicy) {
+        self.recovery = policy;
+    }
+
+    fn pause(&mut self) -> io::Result<()> {
+        self.pause_stream()
+    }
+
+    fn resume(&mut self) -> io::Result<()> {
+        self.resume_stream()
+    }
+
+    fn drain(&mut self) -> io::Result<()> {
+        self.drain_stream()
+    }
+
+    fn drop_queued(&mut self) -> io::Result<()> {
+        self.drop_stream()
+    }
 }
 
 trait FromBuffer {
@@ -417,20 +889,57 @@ s! {
     }
 }
 
+impl Format {
+
+    pub fn new(channels: u16, sample_rate: usize, bits_width: u16) -> Self {
+
+        let block_align = channels * ((bits_width + 7) / 8);
+
+        Format {
+            format: 1,
+            channels: channels,
+            sample_rate: sample_rate,
+            byte_per_sec: sample_rate * block_align as usize,
+            block_align: block_align,
+            bits_width: bits_width
+        }
+    }
+}
+
 type PlaybackWriter = NonBlockingSoundPcmPlaybackWriter;
 
-enum SoundPcmIORequest {
+pub enum SoundPcmIORequest<S: Sink> {
     SetParams(Format),
-    Write(WriteBuffer<PlaybackWriter>),
+    SetRecovery(RecoveryPolicy),
+    Write(WriteBuffer<S>),
+    Pause,
+    Resume,
+    Drain,
+    Drop,
+    Stats,
+    Position,
+    // the format the written bytes are in. it goes ahead of SetParams
+    // for the sink to be opened in it, and the bytes are metered in it.
+    SetSampleFormat(SampleFormat),
+    // levels are sent to the subscriber as the written frames are metered
+    Subscribe(Sender<Levels>),
     Close
 }
 
-unsafe impl Send for SoundPcmIORequest {}
+unsafe impl<S: Sink> Send for SoundPcmIORequest<S> {}
 
-enum SoundPcmIOResponse {
+pub enum SoundPcmIOResponse {
     IsSet,
     Written(usize),
+    Paused,
+    Resumed,
+    Drained,
+    Dropped,
+    Stats(XrunStats),
+    Position(Position),
+    Subscribed,
     Failed(IOError), 
+    Closed,
     Timeout
 }
 
@@ -439,8 +948,8 @@ unsafe impl Send for SoundPcmIOResponse {}
 const REQUEST_IS_ODD: &'static str = 
     "this function can't handle Close request";
 
-fn handle_sp_io_request(writer: &mut PlaybackWriter,
-    req: SoundPcmIORequest) -> SoundPcmIOResponse {
+fn handle_sp_io_request<S: Sink>(writer: &mut S,
+    req: SoundPcmIORequest<S>) -> SoundPcmIOResponse {
 
     match req {
 
@@ -448,7 +957,7 @@ fn handle_sp_io_request(writer: &mut PlaybackWriter,
             ::SetParams(format) => match format.format { 
 
                 1 => match writer.set_params(format.bits_width as u8,
-                    format.sample_rate as u16,
+                    format.sample_rate as u32,
                     format.channels as u8) { 
                     Ok(_) => SoundPcmIOResponse
                         ::IsSet,
@@ -461,6 +970,52 @@ fn handle_sp_io_request(writer: &mut PlaybackWriter,
                         "unknown sp_io request"))
         },
 
+        SoundPcmIORequest
+            ::SetSampleFormat(format) => {
+                writer.use_format(format);
+                SoundPcmIOResponse::IsSet
+            },
+
+        SoundPcmIORequest
+            ::SetRecovery(policy) => {
+                writer.set_recovery(policy);
+                SoundPcmIOResponse::IsSet
+            },
+
+        SoundPcmIORequest
+            ::Pause => match writer.pause() {
+                Ok(_) => SoundPcmIOResponse::Paused,
+                Err(err) => SoundPcmIOResponse::Failed(err)
+            },
+
+        SoundPcmIORequest
+            ::Resume => match writer.resume() {
+                Ok(_) => SoundPcmIOResponse::Resumed,
+                Err(err) => SoundPcmIOResponse::Failed(err)
+            },
+
+        SoundPcmIORequest
+            ::Drain => match writer.drain() {
+                Ok(_) => SoundPcmIOResponse::Drained,
+                Err(err) => SoundPcmIOResponse::Failed(err)
+            },
+
+        SoundPcmIORequest
+            ::Drop => match writer.drop_queued() {
+                Ok(_) => SoundPcmIOResponse::Dropped,
+                Err(err) => SoundPcmIOResponse::Failed(err)
+            },
+
+        SoundPcmIORequest
+            ::Stats => SoundPcmIOResponse
+                ::Stats(writer.stats()),
+
+        SoundPcmIORequest
+            ::Position => match writer.position() {
+                Ok(position) => SoundPcmIOResponse::Position(position),
+                Err(err) => SoundPcmIOResponse::Failed(err)
+            },
+
         SoundPcmIORequest
             ::Write(mut buf) => match buf
                 .write(writer) {
@@ -474,19 +1029,241 @@ fn handle_sp_io_request(writer: &mut PlaybackWriter,
     } 
 }
 
-struct Worker {
+// the state of the worker around the requests: what has been written is
+// metered in the format it has been set up with, and the levels are
+// sent to whoever has subscribed to them.
+struct Metering {
+    format      : Option<SampleFormat>,
+    params      : (u16, u32),
+    meter       : Option<LevelMeter>,
+    subscribers : Vec<Sender<Levels>>
+}
+
+impl Metering {
+
+    fn new() -> Self {
+        Metering {
+            format: None,
+            params: (0, 0),
+            meter: None,
+            subscribers: Vec::new()
+        }
+    }
+
+    // a new meter for the params and the format, once both are known
+    fn restart(&mut self) {
+        let (channels, rate) = self.params;
+        self.meter = match self.format {
+            Some(format) if channels > 0 && rate > 0 =>
+                Some(LevelMeter::new(format, channels, rate)),
+            _ => None
+        };
+    }
+
+    fn publish(&mut self, bytes: &[u8]) {
+
+        let levels = match self.meter {
+            Some(ref mut meter) if !self.subscribers.is_empty() =>
+                meter.push(bytes),
+            _ => return
+        };
+
+        // a subscriber that has gone away is forgotten
+        for level in levels {
+            let subscribers = self.subscribers.split_off(0);
+            self.subscribers = subscribers
+                .into_iter()
+                .filter(|tx| tx.send(level.clone()).is_ok())
+                .collect();
+        }
+    }
+
+    fn handle<S: Sink>(&mut self, writer: &mut S,
+        req: SoundPcmIORequest<S>) -> SoundPcmIOResponse {
+
+        match req {
+
+            SoundPcmIORequest
+                ::SetParams(format) => {
+                    let params = (format.channels, format.sample_rate as u32);
+                    let res = handle_sp_io_request(writer,
+                        SoundPcmIORequest::SetParams(format));
+                    if let SoundPcmIOResponse::IsSet = res {
+                        self.params = params;
+                        self.restart();
+                    }
+                    res
+                },
+
+            SoundPcmIORequest
+                ::SetSampleFormat(format) => {
+                    self.format = Some(format);
+                    self.restart();
+                    handle_sp_io_request(writer,
+                        SoundPcmIORequest::SetSampleFormat(format))
+                },
+
+            SoundPcmIORequest
+                ::Subscribe(tx) => {
+                    self.subscribers.push(tx);
+                    SoundPcmIOResponse::Subscribed
+                },
+
+            SoundPcmIORequest
+                ::Write(mut buf) => match buf
+                    .write(writer) {
+                    Ok(n) => {
+                        self.publish(&unsafe { buf.load() }[..n]);
+                        SoundPcmIOResponse::Written(n)
+                    },
+                    Err(err) => SoundPcmIOResponse
+                        ::Failed(err)
+                },
+
+            SoundPcmIORequest
+                ::Drop => {
+                    if let Some(ref mut meter) = self.meter {
+                        meter.reset();
+                    }
+                    handle_sp_io_request(writer, SoundPcmIORequest::Drop)
+                },
+
+            req => handle_sp_io_request(writer, req)
+        }
+    }
+}
+
+pub type SoundPcmIOCallbackRet = ();
+
+// the sink is handed over to the worker thread on start, so any sink
+// (a sound card, a file, memory) can be driven through the same requests.
+pub struct SoundPcmIO<S: Sink + Send + 'static> {
+    sink   : Option<S>,
+    handle : Option<JoinHandle<SoundPcmIOCallbackRet>>,
+    tx     : Option<Sender<SoundPcmIORequest<S>>>,
+    rx     : Option<Receiver<SoundPcmIOResponse>>,
+    timer  : Timer
 }
-/*
-struct Worker {
-    tx    : Option<Sender<FileIOResponse>>,
-    rx    : Option<Receiver<FileIORequest>>,
+
+impl<S: Sink + Send + 'static> SoundPcmIO<S> {
+
+    pub fn new(sink: S,
+           secs: u64,
+           nanos: u32) -> Self {
+
+        SoundPcmIO {
+            sink: Some(sink),
+            handle: None,
+            tx: None,
+            rx: None,
+            timer: Timer::new(secs, nanos)
+        }
+    }
+}
+
+impl<S: Sink + Send + 'static> IO for SoundPcmIO<S> {
+
+    type T = S;
+    type R = SoundPcmIOCallbackRet;
+    type Req = SoundPcmIORequest<S>;
+    type Res = SoundPcmIOResponse;
+
+    fn start(&mut self) -> IOResult<()> {
+
+        let sink = match self.sink.take() {
+            Some(sink) => sink,
+            _ => return Err(IOError::new(IO_ERROR,
+                "sink has been already used."))
+        };
+
+        let ((req_tx, req_rx), (res_tx, res_rx)) = 
+            (channel::<SoundPcmIORequest<S>>(),
+             channel::<SoundPcmIOResponse>());
+
+        self.timer.start();
+        let timer = self.timer();
+
+        let handle = spawn(move || {
+
+            let mut worker = Worker::new(res_tx, req_rx, timer);
+            let mut metering = Metering::new();
+            let handler = Box::new(move |writer: &mut S,
+                req: SoundPcmIORequest<S>| metering.handle(writer, req));
+
+            worker
+                .run(handler, sink)
+                .unwrap()
+        });
+
+        self.handle = Some(handle);
+        self.tx = Some(req_tx);
+        self.rx = Some(res_rx);
+        Ok(())
+    }
+
+    fn send(&self, req: SoundPcmIORequest<S>) 
+     -> Result<(), SendError<SoundPcmIORequest<S>>> {
+        match self.tx {
+            Some(ref tx) => tx.send(req),
+            _ => panic!("no sender")
+        }
+    }
+
+    fn recv(&self) -> Result<SoundPcmIOResponse, RecvError> {
+        match self.rx {
+            Some(ref rx) => rx.recv(),
+            _ => panic!("no receiver")
+        }
+    }
+
+    fn timer(&self) -> Timer {
+        self.timer
+    }
+
+    fn stop(&mut self) -> IOResult<()> {
+
+        match self.send(SoundPcmIORequest::Close) {
+
+            Ok(_) => match self.recv() {
+                Ok(SoundPcmIOResponse::Closed) => {
+                    self.handle.take();
+                    Ok(())
+                },
+                Ok(_) => panic!("unexpected response type"),
+                Err(e) => io_error(e)
+            },
+
+            Err(e) => io_error(e)
+        }
+    }
+
+    fn join(&mut self) -> std::thread::Result<()> {
+        match self.handle.take() {
+            Some(handle) => handle
+                .join(),
+            _ => panic!("no thread handle")
+        }
+    }
+
+    fn sender(&self) -> Option<&Sender<SoundPcmIORequest<S>>> {
+        inner_ref!(self, tx)
+    }
+
+    fn receiver(&self) -> Option<&Receiver<SoundPcmIOResponse>> {
+        inner_ref!(self, rx)
+    }
+}
+
+struct Worker<S: Sink> {
+    tx    : Option<Sender<SoundPcmIOResponse>>,
+    rx    : Option<Receiver<SoundPcmIORequest<S>>>,
     timer : Timer
 }
 
-impl Worker {
+impl<S: Sink> Worker<S> {
 
-    fn new(tx: Sender<FileIOResponse>, 
-           rx: Receiver<FileIORequest>,
+    fn new(tx: Sender<SoundPcmIOResponse>, 
+           rx: Receiver<SoundPcmIORequest<S>>,
            timer: Timer) -> Self {
         
         if !timer.is_started() {
@@ -500,11 +1277,11 @@ impl Worker {
         }
     }
 
-    fn sender(&mut self) -> Option<Sender<FileIOResponse>> {
+    fn sender(&mut self) -> Option<Sender<SoundPcmIOResponse>> {
         self.tx.take()
     }
 
-    fn receiver(&mut self) -> Option<Receiver<FileIORequest>> {
+    fn receiver(&mut self) -> Option<Receiver<SoundPcmIORequest<S>>> {
         self.rx.take()
     }
 
@@ -512,7 +1289,83 @@ impl Worker {
         self.timer
     }
 }
-*/
+
+const SEND_ERROR : &'static str = "send error";
+const DISCONNECTED : &'static str = "disconnected";
+const INTERVAL : u64 = 10;
+
+impl<S: Sink + 'static> Loop<SoundPcmIOResponse, 
+                             SoundPcmIORequest<S>, 
+                             SoundPcmIOCallbackRet> for Worker<S> {
+
+    type In = S;
+    type Callback = for<'a> FnMut<(&'a mut S, SoundPcmIORequest<S>),
+                                  Output=SoundPcmIOResponse> + Send;
+    type Args = (&'static mut S, SoundPcmIORequest<S>);
+    type Out = SoundPcmIOCallbackRet;
+
+    // the worker gives up once it has been idle for as long as its
+    // timer runs, except while the sink is paused.
+    fn run(&mut self, mut callback: Box<Self::Callback>, mut input: S)
+     -> IOResult<()> {
+
+        let mut timer = self.timer();
+        let (mut paused, mut closed) = (false, false);
+
+        let (tx, rx) = match (self.sender(), self.receiver()) {
+            (Some(tx), Some(rx)) => (tx, rx),
+            _ => return Err(IOError::new(IO_ERROR,
+                "worker has been already used."))
+        };
+
+        let interval = Duration::from_millis(INTERVAL);
+
+        while !closed && (paused || !timer.is_timeout()) {
+
+            match rx.try_recv() {
+
+                Ok(SoundPcmIORequest::Close) => closed = true,
+
+                Ok(req) => {
+
+                    let res = callback(&mut input, req);
+
+                    paused = match res {
+                        SoundPcmIOResponse::Paused => true,
+                        SoundPcmIOResponse::Resumed |
+                        SoundPcmIOResponse::Drained => false,
+                        _ => paused
+                    };
+                    timer.restart();
+
+                    match tx.send(res) {
+                        Ok(_) => (),
+                        _ => panic!(SEND_ERROR)
+                    }
+                },
+
+                // a device has to be fed as soon as data comes in,
+                // so the worker only sleeps while it is idle.
+                Err( TryRecvError::Empty ) => sleep(interval),
+
+                _ => panic!(DISCONNECTED)
+            }
+        }
+
+        match closed {
+
+            false => {
+                tx.send(SoundPcmIOResponse::Timeout).unwrap();
+                Err(IOError::new(IO_ERROR, "timeout"))
+            },
+
+            _ => {
+                tx.send(SoundPcmIOResponse::Closed).unwrap();
+                Ok(())
+            }
+        }
+    }
+}
 
 #[cfg(test)]
 #[allow(unused_imports)]
@@ -524,6 +1377,7 @@ mod tests {
     use std::fs::File;
     use std::mem::size_of;
     use std::str::from_utf8;
+    use std::time::Duration;
 
     use self::futures::Future; 
 
@@ -531,12 +1385,27 @@ mod tests {
     use super::{ handle_sp_io_request, FromBuffer, 
                  SoundPcmIORequest, SoundPcmIOResponse } ;
 
+    use fixtures::{ TempDir, sample_file };
     use io::*;
+    use sample::SampleFormat;
+    use sink::*;
 
     const SOUNDCARD : &'static str = "plughw:0,0";
-    const WAVE_FILE_SAMPLE : &'static str = "/usr/share/sounds/k3b_success1.wav";
     const WBUF_ALIGNMENT : usize = 1;
 
+    const SAMPLE_DATA : [u8; 8] = [0, 1, 2, 3, 4, 5, 6, 7];
+
+    fn pcm_16bit_stereo() -> Format {
+        Format {
+            format: 1,
+            channels: 2,
+            sample_rate: 8000,
+            byte_per_sec: 32000,
+            block_align: 4,
+            bits_width: 16
+        }
+    }
+
     fn read_file_and_convert<T: FromBuffer + Default>(f: &mut File) 
      -> Result<T, &'static str> {
 
@@ -563,13 +1432,16 @@ mod tests {
       
         const FREAD_ERROR : &'static str = "failed to read file"; 
 
+        let dir = TempDir::new("sp_io_playback_stream_test");
+        let path = sample_file(&dir);
+
         let (tx, rx) = futures
             ::oneshot();
 
         let handle = thread::spawn(move || {
             tx.complete(
                 (
-                    File::open(WAVE_FILE_SAMPLE)
+                    File::open(path)
                         .unwrap(),
                     NonBlockingSoundPcmPlaybackWriter
                         ::create(SOUNDCARD)
@@ -612,7 +1484,7 @@ mod tests {
                     ::<Format>(&mut file) {
                     Ok(format) => {
                         pcm.set_params(format.bits_width as u8,
-                            format.sample_rate as u16,
+                            format.sample_rate as u32,
                             format.channels as u8)
                             .unwrap();
                         (file, pcm)
@@ -668,13 +1540,17 @@ mod tests {
     #[allow(unused_mut)]
     #[cfg(feature = "optional")]
     fn handle_sp_io_request_test() {
+
+        let dir = TempDir::new("sp_io_handle_sp_io_request_test");
+        let path = sample_file(&dir);
+
         let (tx, rx) = futures
             ::oneshot();
 
         let handle = thread::spawn(move || {
             tx.complete(
                 (
-                    File::open(WAVE_FILE_SAMPLE).unwrap(),
+                    File::open(path).unwrap(),
                     NonBlockingSoundPcmPlaybackWriter
                         ::create(SOUNDCARD).unwrap()
                 )
@@ -747,4 +1623,278 @@ mod tests {
             .join()
             .unwrap();
     }
+
+    #[test]
+    fn handle_sp_io_request_with_memory_sink_test() {
+
+        let mut sink = MemorySink::memory();
+        let (output, log) = (sink.output().clone(), sink.log());
+
+        match handle_sp_io_request(&mut sink,
+            SoundPcmIORequest::SetParams(pcm_16bit_stereo())) {
+            SoundPcmIOResponse::IsSet => (),
+            _ => panic!("failed to handle set_params request properly")
+        }
+
+        let wbuf = WriteBuffer::<MemorySink>::new(&SAMPLE_DATA,
+            WBUF_ALIGNMENT);
+
+        match handle_sp_io_request(&mut sink,
+            SoundPcmIORequest::Write(wbuf)) {
+            SoundPcmIOResponse::Written(size) => 
+                assert_eq!(SAMPLE_DATA.len(), size),
+            _ => panic!("failed to handle write request properly")
+        }
+
+        assert_eq!(SAMPLE_DATA.to_vec(), output.bytes());
+        assert_eq!(Some(SinkParams { bits: 16, rate: 8000, channels: 2,
+                                     format: SampleFormat::S16_LE }),
+            log.params());
+    }
+
+    #[test]
+    fn sound_pcm_io_test() {
+
+        let sink = MemorySink::memory();
+        let (output, log) = (sink.output().clone(), sink.log());
+
+        let mut sp_io = SoundPcmIO::new(sink, 10, 0);
+
+        sp_io
+            .start()
+            .unwrap();
+
+        sp_io.send(SoundPcmIORequest::SetParams(pcm_16bit_stereo()))
+            .unwrap();
+
+        match sp_io.recv() {
+            Ok(SoundPcmIOResponse::IsSet) => (),
+            _ => panic!("is set response is expected")
+        }
+
+        for _ in 0..2 {
+
+            let wbuf = WriteBuffer::<MemorySink>::new(&SAMPLE_DATA,
+                WBUF_ALIGNMENT);
+
+            sp_io.send(SoundPcmIORequest::Write(wbuf))
+                .unwrap();
+
+            match sp_io.recv() {
+                Ok(SoundPcmIOResponse::Written(size)) => 
+                    assert_eq!(SAMPLE_DATA.len(), size),
+                _ => panic!("written response is expected")
+            }
+        }
+
+        sp_io.send(SoundPcmIORequest::Stats)
+            .unwrap();
+
+        match sp_io.recv() {
+            Ok(SoundPcmIOResponse::Stats(stats)) => 
+                assert_eq!(XrunStats::default(), stats),
+            _ => panic!("stats response is expected")
+        }
+
+        sp_io
+            .stop()
+            .unwrap();
+
+        let mut expected = SAMPLE_DATA.to_vec();
+        expected.extend_from_slice(&SAMPLE_DATA);
+
+        assert_eq!(expected, output.bytes());
+        assert_eq!(2 * SAMPLE_DATA.len(), log.written());
+    }
+
+    #[test]
+    fn idle_timeout_test() {
+
+        let mut sp_io = SoundPcmIO::new(MemorySink::memory(), 0, 200_000_000);
+
+        sp_io
+            .start()
+            .unwrap();
+
+        let requests = vec![
+            SoundPcmIORequest::SetParams(pcm_16bit_stereo()),
+            SoundPcmIORequest::Pause,
+            SoundPcmIORequest::Resume
+        ];
+
+        // the timeout runs from the last request, and not at all while
+        // the sink is paused
+        for req in requests {
+            sp_io.send(req).unwrap();
+            match sp_io.recv() {
+                Ok(SoundPcmIOResponse::Paused) =>
+                    thread::sleep(Duration::from_millis(400)),
+                Ok(SoundPcmIOResponse::Timeout) => panic!("timed out"),
+                Ok(_) => (),
+                _ => panic!("disconnected")
+            }
+        }
+
+        match sp_io.recv() {
+            Ok(SoundPcmIOResponse::Timeout) => (),
+            _ => panic!("timeout response is expected")
+        }
+    }
+
+    #[test]
+    fn underrun_stats_test() {
+
+        let sink = MemorySink::memory()
+            .realtime();
+
+        let mut sp_io = SoundPcmIO::new(sink, 10, 0);
+
+        sp_io
+            .start()
+            .unwrap();
+
+        let requests = vec![
+            SoundPcmIORequest::SetParams(pcm_16bit_stereo()),
+            SoundPcmIORequest::SetRecovery(RecoveryPolicy::Fail)
+        ];
+
+        for req in requests {
+            sp_io.send(req).unwrap();
+            match sp_io.recv() {
+                Ok(SoundPcmIOResponse::IsSet) => (),
+                _ => panic!("is set response is expected")
+            }
+        }
+
+        for _ in 0..2 {
+            let wbuf = WriteBuffer::<MemorySink>::new(&SAMPLE_DATA,
+                WBUF_ALIGNMENT);
+            sp_io.send(SoundPcmIORequest::Write(wbuf))
+                .unwrap();
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        match sp_io.recv() {
+            Ok(SoundPcmIOResponse::Written(_)) => (),
+            _ => panic!("written response is expected")
+        }
+
+        match sp_io.recv() {
+            Ok(SoundPcmIOResponse::Failed(_)) => (),
+            _ => panic!("the underrun should have failed the write")
+        }
+
+        sp_io.send(SoundPcmIORequest::Stats)
+            .unwrap();
+
+        match sp_io.recv() {
+            Ok(SoundPcmIOResponse::Stats(stats)) => {
+                assert_eq!(1, stats.xruns);
+                assert_eq!(0, stats.recoveries);
+            },
+            _ => panic!("stats response is expected")
+        }
+
+        sp_io
+            .stop()
+            .unwrap();
+    }
+
+    #[test]
+    fn stream_control_test() {
+
+        let sink = MemorySink::memory();
+        let log = sink.log();
+
+        let mut sp_io = SoundPcmIO::new(sink, 10, 0);
+
+        sp_io
+            .start()
+            .unwrap();
+
+        sp_io.send(SoundPcmIORequest::SetParams(pcm_16bit_stereo()))
+            .unwrap();
+        sp_io.recv().unwrap();
+
+        let requests = vec![
+            SoundPcmIORequest::Pause,
+            SoundPcmIORequest::Resume,
+            SoundPcmIORequest::Drop,
+            SoundPcmIORequest::Drain
+        ];
+
+        for req in requests {
+
+            sp_io.send(req)
+                .unwrap();
+
+            match sp_io.recv() {
+                Ok(SoundPcmIOResponse::Paused) |
+                Ok(SoundPcmIOResponse::Resumed) |
+                Ok(SoundPcmIOResponse::Dropped) |
+                Ok(SoundPcmIOResponse::Drained) => (),
+                _ => panic!("unexpected response type")
+            }
+        }
+
+        sp_io
+            .stop()
+            .unwrap();
+
+        let events = log.events();
+        assert_eq!(5, events.len());
+
+        match (&events[1], &events[2], &events[3], &events[4]) {
+            (&SinkEvent::Pause { .. }, &SinkEvent::Resume { .. },
+             &SinkEvent::Drop { .. }, &SinkEvent::Drain { .. }) => (),
+            _ => panic!("events are not in order")
+        }
+    }
+
+    #[test]
+    fn position_test() {
+
+        let sink = MemorySink::memory()
+            .realtime();
+
+        let mut sp_io = SoundPcmIO::new(sink, 10, 0);
+
+        sp_io
+            .start()
+            .unwrap();
+
+        sp_io.send(SoundPcmIORequest::SetParams(pcm_16bit_stereo()))
+            .unwrap();
+        sp_io.recv().unwrap();
+
+        // a second of 16 bit stereo at 8000 Hz
+        let data = vec![0u8; 32000];
+        let wbuf = WriteBuffer::<MemorySink>::new(&data, WBUF_ALIGNMENT);
+
+        sp_io.send(SoundPcmIORequest::Write(wbuf))
+            .unwrap();
+        sp_io.recv().unwrap();
+
+        sp_io.send(SoundPcmIORequest::Position)
+            .unwrap();
+
+        match sp_io.recv() {
+            Ok(SoundPcmIOResponse::Position(position)) => {
+                assert_eq!(8000, position.written);
+                assert_eq!(8000, position.rate);
+                assert!(position.delay > 0);
+                assert!(position.elapsed() < Duration::from_millis(500));
+                assert!(position.timestamp.is_some());
+            },
+            _ => panic!("position response is expected")
+        }
+
+        sp_io.send(SoundPcmIORequest::Drop)
+            .unwrap();
+        sp_io.recv().unwrap();
+
+        sp_io
+            .stop()
+            .unwrap();
+    }
 }
@@ -0,0 +1,769 @@
+use std::any::Any;
+use std::f64;
+use std::f64::consts::PI;
+use std::io::Write;
+use std::sync::mpsc::Sender;
+
+use chain::Processor;
+use io::*;
+use player::FRAMES_PER_WRITE;
+use wav::WaveReader;
+
+pub const DEFAULT_SIZE    : usize = 2048;
+pub const DEFAULT_OVERLAP : f64 = 0.5;
+
+// the smallest transform, and the level bins are held above
+pub const MIN_SIZE : usize = 16;
+pub const MIN_DB   : f64 = -200.0;
+
+// the range of levels a spectrogram is coloured over
+pub const IMAGE_FLOOR_DB : f64 = -120.0;
+
+// the range of a rendered live spectrum
+const RENDER_FLOOR_DB : f64 = -90.0;
+const RENDER_LOWEST   : f64 = 20.0;
+const RENDER_RAMP     : &'static [u8] = b" .:-=+*#%@";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Window::Hann
+    }
+}
+
+impl Window {
+
+    pub fn from_name(name: &str) -> Option<Window> {
+        match name {
+            "rectangular" | "rect" | "none" => Some(Window::Rectangular),
+            "hann" | "hanning" => Some(Window::Hann),
+            "hamming" => Some(Window::Hamming),
+            "blackman" => Some(Window::Blackman),
+            _ => None
+        }
+    }
+
+    // the periodic form, which sums to a constant when overlapped
+    pub fn coefficients(self, size: usize) -> Vec<f64> {
+        (0..size)
+            .map(|n| {
+                let phase = 2.0 * PI * n as f64 / size as f64;
+                match self {
+                    Window::Rectangular => 1.0,
+                    Window::Hann => 0.5 - 0.5 * phase.cos(),
+                    Window::Hamming => 0.54 - 0.46 * phase.cos(),
+                    Window::Blackman => 0.42 - 0.5 * phase.cos() +
+                        0.08 * (2.0 * phase).cos()
+                }
+            })
+            .collect()
+    }
+}
+
+// an in-place radix-2 transform. the length has to be a power of two.
+pub fn fft(re: &mut [f64], im: &mut [f64]) {
+
+    let size = re.len();
+    assert!(size.is_power_of_two() && im.len() == size);
+
+    // bit reversed order
+    let mut j = 0;
+    for i in 1..size {
+        let mut bit = size >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= size {
+
+        let angle = -2.0 * PI / len as f64;
+        let (step_re, step_im) = (angle.cos(), angle.sin());
+
+        for start in (0..size).filter(|n| n % len == 0) {
+
+            let (mut w_re, mut w_im) = (1.0, 0.0);
+
+            for k in 0..len / 2 {
+
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+
+                let next = w_re * step_re - w_im * step_im;
+                w_im = w_re * step_im + w_im * step_re;
+                w_re = next;
+            }
+        }
+
+        len <<= 1;
+    }
+}
+
+fn to_db(magnitude: f64) -> f64 {
+    match magnitude > 0.0 {
+        true => (20.0 * magnitude.log10()).max(MIN_DB),
+        _ => MIN_DB
+    }
+}
+
+// a short-time transform over frames as they come. the channels are
+// mixed down first. a column is given every hop, the level of each bin
+// in dB, where a full scale sine reads 0 dB.
+pub struct Stft {
+    size     : usize,
+    hop      : usize,
+    kind     : Window,
+    window   : Vec<f64>,
+    gain     : f64,
+    channels : usize,
+    pending  : Vec<f64>
+}
+
+impl Stft {
+
+    pub fn new(size: usize, channels: usize) -> Self {
+
+        assert!(size.is_power_of_two() && size >= MIN_SIZE);
+
+        Stft {
+            size: size,
+            hop: size / 2,
+            kind: Window::default(),
+            window: Vec::new(),
+            gain: 0.0,
+            channels: channels.max(1),
+            pending: Vec::new()
+        }
+        .window(Window::default())
+        .overlap(DEFAULT_OVERLAP)
+    }
+
+    pub fn window(mut self, kind: Window) -> Self {
+        self.kind = kind;
+        self.window = kind.coefficients(self.size);
+        self.gain = 2.0 / self.window
+            .iter()
+            .fold(0.0, |sum, w| sum + w);
+        self
+    }
+
+    // the part of a column the next one starts over, in [0, 1)
+    pub fn overlap(mut self, overlap: f64) -> Self {
+        let overlap = overlap.max(0.0).min(1.0 - 1.0 / self.size as f64);
+        self.hop = ((self.size as f64 * (1.0 - overlap)).round() as usize)
+            .max(1);
+        self
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn hop(&self) -> usize {
+        self.hop
+    }
+
+    pub fn bins(&self) -> usize {
+        self.size / 2 + 1
+    }
+
+    pub fn kind(&self) -> Window {
+        self.kind
+    }
+
+    // the same transform over another number of channels
+    pub fn with_channels(&self, channels: usize) -> Stft {
+        Stft::new(self.size, channels)
+            .window(self.kind)
+            .overlap(1.0 - self.hop as f64 / self.size as f64)
+    }
+
+    pub fn reset(&mut self) {
+        self.pending.clear();
+    }
+
+    // interleaved samples, whole frames of them
+    pub fn push(&mut self, samples: &[f64]) -> Vec<Vec<f64>> {
+
+        let channels = self.channels;
+        self.pending.extend(samples
+            .chunks(channels)
+            .map(|frame| frame.iter().fold(0.0, |sum, s| sum + s) /
+                channels as f64));
+
+        let mut columns = Vec::new();
+        let mut start = 0;
+
+        while start + self.size <= self.pending.len() {
+            columns.push(self.column(start));
+            start += self.hop;
+        }
+
+        self.pending = self.pending.split_off(start.min(self.pending.len()));
+        columns
+    }
+
+    fn column(&self, start: usize) -> Vec<f64> {
+
+        let mut re = self.pending[start..start + self.size]
+            .iter()
+            .zip(self.window.iter())
+            .map(|(s, w)| s * w)
+            .collect::<Vec<_>>();
+        let mut im = vec![0.0; self.size];
+
+        fft(&mut re, &mut im);
+
+        (0..self.bins())
+            .map(|bin| {
+                // the ends have no mirror image to fold in
+                let gain = match bin == 0 || bin == self.size / 2 {
+                    true => self.gain / 2.0,
+                    _ => self.gain
+                };
+                to_db((re[bin] * re[bin] + im[bin] * im[bin]).sqrt() * gain)
+            })
+            .collect()
+    }
+}
+
+// the columns of a file, oldest first
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spectrogram {
+    pub rate    : u32,
+    pub size    : usize,
+    pub hop     : usize,
+    pub columns : Vec<Vec<f64>>
+}
+
+impl Spectrogram {
+
+    pub fn frequency(&self, bin: usize) -> f64 {
+        bin as f64 * self.rate as f64 / self.size as f64
+    }
+
+    pub fn time(&self, column: usize) -> f64 {
+        (column * self.hop) as f64 / self.rate as f64
+    }
+
+    // the power of every bin averaged over the columns, in dB
+    pub fn average(&self) -> Vec<f64> {
+
+        let bins = self.size / 2 + 1;
+        let count = self.columns.len().max(1) as f64;
+
+        (0..bins)
+            .map(|bin| {
+                let power = self.columns
+                    .iter()
+                    .fold(0.0, |sum, column| sum +
+                        10.0f64.powf(column[bin] / 10.0));
+                match power > 0.0 {
+                    true => (10.0 * (power / count).log10()).max(MIN_DB),
+                    _ => MIN_DB
+                }
+            })
+            .collect()
+    }
+}
+
+// decodes the file at `path` through and transforms it with `stft`
+// set up for its channels
+pub fn spectrogram(path: &str, stft: &Stft) -> IOResult<Spectrogram> {
+
+    let mut reader = try!(WaveReader::open(path));
+    let (channels, rate) = (reader.format().channels as usize,
+        reader.format().sample_rate);
+
+    let mut stft = stft.with_channels(channels);
+    let mut columns = Vec::new();
+
+    loop {
+        let samples = try!(reader.read_frames(FRAMES_PER_WRITE));
+        if samples.is_empty() {
+            break;
+        }
+        columns.extend(stft.push(&samples));
+    }
+
+    Ok(Spectrogram {
+        rate: rate,
+        size: stft.size(),
+        hop: stft.hop(),
+        columns: columns
+    })
+}
+
+// a line of the frequency and level of each bin
+pub fn write_average_csv<W: Write>(output: &mut W, spectrogram: &Spectrogram)
+ -> IOResult<()> {
+
+    try!(writeln!(output, "frequency,db"));
+
+    for (bin, db) in spectrogram.average().iter().enumerate() {
+        try!(writeln!(output, "{},{:.2}", spectrogram.frequency(bin), db));
+    }
+
+    Ok(())
+}
+
+// a line for each column, its time and then the level of each bin,
+// under a line of the frequencies
+pub fn write_csv<W: Write>(output: &mut W, spectrogram: &Spectrogram)
+ -> IOResult<()> {
+
+    let bins = spectrogram.size / 2 + 1;
+
+    let header = (0..bins)
+        .map(|bin| format!("{}", spectrogram.frequency(bin)))
+        .collect::<Vec<_>>()
+        .join(",");
+    try!(writeln!(output, "time,{}", header));
+
+    for (index, column) in spectrogram.columns.iter().enumerate() {
+        let levels = column
+            .iter()
+            .map(|db| format!("{:.2}", db))
+            .collect::<Vec<_>>()
+            .join(",");
+        try!(writeln!(output, "{:.6},{}", spectrogram.time(index), levels));
+    }
+
+    Ok(())
+}
+
+// black through blue, purple, orange and yellow to white
+const COLOURS : [[f64; 3]; 6] = [
+    [0.0, 0.0, 0.0],
+    [0.0, 0.0, 128.0],
+    [128.0, 0.0, 128.0],
+    [255.0, 64.0, 0.0],
+    [255.0, 255.0, 0.0],
+    [255.0, 255.0, 255.0]
+];
+
+fn colour(db: f64) -> [u8; 3] {
+
+    let t = ((db - IMAGE_FLOOR_DB) / -IMAGE_FLOOR_DB).max(0.0).min(1.0) *
+        (COLOURS.len() - 1) as f64;
+    let index = (t.floor() as usize).min(COLOURS.len() - 2);
+    let fraction = t - index as f64;
+
+    let (from, to) = (COLOURS[index], COLOURS[index + 1]);
+    [(from[0] + (to[0] - from[0]) * fraction).round() as u8,
+     (from[1] + (to[1] - from[1]) * fraction).round() as u8,
+     (from[2] + (to[2] - from[2]) * fraction).round() as u8]
+}
+
+// rows of rgb pixels, time going right and frequency going up. a file
+// shorter than the fft has no columns to draw.
+fn pixels(spectrogram: &Spectrogram) -> IOResult<(usize, usize, Vec<u8>)> {
+
+    let (width, height) = (spectrogram.columns.len(), spectrogram.size / 2 + 1);
+    if width == 0 {
+        return Err(IOError::new(IOErrorKind::InvalidInput,
+            "the spectrogram has no columns"));
+    }
+
+    let mut rgb = Vec::with_capacity(width * height * 3);
+
+    for row in 0..height {
+        let bin = height - 1 - row;
+        for column in spectrogram.columns.iter() {
+            rgb.extend_from_slice(&colour(column[bin]));
+        }
+    }
+
+    Ok((width, height, rgb))
+}
+
+pub fn write_ppm<W: Write>(output: &mut W, spectrogram: &Spectrogram)
+ -> IOResult<()> {
+    let (width, height, rgb) = try!(pixels(spectrogram));
+    try!(write!(output, "P6\n{} {}\n255\n", width, height));
+    output.write_all(&rgb)
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| match crc & 1 {
+            1 => (crc >> 1) ^ 0xedb88320,
+            _ => crc >> 1
+        })
+    })
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (a, b) = bytes.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
+
+fn be_u32(value: u32) -> [u8; 4] {
+    [(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]
+}
+
+fn png_chunk(png: &mut Vec<u8>, id: &[u8], body: &[u8]) {
+    let mut data = id.to_vec();
+    data.extend_from_slice(body);
+    png.extend_from_slice(&be_u32(body.len() as u32));
+    png.extend_from_slice(&data);
+    png.extend_from_slice(&be_u32(crc32(&data)));
+}
+
+// a zlib stream of stored deflate blocks, which any decoder reads
+fn zlib_stored(bytes: &[u8]) -> Vec<u8> {
+
+    let mut zlib = vec![0x78, 0x01];
+    let blocks = bytes.chunks(0xffff).collect::<Vec<_>>();
+
+    if blocks.is_empty() {
+        zlib.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+
+    for (index, block) in blocks.iter().enumerate() {
+        let len = block.len() as u16;
+        zlib.push((index + 1 == blocks.len()) as u8);
+        zlib.extend_from_slice(&[len as u8, (len >> 8) as u8,
+            !len as u8, (!len >> 8) as u8]);
+        zlib.extend_from_slice(block);
+    }
+
+    zlib.extend_from_slice(&be_u32(adler32(bytes)));
+    zlib
+}
+
+// an 8 bit rgb png, left uncompressed
+pub fn write_png<W: Write>(output: &mut W, spectrogram: &Spectrogram)
+ -> IOResult<()> {
+
+    let (width, height, rgb) = try!(pixels(spectrogram));
+
+    // every row starts with its filter, none
+    let mut raw = Vec::with_capacity((width * 3 + 1) * height);
+    for row in rgb.chunks(width * 3) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&be_u32(width as u32));
+    header.extend_from_slice(&be_u32(height as u32));
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png_chunk(&mut png, b"IHDR", &header);
+    png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    png_chunk(&mut png, b"IEND", &[]);
+
+    output.write_all(&png)
+}
+
+// what a spectrogram can be written as, told by the extension of the
+// file it goes to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Export {
+    Csv,
+    Ppm,
+    Png
+}
+
+impl Export {
+
+    pub fn from_path(path: &str) -> Option<Export> {
+        match path.rsplit('.').next().map(|ext| ext.to_lowercase()) {
+            Some(ref ext) if ext == "csv" => Some(Export::Csv),
+            Some(ref ext) if ext == "ppm" => Some(Export::Ppm),
+            Some(ref ext) if ext == "png" => Some(Export::Png),
+            _ => None
+        }
+    }
+
+    pub fn write<W: Write>(self, output: &mut W, spectrogram: &Spectrogram)
+     -> IOResult<()> {
+        match self {
+            Export::Csv => write_csv(output, spectrogram),
+            Export::Ppm => write_ppm(output, spectrogram),
+            Export::Png => write_png(output, spectrogram)
+        }
+    }
+}
+
+// a column of what is being played, and the rate to tell its bins by
+#[derive(Clone, Debug, PartialEq)]
+pub struct Column {
+    pub rate : u32,
+    pub size : usize,
+    pub db   : Vec<f64>
+}
+
+// a processor that lets the samples through and sends their columns
+// to `tx` as they are transformed
+pub struct Tap {
+    tx   : Sender<Column>,
+    stft : Stft,
+    rate : u32
+}
+
+impl Tap {
+    pub fn new(tx: Sender<Column>, stft: Stft) -> Self {
+        Tap {
+            tx: tx,
+            stft: stft,
+            rate: 0
+        }
+    }
+}
+
+impl Processor for Tap {
+
+    fn configure(&mut self, channels: u16, rate: u32) -> (u16, u32) {
+        self.stft = self.stft.with_channels(channels as usize);
+        self.rate = rate;
+        (channels, rate)
+    }
+
+    fn process(&mut self, samples: Vec<f64>) -> Vec<f64> {
+
+        // nobody may be watching any more, which is fine
+        for db in self.stft.push(&samples) {
+            let _ = self.tx.send(Column {
+                rate: self.rate,
+                size: self.stft.size(),
+                db: db
+            });
+        }
+
+        samples
+    }
+
+    fn reset(&mut self) {
+        self.stft.reset();
+    }
+
+    fn as_any(&mut self) -> &mut Any {
+        self
+    }
+}
+
+// the column as a line `width` characters wide, of bands spaced evenly
+// in pitch from 20 Hz up, each drawn by its loudest bin
+pub fn render(column: &Column, width: usize) -> String {
+
+    let nyquist = column.rate as f64 / 2.0;
+    let ratio = (nyquist / RENDER_LOWEST).max(1.0);
+    let bin_of = |frequency: f64| (frequency * column.size as f64 /
+        column.rate as f64).round() as usize;
+
+    (0..width)
+        .map(|band| {
+
+            let low = RENDER_LOWEST * ratio.powf(band as f64 / width as f64);
+            let high = RENDER_LOWEST *
+                ratio.powf((band + 1) as f64 / width as f64);
+            let last = column.db.len().saturating_sub(1);
+            let (from, to) = (bin_of(low).min(last), bin_of(high).min(last));
+
+            let db = column.db[from..to + 1]
+                .iter()
+                .fold(MIN_DB, |max, &db| max.max(db));
+
+            let level = ((db - RENDER_FLOOR_DB) / -RENDER_FLOOR_DB)
+                .max(0.0)
+                .min(1.0);
+            RENDER_RAMP[(level * (RENDER_RAMP.len() - 1) as f64).round()
+                as usize] as char
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::f64::consts::PI;
+    use std::sync::mpsc::channel;
+
+    use super::*;
+    use chain::Processor;
+    use fixtures::{ TempDir, wave_of };
+    use sample::SampleFormat;
+
+    fn sine(frequency: f64, rate: f64, amplitude: f64, frames: usize)
+     -> Vec<f64> {
+        (0..frames)
+            .map(|n| amplitude * (2.0 * PI * frequency * n as f64 / rate).sin())
+            .collect()
+    }
+
+    #[test]
+    fn fft_test() {
+
+        // an impulse is flat, a cosine lands in its two bins
+        let (mut re, mut im) = (vec![0.0; 8], vec![0.0; 8]);
+        re[0] = 1.0;
+        fft(&mut re, &mut im);
+        assert!(re.iter().all(|&x| (x - 1.0).abs() < 1e-12));
+        assert!(im.iter().all(|&x| x.abs() < 1e-12));
+
+        let mut re = (0..16)
+            .map(|n| (2.0 * PI * 3.0 * n as f64 / 16.0).cos())
+            .collect::<Vec<_>>();
+        let mut im = vec![0.0; 16];
+        fft(&mut re, &mut im);
+
+        for bin in 0..16 {
+            let magnitude = (re[bin] * re[bin] + im[bin] * im[bin]).sqrt();
+            match bin {
+                3 | 13 => assert!((magnitude - 8.0).abs() < 1e-9),
+                _ => assert!(magnitude < 1e-9)
+            }
+        }
+    }
+
+    #[test]
+    fn stft_test() {
+
+        // 1 kHz sits on bin 64 of 512 at 8000 Hz
+        let mut stft = Stft::new(512, 1);
+        let samples = sine(1000.0, 8000.0, 0.5, 2048);
+
+        let mut columns = stft.push(&samples[..1000]);
+        columns.extend(stft.push(&samples[1000..]));
+
+        // a column every 256 frames once 512 have come
+        assert_eq!(7, columns.len());
+        assert_eq!(257, columns[0].len());
+        assert!((columns[3][64] + 6.02).abs() < 0.01);
+        assert!(columns[3][20] < -100.0);
+
+        // channels are mixed down
+        let mut stft = Stft::new(512, 2)
+            .window(Window::Blackman)
+            .overlap(0.75);
+        let stereo = samples
+            .iter()
+            .flat_map(|&s| vec![s, -s])
+            .collect::<Vec<_>>();
+        assert_eq!(128, stft.hop());
+        assert!(stft.push(&stereo).iter().all(|column| column[64] == MIN_DB));
+    }
+
+    #[test]
+    fn spectrogram_test() {
+
+        let dir = TempDir::new("spectrum_spectrogram_test");
+
+        // 16 bit mono at 8000 Hz, 1024 frames of 2 kHz at full scale
+        let samples = sine(2000.0, 8000.0, 1.0, 1024);
+        let path = dir.write("spectrum.wav", &wave_of(SampleFormat::S16_LE, 1,
+            8000, &samples));
+
+        let spectrogram = spectrogram(&path, &Stft::new(256, 1)).unwrap();
+
+        assert_eq!((8000, 256, 128, 7), (spectrogram.rate, spectrogram.size,
+            spectrogram.hop, spectrogram.columns.len()));
+        assert_eq!(2000.0, spectrogram.frequency(64));
+        assert_eq!(0.032, spectrogram.time(2));
+
+        let average = spectrogram.average();
+        assert!(average[64].abs() < 0.01);
+
+        let mut csv = Vec::new();
+        write_average_csv(&mut csv, &spectrogram).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.lines().nth(65).unwrap().starts_with("2000,"));
+
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &spectrogram).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(8, csv.lines().count());
+        assert!(csv.starts_with("time,0,31.25,62.5,"));
+        assert!(csv.lines().nth(3).unwrap().starts_with("0.032000,"));
+    }
+
+    fn tiny() -> Spectrogram {
+        Spectrogram {
+            rate: 64,
+            size: 16,
+            hop: 8,
+            columns: vec![vec![0.0; 9], vec![IMAGE_FLOOR_DB; 9]]
+        }
+    }
+
+    #[test]
+    fn image_test() {
+
+        let mut ppm = Vec::new();
+        write_ppm(&mut ppm, &tiny()).unwrap();
+        assert!(ppm.starts_with(b"P6\n2 9\n255\n"));
+        assert_eq!(11 + 2 * 9 * 3, ppm.len());
+        assert_eq!(&[255, 255, 255, 0, 0, 0], &ppm[11..17]);
+
+        let mut png = Vec::new();
+        write_png(&mut png, &tiny()).unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR\
+            \x00\x00\x00\x02\x00\x00\x00\x09\x08\x02\x00\x00\x00"));
+        // the crc every empty IEND chunk has
+        assert!(png.ends_with(b"IEND\xae\x42\x60\x82"));
+
+        assert_eq!(Some(Export::Png), Export::from_path("out/a.b.PNG"));
+        assert_eq!(Some(Export::Csv), Export::from_path("a.csv"));
+        assert_eq!(None, Export::from_path("a.jpg"));
+
+        // nothing to draw of a file shorter than the fft
+        let dir = TempDir::new("spectrum_image_test");
+        let path = dir.write("short.wav", &wave_of(SampleFormat::S16_LE, 1,
+            8000, &[0.5; 100]));
+        let short = spectrogram(&path, &Stft::new(256, 1)).unwrap();
+        assert!(short.columns.is_empty());
+        assert_eq!(IOErrorKind::InvalidInput,
+            write_png(&mut Vec::new(), &short).unwrap_err().kind());
+        assert_eq!(IOErrorKind::InvalidInput,
+            write_ppm(&mut Vec::new(), &short).unwrap_err().kind());
+
+        assert_eq!(0x11e60398, adler32(b"Wikipedia"));
+        assert_eq!(0xcbf43926, crc32(b"123456789"));
+    }
+
+    #[test]
+    fn tap_test() {
+
+        let (tx, rx) = channel();
+        let mut tap = Tap::new(tx, Stft::new(64, 1)
+            .window(Window::Rectangular));
+        assert_eq!((2, 8000), tap.configure(2, 8000));
+
+        let samples = vec![0.25; 256];
+        assert_eq!(samples, tap.process(samples.clone()));
+
+        let column = rx.recv().unwrap();
+        assert_eq!((8000, 64, 33), (column.rate, column.size,
+            column.db.len()));
+
+        // dc at -12 dB shows on the bands below 125 Hz, which are
+        // narrower than a bin
+        assert_eq!("%%%       ", render(&column, 10));
+    }
+}
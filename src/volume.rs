@@ -0,0 +1,261 @@
+use std::any::Any;
+
+use chain::Processor;
+use io::*;
+
+// below this a gain is taken as silence
+pub const MIN_DB : f64 = -120.0;
+
+// how long a change of volume and a mute take, so that the gain doesn't
+// jump from one sample to the next and click
+const RAMP_MILLIS : u64 = 10;
+const FADE_MILLIS : u64 = 20;
+
+pub fn db_to_linear(db: f64) -> f64 {
+    match db <= MIN_DB {
+        true => 0.0,
+        _ => 10.0f64.powf(db / 20.0)
+    }
+}
+
+pub fn linear_to_db(gain: f64) -> f64 {
+    match gain <= 0.0 {
+        true => MIN_DB,
+        _ => (20.0 * gain.log10()).max(MIN_DB)
+    }
+}
+
+// "0.5" is a linear gain, "-6db" or "-6 dB" a gain in decibels
+pub fn parse_gain(st: &str) -> Option<f64> {
+
+    let st = st.trim().to_lowercase();
+
+    let (number, db) = match st.ends_with("db") {
+        true => (st[..st.len() - 2].trim(), true),
+        _ => (st.as_str(), false)
+    };
+
+    match (number.parse::<f64>(), db) {
+        (Ok(db), true) if db.is_finite() => Some(db_to_linear(db)),
+        (Ok(gain), false) if gain.is_finite() && gain >= 0.0 => Some(gain),
+        _ => None
+    }
+}
+
+// a volume control of the device itself, used in place of scaling the
+// samples when there is one
+pub trait HardwareVolume: Send {
+    fn set_db(&mut self, db: f64) -> IOResult<()>;
+    fn set_mute(&mut self, muted: bool) -> IOResult<()>;
+}
+
+// a gain applied to interleaved samples. changes are ramped linearly
+// over a few milliseconds, a mute fades out and an unmute back in.
+pub struct Volume {
+    gain      : f64,
+    muted     : bool,
+    current   : f64,
+    step      : f64,
+    remaining : u64,
+    rate      : u32,
+    channels  : usize
+}
+
+impl Volume {
+
+    pub fn new() -> Self {
+        Volume {
+            gain: 1.0,
+            muted: false,
+            current: 1.0,
+            step: 0.0,
+            remaining: 0,
+            rate: 0,
+            channels: 1
+        }
+    }
+
+    // the rate the ramps are timed by. with no rate set, changes are
+    // made at once.
+    pub fn set_rate(&mut self, rate: u32) {
+        self.rate = rate;
+    }
+
+    pub fn linear(&self) -> f64 {
+        self.gain
+    }
+
+    pub fn db(&self) -> f64 {
+        linear_to_db(self.gain)
+    }
+
+    pub fn set_linear(&mut self, gain: f64) {
+        self.gain = gain.max(0.0);
+        self.ramp(RAMP_MILLIS);
+    }
+
+    pub fn set_db(&mut self, db: f64) {
+        self.set_linear(db_to_linear(db));
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn mute(&mut self) {
+        self.muted = true;
+        self.ramp(FADE_MILLIS);
+    }
+
+    pub fn unmute(&mut self) {
+        self.muted = false;
+        self.ramp(FADE_MILLIS);
+    }
+
+    // whether samples come out as they went in
+    pub fn is_unity(&self) -> bool {
+        self.remaining == 0 && self.current == 1.0
+    }
+
+    fn target(&self) -> f64 {
+        match self.muted {
+            true => 0.0,
+            _ => self.gain
+        }
+    }
+
+    fn ramp(&mut self, millis: u64) {
+
+        let frames = self.rate as u64 * millis / 1000;
+
+        match frames {
+            0 => {
+                self.current = self.target();
+                self.remaining = 0;
+            },
+            frames => {
+                self.step = (self.target() - self.current) / frames as f64;
+                self.remaining = frames;
+            }
+        }
+    }
+
+    pub fn process(&mut self, samples: &mut [f64], channels: usize) {
+
+        if self.is_unity() {
+            return;
+        }
+
+        for frame in samples.chunks_mut(channels.max(1)) {
+
+            if self.remaining > 0 {
+                self.remaining -= 1;
+                self.current = match self.remaining {
+                    0 => self.target(),
+                    _ => self.current + self.step
+                };
+            }
+
+            for sample in frame.iter_mut() {
+                *sample *= self.current;
+            }
+        }
+    }
+}
+
+impl Processor for Volume {
+
+    fn configure(&mut self, channels: u16, rate: u32) -> (u16, u32) {
+        self.channels = channels as usize;
+        self.set_rate(rate);
+        (channels, rate)
+    }
+
+    fn process(&mut self, mut samples: Vec<f64>) -> Vec<f64> {
+        let channels = self.channels;
+        Volume::process(self, &mut samples, channels);
+        samples
+    }
+
+    fn as_any(&mut self) -> &mut Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn gain_test() {
+
+        assert!((db_to_linear(-6.0) - 0.501).abs() < 0.001);
+        assert!((linear_to_db(0.5) + 6.02).abs() < 0.01);
+        assert_eq!(0.0, db_to_linear(MIN_DB));
+        assert_eq!(MIN_DB, linear_to_db(0.0));
+
+        assert_eq!(Some(0.5), parse_gain("0.5"));
+        assert_eq!(Some(1.0), parse_gain("0dB"));
+        assert!((parse_gain("-20 db").unwrap() - 0.1).abs() < 1e-12);
+        assert_eq!(None, parse_gain("-0.5"));
+        assert_eq!(None, parse_gain("loud"));
+    }
+
+    #[test]
+    fn ramp_test() {
+
+        let mut volume = Volume::new();
+        volume.set_rate(1000);
+        volume.set_linear(0.0);
+
+        // 10 frames of ramp at 1000 Hz, stereo
+        let mut samples = vec![1.0; 30];
+        volume.process(&mut samples, 2);
+
+        for n in 0..10 {
+            let expected = 1.0 - (n + 1) as f64 / 10.0;
+            assert!((samples[2 * n] - expected).abs() < 1e-12);
+            assert_eq!(samples[2 * n], samples[2 * n + 1]);
+        }
+        assert!(samples[20..].iter().all(|sample| *sample == 0.0));
+
+        // without a rate there is no ramp
+        let mut volume = Volume::new();
+        volume.set_db(-6.0);
+        let mut samples = vec![1.0; 4];
+        volume.process(&mut samples, 1);
+        assert!(samples.iter().all(|sample| (sample - 0.501).abs() < 0.001));
+    }
+
+    #[test]
+    fn mute_test() {
+
+        let mut volume = Volume::new();
+        volume.set_rate(1000);
+        volume.set_linear(0.5);
+        volume.process(&mut vec![0.0; 10], 1);
+
+        volume.mute();
+        let mut samples = vec![1.0; 40];
+        volume.process(&mut samples, 1);
+
+        // a 20 ms fade, then silence
+        assert!(samples[0] < 0.5 && samples[0] > 0.45);
+        assert_eq!(0.0, samples[19]);
+        assert!(samples.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert!(volume.is_muted());
+        assert_eq!(0.5, volume.linear());
+
+        // back to where it was
+        volume.unmute();
+        let mut samples = vec![1.0; 40];
+        volume.process(&mut samples, 1);
+        assert_eq!(0.5, samples[39]);
+        assert!(!volume.is_unity());
+
+        volume.set_linear(1.0);
+        volume.process(&mut vec![0.0; 10], 1);
+        assert!(volume.is_unity());
+    }
+}
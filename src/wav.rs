@@ -0,0 +1,856 @@
+use std::fs::File;
+use std::io::{ copy, Read, Seek, SeekFrom, Write };
+use std::time::Duration;
+
+use io::*;
+use sample::SampleFormat;
+
+pub const WAVE_FORMAT_PCM        : u16 = 0x0001;
+pub const WAVE_FORMAT_IEEE_FLOAT : u16 = 0x0003;
+pub const WAVE_FORMAT_IMA_ADPCM  : u16 = 0x0011;
+pub const WAVE_FORMAT_EXTENSIBLE : u16 = 0xfffe;
+
+pub type ChunkId = [u8; 4];
+
+pub const RIFF_ID : ChunkId = *b"RIFF";
+pub const WAVE_ID : ChunkId = *b"WAVE";
+pub const FMT_ID  : ChunkId = *b"fmt ";
+pub const DATA_ID : ChunkId = *b"data";
+pub const FACT_ID : ChunkId = *b"fact";
+
+const RIFF_HEADER_SIZE  : u64 = 12;
+const CHUNK_HEADER_SIZE : u64 = 8;
+const FMT_MIN_SIZE      : u32 = 16;
+
+fn le_u16(buf: &[u8]) -> u16 {
+    buf[0] as u16 | (buf[1] as u16) << 8
+}
+
+fn le_u32(buf: &[u8]) -> u32 {
+    le_u16(buf) as u32 | (le_u16(&buf[2..]) as u32) << 16
+}
+
+fn le_bytes(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
+
+fn invalid_data<T>(msg: &'static str) -> IOResult<T> {
+    Err(IOError::new(IOErrorKind::InvalidData, msg))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Pcm,
+    Float,
+    ImaAdpcm
+}
+
+// the contents of a fmt chunk. for WAVE_FORMAT_EXTENSIBLE, format_tag is
+// taken from the sub format and valid_bits/channel_mask are filled in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WaveFormat {
+    pub format_tag        : u16,
+    pub channels          : u16,
+    pub sample_rate       : u32,
+    pub byte_per_sec      : u32,
+    pub block_align       : u16,
+    pub bits_per_sample   : u16,
+    pub valid_bits        : u16,
+    pub channel_mask      : u32,
+    pub samples_per_block : u16
+}
+
+impl WaveFormat {
+
+    pub fn parse(buf: &[u8]) -> IOResult<WaveFormat> {
+
+        if buf.len() < FMT_MIN_SIZE as usize {
+            return invalid_data("fmt chunk is too short");
+        }
+
+        let mut format = WaveFormat {
+            format_tag: le_u16(&buf[0..]),
+            channels: le_u16(&buf[2..]),
+            sample_rate: le_u32(&buf[4..]),
+            byte_per_sec: le_u32(&buf[8..]),
+            block_align: le_u16(&buf[12..]),
+            bits_per_sample: le_u16(&buf[14..]),
+            valid_bits: le_u16(&buf[14..]),
+            channel_mask: 0,
+            samples_per_block: 0
+        };
+
+        // cbSize and the extension
+        let ext = match buf.len() >= 18 {
+            true => {
+                let size = le_u16(&buf[16..]) as usize;
+                &buf[18..buf.len().min(18 + size)]
+            },
+            _ => &buf[0..0]
+        };
+
+        match format.format_tag {
+            WAVE_FORMAT_EXTENSIBLE => {
+                if ext.len() < 22 {
+                    return invalid_data("extensible fmt chunk is too short");
+                }
+                format.valid_bits = le_u16(&ext[0..]);
+                format.channel_mask = le_u32(&ext[2..]);
+                // the first two bytes of the sub format guid hold the tag
+                format.format_tag = le_u16(&ext[6..]);
+            },
+            WAVE_FORMAT_IMA_ADPCM if ext.len() >= 2 => {
+                format.samples_per_block = le_u16(&ext[0..]);
+            },
+            _ => ()
+        }
+
+        if format.channels == 0 || format.sample_rate == 0 ||
+            format.block_align == 0 {
+            return invalid_data("fmt chunk has zero fields");
+        }
+
+        match format.format_tag {
+
+            WAVE_FORMAT_IMA_ADPCM => {
+                if format.samples_per_block == 0 {
+                    format.samples_per_block = ima_samples_per_block(
+                        format.block_align,
+                        format.channels);
+                }
+                // blocks too small for their headers hold no frames
+                if format.samples_per_block == 0 {
+                    return invalid_data("ima adpcm blocks hold no samples");
+                }
+            },
+
+            WAVE_FORMAT_PCM | WAVE_FORMAT_IEEE_FLOAT => {
+                if format.block_align as u32 !=
+                    format.channels as u32 * format.bits_per_sample as u32 / 8 {
+                    return invalid_data("block align doesn't match the frame");
+                }
+            },
+
+            _ => ()
+        }
+
+        Ok(format)
+    }
+
+    pub fn encoding(&self) -> IOResult<Encoding> {
+        match (self.format_tag, self.bits_per_sample) {
+            (WAVE_FORMAT_PCM, 8) | (WAVE_FORMAT_PCM, 16) |
+            (WAVE_FORMAT_PCM, 24) | (WAVE_FORMAT_PCM, 32) => Ok(Encoding::Pcm),
+            (WAVE_FORMAT_IEEE_FLOAT, 32) |
+            (WAVE_FORMAT_IEEE_FLOAT, 64) => Ok(Encoding::Float),
+            (WAVE_FORMAT_IMA_ADPCM, 4) => Ok(Encoding::ImaAdpcm),
+            _ => Err(IOError::new(IOErrorKind::InvalidData,
+                "unsupported wave format"))
+        }
+    }
+
+    // how samples are laid out in the data chunk, None for compressed data
+    pub fn sample_format(&self) -> Option<SampleFormat> {
+        match (self.encoding(), self.bits_per_sample) {
+            (Ok(Encoding::Pcm), 8) => Some(SampleFormat::U8),
+            (Ok(Encoding::Pcm), 16) => Some(SampleFormat::S16_LE),
+            (Ok(Encoding::Pcm), 24) => Some(SampleFormat::S24_3LE),
+            (Ok(Encoding::Pcm), 32) => Some(SampleFormat::S32_LE),
+            (Ok(Encoding::Float), 32) => Some(SampleFormat::FLOAT_LE),
+            (Ok(Encoding::Float), 64) => Some(SampleFormat::FLOAT64_LE),
+            _ => None
+        }
+    }
+
+    // the format a data chunk of `format` samples is written in, None
+    // for formats that wave files don't hold
+    pub fn from_sample_format(format: SampleFormat, channels: u16, rate: u32)
+     -> Option<WaveFormat> {
+
+        let (format_tag, bits) = match format {
+            SampleFormat::U8 => (WAVE_FORMAT_PCM, 8),
+            SampleFormat::S16_LE => (WAVE_FORMAT_PCM, 16),
+            SampleFormat::S24_3LE => (WAVE_FORMAT_PCM, 24),
+            SampleFormat::S32_LE => (WAVE_FORMAT_PCM, 32),
+            SampleFormat::FLOAT_LE => (WAVE_FORMAT_IEEE_FLOAT, 32),
+            SampleFormat::FLOAT64_LE => (WAVE_FORMAT_IEEE_FLOAT, 64),
+            _ => return None
+        };
+
+        let block_align = channels * bits / 8;
+
+        Some(WaveFormat {
+            format_tag: format_tag,
+            channels: channels,
+            sample_rate: rate,
+            byte_per_sec: rate * block_align as u32,
+            block_align: block_align,
+            bits_per_sample: bits,
+            valid_bits: bits,
+            channel_mask: 0,
+            samples_per_block: 0
+        })
+    }
+
+    // the body of a plain fmt chunk, without an extension
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(FMT_MIN_SIZE as usize);
+        buf.extend_from_slice(&le_bytes(self.format_tag as u32)[..2]);
+        buf.extend_from_slice(&le_bytes(self.channels as u32)[..2]);
+        buf.extend_from_slice(&le_bytes(self.sample_rate));
+        buf.extend_from_slice(&le_bytes(self.byte_per_sec));
+        buf.extend_from_slice(&le_bytes(self.block_align as u32)[..2]);
+        buf.extend_from_slice(&le_bytes(self.bits_per_sample as u32)[..2]);
+        buf
+    }
+
+    // frames held by a single block of the data chunk
+    pub fn frames_per_block(&self) -> usize {
+        match self.format_tag {
+            WAVE_FORMAT_IMA_ADPCM => self.samples_per_block as usize,
+            _ => 1
+        }
+    }
+
+    pub fn frames_in(&self, bytes: u64) -> u64 {
+        bytes / self.block_align as u64 * self.frames_per_block() as u64
+    }
+
+    pub fn duration_of(&self, frames: u64) -> Duration {
+        let rate = self.sample_rate as u64;
+        Duration::new(frames / rate,
+            ((frames % rate) * 1_000_000_000 / rate) as u32)
+    }
+
+    // the frame being played at the given time
+    pub fn frame_at(&self, time: Duration) -> u64 {
+        let rate = self.sample_rate as u64;
+        time.as_secs() * rate +
+            time.subsec_nanos() as u64 * rate / 1_000_000_000
+    }
+}
+
+// where to jump to in the data chunk. byte offsets are relative to the
+// start of the data chunk.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SeekTarget {
+    Byte(u64),
+    Frame(u64),
+    Time(Duration)
+}
+
+// a block boundary in the data chunk and the first frame of that block
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SeekPoint {
+    pub offset : u64,
+    pub frame  : u64
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chunk {
+    pub id     : ChunkId,
+    pub offset : u64,
+    pub size   : u32
+}
+
+// where things are in a wave file. offsets point at chunk bodies.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WaveHeader {
+    pub format      : WaveFormat,
+    pub data_offset : u64,
+    pub data_size   : u64,
+    pub chunks      : Vec<Chunk>
+}
+
+impl WaveHeader {
+
+    pub fn parse<R: Read + Seek>(input: &mut R) -> IOResult<WaveHeader> {
+
+        let file_size = try!(input.seek(SeekFrom::End(0)));
+        try!(input.seek(SeekFrom::Start(0)));
+
+        let mut riff = [0u8; 12];
+        if try!(read_full(input, &mut riff)) < riff.len() {
+            return invalid_data("riff header is truncated");
+        }
+
+        if riff[0..4] != RIFF_ID || riff[8..12] != WAVE_ID {
+            return invalid_data("not a riff wave file");
+        }
+
+        let (mut format, mut data) = (None, None);
+        let mut chunks = Vec::new();
+        let mut offset = RIFF_HEADER_SIZE;
+
+        while offset + CHUNK_HEADER_SIZE <= file_size {
+
+            let mut header = [0u8; 8];
+            try!(input.seek(SeekFrom::Start(offset)));
+            try!(read_full(input, &mut header));
+
+            let chunk = Chunk {
+                id: [header[0], header[1], header[2], header[3]],
+                offset: offset + CHUNK_HEADER_SIZE,
+                size: le_u32(&header[4..])
+            };
+
+            let available = file_size - chunk.offset;
+
+            match chunk.id {
+
+                FMT_ID => {
+                    if (chunk.size as u64) > available ||
+                        chunk.size < FMT_MIN_SIZE {
+                        return invalid_data("fmt chunk is truncated");
+                    }
+                    let mut buf = vec![0u8; chunk.size as usize];
+                    try!(read_full(input, &mut buf));
+                    format = Some(try!(WaveFormat::parse(&buf)));
+                },
+
+                // a writer that never came back to fix the sizes up
+                // leaves a data chunk running to the end of file.
+                DATA_ID if data.is_none() =>
+                    data = Some((chunk.offset,
+                        (chunk.size as u64).min(available))),
+
+                _ => ()
+            }
+
+            // chunks are padded to even sizes
+            offset = chunk.offset + chunk.size as u64 +
+                (chunk.size & 1) as u64;
+            chunks.push(chunk);
+        }
+
+        match (format, data) {
+            (Some(format), Some((data_offset, data_size))) => Ok(WaveHeader {
+                format: format,
+                data_offset: data_offset,
+                data_size: data_size,
+                chunks: chunks
+            }),
+            (None, _) => invalid_data("no fmt chunk"),
+            _ => invalid_data("no data chunk")
+        }
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.format.frames_in(self.data_size)
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.format.duration_of(self.frames())
+    }
+
+    // the start of the block holding the target, so that decoding can
+    // begin right there. targets past the end land on the end of data.
+    pub fn seek_point(&self, target: SeekTarget) -> SeekPoint {
+
+        let block_align = self.format.block_align as u64;
+        let frames_per_block = self.format.frames_per_block() as u64;
+
+        let block = match target {
+            SeekTarget::Byte(offset) => offset / block_align,
+            SeekTarget::Frame(frame) => frame / frames_per_block,
+            SeekTarget::Time(time) =>
+                self.format.frame_at(time) / frames_per_block
+        };
+
+        let block = block.min(self.data_size / block_align);
+
+        SeekPoint {
+            offset: block * block_align,
+            frame: block * frames_per_block
+        }
+    }
+}
+
+fn read_full<R: Read>(input: &mut R, buf: &mut [u8]) -> IOResult<usize> {
+
+    let mut read = 0;
+
+    while read < buf.len() {
+        match input.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == IOErrorKind::Interrupted => (),
+            Err(e) => return Err(e)
+        }
+    }
+
+    Ok(read)
+}
+
+// what comes ahead of `data_size` bytes of data in a file of a fmt and a
+// data chunk
+pub fn header_bytes(format: &WaveFormat, data_size: u32) -> Vec<u8> {
+
+    let fmt = format.to_bytes();
+    let riff_size = 4 + 2 * CHUNK_HEADER_SIZE as u32 + fmt.len() as u32 +
+        data_size + (data_size & 1);
+
+    let mut buf = Vec::with_capacity(RIFF_HEADER_SIZE as usize +
+        2 * CHUNK_HEADER_SIZE as usize + fmt.len());
+    buf.extend_from_slice(&RIFF_ID);
+    buf.extend_from_slice(&le_bytes(riff_size));
+    buf.extend_from_slice(&WAVE_ID);
+    buf.extend_from_slice(&FMT_ID);
+    buf.extend_from_slice(&le_bytes(fmt.len() as u32));
+    buf.extend(fmt);
+    buf.extend_from_slice(&DATA_ID);
+    buf.extend_from_slice(&le_bytes(data_size));
+    buf
+}
+
+// copies exactly `size` bytes at the position of the input
+fn copy_exact<R: Read, W: Write>(input: &mut R, output: &mut W, size: u64)
+ -> IOResult<()> {
+    match try!(copy(&mut input.by_ref().take(size), output)) {
+        copied if copied == size => Ok(()),
+        _ => invalid_data("chunk is truncated")
+    }
+}
+
+// writes the file `header` was parsed from again, keeping only the given
+// ranges of frames [start, end) of its data, in order. ranges are
+// widened to whole blocks. every other chunk is copied as it is but for
+// the frame count of a fact chunk. returns the frames written.
+pub fn rewrite<R: Read + Seek, W: Write>(input: &mut R, header: &WaveHeader,
+ ranges: &[(u64, u64)], output: &mut W) -> IOResult<u64> {
+
+    let file_size = try!(input.seek(SeekFrom::End(0)));
+    let block_align = header.format.block_align as u64;
+    let frames_per_block = header.format.frames_per_block() as u64;
+    let data_end = header.data_size / block_align * block_align;
+
+    let mut blocks : Vec<(u64, u64)> = Vec::new();
+    for &(start, end) in ranges {
+        let from = header.seek_point(SeekTarget::Frame(start)).offset;
+        let to = ((end + frames_per_block - 1) / frames_per_block *
+            block_align).min(data_end);
+        let from = match blocks.last() {
+            Some(&(_, last)) => from.max(last),
+            _ => from
+        };
+        if from < to {
+            blocks.push((from, to));
+        }
+    }
+
+    let data_size = blocks
+        .iter()
+        .fold(0, |size, &(from, to)| size + to - from);
+    let frames = header.format.frames_in(data_size);
+
+    // what is left of a chunk cut short by the end of file is kept
+    let sizes = header.chunks
+        .iter()
+        .map(|chunk| match chunk.offset == header.data_offset {
+            true => data_size,
+            _ => (chunk.size as u64).min(file_size - chunk.offset)
+        })
+        .collect::<Vec<_>>();
+
+    let riff_size = sizes
+        .iter()
+        .fold(4, |sum, size| sum + CHUNK_HEADER_SIZE + size + (size & 1));
+
+    if riff_size > u32::max_value() as u64 {
+        return invalid_data("wave file is too large");
+    }
+
+    try!(output.write_all(&RIFF_ID));
+    try!(output.write_all(&le_bytes(riff_size as u32)));
+    try!(output.write_all(&WAVE_ID));
+
+    for (chunk, &size) in header.chunks.iter().zip(sizes.iter()) {
+
+        try!(output.write_all(&chunk.id));
+        try!(output.write_all(&le_bytes(size as u32)));
+
+        if chunk.offset == header.data_offset {
+            for &(from, to) in blocks.iter() {
+                try!(input.seek(SeekFrom::Start(chunk.offset + from)));
+                try!(copy_exact(input, output, to - from));
+            }
+        } else if chunk.id == FACT_ID && size >= 4 {
+            try!(output.write_all(&le_bytes(frames as u32)));
+            try!(input.seek(SeekFrom::Start(chunk.offset + 4)));
+            try!(copy_exact(input, output, size - 4));
+        } else {
+            try!(input.seek(SeekFrom::Start(chunk.offset)));
+            try!(copy_exact(input, output, size));
+        }
+
+        if size & 1 == 1 {
+            try!(output.write_all(&[0]));
+        }
+    }
+
+    Ok(frames)
+}
+
+// decodes whole blocks of a data chunk into interleaved samples in
+// [-1.0, 1.0). a trailing partial block is ignored.
+pub fn decode(format: &WaveFormat, bytes: &[u8]) -> IOResult<Vec<f64>> {
+
+    let block_align = format.block_align as usize;
+    let bytes = &bytes[..bytes.len() / block_align * block_align];
+
+    match try!(format.encoding()) {
+        Encoding::ImaAdpcm => Ok(bytes
+            .chunks(block_align)
+            .flat_map(|block| ima_decode_block(block,
+                format.channels as usize,
+                format.samples_per_block as usize))
+            .collect()),
+        _ => match format.sample_format() {
+            Some(sample_format) => Ok(sample_format.decode_samples(bytes)),
+            _ => invalid_data("unsupported wave format")
+        }
+    }
+}
+
+const IMA_INDEX_TABLE : [i32; 16] = [
+    -1, -1, -1, -1, 2, 4, 6, 8,
+    -1, -1, -1, -1, 2, 4, 6, 8
+];
+
+const IMA_STEP_TABLE : [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17,
+    19, 21, 23, 25, 28, 31, 34, 37, 41, 45,
+    50, 55, 60, 66, 73, 80, 88, 97, 107, 118,
+    130, 143, 157, 173, 190, 209, 230, 253, 279, 307,
+    337, 371, 408, 449, 494, 544, 598, 658, 724, 796,
+    876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066,
+    2272, 2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358,
+    5894, 6484, 7132, 7845, 8630, 9493, 10442, 11487, 12635, 13899,
+    15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767
+];
+
+fn ima_samples_per_block(block_align: u16, channels: u16) -> u16 {
+    let (block_align, channels) = (block_align as usize, channels as usize);
+    match block_align > 4 * channels {
+        true => ((block_align - 4 * channels) * 2 / channels + 1) as u16,
+        _ => 0
+    }
+}
+
+struct ImaChannel {
+    predictor : i32,
+    index     : i32
+}
+
+impl ImaChannel {
+
+    fn decode(&mut self, nibble: u8) -> i16 {
+
+        let step = IMA_STEP_TABLE[self.index as usize];
+        let nibble = nibble as i32;
+
+        let mut diff = step >> 3;
+        if nibble & 1 != 0 { diff += step >> 2; }
+        if nibble & 2 != 0 { diff += step >> 1; }
+        if nibble & 4 != 0 { diff += step; }
+        if nibble & 8 != 0 { diff = -diff; }
+
+        self.predictor = (self.predictor + diff).max(-32768).min(32767);
+        self.index = (self.index + IMA_INDEX_TABLE[nibble as usize])
+            .max(0)
+            .min(88);
+
+        self.predictor as i16
+    }
+}
+
+// a block starts with a 4 byte header per channel followed by groups of
+// 4 bytes (8 samples) per channel, low nibble first.
+fn ima_decode_block(block: &[u8], channels: usize, frames: usize) -> Vec<f64> {
+
+    let mut samples = vec![0i16; frames * channels];
+    let header_size = 4 * channels;
+
+    if block.len() < header_size || frames == 0 {
+        return Vec::new();
+    }
+
+    let mut states = (0..channels)
+        .map(|ch| {
+            let header = &block[4 * ch..];
+            let predictor = le_u16(header) as i16;
+            samples[ch] = predictor;
+            ImaChannel {
+                predictor: predictor as i32,
+                index: (header[2] as i32).min(88)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let groups = &block[header_size..];
+
+    for (n, group) in groups.chunks(4).enumerate() {
+
+        let ch = n % channels;
+        let first = 1 + n / channels * 8;
+
+        for (i, byte) in group.iter().enumerate() {
+            for (j, nibble) in [byte & 0x0f, byte >> 4].iter().enumerate() {
+                let frame = first + 2 * i + j;
+                if frame < frames {
+                    samples[frame * channels + ch] = states[ch].decode(*nibble);
+                }
+            }
+        }
+    }
+
+    samples
+        .iter()
+        .map(|s| *s as f64 / 32768.0)
+        .collect()
+}
+
+// reads decoded frames from the data chunk of a wave file
+pub struct WaveReader<R: Read + Seek> {
+    input    : R,
+    header   : WaveHeader,
+    position : u64
+}
+
+impl WaveReader<File> {
+    pub fn open(path: &str) -> IOResult<WaveReader<File>> {
+        File::open(path)
+            .and_then(WaveReader::new)
+    }
+}
+
+impl<R: Read + Seek> WaveReader<R> {
+
+    pub fn new(mut input: R) -> IOResult<WaveReader<R>> {
+
+        let header = try!(WaveHeader::parse(&mut input));
+        try!(header.format.encoding());
+        try!(input.seek(SeekFrom::Start(header.data_offset)));
+
+        Ok(WaveReader {
+            input: input,
+            header: header,
+            position: 0
+        })
+    }
+
+    pub fn header(&self) -> &WaveHeader {
+        &self.header
+    }
+
+    pub fn format(&self) -> &WaveFormat {
+        &self.header.format
+    }
+
+    // raw bytes of whole blocks, at most `max_frames` worth of them
+    pub fn read_blocks(&mut self, max_frames: usize) -> IOResult<Vec<u8>> {
+
+        let format = self.header.format.clone();
+        let block_align = format.block_align as u64;
+        let blocks = (max_frames / format.frames_per_block()).max(1) as u64;
+
+        let left = (self.header.data_size - self.position) / block_align;
+        let mut buf = vec![0u8; (blocks.min(left) * block_align) as usize];
+
+        let read = try!(read_full(&mut self.input, &mut buf));
+        buf.truncate(read);
+        self.position += read as u64;
+        Ok(buf)
+    }
+
+    // interleaved samples, empty at the end of data
+    pub fn read_frames(&mut self, max_frames: usize) -> IOResult<Vec<f64>> {
+        let buf = try!(self.read_blocks(max_frames));
+        decode(&self.header.format, &buf)
+    }
+
+    pub fn seek(&mut self, target: SeekTarget) -> IOResult<SeekPoint> {
+
+        let point = self.header.seek_point(target);
+
+        try!(self.input.seek(SeekFrom::Start(self.header.data_offset +
+            point.offset)));
+        self.position = point.offset;
+        Ok(point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    use super::*;
+    use fixtures::{ chunk, fmt_chunk, riff };
+    use sample::SampleFormat;
+
+    #[test]
+    fn parse_test() {
+
+        let file = riff(&[
+            fmt_chunk(SampleFormat::S16_LE, 2, 8000),
+            chunk(b"LIST", b"odd"),
+            chunk(b"data", &[0, 0, 0, 64, 0, 192, 0, 0])
+        ]);
+
+        let header = WaveHeader::parse(&mut Cursor::new(file))
+            .unwrap();
+
+        assert_eq!(2, header.format.channels);
+        assert_eq!(8000, header.format.sample_rate);
+        assert_eq!(Encoding::Pcm, header.format.encoding().unwrap());
+        assert_eq!(8, header.data_size);
+        assert_eq!(2, header.frames());
+        assert_eq!(3, header.chunks.len());
+        assert_eq!(12 + 8 + 16 + 8 + 4 + 8, header.data_offset);
+    }
+
+    #[test]
+    fn read_frames_test() {
+
+        let file = riff(&[
+            fmt_chunk(SampleFormat::S16_LE, 2, 8000),
+            chunk(b"data", &[0, 0, 0, 64, 0, 192, 0, 0])
+        ]);
+
+        let mut reader = WaveReader::new(Cursor::new(file))
+            .unwrap();
+
+        assert_eq!(vec![0.0, 0.5], reader.read_frames(1).unwrap());
+        assert_eq!(vec![-0.5, 0.0], reader.read_frames(4).unwrap());
+        assert!(reader.read_frames(4).unwrap().is_empty());
+    }
+
+    #[test]
+    fn seek_test() {
+
+        let file = riff(&[
+            fmt_chunk(SampleFormat::S16_LE, 2, 8000),
+            chunk(b"data", &[0, 0, 0, 64, 0, 192, 0, 0])
+        ]);
+
+        let mut reader = WaveReader::new(Cursor::new(file))
+            .unwrap();
+
+        // a byte in the middle of a frame goes back to its start
+        assert_eq!(SeekPoint { offset: 4, frame: 1 },
+            reader.seek(SeekTarget::Byte(6)).unwrap());
+        assert_eq!(vec![-0.5, 0.0], reader.read_frames(4).unwrap());
+
+        assert_eq!(SeekPoint { offset: 0, frame: 0 },
+            reader.seek(SeekTarget::Time(Duration::new(0, 100_000)))
+                .unwrap());
+        assert_eq!(vec![0.0, 0.5], reader.read_frames(1).unwrap());
+
+        assert_eq!(SeekPoint { offset: 8, frame: 2 },
+            reader.seek(SeekTarget::Frame(100)).unwrap());
+        assert!(reader.read_frames(4).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rewrite_test() {
+
+        let file = riff(&[
+            fmt_chunk(SampleFormat::S16_LE, 2, 8000),
+            chunk(b"fact", &[3, 0, 0, 0]),
+            chunk(b"data", &[0, 0, 0, 64, 0, 192, 0, 0, 1, 0, 1, 0]),
+            chunk(b"LIST", b"odd")
+        ]);
+        let header = WaveHeader::parse(&mut Cursor::new(file.clone()))
+            .unwrap();
+
+        let mut output = Vec::new();
+        assert_eq!(2, rewrite(&mut Cursor::new(file), &header,
+            &[(1, 2), (2, 100)], &mut output).unwrap());
+
+        assert_eq!(riff(&[
+            fmt_chunk(SampleFormat::S16_LE, 2, 8000),
+            chunk(b"fact", &[2, 0, 0, 0]),
+            chunk(b"data", &[0, 192, 0, 0, 1, 0, 1, 0]),
+            chunk(b"LIST", b"odd")
+        ]), output);
+    }
+
+    #[test]
+    fn header_bytes_test() {
+
+        let format = WaveFormat::from_sample_format(SampleFormat::S16_LE, 2,
+            8000).unwrap();
+        assert_eq!(vec![1, 0, 2, 0, 0x40, 0x1f, 0, 0,
+                        0, 0x7d, 0, 0, 4, 0, 16, 0],
+            format.to_bytes());
+
+        let mut file = header_bytes(&format, 8);
+        file.extend_from_slice(&[0, 0, 0, 64, 0, 192, 0, 0]);
+        assert_eq!(riff(&[
+            fmt_chunk(SampleFormat::S16_LE, 2, 8000),
+            chunk(b"data", &[0, 0, 0, 64, 0, 192, 0, 0])
+        ]), file);
+
+        let header = WaveHeader::parse(&mut Cursor::new(file)).unwrap();
+        assert_eq!(format, header.format);
+
+        let float = WaveFormat::from_sample_format(SampleFormat::FLOAT64_LE,
+            1, 44100).unwrap();
+        assert_eq!(Encoding::Float, float.encoding().unwrap());
+        assert_eq!(Some(SampleFormat::FLOAT64_LE), float.sample_format());
+        assert!(WaveFormat::from_sample_format(SampleFormat::S16_BE, 1,
+            44100).is_none());
+    }
+
+    #[test]
+    fn not_a_wave_test() {
+        let file = b"RIFX\x04\x00\x00\x00WAVE".to_vec();
+        assert!(WaveHeader::parse(&mut Cursor::new(file)).is_err());
+    }
+
+    #[test]
+    fn ima_adpcm_test() {
+
+        // mono, 8 samples per byte group: header + 4 bytes = 9 frames
+        let mut body = WaveFormat {
+            format_tag: WAVE_FORMAT_IMA_ADPCM,
+            channels: 1,
+            sample_rate: 8000,
+            byte_per_sec: 64000,
+            block_align: 8,
+            bits_per_sample: 4,
+            valid_bits: 4,
+            channel_mask: 0,
+            samples_per_block: 0
+        }.to_bytes();
+        body.extend_from_slice(&[2, 0, 9, 0]);
+
+        let block = [0x00, 0x10, 0x00, 0x00, 0x77, 0x77, 0x77, 0x77];
+
+        let file = riff(&[
+            chunk(b"fmt ", &body),
+            chunk(b"data", &block)
+        ]);
+
+        let mut reader = WaveReader::new(Cursor::new(file))
+            .unwrap();
+
+        assert_eq!(9, reader.header().frames());
+
+        let samples = reader.read_frames(9).unwrap();
+        assert_eq!(9, samples.len());
+        assert_eq!(0x1000 as f64 / 32768.0, samples[0]);
+
+        // every nibble is a positive step, so the signal keeps rising
+        for pair in samples.windows(2) {
+            assert!(pair[1] > pair[0]);
+        }
+
+        // frames inside a block can only be reached from its header
+        assert_eq!(SeekPoint { offset: 0, frame: 0 },
+            reader.seek(SeekTarget::Frame(5)).unwrap());
+        assert_eq!(samples, reader.read_frames(9).unwrap());
+    }
+}
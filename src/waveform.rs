@@ -0,0 +1,376 @@
+use std::char;
+use std::f64;
+use std::io::Write;
+
+use io::*;
+use player::FRAMES_PER_WRITE;
+use wav::WaveReader;
+
+pub const DEFAULT_ZOOM : u32 = 256;
+pub const DEFAULT_BITS : u8 = 16;
+
+// the version of the audiowaveform .dat layout written
+const DAT_VERSION : i32 = 2;
+const DAT_8_BIT   : u32 = 0x1;
+
+// the dots of a braille cell by column and row, from U+2800
+const BRAILLE      : u32 = 0x2800;
+const BRAILLE_DOTS : [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40],
+                                      [0x08, 0x10, 0x20, 0x80]];
+
+// the lowest and highest sample of each channel over every
+// `samples_per_pixel` frames, the way audiowaveform keeps them. data
+// holds, pixel by pixel, a min and then a max for each channel, in
+// `bits` signed bits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Peaks {
+    pub sample_rate       : u32,
+    pub channels          : u16,
+    pub bits              : u8,
+    pub samples_per_pixel : u32,
+    pub data              : Vec<i16>
+}
+
+impl Peaks {
+
+    // the number of pixels
+    pub fn length(&self) -> usize {
+        self.data.len() / (2 * self.channels.max(1) as usize)
+    }
+
+    // samples are decoded against this, and the highest one is a step
+    // short of it
+    fn full_scale(&self) -> f64 {
+        (1u32 << (self.bits - 1)) as f64
+    }
+
+    // the .dat layout of audiowaveform, a header of little endian words
+    // and then the data in 8 or 16 bits
+    pub fn write_dat<W: Write>(&self, output: &mut W) -> IOResult<()> {
+
+        let flags = match self.bits {
+            8 => DAT_8_BIT,
+            _ => 0
+        };
+
+        let mut dat = Vec::new();
+        for word in [DAT_VERSION as u32, flags, self.sample_rate,
+                     self.samples_per_pixel, self.length() as u32,
+                     self.channels as u32].iter() {
+            dat.extend_from_slice(&[*word as u8, (*word >> 8) as u8,
+                (*word >> 16) as u8, (*word >> 24) as u8]);
+        }
+
+        for value in self.data.iter() {
+            match self.bits {
+                8 => dat.push(*value as i8 as u8),
+                _ => dat.extend_from_slice(&[*value as u8,
+                    (*value >> 8) as u8])
+            }
+        }
+
+        output.write_all(&dat)
+    }
+
+    // the json layout of audiowaveform
+    pub fn to_json(&self) -> String {
+
+        let data = self.data
+            .iter()
+            .map(|value| format!("{}", value))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        format!("{{\"version\":{},\"channels\":{},\"sample_rate\":{},\
+            \"samples_per_pixel\":{},\"bits\":{},\"length\":{},\
+            \"data\":[{}]}}",
+            DAT_VERSION,
+            self.channels,
+            self.sample_rate,
+            self.samples_per_pixel,
+            self.bits,
+            self.length(),
+            data)
+    }
+
+    // the lowest and highest level over every channel of the pixels in
+    // [from, to), in [-1.0, 1.0]
+    fn range(&self, from: usize, to: usize) -> Option<(f64, f64)> {
+
+        let stride = 2 * self.channels as usize;
+
+        self.data[from * stride..to * stride]
+            .chunks(2)
+            .fold(None, |range, pair| {
+                let (min, max) = (pair[0] as f64, pair[1] as f64);
+                Some(match range {
+                    Some((low, high)) => (min.min(low), max.max(high)),
+                    _ => (min, max)
+                })
+            })
+            .map(|(min, max)| (min / self.full_scale(),
+                max / self.full_scale()))
+    }
+
+    // the levels of `columns` columns spread over the pixels
+    fn columns(&self, columns: usize) -> Vec<Option<(f64, f64)>> {
+
+        let length = self.length();
+
+        (0..columns)
+            .map(|column| {
+                let from = column * length / columns;
+                let to = ((column + 1) * length / columns).max(from + 1);
+                match to <= length {
+                    true => self.range(from, to),
+                    _ => None
+                }
+            })
+            .collect()
+    }
+}
+
+// the row of `rows` a level falls on, the top one for full scale
+fn row_of(level: f64, rows: usize) -> usize {
+    let level = level.max(-1.0).min(1.0);
+    ((1.0 - level) / 2.0 * (rows - 1) as f64).round() as usize
+}
+
+// lines of `width` braille cells, `height` of them, two columns and four
+// rows of dots each
+pub fn render_braille(peaks: &Peaks, width: usize, height: usize)
+ -> String {
+
+    let rows = height * 4;
+    let mut cells = vec![vec![0u32; width]; height];
+
+    for (column, range) in peaks.columns(width * 2).iter().enumerate() {
+        if let Some((min, max)) = *range {
+            for row in row_of(max, rows)..row_of(min, rows) + 1 {
+                cells[row / 4][column / 2] |=
+                    BRAILLE_DOTS[column % 2][row % 4];
+            }
+        }
+    }
+
+    cells
+        .iter()
+        .map(|line| line
+            .iter()
+            .map(|dots| char::from_u32(BRAILLE + dots).unwrap())
+            .collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// the same with a `#` for every cell the waveform covers
+pub fn render_ascii(peaks: &Peaks, width: usize, height: usize) -> String {
+
+    let mut cells = vec![vec![' '; width]; height];
+
+    for (column, range) in peaks.columns(width).iter().enumerate() {
+        if let Some((min, max)) = *range {
+            for row in row_of(max, height)..row_of(min, height) + 1 {
+                cells[row][column] = '#';
+            }
+        }
+    }
+
+    cells
+        .iter()
+        .map(|line| line.iter().cloned().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// a zoom being scanned, the pixel it is in the middle of
+struct Level {
+    peaks   : Peaks,
+    count   : u32,
+    current : Vec<(f64, f64)>
+}
+
+impl Level {
+
+    fn push_pixel(&mut self) {
+
+        let scale = self.peaks.full_scale();
+
+        for &(min, max) in self.current.iter() {
+            let (min, max) = ((min * scale).round().max(-scale),
+                (max * scale).round().min(scale - 1.0));
+            self.peaks.data.push(min as i16);
+            self.peaks.data.push(max as i16);
+        }
+
+        for range in self.current.iter_mut() {
+            *range = (f64::INFINITY, f64::NEG_INFINITY);
+        }
+        self.count = 0;
+    }
+}
+
+// scans the file at `path` once for the peaks at every zoom, in frames
+// a pixel. a last pixel of fewer frames is kept.
+pub fn scan(path: &str, zooms: &[u32], bits: u8) -> IOResult<Vec<Peaks>> {
+
+    if bits != 8 && bits != 16 {
+        return Err(IOError::new(IOErrorKind::InvalidInput,
+            "peaks are 8 or 16 bits"));
+    }
+
+    if zooms.is_empty() || zooms.contains(&0) {
+        return Err(IOError::new(IOErrorKind::InvalidInput,
+            "a zoom has to be a frame or more"));
+    }
+
+    let mut reader = try!(WaveReader::open(path));
+    let (channels, rate) = (reader.format().channels,
+        reader.format().sample_rate);
+
+    let mut levels = zooms
+        .iter()
+        .map(|&zoom| Level {
+            peaks: Peaks {
+                sample_rate: rate,
+                channels: channels,
+                bits: bits,
+                samples_per_pixel: zoom,
+                data: Vec::new()
+            },
+            count: 0,
+            current: vec![(f64::INFINITY, f64::NEG_INFINITY);
+                channels as usize]
+        })
+        .collect::<Vec<_>>();
+
+    loop {
+
+        let samples = try!(reader.read_frames(FRAMES_PER_WRITE));
+        if samples.is_empty() {
+            break;
+        }
+
+        for frame in samples.chunks(channels as usize) {
+            for level in levels.iter_mut() {
+
+                for (range, &sample) in level.current.iter_mut()
+                    .zip(frame.iter()) {
+                    *range = (range.0.min(sample), range.1.max(sample));
+                }
+
+                level.count += 1;
+                if level.count == level.peaks.samples_per_pixel {
+                    level.push_pixel();
+                }
+            }
+        }
+    }
+
+    Ok(levels
+        .into_iter()
+        .map(|mut level| {
+            if level.count > 0 {
+                level.push_pixel();
+            }
+            level.peaks
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use fixtures::{ TempDir, pcm };
+    use sample::SampleFormat;
+
+    // 16 bit stereo at 8000 Hz, the left rising by a quarter of full
+    // scale a frame and the right its negative
+    fn write_ramp(dir: &TempDir) -> String {
+
+        let mut data = Vec::new();
+        for value in [0i16, 8192, 16384, 24576, 32767].iter() {
+            let negative = -*value;
+            data.extend_from_slice(&[*value as u8, (*value >> 8) as u8,
+                negative as u8, (negative >> 8) as u8]);
+        }
+
+        dir.write("ramp.wav", &pcm(SampleFormat::S16_LE, 2, 8000, &data))
+    }
+
+    #[test]
+    fn scan_test() {
+
+        let dir = TempDir::new("waveform_scan_test");
+        let path = write_ramp(&dir);
+
+        let peaks = scan(&path, &[2, 4], 16).unwrap();
+        let small = scan(&path, &[5], 8).unwrap();
+
+        assert_eq!(2, peaks.len());
+        assert_eq!(3, peaks[0].length());
+        assert_eq!(vec![0, 8192, -8192, 0,
+                        16384, 24576, -24576, -16384,
+                        32767, 32767, -32767, -32767],
+            peaks[0].data);
+        assert_eq!((4, 2), (peaks[1].samples_per_pixel, peaks[1].length()));
+
+        assert_eq!(vec![0, 127, -128, 0], small[0].data);
+
+        assert!(scan(&dir.path("missing.wav"), &[4], 16).is_err());
+        assert!(scan(&path, &[4], 12).is_err());
+    }
+
+    fn stereo_peaks() -> Peaks {
+        Peaks {
+            sample_rate: 8000,
+            channels: 2,
+            bits: 8,
+            samples_per_pixel: 4,
+            data: vec![-1, 2, -128, 127]
+        }
+    }
+
+    #[test]
+    fn dat_test() {
+
+        let mut dat = Vec::new();
+        stereo_peaks().write_dat(&mut dat).unwrap();
+
+        assert_eq!(vec![2, 0, 0, 0, 1, 0, 0, 0, 0x40, 0x1f, 0, 0,
+                        4, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0,
+                        0xff, 2, 0x80, 0x7f],
+            dat);
+
+        let mut peaks = stereo_peaks();
+        peaks.bits = 16;
+        let mut dat = Vec::new();
+        peaks.write_dat(&mut dat).unwrap();
+        assert_eq!(&[0, 0, 0, 0], &dat[4..8]);
+        assert_eq!(&[0xff, 0xff, 2, 0], &dat[24..28]);
+
+        assert_eq!("{\"version\":2,\"channels\":2,\"sample_rate\":8000,\
+                    \"samples_per_pixel\":4,\"bits\":8,\"length\":1,\
+                    \"data\":[-1,2,-128,127]}",
+            stereo_peaks().to_json());
+    }
+
+    #[test]
+    fn render_test() {
+
+        // a pixel of silence, then one at full scale
+        let peaks = Peaks {
+            sample_rate: 8000,
+            channels: 1,
+            bits: 8,
+            samples_per_pixel: 4,
+            data: vec![0, 0, -127, 127]
+        };
+
+        assert_eq!(" #\n##\n #", render_ascii(&peaks, 2, 3));
+
+        // the middle of 8 rows of dots rounds down to the fifth
+        assert_eq!("\u{28b8}\n\u{28b9}", render_braille(&peaks, 1, 2));
+    }
+}